@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Instant;
+
+use tracing::field::Empty;
+use tracing::Span;
+
+/// Per-invocation tracing span for a tool call.
+///
+/// Records the tool name, a summary of resolved inputs, cache hit/miss, and relay count
+/// as the call progresses, then emits a completion event with the elapsed time on drop.
+/// Logged at INFO when `server.log_tool_calls` is set, DEBUG otherwise. Never record
+/// invoices or preimages here — only truncated identifiers.
+pub struct ToolCallLog {
+    span: Span,
+    start: Instant,
+    verbose: bool,
+}
+
+impl ToolCallLog {
+    pub fn new(tool: &'static str, verbose: bool) -> Self {
+        let span = if verbose {
+            tracing::info_span!(
+                "tool_call",
+                tool,
+                input = Empty,
+                cache = Empty,
+                relays = Empty,
+                elapsed_ms = Empty
+            )
+        } else {
+            tracing::debug_span!(
+                "tool_call",
+                tool,
+                input = Empty,
+                cache = Empty,
+                relays = Empty,
+                elapsed_ms = Empty
+            )
+        };
+        Self {
+            span,
+            start: Instant::now(),
+            verbose,
+        }
+    }
+
+    /// Record a short summary of the resolved inputs, e.g. `pubkey=7e7e9c42…`.
+    pub fn input(&self, summary: &str) {
+        self.span.record("input", summary);
+    }
+
+    pub fn cache(&self, hit: bool) {
+        self.span.record("cache", if hit { "hit" } else { "miss" });
+    }
+
+    pub fn relays(&self, count: usize) {
+        self.span.record("relays", count);
+    }
+}
+
+impl Drop for ToolCallLog {
+    fn drop(&mut self) {
+        let elapsed_ms = self.start.elapsed().as_millis() as u64;
+        self.span.record("elapsed_ms", elapsed_ms);
+        let _enter = self.span.enter();
+        if self.verbose {
+            tracing::info!("tool call finished");
+        } else {
+            tracing::debug!("tool call finished");
+        }
+    }
+}
+
+/// Wall-clock milliseconds per phase of a tool call (`relay_fetch`, `cache`,
+/// `primal`, `enrich`, ...), returned to clients that pass `include_timing`.
+/// A phase timed more than once accumulates. Disabled timers record nothing.
+pub struct PhaseTimings {
+    phases: Option<BTreeMap<String, u64>>,
+}
+
+impl PhaseTimings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            phases: enabled.then(BTreeMap::new),
+        }
+    }
+
+    /// Await `fut`, adding its duration to `phase`.
+    pub async fn time<T>(&mut self, phase: &str, fut: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let output = fut.await;
+        if let Some(phases) = &mut self.phases {
+            *phases.entry(phase.to_string()).or_default() += start.elapsed().as_millis() as u64;
+        }
+        output
+    }
+
+    /// The recorded phases, or `None` when timing wasn't requested.
+    pub fn finish(self) -> Option<BTreeMap<String, u64>> {
+        self.phases
+    }
+}
+
+/// Truncate a hex pubkey (or any long identifier) for logging.
+pub fn short_id(id: &str) -> String {
+    if id.chars().count() > 12 {
+        format!("{}…", id.chars().take(8).collect::<String>())
+    } else {
+        id.to_string()
+    }
+}
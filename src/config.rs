@@ -1,6 +1,21 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
 use anyhow::Context;
 use serde::Deserialize;
 
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+/// Upper bound for cache TTLs (30 days); anything larger is almost certainly a typo.
+const MAX_TTL_SECONDS: u64 = 30 * 86400;
+const MAX_CALLS_PER_DAY: u32 = 100_000;
+const MAX_PRIMAL_ATTEMPTS: u32 = 10;
+/// Each expanded follow costs a relay round trip, so keep depth-2 graphs bounded
+const MAX_FOLLOWER_GRAPH_FANOUT: u32 = 500;
+/// Fewest notes trending_notes samples, however short the timeframe
+const MIN_TRENDING_SAMPLE: u32 = 50;
+/// Relays cap results per query, so a larger sample only slows the fetch down
+const MAX_TRENDING_SAMPLE: u32 = 5000;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
@@ -9,6 +24,14 @@ pub struct Config {
     pub free_tier: FreeTierConfig,
     pub pricing: PricingConfig,
     pub payment: PaymentConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub tools: ToolsConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -20,6 +43,36 @@ pub struct ServerConfig {
     pub transport: String,
     #[serde(default = "default_http_port")]
     pub http_port: u16,
+    /// Log a span per tool invocation at INFO (otherwise DEBUG)
+    #[serde(default)]
+    pub log_tool_calls: bool,
+    /// Allowed `X-API-Key` values for HTTP clients (empty = accept any key)
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// `X-Admin-Key` values that unlock operator tools such as wallet_status
+    /// (empty = operator tools disabled)
+    #[serde(default)]
+    pub admin_keys: Vec<String>,
+    /// Replaces the default instructions sent to clients at initialize
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// Per-tool description overrides, keyed by tool name
+    #[serde(default)]
+    pub tool_descriptions: HashMap<String, String>,
+    /// Browser origins allowed to call the HTTP endpoints (CORS), e.g.
+    /// "https://dashboard.example.com"; "*" allows any. Empty = no CORS headers.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    /// Per-IP request cap on the HTTP transport, independent of the free tier
+    /// (0 = unlimited)
+    #[serde(default)]
+    pub ip_requests_per_minute: u32,
+    /// Take the client IP from `X-Forwarded-For`; enable only behind a proxy that sets it
+    #[serde(default)]
+    pub trust_x_forwarded_for: bool,
+    /// Overall limit on a single tool call; slower calls are cancelled
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout_seconds: u64,
 }
 
 fn default_transport() -> String {
@@ -30,21 +83,142 @@ fn default_http_port() -> u16 {
     3000
 }
 
+fn default_request_timeout() -> u64 {
+    120
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct RelayConfig {
     pub default: Vec<String>,
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval_seconds: u64,
+    /// How long a relay with a high recent failure rate is skipped before a fetch
+    /// probes it again
+    #[serde(default = "default_unhealthy_retry")]
+    pub unhealthy_retry_seconds: u64,
+    /// Cap on relays connected at once; relays beyond it in `default` are skipped.
+    /// Unset means no cap (every default relay is connected).
+    #[serde(default)]
+    pub max_concurrent_connections: Option<usize>,
+    /// When non-empty, only these relays are used; others are dropped silently
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    /// Relays that are never used; asking for one is an error
+    #[serde(default)]
+    pub denylist: Vec<String>,
+}
+
+impl RelayConfig {
+    /// Whether `url` may be used under `allowlist`/`denylist`: an error for a
+    /// denied relay, `Ok(false)` for one missing from a non-empty allowlist.
+    pub fn check_policy(&self, url: &str) -> Result<bool, String> {
+        let url = normalize_relay_url(url);
+        if self.denylist.iter().any(|d| normalize_relay_url(d) == url) {
+            return Err(format!("Relay {url} is denied by the operator"));
+        }
+        Ok(self.allowlist.is_empty()
+            || self.allowlist.iter().any(|a| normalize_relay_url(a) == url))
+    }
+
+    /// Default relays permitted by the allowlist (denied ones fail validation).
+    pub fn permitted_defaults(&self) -> Vec<String> {
+        self.default
+            .iter()
+            .filter(|url| matches!(self.check_policy(url), Ok(true)))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Compare relay URLs case-insensitively and ignoring a trailing slash.
+fn normalize_relay_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
+fn default_health_check_interval() -> u64 {
+    300
+}
+
+fn default_unhealthy_retry() -> u64 {
+    60
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct CacheConfig {
+    /// `file` (SQLite at `database_path`) or `memory` (lost on restart)
+    #[serde(default)]
+    pub backend: CacheBackend,
+    /// SQLite file path (only used by the `file` backend)
+    #[serde(default)]
     pub database_path: String,
     pub profile_ttl_seconds: u64,
     pub relay_info_ttl_seconds: u64,
+    /// How long fetched contact lists (kind 3) and relay lists (kind 10002) are reused
+    #[serde(default = "default_list_ttl_seconds")]
+    pub list_ttl_seconds: u64,
+    /// How long events fetched by ID are reused
+    #[serde(default = "default_event_ttl_seconds")]
+    pub event_ttl_seconds: u64,
+    /// Recently used profiles kept in memory in front of SQLite; 0 disables
+    #[serde(default = "default_profile_lru_size")]
+    pub profile_lru_size: usize,
+    /// How long SQLite waits on a locked database before erroring (file backend)
+    #[serde(default = "default_busy_timeout_ms")]
+    pub busy_timeout_ms: u64,
+}
+
+fn default_busy_timeout_ms() -> u64 {
+    crate::nostr::cache::DEFAULT_BUSY_TIMEOUT_MS
+}
+
+fn default_event_ttl_seconds() -> u64 {
+    crate::nostr::cache::DEFAULT_EVENT_TTL_SECONDS
+}
+
+fn default_list_ttl_seconds() -> u64 {
+    crate::nostr::cache::DEFAULT_LIST_TTL_SECONDS
+}
+
+fn default_profile_lru_size() -> usize {
+    crate::nostr::cache::DEFAULT_PROFILE_LRU_SIZE
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    File,
+    Memory,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct FreeTierConfig {
     pub calls_per_day: u32,
+    /// Which identity the daily quota is counted against
+    #[serde(default)]
+    pub identity: FreeTierIdentity,
+    /// Environment variable holding the client ID when `identity = "env"`
+    #[serde(default = "default_identity_env")]
+    pub identity_env: String,
+}
+
+/// Source of the free-tier identity for sessions without an `X-API-Key`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreeTierIdentity {
+    /// "stdio" for the stdio transport, a fresh ID per HTTP connection
+    #[default]
+    Session,
+    /// One random ID per server process, shared by all of its sessions
+    Process,
+    /// The value of `free_tier.identity_env`, e.g. set per tenant by a broker
+    Env,
+    /// HTTP clients must send an `X-API-Key`; stdio falls back to `session`
+    ApiKey,
+}
+
+fn default_identity_env() -> String {
+    "NOSTR_INTEL_CLIENT_ID".into()
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -54,34 +228,353 @@ pub struct PricingConfig {
     pub trending_notes: u64,
     pub get_follower_graph: u64,
     pub zap_analytics: u64,
+    #[serde(default = "default_common_follows_price")]
+    pub common_follows: u64,
+    #[serde(default = "default_account_activity_price")]
+    pub account_activity: u64,
+    /// Bundle price for get_dossier (priced below its component tools combined)
+    #[serde(default = "default_get_dossier_price")]
+    pub get_dossier: u64,
+    #[serde(default = "default_get_bookmarks_price")]
+    pub get_bookmarks: u64,
+    #[serde(default = "default_get_lists_price")]
+    pub get_lists: u64,
+    #[serde(default = "default_compare_accounts_price")]
+    pub compare_accounts: u64,
+    #[serde(default = "default_note_zaps_price")]
+    pub note_zaps: u64,
+}
+
+fn default_common_follows_price() -> u64 {
+    50
+}
+
+fn default_account_activity_price() -> u64 {
+    30
+}
+
+fn default_get_dossier_price() -> u64 {
+    100
+}
+
+fn default_get_bookmarks_price() -> u64 {
+    20
+}
+
+fn default_get_lists_price() -> u64 {
+    20
+}
+
+fn default_compare_accounts_price() -> u64 {
+    50
+}
+
+fn default_note_zaps_price() -> u64 {
+    20
+}
+
+impl PricingConfig {
+    /// search_events price: base, +15 sats above 20 results, +25 more above 50.
+    pub fn search_events_price(&self, limit: Option<u32>) -> u64 {
+        let mut price = self.search_events_base;
+        if let Some(limit) = limit {
+            if limit > 20 {
+                price += 15;
+            }
+            if limit > 50 {
+                price += 25;
+            }
+        }
+        price
+    }
+
+    /// get_follower_graph price: base for depth 1, doubled for depth 2.
+    pub fn follower_graph_price(&self, depth: u8) -> u64 {
+        if depth >= 2 {
+            self.get_follower_graph * 2
+        } else {
+            self.get_follower_graph
+        }
+    }
+
+    /// Price in sats for a paid tool given its cost-affecting params.
+    /// Returns `None` for unknown (or free) tools.
+    pub fn price_for(&self, tool_name: &str, limit: Option<u32>, depth: Option<u8>) -> Option<u64> {
+        match tool_name {
+            "search_events" => Some(self.search_events_price(limit)),
+            "relay_discovery" => Some(self.relay_discovery),
+            "trending_notes" => Some(self.trending_notes),
+            "get_follower_graph" => Some(self.follower_graph_price(depth.unwrap_or(1).clamp(1, 2))),
+            "zap_analytics" => Some(self.zap_analytics),
+            "common_follows" => Some(self.common_follows),
+            "account_activity" => Some(self.account_activity),
+            "get_dossier" => Some(self.get_dossier),
+            "get_bookmarks" => Some(self.get_bookmarks),
+            "get_lists" => Some(self.get_lists),
+            "compare_accounts" => Some(self.compare_accounts),
+            "note_zaps" => Some(self.note_zaps),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct PaymentConfig {
-    pub nwc_url: String,
+    /// NWC connection URIs, tried in order; a comma-separated string or an array
+    #[serde(rename = "nwc_url", deserialize_with = "deserialize_nwc_urls")]
+    pub nwc_urls: Vec<String>,
     pub invoice_expiry_seconds: u64,
+    /// How long a settled payment_hash can be reused for the same tool
+    #[serde(default = "default_payment_reuse_window")]
+    pub payment_reuse_window_seconds: u64,
     #[serde(default)]
     pub l402_secret: String,
     #[serde(default)]
     pub enable_l402: bool,
     #[serde(default)]
-    #[allow(dead_code)]
     pub enable_x402: bool,
+    /// USDC (Base) address quoted in x402 payment details
+    #[serde(default)]
+    pub x402_address: String,
+    /// Sats per US cent used to convert tool prices for x402
+    #[serde(default = "default_x402_sats_per_cent")]
+    pub x402_sats_per_cent: u64,
+    /// BTC price feed for USD estimates on invoices (JSON with a `USD` field, e.g.
+    /// `https://mempool.space/api/v1/prices`). Empty (the default) disables estimates,
+    /// so no outbound price requests are made unless an operator opts in.
+    #[serde(default)]
+    pub price_api_url: String,
+    #[serde(default = "default_price_refresh_seconds")]
+    pub price_refresh_seconds: u64,
+    /// POSTed a JSON notice whenever an invoice is first seen settled; empty disables
+    #[serde(default)]
+    pub payment_webhook_url: String,
+}
+
+fn split_nwc_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .collect()
+}
+
+fn deserialize_nwc_urls<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(urls) => split_nwc_urls(&urls),
+        OneOrMany::Many(urls) => urls
+            .iter()
+            .map(|u| u.trim().to_string())
+            .filter(|u| !u.is_empty())
+            .collect(),
+    })
+}
+
+fn default_payment_reuse_window() -> u64 {
+    600
+}
+
+fn default_x402_sats_per_cent() -> u64 {
+    15
+}
+
+fn default_price_refresh_seconds() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolsConfig {
+    /// Tools the operator doesn't offer; they aren't registered with clients
+    #[serde(default)]
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetworkConfig {
+    /// SOCKS5 proxy for relay connections and outbound HTTP, e.g. "socks5h://127.0.0.1:9050"
+    /// (socks5h resolves hostnames through the proxy, which Tor needs for .onion). Empty = direct.
+    #[serde(default)]
+    pub socks_proxy: String,
+    /// Per-request timeout for NIP-05 lookups (a timed-out lookup is retried once)
+    #[serde(default = "default_nip05_timeout")]
+    pub nip05_timeout_seconds: u64,
+    /// User-Agent sent on outbound HTTP (NIP-11, NIP-05, search, webhooks);
+    /// some relays behind CDNs reject requests without a recognizable one
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            socks_proxy: String::new(),
+            nip05_timeout_seconds: default_nip05_timeout(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+fn default_nip05_timeout() -> u64 {
+    crate::nostr::nip05::DEFAULT_TIMEOUT_SECONDS
+}
+
+fn default_user_agent() -> String {
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).into()
+}
+
+impl NetworkConfig {
+    /// The proxy's socket address for the relay pool, resolving its host if needed.
+    pub fn socks_proxy_addr(&self) -> anyhow::Result<Option<std::net::SocketAddr>> {
+        use std::net::ToSocketAddrs;
+
+        if self.socks_proxy.trim().is_empty() {
+            return Ok(None);
+        }
+        let url = url::Url::parse(self.socks_proxy.trim())
+            .with_context(|| format!("network.socks_proxy: invalid URL '{}'", self.socks_proxy))?;
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port().unwrap_or_default();
+        let addr = (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("network.socks_proxy: can't resolve '{host}:{port}'"))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("network.socks_proxy: no address for '{host}'"))?;
+        Ok(Some(addr))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    /// Primal cache API endpoint used by search_profiles (self-hosted caches or mirrors)
+    #[serde(default = "default_primal_api_url")]
+    pub primal_api_url: String,
+    /// Total tries per Primal search request (retries on timeouts and 5xx)
+    #[serde(default = "default_primal_max_attempts")]
+    pub primal_max_attempts: u32,
+    /// Kinds search_events queries when the caller gives no `kinds`
+    /// (empty = all kinds)
+    #[serde(default = "default_search_kinds")]
+    pub default_kinds: Vec<u16>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            primal_api_url: default_primal_api_url(),
+            primal_max_attempts: default_primal_max_attempts(),
+            default_kinds: default_search_kinds(),
+        }
+    }
+}
+
+fn default_primal_api_url() -> String {
+    "https://cache1.primal.net/api".into()
+}
+
+fn default_primal_max_attempts() -> u32 {
+    3
+}
+
+/// Text notes; reactions and reposts drown everything else out
+fn default_search_kinds() -> Vec<u16> {
+    vec![1]
+}
+
+/// Result-count defaults and hard caps for tools that take a `limit`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub search_events: ToolLimit,
+    pub search_profiles: ToolLimit,
+    pub trending_notes: ToolLimit,
+    /// Followed accounts whose contact lists get_follower_graph expands at depth 2
+    pub follower_graph_fanout: u32,
+    /// Recent notes trending_notes samples per hour of timeframe
+    pub trending_sample_per_hour: u32,
+    /// Cap on the trending_notes sample, whatever the timeframe or `sample_size`
+    pub trending_sample_max: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            search_events: ToolLimit::new(20, 100),
+            search_profiles: ToolLimit::new(5, 20),
+            trending_notes: ToolLimit::new(20, 50),
+            follower_graph_fanout: 50,
+            trending_sample_per_hour: 25,
+            trending_sample_max: 1000,
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Notes trending_notes fetches for a timeframe: the caller's `sample_size`,
+    /// or `trending_sample_per_hour` per started hour (at least
+    /// `MIN_TRENDING_SAMPLE`), capped at `trending_sample_max` either way.
+    pub fn trending_sample(&self, timeframe_secs: u64, requested: Option<usize>) -> usize {
+        let max = self.trending_sample_max as usize;
+        match requested {
+            Some(size) => size.min(max),
+            None => {
+                let hours = timeframe_secs.div_ceil(3600) as usize;
+                (hours * self.trending_sample_per_hour as usize)
+                    .clamp(MIN_TRENDING_SAMPLE as usize, max)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ToolLimit {
+    /// Used when the caller omits `limit`
+    pub default: u32,
+    /// Requests above this are clamped to it
+    pub max: u32,
+}
+
+impl ToolLimit {
+    pub const fn new(default: u32, max: u32) -> Self {
+        Self { default, max }
+    }
+
+    /// Effective limit for a request: the caller's value (or the default), kept within
+    /// `1..=max` so a `limit` of 0 still returns a result.
+    pub fn resolve(&self, requested: Option<u32>) -> u32 {
+        requested.unwrap_or(self.default).clamp(1, self.max)
+    }
 }
 
 impl Config {
+    /// Load config from the path given by `--config <path>`, then `CONFIG_PATH`,
+    /// falling back to `config.toml` in the working directory.
     pub fn load() -> anyhow::Result<Self> {
         dotenvy::dotenv().ok();
 
-        let content =
-            std::fs::read_to_string("config.toml").context("Failed to read config.toml")?;
+        let path = resolve_config_path(std::env::args().skip(1), std::env::var("CONFIG_PATH").ok());
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file at {}", path.display()))?;
 
-        let mut config: Config = toml::from_str(&content).context("Failed to parse config.toml")?;
+        let mut config: Config = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file at {}", path.display()))?;
 
-        // Override nwc_url from env var if set
+        // Override NWC connections from env var if set (comma-separated for failover)
         if let Ok(nwc_url) = std::env::var("NWC_URL") {
             if !nwc_url.is_empty() {
-                config.payment.nwc_url = nwc_url;
+                config.payment.nwc_urls = split_nwc_urls(&nwc_url);
             }
         }
 
@@ -99,6 +592,774 @@ impl Config {
             }
         }
 
+        // Override API and admin key lists from env vars (comma-separated)
+        for (var, target) in [
+            ("API_KEYS", &mut config.server.api_keys),
+            ("ADMIN_KEYS", &mut config.server.admin_keys),
+        ] {
+            if let Ok(keys) = std::env::var(var) {
+                if !keys.is_empty() {
+                    *target = keys
+                        .split(',')
+                        .map(|k| k.trim().to_string())
+                        .filter(|k| !k.is_empty())
+                        .collect();
+                }
+            }
+        }
+
+        // Override pricing and free-tier limits from env vars (non-fatal on parse errors)
+        let pricing = &mut config.pricing;
+        for (var, target) in [
+            ("PRICE_SEARCH_EVENTS", &mut pricing.search_events_base),
+            ("PRICE_RELAY_DISCOVERY", &mut pricing.relay_discovery),
+            ("PRICE_TRENDING_NOTES", &mut pricing.trending_notes),
+            ("PRICE_GET_FOLLOWER_GRAPH", &mut pricing.get_follower_graph),
+            ("PRICE_ZAP_ANALYTICS", &mut pricing.zap_analytics),
+            ("PRICE_COMMON_FOLLOWS", &mut pricing.common_follows),
+            ("PRICE_ACCOUNT_ACTIVITY", &mut pricing.account_activity),
+            ("PRICE_GET_DOSSIER", &mut pricing.get_dossier),
+            ("PRICE_GET_BOOKMARKS", &mut pricing.get_bookmarks),
+            ("PRICE_GET_LISTS", &mut pricing.get_lists),
+            ("PRICE_COMPARE_ACCOUNTS", &mut pricing.compare_accounts),
+            ("PRICE_NOTE_ZAPS", &mut pricing.note_zaps),
+        ] {
+            override_from_env(var, target);
+        }
+        override_from_env(
+            "FREE_TIER_CALLS_PER_DAY",
+            &mut config.free_tier.calls_per_day,
+        );
+
+        config.validate()?;
         Ok(config)
     }
+
+    /// Check semantic constraints that TOML parsing alone can't enforce.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.relays.default.is_empty() {
+            anyhow::bail!("relays.default must contain at least one relay");
+        }
+        for relay in &self.relays.default {
+            let url = url::Url::parse(relay)
+                .with_context(|| format!("relays.default: invalid relay URL '{relay}'"))?;
+            if !matches!(url.scheme(), "wss" | "ws") {
+                anyhow::bail!("relays.default: relay URL '{relay}' must use wss:// or ws://");
+            }
+            if let Err(e) = self.relays.check_policy(relay) {
+                anyhow::bail!("relays.default: {e} (listed in relays.denylist)");
+            }
+        }
+        if self.relays.permitted_defaults().is_empty() {
+            anyhow::bail!("relays.allowlist excludes every relay in relays.default");
+        }
+        for origin in &self.server.cors_allowed_origins {
+            if origin == "*" {
+                continue;
+            }
+            let url = url::Url::parse(origin).with_context(|| {
+                format!("server.cors_allowed_origins: invalid origin '{origin}'")
+            })?;
+            if !matches!(url.scheme(), "https" | "http")
+                || url.origin().ascii_serialization() != *origin
+            {
+                anyhow::bail!(
+                    "server.cors_allowed_origins: '{origin}' must be a scheme://host[:port] origin without a path"
+                );
+            }
+        }
+        if self.server.request_timeout_seconds == 0 {
+            anyhow::bail!("server.request_timeout_seconds must be greater than 0");
+        }
+        if self.relays.health_check_interval_seconds == 0 {
+            anyhow::bail!("relays.health_check_interval_seconds must be greater than 0");
+        }
+        if self.relays.unhealthy_retry_seconds == 0 {
+            anyhow::bail!("relays.unhealthy_retry_seconds must be greater than 0");
+        }
+        if self.relays.max_concurrent_connections == Some(0) {
+            anyhow::bail!("relays.max_concurrent_connections must be greater than 0 when set");
+        }
+
+        if self.cache.backend == CacheBackend::File && self.cache.database_path.trim().is_empty() {
+            anyhow::bail!("cache.database_path is required when cache.backend = \"file\"");
+        }
+        if self.network.nip05_timeout_seconds == 0 {
+            anyhow::bail!("network.nip05_timeout_seconds must be greater than 0");
+        }
+        if self.network.user_agent.trim().is_empty()
+            || reqwest::header::HeaderValue::from_str(&self.network.user_agent).is_err()
+        {
+            anyhow::bail!("network.user_agent must be a non-empty, valid header value");
+        }
+        if self.cache.busy_timeout_ms == 0 {
+            anyhow::bail!("cache.busy_timeout_ms must be greater than 0");
+        }
+
+        for (name, ttl) in [
+            ("cache.profile_ttl_seconds", self.cache.profile_ttl_seconds),
+            (
+                "cache.relay_info_ttl_seconds",
+                self.cache.relay_info_ttl_seconds,
+            ),
+            ("cache.list_ttl_seconds", self.cache.list_ttl_seconds),
+            ("cache.event_ttl_seconds", self.cache.event_ttl_seconds),
+        ] {
+            if ttl == 0 || ttl > MAX_TTL_SECONDS {
+                anyhow::bail!("{name} must be between 1 and {MAX_TTL_SECONDS} (got {ttl})");
+            }
+        }
+
+        if self.free_tier.identity == FreeTierIdentity::Env {
+            let var = &self.free_tier.identity_env;
+            if std::env::var(var).map_or(true, |v| v.trim().is_empty()) {
+                anyhow::bail!("free_tier.identity = \"env\" but {var} is not set");
+            }
+        }
+        if self.free_tier.calls_per_day > MAX_CALLS_PER_DAY {
+            anyhow::bail!(
+                "free_tier.calls_per_day must be at most {MAX_CALLS_PER_DAY} (got {})",
+                self.free_tier.calls_per_day
+            );
+        }
+
+        let primal_url = url::Url::parse(&self.search.primal_api_url).with_context(|| {
+            format!(
+                "search.primal_api_url: invalid URL '{}'",
+                self.search.primal_api_url
+            )
+        })?;
+        if !matches!(primal_url.scheme(), "https" | "http") {
+            anyhow::bail!(
+                "search.primal_api_url: '{}' must use https:// or http://",
+                self.search.primal_api_url
+            );
+        }
+        if !(1..=MAX_PRIMAL_ATTEMPTS).contains(&self.search.primal_max_attempts) {
+            anyhow::bail!(
+                "search.primal_max_attempts must be between 1 and {MAX_PRIMAL_ATTEMPTS} (got {})",
+                self.search.primal_max_attempts
+            );
+        }
+
+        for (name, limit) in [
+            ("limits.search_events", self.limits.search_events),
+            ("limits.search_profiles", self.limits.search_profiles),
+            ("limits.trending_notes", self.limits.trending_notes),
+        ] {
+            if limit.max == 0 {
+                anyhow::bail!("{name}.max must be greater than 0");
+            }
+            if limit.default == 0 || limit.default > limit.max {
+                anyhow::bail!(
+                    "{name}.default must be between 1 and {name}.max ({}) (got {})",
+                    limit.max,
+                    limit.default
+                );
+            }
+        }
+
+        if !(1..=MAX_FOLLOWER_GRAPH_FANOUT).contains(&self.limits.follower_graph_fanout) {
+            anyhow::bail!(
+                "limits.follower_graph_fanout must be between 1 and {MAX_FOLLOWER_GRAPH_FANOUT} (got {})",
+                self.limits.follower_graph_fanout
+            );
+        }
+
+        if self.limits.trending_sample_per_hour == 0 {
+            anyhow::bail!("limits.trending_sample_per_hour must be greater than 0");
+        }
+        if !(MIN_TRENDING_SAMPLE..=MAX_TRENDING_SAMPLE).contains(&self.limits.trending_sample_max) {
+            anyhow::bail!(
+                "limits.trending_sample_max must be between {MIN_TRENDING_SAMPLE} and {MAX_TRENDING_SAMPLE} (got {})",
+                self.limits.trending_sample_max
+            );
+        }
+
+        let proxy = self.network.socks_proxy.trim();
+        if !proxy.is_empty() {
+            let url = url::Url::parse(proxy)
+                .with_context(|| format!("network.socks_proxy: invalid URL '{proxy}'"))?;
+            if !matches!(url.scheme(), "socks5" | "socks5h") {
+                anyhow::bail!("network.socks_proxy: '{proxy}' must use socks5:// or socks5h://");
+            }
+            if url.host_str().is_none_or(str::is_empty) || url.port().is_none() {
+                anyhow::bail!("network.socks_proxy: '{proxy}' must include a host and port");
+            }
+        }
+
+        if self.payment.invoice_expiry_seconds == 0 {
+            anyhow::bail!("payment.invoice_expiry_seconds must be greater than 0");
+        }
+
+        if self.payment.enable_l402 {
+            let secret = hex::decode(&self.payment.l402_secret)
+                .map_err(|_| anyhow::anyhow!("payment.l402_secret must be hex-encoded"))?;
+            if secret.len() < 32 {
+                anyhow::bail!(
+                    "payment.l402_secret must be at least 32 bytes when enable_l402 is set (got {})",
+                    secret.len()
+                );
+            }
+        }
+
+        if !self.payment.price_api_url.trim().is_empty() {
+            let url = url::Url::parse(&self.payment.price_api_url).with_context(|| {
+                format!(
+                    "payment.price_api_url: invalid URL '{}'",
+                    self.payment.price_api_url
+                )
+            })?;
+            if !matches!(url.scheme(), "https" | "http") {
+                anyhow::bail!(
+                    "payment.price_api_url: '{}' must use https:// or http://",
+                    self.payment.price_api_url
+                );
+            }
+            if self.payment.price_refresh_seconds == 0 {
+                anyhow::bail!("payment.price_refresh_seconds must be greater than 0");
+            }
+        }
+
+        if !self.payment.payment_webhook_url.trim().is_empty() {
+            let url = url::Url::parse(&self.payment.payment_webhook_url).with_context(|| {
+                format!(
+                    "payment.payment_webhook_url: invalid URL '{}'",
+                    self.payment.payment_webhook_url
+                )
+            })?;
+            if !matches!(url.scheme(), "https" | "http") {
+                anyhow::bail!(
+                    "payment.payment_webhook_url: '{}' must use https:// or http://",
+                    self.payment.payment_webhook_url
+                );
+            }
+        }
+
+        if self.payment.enable_x402 {
+            if self.payment.x402_address.trim().is_empty() {
+                anyhow::bail!("payment.x402_address is required when enable_x402 is set");
+            }
+            if self.payment.x402_sats_per_cent == 0 {
+                anyhow::bail!("payment.x402_sats_per_cent must be greater than 0");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace `target` with the parsed value of env var `var` if it is set and parseable.
+/// Parse failures are logged and the existing (TOML) value is kept.
+fn override_from_env<T>(var: &str, target: &mut T)
+where
+    T: std::str::FromStr + std::fmt::Display,
+{
+    let Ok(raw) = std::env::var(var) else {
+        return;
+    };
+    if raw.is_empty() {
+        return;
+    }
+    match raw.trim().parse::<T>() {
+        Ok(value) => {
+            tracing::info!("Config override from {var}: {target} -> {value}");
+            *target = value;
+        }
+        Err(_) => {
+            tracing::warn!("Ignoring {var}={raw}: not a valid number, keeping {target}");
+        }
+    }
+}
+
+/// Resolve the config file path: `--config <path>` (or `--config=<path>`) wins over
+/// the `CONFIG_PATH` env var, which wins over `config.toml`.
+fn resolve_config_path(
+    args: impl IntoIterator<Item = String>,
+    env_path: Option<String>,
+) -> PathBuf {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return PathBuf::from(path);
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return PathBuf::from(path);
+        }
+    }
+
+    match env_path {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => PathBuf::from(DEFAULT_CONFIG_PATH),
+    }
+}
+
+#[cfg(test)]
+const TEST_CONFIG: &str = r#"
+[server]
+name = "test"
+version = "0.0.1"
+
+[relays]
+default = ["wss://relay.example.com"]
+
+[cache]
+database_path = ":memory:"
+profile_ttl_seconds = 60
+relay_info_ttl_seconds = 60
+
+[free_tier]
+calls_per_day = 3
+
+[pricing]
+search_events_base = 1
+relay_discovery = 2
+trending_notes = 3
+get_follower_graph = 4
+zap_analytics = 5
+
+[payment]
+nwc_url = ""
+invoice_expiry_seconds = 600
+"#;
+
+#[cfg(test)]
+impl Config {
+    /// Minimal valid config for tests.
+    pub fn test_default() -> Self {
+        toml::from_str(TEST_CONFIG).expect("valid test config")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes tests that mutate process-wide env vars read by `Config::load`.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn write_temp_config(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("nostr-intel-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn resolve_path_precedence() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            resolve_config_path(args(&[]), None),
+            PathBuf::from("config.toml")
+        );
+        assert_eq!(
+            resolve_config_path(args(&[]), Some("/etc/env.toml".into())),
+            PathBuf::from("/etc/env.toml")
+        );
+        assert_eq!(
+            resolve_config_path(
+                args(&["--config", "/etc/cli.toml"]),
+                Some("/etc/env.toml".into())
+            ),
+            PathBuf::from("/etc/cli.toml")
+        );
+        assert_eq!(
+            resolve_config_path(args(&["--config=/etc/cli.toml"]), None),
+            PathBuf::from("/etc/cli.toml")
+        );
+    }
+
+    #[test]
+    fn load_from_env_override() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let path = write_temp_config("env-override", TEST_CONFIG);
+        std::env::set_var("CONFIG_PATH", &path);
+
+        let config = Config::load().unwrap();
+        std::env::remove_var("CONFIG_PATH");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(config.server.name, "test");
+        assert_eq!(config.free_tier.calls_per_day, 3);
+    }
+
+    fn sample_config() -> Config {
+        Config::test_default()
+    }
+
+    fn assert_invalid(config: Config, needle: &str) {
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains(needle), "expected '{needle}' in '{err}'");
+    }
+
+    #[test]
+    fn sample_config_is_valid() {
+        sample_config().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_empty_relays() {
+        let mut config = sample_config();
+        config.relays.default.clear();
+        assert_invalid(config, "at least one relay");
+    }
+
+    #[test]
+    fn rejects_non_websocket_relay() {
+        let mut config = sample_config();
+        config.relays.default = vec!["https://relay.example.com".into()];
+        assert_invalid(config, "must use wss:// or ws://");
+
+        let mut config = sample_config();
+        config.relays.default = vec!["not a url".into()];
+        assert_invalid(config, "invalid relay URL");
+    }
+
+    #[test]
+    fn rejects_zero_health_check_interval() {
+        let mut config = sample_config();
+        config.relays.health_check_interval_seconds = 0;
+        assert_invalid(config, "health_check_interval_seconds");
+
+        let mut config = sample_config();
+        config.relays.unhealthy_retry_seconds = 0;
+        assert_invalid(config, "unhealthy_retry_seconds");
+
+        let mut config = sample_config();
+        config.server.request_timeout_seconds = 0;
+        assert_invalid(config, "request_timeout_seconds");
+
+        let mut config = sample_config();
+        config.network.nip05_timeout_seconds = 0;
+        assert_invalid(config, "nip05_timeout_seconds");
+
+        let mut config = sample_config();
+        config.network.user_agent = "bad\nagent".into();
+        assert_invalid(config, "user_agent");
+    }
+
+    #[test]
+    fn cors_origins_must_be_bare_origins() {
+        let mut config = sample_config();
+        config.server.cors_allowed_origins = vec![
+            "https://dash.example.com".into(),
+            "http://localhost:5173".into(),
+            "*".into(),
+        ];
+        config.validate().unwrap();
+
+        for bad in [
+            "https://dash.example.com/",
+            "dash.example.com",
+            "ftp://x.example",
+        ] {
+            let mut config = sample_config();
+            config.server.cors_allowed_origins = vec![bad.into()];
+            assert_invalid(config, "cors_allowed_origins");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_ttls() {
+        let mut config = sample_config();
+        config.cache.profile_ttl_seconds = 0;
+        assert_invalid(config, "cache.profile_ttl_seconds");
+
+        let mut config = sample_config();
+        config.cache.relay_info_ttl_seconds = MAX_TTL_SECONDS + 1;
+        assert_invalid(config, "cache.relay_info_ttl_seconds");
+    }
+
+    #[test]
+    fn relay_denylist_and_allowlist() {
+        let mut config = sample_config();
+        config.relays.default = vec![
+            "wss://relay.example.com".into(),
+            "wss://other.example.com".into(),
+        ];
+        config.relays.denylist = vec!["wss://EVIL.example.com/".into()];
+        config.relays.allowlist = vec!["wss://relay.example.com/".into()];
+        config.validate().unwrap();
+
+        // Denied relays are an error; non-allowlisted ones are just not permitted
+        let relays = &config.relays;
+        assert!(relays
+            .check_policy("wss://evil.example.com")
+            .unwrap_err()
+            .contains("denied"));
+        assert_eq!(relays.check_policy("wss://relay.example.com"), Ok(true));
+        assert_eq!(relays.check_policy("wss://other.example.com"), Ok(false));
+        assert_eq!(relays.permitted_defaults(), vec!["wss://relay.example.com"]);
+
+        let mut denied = config.clone();
+        denied.relays.denylist = vec!["wss://other.example.com".into()];
+        assert_invalid(denied, "relays.denylist");
+
+        config.relays.allowlist = vec!["wss://elsewhere.example.com".into()];
+        assert_invalid(config, "relays.allowlist");
+    }
+
+    #[test]
+    fn rejects_absurd_calls_per_day() {
+        let mut config = sample_config();
+        config.free_tier.calls_per_day = MAX_CALLS_PER_DAY + 1;
+        assert_invalid(config, "free_tier.calls_per_day");
+    }
+
+    #[test]
+    fn env_identity_requires_the_variable() {
+        let mut config = sample_config();
+        config.free_tier.identity = FreeTierIdentity::Env;
+        config.free_tier.identity_env = "NOSTR_INTEL_TEST_UNSET_IDENTITY".into();
+        assert_invalid(config, "NOSTR_INTEL_TEST_UNSET_IDENTITY");
+    }
+
+    #[test]
+    fn rejects_bad_primal_api_url() {
+        let config = sample_config();
+        assert_eq!(
+            config.search.primal_api_url,
+            "https://cache1.primal.net/api"
+        );
+
+        let mut config = sample_config();
+        config.search.primal_api_url = "not a url".into();
+        assert_invalid(config, "search.primal_api_url");
+
+        let mut config = sample_config();
+        config.search.primal_api_url = "wss://cache1.primal.net/v1".into();
+        assert_invalid(config, "must use https://");
+    }
+
+    #[test]
+    fn file_backend_requires_database_path() {
+        let mut config = sample_config();
+        assert_eq!(config.cache.backend, CacheBackend::File);
+        config.cache.database_path = String::new();
+        assert_invalid(config, "cache.database_path");
+
+        let mut config = sample_config();
+        config.cache.busy_timeout_ms = 0;
+        assert_invalid(config, "cache.busy_timeout_ms");
+
+        let mut config = sample_config();
+        config.cache.backend = CacheBackend::Memory;
+        config.cache.database_path = String::new();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn parses_memory_backend() {
+        let config: Config =
+            toml::from_str(&TEST_CONFIG.replace("[cache]", "[cache]\nbackend = \"memory\""))
+                .unwrap();
+        assert_eq!(config.cache.backend, CacheBackend::Memory);
+    }
+
+    #[test]
+    fn rejects_out_of_range_primal_attempts() {
+        let mut config = sample_config();
+        assert_eq!(config.search.primal_max_attempts, 3);
+        config.search.primal_max_attempts = 0;
+        assert_invalid(config, "search.primal_max_attempts");
+    }
+
+    #[test]
+    fn trending_sample_scales_with_timeframe() {
+        let limits = sample_config().limits;
+        let hour = 3600;
+        // Short windows still get the minimum sample
+        assert_eq!(limits.trending_sample(30 * 60, None), 50);
+        assert_eq!(limits.trending_sample(hour, None), 50);
+        assert_eq!(limits.trending_sample(6 * hour, None), 150);
+        assert_eq!(limits.trending_sample(24 * hour, None), 600);
+        assert_eq!(limits.trending_sample(7 * 24 * hour, None), 1000);
+        // An explicit size wins, but not past the cap
+        assert_eq!(limits.trending_sample(7 * 24 * hour, Some(10)), 10);
+        assert_eq!(limits.trending_sample(hour, Some(5000)), 1000);
+
+        let mut config = sample_config();
+        config.limits.trending_sample_max = 10;
+        assert_invalid(config, "limits.trending_sample_max");
+    }
+
+    #[test]
+    fn configured_limit_overrides_builtin_cap() {
+        let config = sample_config();
+        assert_eq!(config.limits.search_events.resolve(None), 20);
+        assert_eq!(config.limits.search_events.resolve(Some(500)), 100);
+        assert_eq!(config.limits.search_events.resolve(Some(0)), 1);
+
+        let config: Config = toml::from_str(&format!(
+            "{TEST_CONFIG}\n[limits.search_events]\ndefault = 10\nmax = 30\n"
+        ))
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.limits.search_events.resolve(None), 10);
+        assert_eq!(config.limits.search_events.resolve(Some(100)), 30);
+        // Untouched tools keep their built-in numbers
+        assert_eq!(config.limits.trending_notes.resolve(Some(500)), 50);
+    }
+
+    #[test]
+    fn rejects_default_above_max_limit() {
+        let mut config = sample_config();
+        config.limits.search_profiles = ToolLimit::new(25, 20);
+        assert_invalid(config, "limits.search_profiles.default");
+
+        let mut config = sample_config();
+        config.limits.trending_notes.max = 0;
+        assert_invalid(config, "limits.trending_notes.max");
+
+        let mut config = sample_config();
+        config.limits.follower_graph_fanout = 0;
+        assert_invalid(config, "limits.follower_graph_fanout");
+    }
+
+    #[test]
+    fn rejects_zero_invoice_expiry() {
+        let mut config = sample_config();
+        config.payment.invoice_expiry_seconds = 0;
+        assert_invalid(config, "invoice_expiry_seconds");
+    }
+
+    #[test]
+    fn rejects_short_l402_secret_when_enabled() {
+        let mut config = sample_config();
+        config.payment.enable_l402 = true;
+        config.payment.l402_secret = hex::encode([0xab_u8; 16]);
+        assert_invalid(config, "at least 32 bytes");
+
+        let mut config = sample_config();
+        config.payment.enable_l402 = true;
+        config.payment.l402_secret = "zz".into();
+        assert_invalid(config, "hex-encoded");
+
+        let mut config = sample_config();
+        config.payment.enable_l402 = true;
+        config.payment.l402_secret = hex::encode([0xab_u8; 32]);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn x402_requires_address_when_enabled() {
+        let mut config = sample_config();
+        config.payment.enable_x402 = true;
+        assert_invalid(config.clone(), "payment.x402_address");
+
+        config.payment.x402_address = "0xabc".into();
+        config.validate().unwrap();
+        config.payment.x402_sats_per_cent = 0;
+        assert_invalid(config, "x402_sats_per_cent");
+    }
+
+    #[test]
+    fn price_feed_is_opt_in() {
+        assert!(Config::test_default().payment.price_api_url.is_empty());
+
+        let mut config = sample_config();
+        config.payment.price_api_url = "https://mempool.space/api/v1/prices".into();
+        config.validate().unwrap();
+        config.payment.price_api_url = "ftp://prices.example".into();
+        assert_invalid(config, "price_api_url");
+    }
+
+    #[test]
+    fn nwc_url_accepts_list_or_comma_separated_string() {
+        let listed = TEST_CONFIG.replace(
+            "nwc_url = \"\"",
+            "nwc_url = [\"nostr+walletconnect://a\", \"nostr+walletconnect://b\"]",
+        );
+        let config: Config = toml::from_str(&listed).unwrap();
+        assert_eq!(config.payment.nwc_urls.len(), 2);
+
+        let joined = TEST_CONFIG.replace(
+            "nwc_url = \"\"",
+            "nwc_url = \"nostr+walletconnect://a, nostr+walletconnect://b\"",
+        );
+        let config: Config = toml::from_str(&joined).unwrap();
+        assert_eq!(
+            config.payment.nwc_urls,
+            ["nostr+walletconnect://a", "nostr+walletconnect://b"]
+        );
+
+        assert!(Config::test_default().payment.nwc_urls.is_empty());
+    }
+
+    #[test]
+    fn socks_proxy_must_be_socks5_with_port() {
+        let mut config = sample_config();
+        config.network.socks_proxy = "http://127.0.0.1:8080".into();
+        assert_invalid(config.clone(), "socks5://");
+        config.network.socks_proxy = "socks5h://127.0.0.1".into();
+        assert_invalid(config.clone(), "host and port");
+
+        config.network.socks_proxy = "socks5h://127.0.0.1:9050".into();
+        config.validate().unwrap();
+        assert_eq!(
+            config.network.socks_proxy_addr().unwrap(),
+            Some("127.0.0.1:9050".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn pricing_and_free_tier_env_overrides() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let path = write_temp_config("pricing-override", TEST_CONFIG);
+        std::env::set_var("PRICE_TRENDING_NOTES", "42");
+        std::env::set_var("FREE_TIER_CALLS_PER_DAY", "25");
+        std::env::set_var("PRICE_ZAP_ANALYTICS", "not-a-number");
+
+        let config = Config::load_from(&path);
+        std::env::remove_var("PRICE_TRENDING_NOTES");
+        std::env::remove_var("FREE_TIER_CALLS_PER_DAY");
+        std::env::remove_var("PRICE_ZAP_ANALYTICS");
+        std::fs::remove_file(&path).ok();
+
+        let config = config.unwrap();
+        assert_eq!(config.pricing.trending_notes, 42);
+        assert_eq!(config.free_tier.calls_per_day, 25);
+        // Unparseable override keeps the TOML value
+        assert_eq!(config.pricing.zap_analytics, 5);
+        assert_eq!(config.pricing.search_events_base, 1);
+    }
+
+    #[test]
+    fn search_events_price_tiers() {
+        let pricing = Config::test_default().pricing;
+        assert_eq!(pricing.search_events_price(None), 1);
+        assert_eq!(pricing.search_events_price(Some(20)), 1);
+        assert_eq!(pricing.search_events_price(Some(21)), 16);
+        assert_eq!(pricing.search_events_price(Some(50)), 16);
+        assert_eq!(pricing.search_events_price(Some(51)), 41);
+    }
+
+    #[test]
+    fn follower_graph_price_by_depth() {
+        let pricing = Config::test_default().pricing;
+        assert_eq!(pricing.price_for("get_follower_graph", None, None), Some(4));
+        assert_eq!(
+            pricing.price_for("get_follower_graph", None, Some(1)),
+            Some(4)
+        );
+        assert_eq!(
+            pricing.price_for("get_follower_graph", None, Some(2)),
+            Some(8)
+        );
+        // Depth is clamped like the tool itself does
+        assert_eq!(
+            pricing.price_for("get_follower_graph", None, Some(9)),
+            Some(8)
+        );
+        assert_eq!(pricing.price_for("decode_nostr_uri", None, None), None);
+    }
+
+    #[test]
+    fn missing_file_names_path() {
+        let err = Config::load_from(Path::new("/nonexistent/nostr-intel.toml")).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/nostr-intel.toml"));
+    }
 }
@@ -0,0 +1,214 @@
+//! Health probes. `/health/live` answers 200 whenever the process can serve
+//! requests; `/health/ready` (and its alias `/health`) answers 200 when relays
+//! and the NWC wallet are reachable, 503 with a per-subsystem breakdown otherwise.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::server::SharedState;
+
+/// Upper bound on the NWC round-trip so a hung wallet can't stall probes
+const NWC_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long an NWC result is reused; orchestrators probe far more often than
+/// the wallet needs asking
+const NWC_CHECK_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    healthy: bool,
+    relays: RelayCheck,
+    /// Omitted when no wallet is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nwc: Option<NwcCheck>,
+}
+
+#[derive(Debug, Serialize)]
+struct RelayCheck {
+    healthy: bool,
+    connected: usize,
+    configured: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct NwcCheck {
+    healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Last NWC result and when it was taken.
+type NwcCheckCache = Mutex<Option<(Instant, NwcCheck)>>;
+
+pub fn router(shared: Arc<SharedState>) -> axum::Router {
+    routes(shared, NWC_CHECK_TIMEOUT)
+}
+
+fn routes(shared: Arc<SharedState>, nwc_timeout: Duration) -> axum::Router {
+    let nwc_cache: Arc<NwcCheckCache> = Arc::new(Mutex::new(None));
+    let ready = axum::routing::get(move || {
+        let shared = Arc::clone(&shared);
+        let nwc_cache = Arc::clone(&nwc_cache);
+        async move {
+            let report = check(&shared, &nwc_cache, nwc_timeout).await;
+            let status = if report.healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            (status, axum::Json(report)).into_response()
+        }
+    });
+    axum::Router::new()
+        .route(
+            "/health/live",
+            axum::routing::get(|| async { axum::Json(serde_json::json!({ "alive": true })) }),
+        )
+        .route("/health/ready", ready.clone())
+        .route("/health", ready)
+}
+
+async fn check(
+    shared: &SharedState,
+    nwc_cache: &NwcCheckCache,
+    nwc_timeout: Duration,
+) -> HealthReport {
+    let connected = shared.nostr_client.connected_relay_count().await;
+    let relays = RelayCheck {
+        healthy: connected > 0,
+        connected,
+        configured: shared.nostr_client.relay_urls().len(),
+    };
+
+    let nwc = match &shared.nwc_gateway {
+        Some(gw) => {
+            let mut cached = nwc_cache.lock().await;
+            match cached.as_ref() {
+                Some((at, check)) if at.elapsed() < NWC_CHECK_TTL => Some(check.clone()),
+                _ => {
+                    let error = match tokio::time::timeout(nwc_timeout, gw.get_balance()).await {
+                        Ok(Ok(_)) => None,
+                        Ok(Err(e)) => Some(e.to_string()),
+                        Err(_) => Some(format!("NWC wallet did not answer within {nwc_timeout:?}")),
+                    };
+                    let check = NwcCheck {
+                        healthy: error.is_none(),
+                        error,
+                    };
+                    *cached = Some((Instant::now(), check.clone()));
+                    Some(check)
+                }
+            }
+        }
+        None => None,
+    };
+
+    HealthReport {
+        healthy: relays.healthy && nwc.as_ref().is_none_or(|n| n.healthy),
+        relays,
+        nwc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr_sdk::prelude::*;
+
+    use super::*;
+    use crate::mock_relay::{MockRelay, RelayPolicy};
+    use crate::nostr::client::{NostrClient, PoolOptions};
+    use crate::payment::nwc_gateway::NwcPool;
+    use crate::server::NostrIntelServer;
+
+    #[tokio::test]
+    async fn unreachable_wallet_reports_503() {
+        // A relay port nobody listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_relay = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let wallet = Keys::generate();
+        let uri = format!(
+            "nostr+walletconnect://{}?relay={dead_relay}&secret={}",
+            wallet.public_key().to_hex(),
+            Keys::generate().secret_key().to_secret_hex()
+        );
+
+        let server = NostrIntelServer::new_for_test().await;
+        let mut shared = server.shared_state();
+        shared.nwc_gateway = Some(Arc::new(
+            NwcPool::new(&[uri], Arc::clone(&shared.cache)).unwrap(),
+        ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = routes(Arc::new(shared), Duration::from_millis(300));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        for path in ["/health", "/health/ready"] {
+            let resp = reqwest::get(format!("http://{addr}{path}")).await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = resp.json().await.unwrap();
+            assert_eq!(body["healthy"], false);
+            assert_eq!(body["nwc"]["healthy"], false);
+            assert!(body["nwc"]["error"].as_str().is_some());
+            // The test server has no relays configured
+            assert_eq!(body["relays"]["healthy"], false);
+            assert_eq!(body["relays"]["configured"], 0);
+        }
+
+        // Still alive, just not ready
+        let resp = reqwest::get(format!("http://{addr}/health/live"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["alive"], true);
+    }
+
+    #[tokio::test]
+    async fn connected_relay_without_wallet_is_ready() {
+        // A relay that accepts connections and ignores everything sent to it
+        let relay = MockRelay::start(RelayPolicy::Silent).await;
+
+        let server = NostrIntelServer::new_for_test().await;
+        let mut shared = server.shared_state();
+        shared.nostr_client = Arc::new(
+            NostrClient::new(
+                vec![relay.url],
+                Arc::clone(&shared.relay_health),
+                PoolOptions::default(),
+            )
+            .await
+            .unwrap(),
+        );
+        shared
+            .nostr_client
+            .wait_for_connection(Duration::from_secs(5))
+            .await;
+        assert!(shared.nwc_gateway.is_none());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = routes(Arc::new(shared), Duration::from_millis(300));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        for path in ["/health", "/health/ready", "/health/live"] {
+            let resp = reqwest::get(format!("http://{addr}{path}")).await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::OK, "{path}");
+        }
+        let body: serde_json::Value = reqwest::get(format!("http://{addr}/health/ready"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["healthy"], true);
+        assert_eq!(body["relays"]["connected"], 1);
+        // No wallet configured, so payments don't gate readiness
+        assert!(body.get("nwc").is_none());
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-IP token bucket for the HTTP transport. Unlike the free tier, which
+//! counts per session or API key, this caps raw request volume from one address.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Tracked addresses before idle, fully refilled buckets are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct IpRateLimiter {
+    capacity: f64,
+    /// Tokens regained per second
+    refill_rate: f64,
+    /// Key on the first `X-Forwarded-For` address instead of the peer address.
+    /// Only safe behind a proxy that sets the header.
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl IpRateLimiter {
+    /// Allow bursts of `requests_per_minute`, refilling at the same rate.
+    pub fn new(requests_per_minute: u32, trust_forwarded_for: bool) -> Self {
+        let capacity = f64::from(requests_per_minute.max(1));
+        Self {
+            capacity,
+            refill_rate: capacity / 60.0,
+            trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `ip`, or return how long until one is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let full_after = Duration::from_secs_f64(self.capacity / self.refill_rate);
+            buckets.retain(|_, b| now.duration_since(b.updated) < full_after);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_rate,
+            ))
+        }
+    }
+
+    fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        if self.trust_forwarded_for {
+            let forwarded = headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+}
+
+/// Axum middleware rejecting requests over the limit with 429 and `Retry-After`.
+/// Needs the app served with `into_make_service_with_connect_info::<SocketAddr>()`.
+pub async fn limit_by_ip(
+    State(limiter): State<Arc<IpRateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = limiter.client_ip(peer, request.headers());
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::debug!("Rate limited {ip} (retry after {retry_after}s)");
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [("Retry-After", retry_after.to_string())],
+                "Too many requests from this address",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn limited_app(limiter: IpRateLimiter) -> String {
+        let app = axum::Router::new()
+            .route("/mcp", axum::routing::post(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(limiter),
+                limit_by_ip,
+            ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+        format!("http://{addr}/mcp")
+    }
+
+    #[tokio::test]
+    async fn requests_over_limit_get_429_with_retry_after() {
+        let url = limited_app(IpRateLimiter::new(3, false)).await;
+        let http = reqwest::Client::new();
+
+        for _ in 0..3 {
+            let resp = http.post(&url).send().await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        }
+        let resp = http.post(&url).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        // One token per 20s at 3/minute
+        assert!((1..=20).contains(&retry_after), "{retry_after}");
+
+        // Without trusting the header, a spoofed X-Forwarded-For doesn't help
+        let resp = http
+            .post(&url)
+            .header("X-Forwarded-For", "203.0.113.9")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn forwarded_addresses_get_separate_buckets_when_trusted() {
+        let url = limited_app(IpRateLimiter::new(1, true)).await;
+        let http = reqwest::Client::new();
+        let post = |ip: &'static str| {
+            http.post(&url)
+                .header("X-Forwarded-For", format!("{ip}, 10.0.0.1"))
+                .send()
+        };
+
+        assert_eq!(post("203.0.113.1").await.unwrap().status(), 200);
+        assert_eq!(post("203.0.113.1").await.unwrap().status(), 429);
+        assert_eq!(post("203.0.113.2").await.unwrap().status(), 200);
+    }
+}
@@ -1,9 +1,16 @@
+mod call_log;
 mod config;
 #[allow(dead_code)]
 mod error;
+mod health;
+mod ip_limit;
+mod metrics;
+#[cfg(test)]
+mod mock_relay;
 mod nostr;
 mod payment;
 mod server;
+mod single_flight;
 mod tools;
 
 use std::sync::Arc;
@@ -58,19 +65,50 @@ fn spawn_background_tasks(shared: &Arc<server::SharedState>) {
         }
     });
 
-    // Periodic relay reconnect (every 5 minutes)
+    // Periodic relay health check: reconnect when relays have dropped, and re-read
+    // NIP-11 requirements so auth-gated relays are skipped (first pass runs at startup)
     let client = Arc::clone(&shared.nostr_client);
+    let state = Arc::clone(shared);
+    let health_interval = shared.config.relays.health_check_interval_seconds;
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(5 * 60));
-        interval.tick().await; // skip first immediate tick
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(health_interval));
         loop {
             interval.tick().await;
+            state.refresh_relay_requirements().await;
+            let dropped = client.dropped_relays().await;
+            if dropped.is_empty() {
+                tracing::debug!("Relay health check: all relays connected");
+                continue;
+            }
+            tracing::info!(
+                "Relay health check: {} relay(s) dropped ({}), reconnecting",
+                dropped.len(),
+                dropped.join(", ")
+            );
             client.reconnect().await;
-            tracing::debug!("Relay pool reconnect completed");
         }
     });
 
-    tracing::info!("Background tasks started (cache cleanup: 30m, relay reconnect: 5m)");
+    // Periodic BTC/USD refresh for fiat estimates on invoices (first fetch at startup)
+    if shared.fiat.enabled() {
+        let fiat = Arc::clone(&shared.fiat);
+        let http = shared.http.clone();
+        let refresh = shared.config.payment.price_refresh_seconds;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(refresh));
+            loop {
+                interval.tick().await;
+                match fiat.refresh(&http).await {
+                    Ok(rate) => tracing::debug!("BTC/USD rate refreshed: {rate}"),
+                    Err(e) => tracing::warn!("BTC/USD rate refresh failed: {e}"),
+                }
+            }
+        });
+    }
+
+    tracing::info!(
+        "Background tasks started (cache cleanup: 30m, relay health check: {health_interval}s)"
+    );
 }
 
 async fn run_stdio(config: config::Config) -> anyhow::Result<()> {
@@ -98,6 +136,7 @@ async fn run_http(config: config::Config) -> anyhow::Result<()> {
     const LANDING_HTML: &str = include_str!("../static/index.html");
 
     let http_port = config.server.http_port;
+    let cors = cors_layer(&config.server.cors_allowed_origins)?;
     let l402_enabled = config.payment.enable_l402;
     let l402_secret = config.payment.l402_secret.clone();
 
@@ -119,9 +158,8 @@ async fn run_http(config: config::Config) -> anyhow::Result<()> {
 
     // Build axum router
     let mut app = axum::Router::new()
-        .route("/", get(|| async { Html(LANDING_HTML) }))
-        .route("/health", get(|| async { "ok" }))
-        .nest_service("/mcp", mcp_service);
+        .nest_service("/mcp", mcp_service)
+        .layer(compression_layer());
 
     // Add L402 challenge endpoint if enabled
     if l402_enabled && !l402_secret.is_empty() {
@@ -144,14 +182,131 @@ async fn run_http(config: config::Config) -> anyhow::Result<()> {
         tracing::info!("L402 challenge endpoint enabled at /l402/challenge/{{tool_name}}");
     }
 
+    // Per-IP limit covers the routes above (tool calls and invoice creation),
+    // not the landing page or probes added below
+    if let Some(limiter) = &shared.ip_limiter {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::clone(limiter),
+            ip_limit::limit_by_ip,
+        ));
+        tracing::info!(
+            "Per-IP rate limit: {} requests/minute",
+            shared.config.server.ip_requests_per_minute
+        );
+    }
+    app = app
+        .route("/", get(|| async { Html(LANDING_HTML) }))
+        .merge(health::router(Arc::clone(&shared)))
+        .merge(metrics::router(Arc::clone(&shared)));
+
+    if let Some(cors) = cors {
+        app = app.layer(cors);
+    }
+
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{http_port}")).await?;
     tracing::info!("Serving MCP over HTTP on 0.0.0.0:{http_port}");
 
-    axum::serve(listener, app).await?;
+    serve_with_shutdown(listener, app, shutdown_signal()).await?;
+
+    tracing::info!("HTTP server stopped accepting connections, shutting down");
+    shared.shutdown().await;
+    tracing::info!("Shutdown complete");
 
     Ok(())
 }
 
+/// CORS for browser clients, limited to `origins` ("*" = any). `None` when no
+/// origins are configured, so browsers keep blocking cross-origin calls.
+fn cors_layer(origins: &[String]) -> anyhow::Result<Option<tower_http::cors::CorsLayer>> {
+    use axum::http::{header, HeaderName, HeaderValue, Method};
+    use tower_http::cors::{AllowOrigin, CorsLayer};
+
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|o| HeaderValue::from_str(o))
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let session_id = HeaderName::from_static("mcp-session-id");
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
+            .allow_headers([
+                header::CONTENT_TYPE,
+                header::ACCEPT,
+                header::AUTHORIZATION,
+                HeaderName::from_static("x-api-key"),
+                HeaderName::from_static("x-admin-key"),
+                HeaderName::from_static("mcp-protocol-version"),
+                HeaderName::from_static("last-event-id"),
+                session_id.clone(),
+            ])
+            .expose_headers([session_id, header::WWW_AUTHENTICATE]),
+    ))
+}
+
+/// gzip/deflate for JSON responses, negotiated via `Accept-Encoding`. The default
+/// predicate skips `text/event-stream`, so MCP's SSE streams are sent unbuffered.
+fn compression_layer() -> tower_http::compression::CompressionLayer {
+    tower_http::compression::CompressionLayer::new()
+        .gzip(true)
+        .deflate(true)
+}
+
+/// Serve `app` until `signal` resolves, then let in-flight requests finish.
+async fn serve_with_shutdown(
+    listener: tokio::net::TcpListener,
+    app: axum::Router,
+    signal: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    // Peer addresses are needed by the per-IP limiter
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown(signal)
+    .await
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl-C, starting graceful shutdown"),
+        _ = terminate => tracing::info!("Received SIGTERM, starting graceful shutdown"),
+    }
+}
+
 async fn l402_challenge_handler(
     tool_name: String,
     l402_mgr: Arc<payment::l402::L402Manager>,
@@ -172,15 +327,8 @@ async fn l402_challenge_handler(
     };
 
     // Look up price for the tool
-    let amount = match tool_name.as_str() {
-        "search_events" => shared.config.pricing.search_events_base,
-        "relay_discovery" => shared.config.pricing.relay_discovery,
-        "trending_notes" => shared.config.pricing.trending_notes,
-        "get_follower_graph" => shared.config.pricing.get_follower_graph,
-        "zap_analytics" => shared.config.pricing.zap_analytics,
-        _ => {
-            return (StatusCode::NOT_FOUND, "Unknown tool").into_response();
-        }
+    let Some(amount) = shared.config.pricing.price_for(&tool_name, None, None) else {
+        return (StatusCode::NOT_FOUND, "Unknown tool").into_response();
     };
 
     let description = format!("nostr-intel: {tool_name}");
@@ -193,7 +341,10 @@ async fn l402_challenge_handler(
         )
         .await
     {
-        Ok(inv) => inv,
+        Ok(inv) => {
+            metrics::Metrics::inc(&shared.metrics.invoices_created);
+            inv
+        }
         Err(e) => {
             tracing::error!("Failed to create invoice for L402 challenge: {e}");
             return (StatusCode::INTERNAL_SERVER_ERROR, "Invoice creation failed").into_response();
@@ -219,3 +370,110 @@ async fn l402_challenge_handler(
     )
         .into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cors_allows_only_configured_origins() {
+        let cors = cors_layer(&["https://dash.example.com".into()])
+            .unwrap()
+            .unwrap();
+        let app = axum::Router::new()
+            .route("/health", axum::routing::get(|| async { "ok" }))
+            .layer(cors);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let get = |origin: &'static str| {
+            http.get(format!("http://{addr}/health"))
+                .header("Origin", origin)
+                .send()
+        };
+
+        let allowed = get("https://dash.example.com").await.unwrap();
+        assert_eq!(
+            allowed.headers()["access-control-allow-origin"],
+            "https://dash.example.com"
+        );
+        let denied = get("https://evil.example.com").await.unwrap();
+        assert!(denied
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none());
+
+        assert!(cors_layer(&[]).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn large_json_is_compressed_but_event_streams_are_not() {
+        let body = serde_json::json!({ "pubkeys": vec!["ab".repeat(32); 200] }).to_string();
+        let app = axum::Router::new()
+            .route(
+                "/mcp",
+                axum::routing::post(move || {
+                    let body = body.clone();
+                    async move { ([("Content-Type", "application/json")], body) }
+                }),
+            )
+            .route(
+                "/mcp/sse",
+                axum::routing::get(|| async {
+                    (
+                        [("Content-Type", "text/event-stream")],
+                        "data: x\n\n".repeat(200),
+                    )
+                }),
+            )
+            .layer(compression_layer());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let json = http
+            .post(format!("http://{addr}/mcp"))
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(json.headers()["content-encoding"], "gzip");
+        let compressed = json.bytes().await.unwrap();
+        assert!(compressed.len() < 13_000, "{} bytes", compressed.len());
+
+        let plain = http
+            .post(format!("http://{addr}/mcp"))
+            .send()
+            .await
+            .unwrap();
+        assert!(plain.headers().get("content-encoding").is_none());
+
+        let sse = http
+            .get(format!("http://{addr}/mcp/sse"))
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await
+            .unwrap();
+        assert!(sse.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn serve_resolves_after_shutdown_signal() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let app = axum::Router::new().route("/health", axum::routing::get(|| async { "ok" }));
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let serve = tokio::spawn(serve_with_shutdown(listener, app, async {
+            rx.await.ok();
+        }));
+
+        tx.send(()).unwrap();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), serve)
+            .await
+            .expect("serve future did not resolve after shutdown signal");
+        result.unwrap().unwrap();
+    }
+}
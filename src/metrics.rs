@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::nostr::health::RelayHealth;
+use crate::server::SharedState;
+
+/// Process-wide counters exposed in Prometheus text format at `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    tool_calls: RwLock<BTreeMap<String, AtomicU64>>,
+    pub gate_free: AtomicU64,
+    pub gate_paid: AtomicU64,
+    pub gate_exhausted: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub cache_misses: AtomicU64,
+    pub invoices_created: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tool_call(&self, tool_name: &str) {
+        {
+            let calls = self.tool_calls.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(counter) = calls.get(tool_name) {
+                Self::inc(counter);
+                return;
+            }
+        }
+        let mut calls = self.tool_calls.write().unwrap_or_else(|e| e.into_inner());
+        Self::inc(calls.entry(tool_name.to_string()).or_default());
+    }
+
+    /// Render all counters in Prometheus text exposition format.
+    /// Relay fetch errors are taken from the relay health tracker snapshot.
+    pub fn render(&self, relay_health: &[(String, RelayHealth)]) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP nostr_intel_tool_calls_total Tool invocations by tool name.\n");
+        out.push_str("# TYPE nostr_intel_tool_calls_total counter\n");
+        {
+            let calls = self.tool_calls.read().unwrap_or_else(|e| e.into_inner());
+            for (tool, count) in calls.iter() {
+                let _ = writeln!(
+                    out,
+                    "nostr_intel_tool_calls_total{{tool=\"{tool}\"}} {}",
+                    count.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        out.push_str("# HELP nostr_intel_payment_gate_total Payment gate outcomes.\n");
+        out.push_str("# TYPE nostr_intel_payment_gate_total counter\n");
+        for (outcome, counter) in [
+            ("free", &self.gate_free),
+            ("paid", &self.gate_paid),
+            ("exhausted", &self.gate_exhausted),
+        ] {
+            let _ = writeln!(
+                out,
+                "nostr_intel_payment_gate_total{{outcome=\"{outcome}\"}} {}",
+                counter.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP nostr_intel_cache_requests_total Cache lookups by result.\n");
+        out.push_str("# TYPE nostr_intel_cache_requests_total counter\n");
+        for (result, counter) in [("hit", &self.cache_hits), ("miss", &self.cache_misses)] {
+            let _ = writeln!(
+                out,
+                "nostr_intel_cache_requests_total{{result=\"{result}\"}} {}",
+                counter.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP nostr_intel_relay_fetch_errors_total Failed relay fetches.\n");
+        out.push_str("# TYPE nostr_intel_relay_fetch_errors_total counter\n");
+        for (relay, health) in relay_health {
+            let _ = writeln!(
+                out,
+                "nostr_intel_relay_fetch_errors_total{{relay=\"{relay}\"}} {}",
+                health.failures
+            );
+        }
+
+        out.push_str("# HELP nostr_intel_invoices_created_total Lightning invoices created.\n");
+        out.push_str("# TYPE nostr_intel_invoices_created_total counter\n");
+        let _ = writeln!(
+            out,
+            "nostr_intel_invoices_created_total {}",
+            self.invoices_created.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+/// `/metrics` route serving Prometheus text-format counters.
+pub fn router(shared: Arc<SharedState>) -> axum::Router {
+    axum::Router::new().route(
+        "/metrics",
+        axum::routing::get(move || {
+            let shared = Arc::clone(&shared);
+            async move {
+                let relay_health = shared.relay_health.snapshot().await;
+                (
+                    [("Content-Type", "text/plain; version=0.0.4")],
+                    shared.metrics.render(&relay_health),
+                )
+            }
+        }),
+    )
+}
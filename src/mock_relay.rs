@@ -0,0 +1,229 @@
+//! In-process websocket relays for tests.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use nostr_sdk::prelude::*;
+use tokio_tungstenite::tungstenite::Message;
+
+/// How a [`MockRelay`] answers each REQ.
+#[derive(Clone)]
+pub enum RelayPolicy {
+    /// The stored events matching the REQ's filters, then EOSE
+    Events(Vec<Event>),
+    /// The newest `max` stored events matching the REQ's filters, then EOSE,
+    /// like a relay that caps `limit`
+    Capped { events: Vec<Event>, max: usize },
+    /// The stored events matching the REQ's filters, never followed by EOSE
+    EventsWithoutEose(Vec<Event>),
+    /// A CLOSED with this message
+    Closed(String),
+    /// Nothing: connections are accepted and every message is ignored
+    Silent,
+}
+
+/// A relay listening on a local port. It keeps serving until the test's
+/// runtime shuts down.
+pub struct MockRelay {
+    pub url: String,
+    reqs: Arc<AtomicUsize>,
+}
+
+impl MockRelay {
+    pub async fn start(policy: RelayPolicy) -> Self {
+        let reqs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reqs);
+        let url = serve(move || {
+            let (policy, counter) = (policy.clone(), Arc::clone(&counter));
+            move |msg: serde_json::Value| {
+                if msg[0] != "REQ" {
+                    return Vec::new();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                policy.reply(&msg)
+            }
+        })
+        .await;
+        Self { url, reqs }
+    }
+
+    /// Number of REQs received so far, across all connections
+    pub fn reqs(&self) -> usize {
+        self.reqs.load(Ordering::SeqCst)
+    }
+}
+
+impl RelayPolicy {
+    fn reply(&self, req: &serde_json::Value) -> Vec<serde_json::Value> {
+        let sub_id = &req[1];
+        let (events, eose, max) = match self {
+            Self::Events(events) => (events, true, usize::MAX),
+            Self::Capped { events, max } => (events, true, *max),
+            Self::EventsWithoutEose(events) => (events, false, usize::MAX),
+            Self::Closed(message) => {
+                return vec![serde_json::json!(["CLOSED", sub_id, message])];
+            }
+            Self::Silent => return Vec::new(),
+        };
+        let filters: Vec<Filter> = req.as_array().unwrap()[2..]
+            .iter()
+            .map(|f| Filter::from_json(f.to_string()).unwrap())
+            .collect();
+        let mut matching: Vec<&Event> = events
+            .iter()
+            .filter(|e| {
+                filters
+                    .iter()
+                    .any(|f| f.match_event(e, MatchEventOptions::new()))
+            })
+            .collect();
+        if matching.len() > max {
+            matching.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+            matching.truncate(max);
+        }
+        let mut out: Vec<serde_json::Value> = matching
+            .into_iter()
+            .map(|event| serde_json::json!(["EVENT", sub_id, event]))
+            .collect();
+        if eose {
+            out.push(serde_json::json!(["EOSE", sub_id]));
+        }
+        out
+    }
+}
+
+/// Serve a relay holding `events`: each REQ gets the stored events matching
+/// its filters, then EOSE.
+pub async fn mock_relay_with_events(events: Vec<Event>) -> String {
+    MockRelay::start(RelayPolicy::Events(events)).await.url
+}
+
+/// Serve a relay whose replies come from a handler. `new_handler` runs once per
+/// connection, so the handler it returns can keep per-connection state; it is
+/// given every JSON message the client sends and returns the messages to send
+/// back. Returns the relay's `ws://` URL.
+pub async fn serve<F, H>(new_handler: F) -> String
+where
+    F: Fn() -> H + Send + 'static,
+    H: FnMut(serde_json::Value) -> Vec<serde_json::Value> + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let mut handle = new_handler();
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(frame)) = ws.next().await {
+                    let Message::Text(text) = frame else { continue };
+                    let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    for out in handle(msg) {
+                        ws.send(Message::text(out.to_string())).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+    format!("ws://{addr}")
+}
+
+/// How a [`mock_nwc`] wallet answers.
+/// How a [`mock_nwc`] wallet answers.
+#[derive(Clone, Copy, Default)]
+pub struct MockWallet {
+    pub balance_msats: u64,
+    /// Lookups report invoices settled
+    pub settled: bool,
+    /// Every request gets an INTERNAL error response
+    pub failing: bool,
+}
+
+/// Serve a one-wallet NWC relay: decrypts each kind:23194 request with the
+/// wallet key and answers get_balance/get_info. Invoices it makes are already
+/// past their expiry. Returns the connection URI and a count of
+/// lookup_invoice requests served.
+pub async fn mock_nwc(mock: MockWallet) -> (String, Arc<AtomicUsize>) {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let lookups_served = Arc::clone(&lookups);
+    let wallet = Keys::generate();
+    let client_secret = Keys::generate().secret_key().to_secret_hex();
+    let wallet_pubkey = wallet.public_key().to_hex();
+
+    let relay = serve(move || {
+        let wallet = wallet.clone();
+        let lookups = Arc::clone(&lookups_served);
+        let mut sub_id = serde_json::Value::Null;
+        move |msg: serde_json::Value| match msg[0].as_str() {
+            Some("REQ") => {
+                sub_id = msg[1].clone();
+                Vec::new()
+            }
+            Some("EVENT") => {
+                let request = Event::from_json(msg[1].to_string()).unwrap();
+                let ok = serde_json::json!(["OK", request.id, true, ""]);
+                let body =
+                    nip04::decrypt(wallet.secret_key(), &request.pubkey, &request.content).unwrap();
+                let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+                let result = match body["method"].as_str() {
+                    Some("get_balance") => {
+                        serde_json::json!({ "balance": mock.balance_msats })
+                    }
+                    Some("make_invoice") => {
+                        let now = Timestamp::now().as_secs();
+                        serde_json::json!({
+                            "invoice": "lnbcrt1mock",
+                            "payment_hash": "ab".repeat(32),
+                            "created_at": now - 600,
+                            "expires_at": now - 1,
+                        })
+                    }
+                    Some("lookup_invoice") => {
+                        lookups.fetch_add(1, Ordering::SeqCst);
+                        let now = Timestamp::now().as_secs();
+                        let mut result = serde_json::json!({
+                            "type": "incoming",
+                            "state": if mock.settled { "settled" } else { "pending" },
+                            "payment_hash": "ab".repeat(32),
+                            "amount": 10_000,
+                            "fees_paid": 0,
+                            "created_at": now - 600,
+                        });
+                        if mock.settled {
+                            result["settled_at"] = serde_json::json!(now - 60);
+                        }
+                        result
+                    }
+                    _ => serde_json::json!({
+                        "alias": "mock-wallet",
+                        "network": "regtest",
+                        "methods": ["make_invoice", "lookup_invoice", "get_balance"],
+                    }),
+                };
+                let response = if mock.failing {
+                    serde_json::json!({
+                        "result_type": body["method"],
+                        "error": { "code": "INTERNAL", "message": "wallet offline" },
+                    })
+                } else {
+                    serde_json::json!({
+                        "result_type": body["method"],
+                        "result": result,
+                    })
+                };
+                let content =
+                    nip04::encrypt(wallet.secret_key(), &request.pubkey, response.to_string())
+                        .unwrap();
+                let reply = EventBuilder::new(Kind::WalletConnectResponse, content)
+                    .tags([Tag::event(request.id), Tag::public_key(request.pubkey)])
+                    .sign_with_keys(&wallet)
+                    .unwrap();
+                vec![ok, serde_json::json!(["EVENT", sub_id, reply])]
+            }
+            _ => Vec::new(),
+        }
+    })
+    .await;
+    let uri = format!("nostr+walletconnect://{wallet_pubkey}?relay={relay}&secret={client_secret}");
+    (uri, lookups)
+}
@@ -1,15 +1,55 @@
 use anyhow::Context;
+use lru::LruCache;
+use nostr_sdk::{Event, EventId, JsonUtil, Kind};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
 use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Profiles kept in memory in front of SQLite unless configured otherwise
+pub const DEFAULT_PROFILE_LRU_SIZE: usize = 1000;
+/// Contact and relay lists are reused this long unless configured otherwise
+pub const DEFAULT_LIST_TTL_SECONDS: u64 = 600;
+/// Events fetched by ID are reused this long unless configured otherwise
+pub const DEFAULT_EVENT_TTL_SECONDS: u64 = 3600;
+/// How long a connection waits on a locked database before failing
+pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+/// WAL pages written before SQLite checkpoints back into the main file
+const WAL_AUTOCHECKPOINT_PAGES: u32 = 1000;
 
 pub struct Cache {
     pool: SqlitePool,
     profile_ttl: i64,
     relay_ttl: i64,
+    /// TTL for cached replaceable events (contact and relay lists)
+    list_ttl: i64,
+    /// TTL for cached events by ID
+    event_ttl: i64,
+    /// Recently read or written profiles with their `expires_at`, checked
+    /// before SQLite. `None` when disabled.
+    profile_lru: Option<Mutex<LruCache<String, (CachedProfile, i64)>>>,
 }
 
-#[derive(Debug, Clone)]
+/// Rows removed by [`Cache::purge_pubkey`], per table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeCounts {
+    pub profiles: u64,
+    /// Contact and relay lists
+    pub replaceable_events: u64,
+    /// Events cached by ID
+    pub events: u64,
+}
+
+impl PurgeCounts {
+    pub fn total(&self) -> u64 {
+        self.profiles + self.replaceable_events + self.events
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct CachedProfile {
     pub pubkey: String,
     pub name: Option<String>,
@@ -32,6 +72,12 @@ pub struct CachedRelayInfo {
     pub version: Option<String>,
     pub online: bool,
     pub latency_ms: Option<i64>,
+    /// NIP-11 `limitation` object as JSON
+    pub limitation: Option<String>,
+    /// NIP-11 `fees` object as JSON
+    pub fees: Option<String>,
+    /// NIP-11 `icon` URL
+    pub icon: Option<String>,
 }
 
 impl Cache {
@@ -39,11 +85,16 @@ impl Cache {
         database_path: &str,
         profile_ttl_seconds: u64,
         relay_info_ttl_seconds: u64,
+        busy_timeout: Duration,
     ) -> anyhow::Result<Self> {
+        // Concurrent writers (e.g. the rate limiter) wait for the lock instead of
+        // failing with "database is locked"
         let options = SqliteConnectOptions::from_str(&format!("sqlite:{database_path}"))
             .context("Invalid database path")?
             .create_if_missing(true)
-            .journal_mode(SqliteJournalMode::Wal);
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(busy_timeout)
+            .pragma("wal_autocheckpoint", WAL_AUTOCHECKPOINT_PAGES.to_string());
 
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
@@ -55,67 +106,148 @@ impl Cache {
             pool,
             profile_ttl: profile_ttl_seconds as i64,
             relay_ttl: relay_info_ttl_seconds as i64,
-        };
+            list_ttl: DEFAULT_LIST_TTL_SECONDS as i64,
+            event_ttl: DEFAULT_EVENT_TTL_SECONDS as i64,
+            profile_lru: None,
+        }
+        .with_profile_lru(DEFAULT_PROFILE_LRU_SIZE);
 
         cache.init_schema().await?;
         Ok(cache)
     }
 
-    async fn init_schema(&self) -> anyhow::Result<()> {
-        sqlx::query(
-            "CREATE TABLE IF NOT EXISTS profiles (
-                pubkey TEXT PRIMARY KEY NOT NULL,
-                name TEXT,
-                display_name TEXT,
-                about TEXT,
-                picture TEXT,
-                banner TEXT,
-                nip05 TEXT,
-                lud16 TEXT,
-                website TEXT,
-                cached_at INTEGER NOT NULL,
-                expires_at INTEGER NOT NULL
-            )",
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Open a cache that lives only in memory, for ephemeral deployments without a disk.
+    pub async fn new_memory(
+        profile_ttl_seconds: u64,
+        relay_info_ttl_seconds: u64,
+    ) -> anyhow::Result<Self> {
+        // WAL needs a file; in-memory databases use an in-memory rollback journal
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .context("Invalid in-memory DSN")?
+            .journal_mode(SqliteJournalMode::Memory);
+
+        // Every connection to :memory: is a separate database, so keep exactly one
+        // connection and never let the pool recycle it
+        let pool = SqlitePoolOptions::new()
+            .min_connections(1)
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .context("Failed to open in-memory SQLite")?;
+
+        let cache = Self {
+            pool,
+            profile_ttl: profile_ttl_seconds as i64,
+            relay_ttl: relay_info_ttl_seconds as i64,
+            list_ttl: DEFAULT_LIST_TTL_SECONDS as i64,
+            event_ttl: DEFAULT_EVENT_TTL_SECONDS as i64,
+            profile_lru: None,
+        }
+        .with_profile_lru(DEFAULT_PROFILE_LRU_SIZE);
+
+        cache.init_schema().await?;
+        Ok(cache)
+    }
 
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_profiles_expires ON profiles(expires_at)")
+    /// Set how long cached contact and relay lists stay fresh.
+    pub fn with_list_ttl(mut self, seconds: u64) -> Self {
+        self.list_ttl = seconds as i64;
+        self
+    }
+
+    /// Set how long events cached by ID stay fresh.
+    pub fn with_event_ttl(mut self, seconds: u64) -> Self {
+        self.event_ttl = seconds as i64;
+        self
+    }
+
+    /// Resize the in-memory profile layer; `0` disables it.
+    pub fn with_profile_lru(mut self, capacity: usize) -> Self {
+        self.profile_lru = NonZeroUsize::new(capacity).map(|c| Mutex::new(LruCache::new(c)));
+        self
+    }
+
+    fn lru_get(&self, pubkey: &str, now: i64) -> Option<CachedProfile> {
+        let mut lru = self.profile_lru.as_ref()?.lock().unwrap();
+        match lru.get(pubkey) {
+            Some((profile, expires_at)) if *expires_at > now => Some(profile.clone()),
+            Some(_) => {
+                lru.pop(pubkey);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn lru_put(&self, profile: &CachedProfile, expires_at: i64) {
+        if let Some(lru) = &self.profile_lru {
+            lru.lock()
+                .unwrap()
+                .put(profile.pubkey.clone(), (profile.clone(), expires_at));
+        }
+    }
+
+    /// Bring the database up to the latest schema, applying every migration
+    /// newer than the recorded `schema_version`.
+    async fn init_schema(&self) -> anyhow::Result<()> {
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
             .execute(&self.pool)
             .await?;
+        let current = self.schema_version().await?;
 
-        sqlx::query(
-            "CREATE TABLE IF NOT EXISTS relay_info (
-                relay_url TEXT PRIMARY KEY NOT NULL,
-                name TEXT,
-                description TEXT,
-                supported_nips TEXT,
-                software TEXT,
-                version TEXT,
-                online BOOLEAN NOT NULL DEFAULT 1,
-                latency_ms INTEGER,
-                cached_at INTEGER NOT NULL,
-                expires_at INTEGER NOT NULL
-            )",
-        )
-        .execute(&self.pool)
-        .await?;
+        for (version, steps) in MIGRATIONS.iter().enumerate() {
+            let version = version as i64 + 1;
+            if version <= current {
+                continue;
+            }
+            for step in *steps {
+                match step {
+                    Step::Sql(sql) => {
+                        sqlx::query(sql).execute(&self.pool).await?;
+                    }
+                    Step::AddColumn(table, column, sql_type) => {
+                        self.ensure_column(table, column, sql_type).await?;
+                    }
+                }
+            }
+            sqlx::query("DELETE FROM schema_version")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
+                .bind(version)
+                .execute(&self.pool)
+                .await
+                .with_context(|| format!("Failed to record cache schema version {version}"))?;
+            tracing::debug!("Applied cache schema migration {version}");
+        }
 
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_relay_info_expires ON relay_info(expires_at)")
-            .execute(&self.pool)
+        Ok(())
+    }
+
+    /// Latest applied migration, or 0 for a fresh (or pre-versioning) database.
+    async fn schema_version(&self) -> anyhow::Result<i64> {
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&self.pool)
             .await?;
+        Ok(version.unwrap_or(0))
+    }
 
-        sqlx::query(
-            "CREATE TABLE IF NOT EXISTS rate_limits (
-                client_id TEXT NOT NULL,
-                day_ordinal INTEGER NOT NULL,
-                count INTEGER NOT NULL DEFAULT 0,
-                PRIMARY KEY (client_id, day_ordinal)
-            )",
-        )
+    /// Add `column` to `table` if an older database was created without it.
+    async fn ensure_column(&self, table: &str, column: &str, sql_type: &str) -> anyhow::Result<()> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({table})"))
+            .fetch_all(&self.pool)
+            .await?;
+        if columns.iter().any(|c| c.get::<String, _>("name") == column) {
+            return Ok(());
+        }
+
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {sql_type}"
+        ))
         .execute(&self.pool)
         .await?;
-
         Ok(())
     }
 
@@ -125,8 +257,12 @@ impl Cache {
 
     pub async fn get_profile(&self, pubkey: &str) -> anyhow::Result<Option<CachedProfile>> {
         let now = Self::now();
+        if let Some(profile) = self.lru_get(pubkey, now) {
+            return Ok(Some(profile));
+        }
+
         let row = sqlx::query(
-            "SELECT pubkey, name, display_name, about, picture, banner, nip05, lud16, website
+            "SELECT pubkey, name, display_name, about, picture, banner, nip05, lud16, website, expires_at
              FROM profiles WHERE pubkey = ? AND expires_at > ?",
         )
         .bind(pubkey)
@@ -134,19 +270,53 @@ impl Cache {
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.map(|r| CachedProfile {
-            pubkey: r.get("pubkey"),
-            name: r.get("name"),
-            display_name: r.get("display_name"),
-            about: r.get("about"),
-            picture: r.get("picture"),
-            banner: r.get("banner"),
-            nip05: r.get("nip05"),
-            lud16: r.get("lud16"),
-            website: r.get("website"),
+        Ok(row.map(|r| {
+            let profile = profile_from_row(&r);
+            self.lru_put(&profile, r.get("expires_at"));
+            profile
         }))
     }
 
+    /// Look up many profiles in one query. Pubkeys that are missing or expired are
+    /// simply absent from the returned map.
+    pub async fn get_profiles_batch(
+        &self,
+        pubkeys: &[String],
+    ) -> anyhow::Result<HashMap<String, CachedProfile>> {
+        let now = Self::now();
+        let mut found = HashMap::new();
+        let mut misses = Vec::new();
+        for pubkey in pubkeys {
+            match self.lru_get(pubkey, now) {
+                Some(profile) => {
+                    found.insert(pubkey.clone(), profile);
+                }
+                None => misses.push(pubkey),
+            }
+        }
+        if misses.is_empty() {
+            return Ok(found);
+        }
+
+        let placeholders = vec!["?"; misses.len()].join(", ");
+        let sql = format!(
+            "SELECT pubkey, name, display_name, about, picture, banner, nip05, lud16, website, expires_at
+             FROM profiles WHERE pubkey IN ({placeholders}) AND expires_at > ?"
+        );
+        let mut query = sqlx::query(&sql);
+        for pubkey in misses {
+            query = query.bind(pubkey);
+        }
+        let rows = query.bind(now).fetch_all(&self.pool).await?;
+
+        for r in rows {
+            let profile = profile_from_row(&r);
+            self.lru_put(&profile, r.get("expires_at"));
+            found.insert(profile.pubkey.clone(), profile);
+        }
+        Ok(found)
+    }
+
     pub async fn set_profile(&self, profile: &CachedProfile) -> anyhow::Result<()> {
         let now = Self::now();
         let expires_at = now + self.profile_ttl;
@@ -170,13 +340,139 @@ impl Cache {
         .execute(&self.pool)
         .await?;
 
+        self.lru_put(profile, expires_at);
+        Ok(())
+    }
+
+    /// Drop a cached profile. Returns whether a row was removed.
+    pub async fn delete_profile(&self, pubkey: &str) -> anyhow::Result<bool> {
+        if let Some(lru) = &self.profile_lru {
+            lru.lock().unwrap().pop(pubkey);
+        }
+        let result = sqlx::query("DELETE FROM profiles WHERE pubkey = ?")
+            .bind(pubkey)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove everything cached about a pubkey (hex), e.g. for a deletion
+    /// request. Tables keyed by pubkey must be added here as they appear.
+    pub async fn purge_pubkey(&self, pubkey: &str) -> anyhow::Result<PurgeCounts> {
+        let profiles = u64::from(self.delete_profile(pubkey).await?);
+        let replaceable_events = sqlx::query("DELETE FROM replaceable_events WHERE pubkey = ?")
+            .bind(pubkey)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        let events = sqlx::query("DELETE FROM events WHERE pubkey = ?")
+            .bind(pubkey)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(PurgeCounts {
+            profiles,
+            replaceable_events,
+            events,
+        })
+    }
+
+    /// Unexpired cached events among `ids`; missing IDs are simply absent.
+    pub async fn get_events(&self, ids: &[EventId]) -> anyhow::Result<Vec<Event>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let sql = format!(
+            "SELECT event_json FROM events WHERE id IN ({placeholders}) AND expires_at > ?"
+        );
+        let mut query = sqlx::query(&sql);
+        for id in ids {
+            query = query.bind(id.to_hex());
+        }
+        let rows = query.bind(Self::now()).fetch_all(&self.pool).await?;
+
+        rows.iter()
+            .map(|r| Event::from_json(r.get::<String, _>("event_json")))
+            .collect::<Result<_, _>>()
+            .context("Corrupt cached event")
+    }
+
+    /// Cache events by ID in one transaction.
+    pub async fn set_events(&self, events: &[Event]) -> anyhow::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let now = Self::now();
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            sqlx::query(
+                "INSERT OR REPLACE INTO events (id, pubkey, event_json, cached_at, expires_at)
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(event.id.to_hex())
+            .bind(event.pubkey.to_hex())
+            .bind(event.as_json())
+            .bind(now)
+            .bind(now + self.event_ttl)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Latest unexpired replaceable event (e.g. a contact list) for an author.
+    pub async fn get_replaceable_event(
+        &self,
+        pubkey: &str,
+        kind: Kind,
+    ) -> anyhow::Result<Option<Event>> {
+        let row = sqlx::query(
+            "SELECT event_json FROM replaceable_events
+             WHERE pubkey = ? AND kind = ? AND expires_at > ?",
+        )
+        .bind(pubkey)
+        .bind(kind.as_u16())
+        .bind(Self::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| Event::from_json(r.get::<String, _>("event_json")))
+            .transpose()
+            .context("Corrupt cached event")
+    }
+
+    /// Cache a replaceable event. An older event never replaces a newer cached
+    /// one, but still refreshes its expiry.
+    pub async fn set_replaceable_event(&self, event: &Event) -> anyhow::Result<()> {
+        let now = Self::now();
+        sqlx::query(
+            "INSERT INTO replaceable_events (pubkey, kind, event_json, created_at, cached_at, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(pubkey, kind) DO UPDATE SET
+                event_json = CASE WHEN excluded.created_at >= created_at
+                                  THEN excluded.event_json ELSE event_json END,
+                created_at = MAX(excluded.created_at, created_at),
+                cached_at = excluded.cached_at,
+                expires_at = excluded.expires_at",
+        )
+        .bind(event.pubkey.to_hex())
+        .bind(event.kind.as_u16())
+        .bind(event.as_json())
+        .bind(event.created_at.as_secs() as i64)
+        .bind(now)
+        .bind(now + self.list_ttl)
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
     pub async fn get_relay_info(&self, relay_url: &str) -> anyhow::Result<Option<CachedRelayInfo>> {
         let now = Self::now();
         let row = sqlx::query(
-            "SELECT relay_url, name, description, supported_nips, software, version, online, latency_ms
+            "SELECT relay_url, name, description, supported_nips, software, version, online, latency_ms,
+                    limitation, fees, icon
              FROM relay_info WHERE relay_url = ? AND expires_at > ?",
         )
         .bind(relay_url)
@@ -199,6 +495,9 @@ impl Cache {
                 version: r.get("version"),
                 online: r.get("online"),
                 latency_ms: r.get("latency_ms"),
+                limitation: r.get("limitation"),
+                fees: r.get("fees"),
+                icon: r.get("icon"),
             }
         }))
     }
@@ -210,8 +509,9 @@ impl Cache {
 
         sqlx::query(
             "INSERT OR REPLACE INTO relay_info
-             (relay_url, name, description, supported_nips, software, version, online, latency_ms, cached_at, expires_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+             (relay_url, name, description, supported_nips, software, version, online, latency_ms,
+              limitation, fees, icon, cached_at, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(&info.relay_url)
         .bind(&info.name)
@@ -221,6 +521,9 @@ impl Cache {
         .bind(&info.version)
         .bind(info.online)
         .bind(info.latency_ms)
+        .bind(&info.limitation)
+        .bind(&info.fees)
+        .bind(&info.icon)
         .bind(now)
         .bind(expires_at)
         .execute(&self.pool)
@@ -272,6 +575,109 @@ impl Cache {
         Ok(row.map(|r| r.get::<u32, _>("count")).unwrap_or(0))
     }
 
+    /// Delete a client's rate limit row for a given day, returning the count it held.
+    pub async fn reset_rate(&self, client_id: &str, day_ordinal: u32) -> anyhow::Result<u32> {
+        let row = sqlx::query(
+            "DELETE FROM rate_limits WHERE client_id = ? AND day_ordinal = ? RETURNING count",
+        )
+        .bind(client_id)
+        .bind(day_ordinal)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| r.get::<u32, _>("count")).unwrap_or(0))
+    }
+
+    /// Remember a payment hash that NWC confirmed as settled for a tool.
+    /// The first record wins, so a hash stays bound to the tool it was first used for.
+    /// Returns true when this call recorded the hash.
+    pub async fn record_settled_payment(
+        &self,
+        payment_hash: &str,
+        tool_name: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO settled_payments (payment_hash, tool_name, settled_at)
+             VALUES (?, ?, ?)",
+        )
+        .bind(payment_hash)
+        .bind(tool_name)
+        .bind(Self::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Look up a previously settled payment hash. Returns the tool it was used for
+    /// and the unix timestamp when it was first recorded.
+    pub async fn get_settled_payment(
+        &self,
+        payment_hash: &str,
+    ) -> anyhow::Result<Option<(String, i64)>> {
+        let row = sqlx::query(
+            "SELECT tool_name, settled_at FROM settled_payments WHERE payment_hash = ?",
+        )
+        .bind(payment_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| (r.get("tool_name"), r.get("settled_at"))))
+    }
+
+    /// Remember an invoice issued by the wallet identified by `wallet` (its NWC
+    /// pubkey) until it settles or expires.
+    pub async fn record_pending_invoice(
+        &self,
+        payment_hash: &str,
+        wallet: &str,
+        tool_name: &str,
+        amount_sats: u64,
+        expires_at: i64,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO pending_invoices
+             (payment_hash, wallet, tool_name, amount_sats, expires_at)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(payment_hash)
+        .bind(wallet)
+        .bind(tool_name)
+        .bind(amount_sats as i64)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Look up a pending invoice. Returns the issuing wallet and the unix
+    /// timestamp the invoice expires at.
+    pub async fn get_pending_invoice(
+        &self,
+        payment_hash: &str,
+    ) -> anyhow::Result<Option<(String, i64)>> {
+        let row =
+            sqlx::query("SELECT wallet, expires_at FROM pending_invoices WHERE payment_hash = ?")
+                .bind(payment_hash)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map(|r| (r.get("wallet"), r.get("expires_at"))))
+    }
+
+    /// Forget an invoice once it has settled or expired.
+    pub async fn delete_pending_invoice(&self, payment_hash: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM pending_invoices WHERE payment_hash = ?")
+            .bind(payment_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Close the pool, waiting for in-flight queries and checkpointing the WAL.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
     pub async fn cleanup_expired(&self) -> anyhow::Result<()> {
         let now = Self::now();
         sqlx::query("DELETE FROM profiles WHERE expires_at < ?")
@@ -282,6 +688,18 @@ impl Cache {
             .bind(now)
             .execute(&self.pool)
             .await?;
+        sqlx::query("DELETE FROM replaceable_events WHERE expires_at < ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM events WHERE expires_at < ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM pending_invoices WHERE expires_at < ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
         // Clean up rate limit rows from previous days
         let today = current_day_ordinal();
         sqlx::query("DELETE FROM rate_limits WHERE day_ordinal < ?")
@@ -292,6 +710,138 @@ impl Cache {
     }
 }
 
+/// One idempotent schema change. Databases created before versioning start
+/// at version 0 and replay every step, so each must tolerate existing state.
+enum Step {
+    Sql(&'static str),
+    /// `(table, column, type)`, skipped when the column already exists
+    AddColumn(&'static str, &'static str, &'static str),
+}
+
+/// Ordered schema migrations; entry `i` is version `i + 1`. Append new
+/// migrations here, never edit released ones.
+const MIGRATIONS: &[&[Step]] = &[
+    // 1: original tables
+    &[
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS profiles (
+                pubkey TEXT PRIMARY KEY NOT NULL,
+                name TEXT,
+                display_name TEXT,
+                about TEXT,
+                picture TEXT,
+                banner TEXT,
+                nip05 TEXT,
+                lud16 TEXT,
+                website TEXT,
+                cached_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        ),
+        Step::Sql("CREATE INDEX IF NOT EXISTS idx_profiles_expires ON profiles(expires_at)"),
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS relay_info (
+                relay_url TEXT PRIMARY KEY NOT NULL,
+                name TEXT,
+                description TEXT,
+                supported_nips TEXT,
+                software TEXT,
+                version TEXT,
+                online BOOLEAN NOT NULL DEFAULT 1,
+                latency_ms INTEGER,
+                cached_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        ),
+        Step::Sql("CREATE INDEX IF NOT EXISTS idx_relay_info_expires ON relay_info(expires_at)"),
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS rate_limits (
+                client_id TEXT NOT NULL,
+                day_ordinal INTEGER NOT NULL,
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (client_id, day_ordinal)
+            )",
+        ),
+    ],
+    // 2: NIP-11 limitation and fee objects
+    &[
+        Step::AddColumn("relay_info", "limitation", "TEXT"),
+        Step::AddColumn("relay_info", "fees", "TEXT"),
+    ],
+    // 3: settled payment hashes
+    &[Step::Sql(
+        "CREATE TABLE IF NOT EXISTS settled_payments (
+            payment_hash TEXT PRIMARY KEY NOT NULL,
+            tool_name TEXT NOT NULL,
+            settled_at INTEGER NOT NULL
+        )",
+    )],
+    // 4: contact and relay lists
+    &[
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS replaceable_events (
+                pubkey TEXT NOT NULL,
+                kind INTEGER NOT NULL,
+                event_json TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                cached_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                PRIMARY KEY (pubkey, kind)
+            )",
+        ),
+        Step::Sql(
+            "CREATE INDEX IF NOT EXISTS idx_replaceable_events_expires
+             ON replaceable_events(expires_at)",
+        ),
+    ],
+    // 5: events by ID
+    &[
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS events (
+                id TEXT PRIMARY KEY NOT NULL,
+                pubkey TEXT NOT NULL,
+                event_json TEXT NOT NULL,
+                cached_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        ),
+        Step::Sql("CREATE INDEX IF NOT EXISTS idx_events_expires ON events(expires_at)"),
+        Step::Sql("CREATE INDEX IF NOT EXISTS idx_events_pubkey ON events(pubkey)"),
+    ],
+    // 6: NIP-11 relay icon
+    &[Step::AddColumn("relay_info", "icon", "TEXT")],
+    // 7: unpaid invoices
+    &[
+        Step::Sql(
+            "CREATE TABLE IF NOT EXISTS pending_invoices (
+                payment_hash TEXT PRIMARY KEY NOT NULL,
+                wallet TEXT NOT NULL,
+                tool_name TEXT NOT NULL,
+                amount_sats INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        ),
+        Step::Sql(
+            "CREATE INDEX IF NOT EXISTS idx_pending_invoices_expires
+             ON pending_invoices(expires_at)",
+        ),
+    ],
+];
+
+fn profile_from_row(r: &sqlx::sqlite::SqliteRow) -> CachedProfile {
+    CachedProfile {
+        pubkey: r.get("pubkey"),
+        name: r.get("name"),
+        display_name: r.get("display_name"),
+        about: r.get("about"),
+        picture: r.get("picture"),
+        banner: r.get("banner"),
+        nip05: r.get("nip05"),
+        lud16: r.get("lud16"),
+        website: r.get("website"),
+    }
+}
+
 fn current_day_ordinal() -> u32 {
     use chrono::Datelike;
     chrono::Utc::now().ordinal()
@@ -301,23 +851,9 @@ fn current_day_ordinal() -> u32 {
 impl Cache {
     /// Create an in-memory SQLite cache for tests.
     pub async fn new_in_memory() -> Self {
-        let options = SqliteConnectOptions::from_str("sqlite::memory:")
-            .expect("valid memory DSN")
-            .journal_mode(SqliteJournalMode::Wal);
-
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect_with(options)
+        Self::new_memory(3600, 600)
             .await
-            .expect("connect in-memory SQLite");
-
-        let cache = Self {
-            pool,
-            profile_ttl: 3600,
-            relay_ttl: 600,
-        };
-        cache.init_schema().await.expect("init schema");
-        cache
+            .expect("open in-memory SQLite")
     }
 }
 
@@ -367,6 +903,27 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn reset_rate_returns_previous_count() {
+        let cache = Cache::new_in_memory().await;
+        for _ in 0..3 {
+            cache
+                .check_and_increment_rate("client1", 1, 10)
+                .await
+                .unwrap();
+        }
+        cache
+            .check_and_increment_rate("client1", 2, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(cache.reset_rate("client1", 1).await.unwrap(), 3);
+        assert_eq!(cache.get_rate_count("client1", 1).await.unwrap(), 0);
+        // Other days are untouched, and resetting again is a no-op
+        assert_eq!(cache.get_rate_count("client1", 2).await.unwrap(), 1);
+        assert_eq!(cache.reset_rate("client1", 1).await.unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn per_client_isolation() {
         let cache = Cache::new_in_memory().await;
@@ -383,6 +940,45 @@ mod tests {
         assert_eq!(cache.get_rate_count("bob", 1).await.unwrap(), 5);
     }
 
+    #[tokio::test]
+    async fn settled_payment_keeps_first_tool() {
+        let cache = Cache::new_in_memory().await;
+        assert!(cache.get_settled_payment("hash1").await.unwrap().is_none());
+
+        assert!(cache
+            .record_settled_payment("hash1", "search_events")
+            .await
+            .unwrap());
+        assert!(!cache
+            .record_settled_payment("hash1", "zap_analytics")
+            .await
+            .unwrap());
+
+        let (tool, _) = cache.get_settled_payment("hash1").await.unwrap().unwrap();
+        assert_eq!(tool, "search_events");
+    }
+
+    #[tokio::test]
+    async fn cleanup_purges_expired_pending_invoices() {
+        let cache = Cache::new_in_memory().await;
+        let now = Cache::now();
+        cache
+            .record_pending_invoice("stale", "wallet", "search_events", 10, now - 1)
+            .await
+            .unwrap();
+        cache
+            .record_pending_invoice("live", "wallet", "search_events", 10, now + 600)
+            .await
+            .unwrap();
+
+        cache.cleanup_expired().await.unwrap();
+        assert!(cache.get_pending_invoice("stale").await.unwrap().is_none());
+        assert_eq!(
+            cache.get_pending_invoice("live").await.unwrap(),
+            Some(("wallet".to_string(), now + 600))
+        );
+    }
+
     #[tokio::test]
     async fn per_day_isolation() {
         let cache = Cache::new_in_memory().await;
@@ -401,4 +997,287 @@ mod tests {
         assert_eq!(cache.get_rate_count("client1", 100).await.unwrap(), 3);
         assert_eq!(cache.get_rate_count("client1", 101).await.unwrap(), 7);
     }
+
+    #[tokio::test]
+    async fn profiles_batch_returns_cached_subset() {
+        let cache = Cache::new_in_memory().await;
+        for pk in ["a", "b"] {
+            cache
+                .set_profile(&CachedProfile {
+                    pubkey: pk.into(),
+                    name: Some(format!("name-{pk}")),
+                    display_name: None,
+                    about: None,
+                    picture: None,
+                    banner: None,
+                    nip05: None,
+                    lud16: None,
+                    website: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let keys = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let found = cache.get_profiles_batch(&keys).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["b"].name.as_deref(), Some("name-b"));
+        assert!(!found.contains_key("c"));
+        assert!(cache.get_profiles_batch(&[]).await.unwrap().is_empty());
+    }
+
+    fn sample_profile() -> CachedProfile {
+        CachedProfile {
+            pubkey: "abc".into(),
+            name: Some("alice".into()),
+            display_name: None,
+            about: None,
+            picture: None,
+            banner: None,
+            nip05: None,
+            lud16: None,
+            website: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_backend_round_trips_profiles() {
+        let cache = Cache::new_memory(3600, 600).await.unwrap();
+        cache.set_profile(&sample_profile()).await.unwrap();
+        let profile = cache.get_profile("abc").await.unwrap().unwrap();
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn repeat_profile_reads_are_served_from_memory() {
+        let cache = Cache::new_in_memory().await;
+        cache.set_profile(&sample_profile()).await.unwrap();
+        cache.profile_lru.as_ref().unwrap().lock().unwrap().clear();
+
+        // First read goes to SQLite and populates the LRU
+        assert!(cache.get_profile("abc").await.unwrap().is_some());
+
+        // Remove the row behind the cache's back: the second read must not query SQLite
+        sqlx::query("DELETE FROM profiles")
+            .execute(&cache.pool)
+            .await
+            .unwrap();
+        let profile = cache.get_profile("abc").await.unwrap().unwrap();
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+        let batch = cache.get_profiles_batch(&["abc".into()]).await.unwrap();
+        assert!(batch.contains_key("abc"));
+    }
+
+    #[tokio::test]
+    async fn newer_replaceable_event_wins() {
+        use nostr_sdk::{EventBuilder, Keys, Tag, Timestamp};
+
+        let cache = Cache::new_in_memory().await;
+        let keys = Keys::generate();
+        let contact_list = |created_at: u64, follows: &Keys| {
+            EventBuilder::new(Kind::ContactList, "")
+                .tag(Tag::public_key(follows.public_key()))
+                .custom_created_at(Timestamp::from(created_at))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let pubkey = keys.public_key().to_hex();
+        let (old, new) = (
+            contact_list(1_000, &Keys::generate()),
+            contact_list(2_000, &Keys::generate()),
+        );
+
+        cache.set_replaceable_event(&old).await.unwrap();
+        cache.set_replaceable_event(&new).await.unwrap();
+        let cached = cache.get_replaceable_event(&pubkey, Kind::ContactList);
+        assert_eq!(cached.await.unwrap().unwrap().id, new.id);
+
+        // A stale list from a lagging relay doesn't overwrite the newer one
+        cache.set_replaceable_event(&old).await.unwrap();
+        let cached = cache.get_replaceable_event(&pubkey, Kind::ContactList);
+        assert_eq!(cached.await.unwrap().unwrap().id, new.id);
+        assert!(cache
+            .get_replaceable_event(&pubkey, Kind::RelayList)
+            .await
+            .unwrap()
+            .is_none());
+
+        let counts = cache.purge_pubkey(&pubkey).await.unwrap();
+        assert_eq!(counts.replaceable_events, 1);
+        assert!(cache
+            .get_replaceable_event(&pubkey, Kind::ContactList)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn purge_removes_everything_for_pubkey() {
+        let cache = Cache::new_in_memory().await;
+        cache.set_profile(&sample_profile()).await.unwrap();
+        let other = CachedProfile {
+            pubkey: "def".into(),
+            ..sample_profile()
+        };
+        cache.set_profile(&other).await.unwrap();
+
+        let counts = cache.purge_pubkey("abc").await.unwrap();
+        assert_eq!(
+            counts,
+            PurgeCounts {
+                profiles: 1,
+                replaceable_events: 0,
+                events: 0,
+            }
+        );
+        assert_eq!(counts.total(), 1);
+        assert!(cache.get_profile("abc").await.unwrap().is_none());
+        assert!(cache
+            .get_profiles_batch(&["abc".into()])
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(cache.get_profile("def").await.unwrap().is_some());
+
+        assert_eq!(cache.purge_pubkey("abc").await.unwrap().total(), 0);
+    }
+
+    #[tokio::test]
+    async fn delete_and_expiry_bypass_the_memory_layer() {
+        let cache = Cache::new_in_memory().await;
+        cache.set_profile(&sample_profile()).await.unwrap();
+        assert!(cache.delete_profile("abc").await.unwrap());
+        assert!(cache.get_profile("abc").await.unwrap().is_none());
+        assert!(!cache.delete_profile("abc").await.unwrap());
+
+        // Entries past their TTL are dropped even if still in the LRU
+        let expired = Cache::new_memory(0, 600).await.unwrap();
+        expired.set_profile(&sample_profile()).await.unwrap();
+        assert!(expired.get_profile("abc").await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_rate_increments_do_not_lock() {
+        let path =
+            std::env::temp_dir().join(format!("nostr-intel-rate-test-{}.db", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        let cache = std::sync::Arc::new(
+            Cache::new(&path_str, 3600, 600, Duration::from_secs(5))
+                .await
+                .unwrap(),
+        );
+
+        let tasks: Vec<_> = (0..64)
+            .map(|_| {
+                let cache = std::sync::Arc::clone(&cache);
+                tokio::spawn(async move { cache.check_and_increment_rate("busy", 1, 50).await })
+            })
+            .collect();
+        let mut allowed = 0;
+        for task in tasks {
+            if task.await.unwrap().expect("no lock errors") {
+                allowed += 1;
+            }
+        }
+
+        assert_eq!(allowed, 50);
+        assert_eq!(cache.get_rate_count("busy", 1).await.unwrap(), 50);
+        cache.close().await;
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path_str}{suffix}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn old_schema_is_migrated_forward() {
+        let path = std::env::temp_dir().join(format!(
+            "nostr-intel-migrate-test-{}.db",
+            std::process::id()
+        ));
+        let path_str = path.to_string_lossy().to_string();
+
+        // A pre-versioning database: relay_info without the NIP-11 columns and
+        // no settled_payments table
+        {
+            let options = SqliteConnectOptions::from_str(&format!("sqlite:{path_str}"))
+                .unwrap()
+                .create_if_missing(true);
+            let pool = SqlitePool::connect_with(options).await.unwrap();
+            sqlx::query(
+                "CREATE TABLE relay_info (
+                    relay_url TEXT PRIMARY KEY NOT NULL, name TEXT, description TEXT,
+                    supported_nips TEXT, software TEXT, version TEXT,
+                    online BOOLEAN NOT NULL DEFAULT 1, latency_ms INTEGER,
+                    cached_at INTEGER NOT NULL, expires_at INTEGER NOT NULL
+                )",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT INTO relay_info (relay_url, name, supported_nips, cached_at, expires_at)
+                 VALUES ('wss://old.example', 'old', '[1]', 0, 9999999999)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            pool.close().await;
+        }
+
+        let cache = Cache::new(&path_str, 3600, 600, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.schema_version().await.unwrap(),
+            MIGRATIONS.len() as i64
+        );
+
+        let old = cache
+            .get_relay_info("wss://old.example")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(old.name.as_deref(), Some("old"));
+        assert!(old.limitation.is_none());
+        assert!(old.icon.is_none());
+        assert!(cache
+            .record_settled_payment(&"ab".repeat(32), "search_events")
+            .await
+            .unwrap());
+        cache.close().await;
+
+        // Reopening at the latest version is a no-op
+        let cache = Cache::new(&path_str, 3600, 600, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.schema_version().await.unwrap(),
+            MIGRATIONS.len() as i64
+        );
+        cache.close().await;
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path_str}{suffix}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn file_backend_round_trips_profiles() {
+        let path =
+            std::env::temp_dir().join(format!("nostr-intel-cache-test-{}.db", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+
+        let cache = Cache::new(&path_str, 3600, 600, Duration::from_secs(5))
+            .await
+            .unwrap();
+        cache.set_profile(&sample_profile()).await.unwrap();
+        let profile = cache.get_profile("abc").await.unwrap().unwrap();
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+        cache.close().await;
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path_str}{suffix}"));
+        }
+    }
 }
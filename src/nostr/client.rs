@@ -1,35 +1,306 @@
 use nostr_sdk::prelude::*;
-use std::time::Duration;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+use crate::nostr::cache::Cache;
+use crate::nostr::health::RelayHealthTracker;
 
 pub struct NostrClient {
     client: Client,
+    relay_urls: Vec<String>,
+    health: Arc<RelayHealthTracker>,
+    /// Relays whose NIP-11 document says they require NIP-42 auth; anonymous
+    /// fetches from them return nothing, so they're skipped
+    auth_required: RwLock<HashSet<String>>,
+    /// Contact lists, relay lists and events by ID are served from here when fresh
+    cache: Option<Arc<Cache>>,
 }
 
-impl NostrClient {
-    pub fn client(&self) -> &Client {
-        &self.client
+/// Relays each event was received from during one fetch
+pub type SeenOn = HashMap<EventId, BTreeSet<String>>;
+
+/// What `fetch_up_to` collected, including which relays let it down. Events
+/// may be incomplete when `relay_errors` is non-empty.
+#[derive(Debug, Default)]
+pub struct RelayResults {
+    pub events: Vec<Event>,
+    pub seen_on: SeenOn,
+    /// `(relay, error)` for each relay that failed or timed out
+    pub relay_errors: Vec<(String, String)>,
+}
+
+/// Relay pool settings passed through to nostr-sdk.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolOptions {
+    /// Cap on relays in the pool (and so connections held open); relays past the
+    /// cap are skipped with a warning
+    pub max_connections: Option<usize>,
+    /// SOCKS5 proxy every relay connection goes through
+    pub socks_proxy: Option<std::net::SocketAddr>,
+}
+
+impl PoolOptions {
+    fn client_options(&self) -> ClientOptions {
+        let mut opts =
+            ClientOptions::new().pool(RelayPoolOptions::new().max_relays(self.max_connections));
+        if let Some(proxy) = self.socks_proxy {
+            opts = opts.connection(Connection::new().proxy(proxy));
+        }
+        opts
     }
+}
 
-    pub async fn new(relay_urls: Vec<String>) -> anyhow::Result<Self> {
-        let client = Client::default();
+impl NostrClient {
+    pub async fn new(
+        relay_urls: Vec<String>,
+        health: Arc<RelayHealthTracker>,
+        opts: PoolOptions,
+    ) -> anyhow::Result<Self> {
+        let client = Client::builder().opts(opts.client_options()).build();
 
+        let mut added = Vec::new();
         for url in &relay_urls {
-            if let Err(e) = client.add_relay(url).await {
-                tracing::warn!("Failed to add relay {url}: {e}");
+            match client.add_relay(url).await {
+                Ok(_) => added.push(url.clone()),
+                Err(e) => tracing::warn!("Failed to add relay {url}: {e}"),
             }
         }
 
         client.connect().await;
         tracing::info!("Nostr client connected to relay pool");
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            relay_urls: added,
+            health,
+            auth_required: RwLock::new(HashSet::new()),
+            cache: None,
+        })
+    }
+
+    /// Check `cache` for contact lists, relay lists and events by ID before
+    /// asking relays.
+    pub fn with_cache(mut self, cache: Arc<Cache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// All relays in the pool, in configured order.
+    pub fn relay_urls(&self) -> &[String] {
+        &self.relay_urls
+    }
+
+    /// Replace the set of relays known to require NIP-42 auth.
+    pub async fn set_auth_required(&self, relays: HashSet<String>) {
+        *self.auth_required.write().await = relays;
+    }
+
+    /// Pool relays skipped because they require auth, sorted.
+    pub async fn skipped_relays(&self) -> Vec<String> {
+        let auth_required = self.auth_required.read().await;
+        let mut skipped: Vec<String> = self
+            .relay_urls
+            .iter()
+            .filter(|url| auth_required.contains(*url))
+            .cloned()
+            .collect();
+        skipped.sort();
+        skipped
+    }
+
+    /// Relays a fetch would currently target: not auth-gated, and healthy.
+    pub async fn target_relays(&self) -> Vec<String> {
+        let candidates: Vec<String> = {
+            let auth_required = self.auth_required.read().await;
+            self.relay_urls
+                .iter()
+                .filter(|url| !auth_required.contains(*url))
+                .cloned()
+                .collect()
+        };
+        if candidates.is_empty() {
+            return candidates;
+        }
+        self.health.healthy_relays(&candidates).await
+    }
+
+    /// Number of relays a fetch would currently target (healthy relays only).
+    pub async fn active_relay_count(&self) -> usize {
+        self.target_relays().await.len()
+    }
+
+    /// Fetch events from every healthy relay in parallel, recording per-relay
+    /// success/failure and latency. Relays with a high recent failure rate, or that
+    /// require auth, are skipped.
+    pub async fn fetch(&self, filter: Filter, timeout: Duration) -> anyhow::Result<Events> {
+        let targets = self.target_relays().await;
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for url in targets {
+            let client = self.client.clone();
+            let filter = filter.clone();
+            tasks.spawn(async move {
+                let start = Instant::now();
+                let result = match client.relay(&url).await {
+                    Ok(relay) => relay
+                        .fetch_events(filter, timeout, ReqExitPolicy::ExitOnEOSE)
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                (url, start.elapsed(), result)
+            });
+        }
+
+        let mut events = Events::new(&filter);
+        let mut any_ok = false;
+        let mut last_error = None;
+        while let Some(joined) = tasks.join_next().await {
+            let (url, elapsed, result) = joined?;
+            match result {
+                // A fetch that runs into the timeout never saw EOSE — keep what arrived,
+                // but count it against the relay
+                Ok(relay_events) if elapsed >= timeout => {
+                    self.health.record_failure(&url, "timeout").await;
+                    last_error = Some(format!("{url}: timeout"));
+                    events.extend(relay_events);
+                }
+                Ok(relay_events) => {
+                    self.health
+                        .record_success(&url, elapsed.as_millis() as u64)
+                        .await;
+                    events.extend(relay_events);
+                    any_ok = true;
+                }
+                Err(e) => {
+                    tracing::debug!("Fetch from {url} failed: {e}");
+                    self.health.record_failure(&url, &e).await;
+                    last_error = Some(format!("{url}: {e}"));
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if !any_ok && events.is_empty() => {
+                anyhow::bail!("All relays failed (last error: {e})")
+            }
+            _ => Ok(events),
+        }
+    }
+
+    /// Like `fetch`, but returns as soon as `limit` distinct events have arrived
+    /// rather than waiting for every relay's EOSE (or the timeout). Relays still
+    /// streaming at that point are dropped without affecting their health score
+    /// or counting as errors, so `seen_on` only lists the relays heard from
+    /// before the cutoff.
+    pub async fn fetch_up_to(
+        &self,
+        filter: Filter,
+        timeout: Duration,
+        limit: usize,
+    ) -> anyhow::Result<RelayResults> {
+        let targets = self.target_relays().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<(String, Event)>(512);
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for url in targets {
+            let client = self.client.clone();
+            let filter = filter.clone();
+            let tx = tx.clone();
+            tasks.spawn(async move {
+                let start = Instant::now();
+                let result = async {
+                    let relay = client.relay(&url).await.map_err(|e| e.to_string())?;
+                    let mut stream = relay
+                        .stream_events(filter, timeout, ReqExitPolicy::ExitOnEOSE)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    while let Some(item) = futures::StreamExt::next(&mut stream).await {
+                        match item {
+                            Ok(event) => {
+                                if tx.send((url.clone(), event)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => return Err(e.to_string()),
+                        }
+                    }
+                    Ok(())
+                }
+                .await;
+                (url, start.elapsed(), result)
+            });
+        }
+        drop(tx);
+
+        let mut events = Events::new(&filter);
+        let mut seen_on = SeenOn::new();
+        let mut any_ok = false;
+        let mut relay_errors = Vec::new();
+        loop {
+            tokio::select! {
+                Some((url, event)) = rx.recv() => {
+                    seen_on.entry(event.id).or_default().insert(url);
+                    events.insert(event);
+                    if events.len() >= limit {
+                        tasks.abort_all();
+                        return Ok(RelayResults {
+                            events: events.into_iter().collect(),
+                            seen_on,
+                            relay_errors,
+                        });
+                    }
+                }
+                joined = tasks.join_next() => {
+                    let Some(joined) = joined else { break };
+                    let (url, elapsed, result) = joined?;
+                    match result {
+                        Ok(()) if elapsed >= timeout => {
+                            self.health.record_failure(&url, "timeout").await;
+                            relay_errors.push((url, "timeout".to_string()));
+                        }
+                        Ok(()) => {
+                            self.health
+                                .record_success(&url, elapsed.as_millis() as u64)
+                                .await;
+                            any_ok = true;
+                        }
+                        Err(e) => {
+                            tracing::debug!("Fetch from {url} failed: {e}");
+                            self.health.record_failure(&url, &e).await;
+                            relay_errors.push((url, e));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Every relay finished; pick up events sent just before their task ended
+        while let Ok((url, event)) = rx.try_recv() {
+            seen_on.entry(event.id).or_default().insert(url);
+            events.insert(event);
+        }
+
+        match relay_errors.last() {
+            Some((url, e)) if !any_ok && events.is_empty() => {
+                anyhow::bail!("All relays failed (last error: {url}: {e})")
+            }
+            _ => Ok(RelayResults {
+                events: events.into_iter().collect(),
+                seen_on,
+                relay_errors,
+            }),
+        }
     }
 
     pub async fn get_metadata(&self, pubkey: &PublicKey) -> anyhow::Result<Option<Metadata>> {
         let filter = Filter::new().kind(Kind::Metadata).author(*pubkey).limit(1);
 
         let timeout = Duration::from_secs(10);
-        let events = self.client.fetch_events(filter, timeout).await?;
+        let events = self.fetch(filter, timeout).await?;
 
         if let Some(event) = events.first() {
             let metadata = Metadata::from_json(&event.content)?;
@@ -39,14 +310,41 @@ impl NostrClient {
         }
     }
 
+    /// Fetch kind:0 metadata for many authors in a single request
+    pub async fn fetch_metadata_batch(&self, pubkeys: &[PublicKey]) -> anyhow::Result<Vec<Event>> {
+        if pubkeys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = Filter::new()
+            .kind(Kind::Metadata)
+            .authors(pubkeys.iter().copied());
+
+        let timeout = Duration::from_secs(10);
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
+    /// NIP-50 full-text search over kind:0 profiles, for when Primal is unavailable
+    pub async fn search_metadata(&self, query: &str, limit: u32) -> anyhow::Result<Vec<Event>> {
+        let filter = Filter::new()
+            .kind(Kind::Metadata)
+            .search(query)
+            .limit(limit as usize);
+
+        let timeout = Duration::from_secs(10);
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
     pub async fn search_events(
         &self,
         authors: Option<Vec<PublicKey>>,
         kinds: Option<Vec<Kind>>,
         search: Option<String>,
         since: Option<Timestamp>,
-        limit: Option<u32>,
-    ) -> anyhow::Result<Vec<Event>> {
+        identifiers: Option<Vec<String>>,
+        limit: u32,
+    ) -> anyhow::Result<RelayResults> {
         let mut filter = Filter::new();
 
         if let Some(authors) = authors {
@@ -61,47 +359,199 @@ impl NostrClient {
         if let Some(since) = since {
             filter = filter.since(since);
         }
+        // `d` tags of parameterized replaceable events (NIP-33 coordinates)
+        if let Some(identifiers) = identifiers {
+            filter = filter.identifiers(identifiers);
+        }
 
-        let limit = limit.unwrap_or(20).min(100);
         filter = filter.limit(limit as usize);
 
+        // Stop as soon as enough events are in rather than waiting out slow relays
         let timeout = Duration::from_secs(15);
-        let events = self.client.fetch_events(filter, timeout).await?;
-
-        Ok(events.into_iter().collect())
+        self.fetch_up_to(filter, timeout, limit as usize).await
     }
 
     /// Fetch kind:10002 (NIP-65 relay list metadata) for a pubkey
     pub async fn fetch_relay_list(&self, pubkey: &PublicKey) -> anyhow::Result<Vec<Event>> {
-        let filter = Filter::new().kind(Kind::RelayList).author(*pubkey).limit(1);
-
-        let timeout = Duration::from_secs(10);
-        let events = self.client.fetch_events(filter, timeout).await?;
-        Ok(events.into_iter().collect())
+        Ok(self
+            .fetch_latest_cached(pubkey, Kind::RelayList)
+            .await?
+            .into_iter()
+            .collect())
     }
 
     /// Fetch kind:3 (contact list) for a pubkey
     pub async fn fetch_contact_list(&self, pubkey: &PublicKey) -> anyhow::Result<Option<Event>> {
+        self.fetch_latest_cached(pubkey, Kind::ContactList).await
+    }
+
+    /// Fetch kind:10003 (NIP-51 bookmark list) for a pubkey
+    pub async fn fetch_bookmark_list(&self, pubkey: &PublicKey) -> anyhow::Result<Option<Event>> {
+        self.fetch_latest_cached(pubkey, Kind::Bookmarks).await
+    }
+
+    /// Fetch NIP-51 lists of the given kinds published by `pubkey`, keeping only
+    /// the newest version of each (kind, `d` identifier)
+    pub async fn fetch_lists(
+        &self,
+        pubkey: &PublicKey,
+        kinds: Vec<Kind>,
+    ) -> anyhow::Result<Vec<Event>> {
         let filter = Filter::new()
-            .kind(Kind::ContactList)
             .author(*pubkey)
-            .limit(1);
+            .kinds(kinds.clone())
+            .limit(200);
+        let timeout = Duration::from_secs(10);
+        let mut newest: HashMap<(Kind, String), Event> = HashMap::new();
+        for event in self.fetch(filter, timeout).await? {
+            if event.pubkey != *pubkey || !kinds.contains(&event.kind) {
+                continue;
+            }
+            let key = (
+                event.kind,
+                event.tags.identifier().unwrap_or_default().to_string(),
+            );
+            match newest.get(&key) {
+                Some(existing) if existing.created_at >= event.created_at => {}
+                _ => {
+                    newest.insert(key, event);
+                }
+            }
+        }
+        let mut lists: Vec<Event> = newest.into_values().collect();
+        lists.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+        Ok(lists)
+    }
 
+    /// Newest replaceable event of `kind` by `pubkey`, from the list cache when
+    /// fresh. Cache errors fall through to the relays.
+    async fn fetch_latest_cached(
+        &self,
+        pubkey: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<Option<Event>> {
+        let pubkey_hex = pubkey.to_hex();
+        if let Some(cache) = &self.cache {
+            match cache.get_replaceable_event(&pubkey_hex, kind).await {
+                Ok(Some(event)) => {
+                    tracing::debug!("Cache hit for kind {kind} of {pubkey_hex}");
+                    return Ok(Some(event));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("List cache read failed: {e}"),
+            }
+        }
+
+        let filter = Filter::new().kind(kind).author(*pubkey).limit(1);
         let timeout = Duration::from_secs(10);
-        let events = self.client.fetch_events(filter, timeout).await?;
-        Ok(events.into_iter().next())
+        // Don't trust relays to honour the filter before caching what they send
+        let event = self
+            .fetch(filter, timeout)
+            .await?
+            .into_iter()
+            .find(|e| e.kind == kind && e.pubkey == *pubkey);
+
+        if let (Some(cache), Some(event)) = (&self.cache, &event) {
+            if let Err(e) = cache.set_replaceable_event(event).await {
+                tracing::warn!("List cache write failed: {e}");
+            }
+        }
+        Ok(event)
+    }
+
+    /// Fetch contact lists that include `pubkey`, one per follower, newest first.
+    /// Pages back with `until` cursors until `want` followers are found or the
+    /// relays run out of older lists.
+    pub async fn fetch_followers(
+        &self,
+        pubkey: &PublicKey,
+        want: usize,
+    ) -> anyhow::Result<Vec<Event>> {
+        const PAGE_SIZE: usize = 500;
+        const MAX_PAGES: usize = 20;
+
+        let mut followers: Vec<Event> = Vec::new();
+        let mut seen: HashSet<PublicKey> = HashSet::new();
+        let mut until: Option<Timestamp> = None;
+        for _ in 0..MAX_PAGES {
+            let mut filter = Filter::new()
+                .kind(Kind::ContactList)
+                .pubkey(*pubkey)
+                .limit(PAGE_SIZE);
+            if let Some(until) = until {
+                filter = filter.until(until);
+            }
+            let mut page: Vec<Event> = self
+                .fetch(filter, Duration::from_secs(15))
+                .await?
+                .into_iter()
+                .collect();
+            page.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+
+            for event in &page {
+                if seen.insert(event.pubkey) {
+                    followers.push(event.clone());
+                }
+            }
+            // A short page doesn't mean the end: relays may cap `limit` below
+            // PAGE_SIZE, so only an empty page or a stuck cursor stops paging
+            if followers.len() >= want || page.is_empty() {
+                break;
+            }
+
+            // Each relay returned at most PAGE_SIZE lists, so everything newer than
+            // the PAGE_SIZE-th newest list has been seen from every relay; resume
+            // there (inclusive) so a relay whose page ended later isn't skipped
+            let cursor = page[page.len().min(PAGE_SIZE) - 1].created_at;
+            if until == Some(cursor) {
+                break;
+            }
+            until = Some(cursor);
+        }
+
+        followers.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+        Ok(followers)
     }
 
     /// Fetch events by their IDs
-    #[allow(dead_code)]
     pub async fn fetch_events_by_ids(&self, ids: Vec<EventId>) -> anyhow::Result<Vec<Event>> {
         if ids.is_empty() {
             return Ok(vec![]);
         }
-        let filter = Filter::new().ids(ids);
+
+        // Only ask relays for the events the cache doesn't have
+        let mut found = Vec::new();
+        let mut misses = ids;
+        if let Some(cache) = &self.cache {
+            match cache.get_events(&misses).await {
+                Ok(cached) => {
+                    misses.retain(|id| !cached.iter().any(|e| e.id == *id));
+                    found = cached;
+                }
+                Err(e) => tracing::warn!("Event cache read failed: {e}"),
+            }
+        }
+        if misses.is_empty() {
+            return Ok(found);
+        }
+
+        let wanted: HashSet<EventId> = misses.iter().copied().collect();
+        let filter = Filter::new().ids(misses);
         let timeout = Duration::from_secs(10);
-        let events = self.client.fetch_events(filter, timeout).await?;
-        Ok(events.into_iter().collect())
+        let fetched: Vec<Event> = self
+            .fetch(filter, timeout)
+            .await?
+            .into_iter()
+            .filter(|e| wanted.contains(&e.id))
+            .collect();
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.set_events(&fetched).await {
+                tracing::warn!("Event cache write failed: {e}");
+            }
+        }
+        found.extend(fetched);
+        Ok(found)
     }
 
     /// Fetch kind:7 reactions referencing the given event IDs
@@ -120,7 +570,27 @@ impl NostrClient {
             filter = filter.since(since);
         }
         let timeout = Duration::from_secs(15);
-        let events = self.client.fetch_events(filter, timeout).await?;
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
+    /// Fetch kind:5 deletion requests referencing the given event IDs. Only
+    /// requests signed by `authors` are asked for; relays may ignore that, so
+    /// callers must still check each request's author (NIP-09).
+    pub async fn fetch_deletions(
+        &self,
+        event_ids: &[EventId],
+        authors: &[PublicKey],
+    ) -> anyhow::Result<Vec<Event>> {
+        if event_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let filter = Filter::new()
+            .kind(Kind::EventDeletion)
+            .events(event_ids.to_vec())
+            .authors(authors.to_vec());
+        let timeout = Duration::from_secs(10);
+        let events = self.fetch(filter, timeout).await?;
         Ok(events.into_iter().collect())
     }
 
@@ -138,7 +608,7 @@ impl NostrClient {
             filter = filter.since(since);
         }
         let timeout = Duration::from_secs(15);
-        let events = self.client.fetch_events(filter, timeout).await?;
+        let events = self.fetch(filter, timeout).await?;
         Ok(events.into_iter().collect())
     }
 
@@ -153,7 +623,33 @@ impl NostrClient {
             filter = filter.since(since);
         }
         let timeout = Duration::from_secs(15);
-        let events = self.client.fetch_events(filter, timeout).await?;
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
+    /// Fetch kind:9735 zap receipts for zaps on one event (`e` tag)
+    pub async fn fetch_note_zap_receipts(&self, event_id: &EventId) -> anyhow::Result<Vec<Event>> {
+        let filter = Filter::new().kind(Kind::ZapReceipt).event(*event_id);
+        let timeout = Duration::from_secs(15);
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
+    /// Fetch kind:9735 zap receipts for zaps *sent* by a pubkey (uppercase `P` tag).
+    /// Receipts without a `P` tag can't be found this way.
+    pub async fn fetch_zaps_sent(
+        &self,
+        pubkey: &PublicKey,
+        since: Option<Timestamp>,
+    ) -> anyhow::Result<Vec<Event>> {
+        let mut filter = Filter::new()
+            .kind(Kind::ZapReceipt)
+            .custom_tag(SingleLetterTag::uppercase(Alphabet::P), pubkey.to_hex());
+        if let Some(since) = since {
+            filter = filter.since(since);
+        }
+        let timeout = Duration::from_secs(15);
+        let events = self.fetch(filter, timeout).await?;
         Ok(events.into_iter().collect())
     }
 
@@ -165,25 +661,409 @@ impl NostrClient {
     ) -> anyhow::Result<Vec<Event>> {
         let filter = Filter::new().kind(Kind::TextNote).since(since).limit(limit);
         let timeout = Duration::from_secs(15);
-        let events = self.client.fetch_events(filter, timeout).await?;
+        let events = self.fetch(filter, timeout).await?;
         Ok(events.into_iter().collect())
     }
 
+    /// Fetch events of the given kinds published by `authors` since a timestamp
+    pub async fn fetch_recent_notes_by_authors(
+        &self,
+        authors: &[PublicKey],
+        kinds: Vec<Kind>,
+        since: Timestamp,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Event>> {
+        if authors.is_empty() {
+            return Ok(vec![]);
+        }
+        let filter = Filter::new()
+            .authors(authors.iter().copied())
+            .kinds(kinds)
+            .since(since)
+            .limit(limit);
+        let timeout = Duration::from_secs(15);
+        let events = self.fetch(filter, timeout).await?;
+        Ok(events.into_iter().collect())
+    }
+
+    /// Relays in the pool with a live connection.
+    pub async fn connected_relay_count(&self) -> usize {
+        self.client
+            .relays()
+            .await
+            .values()
+            .filter(|relay| relay.status() == RelayStatus::Connected)
+            .count()
+    }
+
+    /// Relay URLs whose connection has dropped. Used by the background health check.
+    pub async fn dropped_relays(&self) -> Vec<String> {
+        let relays = self.client.relays().await;
+        dropped_relays(
+            relays
+                .iter()
+                .map(|(url, relay)| (url.to_string(), relay.status())),
+        )
+    }
+
     /// Reconnect to all relays in the pool. Called by background health check.
     pub async fn reconnect(&self) {
         tracing::debug!("Reconnecting to relay pool");
         self.client.connect().await;
     }
 
+    /// Disconnect from all relays and stop the pool.
+    pub async fn shutdown(&self) {
+        self.client.shutdown().await;
+    }
+
+    /// Parse a pubkey given as npub, nprofile, or 64-char hex.
+    ///
+    /// Bech32 inputs are checked by prefix, so a `note`/`nevent`/`naddr` passed where a
+    /// pubkey is expected fails with a specific error. Raw hex can't be told apart
+    /// from an event ID, so a hex event ID is accepted and simply matches nothing.
     pub fn parse_pubkey(input: &str) -> anyhow::Result<PublicKey> {
-        // Try npub (bech32)
-        if let Ok(pk) = PublicKey::from_bech32(input) {
-            return Ok(pk);
+        let lower = input.to_ascii_lowercase();
+        if lower.starts_with("npub1") {
+            return PublicKey::from_bech32(input)
+                .map_err(|e| anyhow::anyhow!("Invalid npub '{input}': {e}"));
         }
-        // Try hex
-        if let Ok(pk) = PublicKey::from_hex(input) {
-            return Ok(pk);
+        if lower.starts_with("nprofile1") {
+            return Nip19Profile::from_bech32(input)
+                .map(|profile| profile.public_key)
+                .map_err(|e| anyhow::anyhow!("Invalid nprofile '{input}': {e}"));
+        }
+        for prefix in ["note1", "nevent1", "naddr1"] {
+            if lower.starts_with(prefix) {
+                anyhow::bail!(
+                    "'{input}' is an event reference ({}), not a pubkey; pass an npub, nprofile, or hex pubkey",
+                    &prefix[..prefix.len() - 1]
+                );
+            }
         }
-        anyhow::bail!("Invalid pubkey format: {input}")
+        if lower.starts_with("nsec1") {
+            anyhow::bail!("That is a private key (nsec), not a pubkey; pass the npub instead");
+        }
+        PublicKey::from_hex(input).map_err(|_| anyhow::anyhow!("Invalid pubkey format: {input}"))
+    }
+}
+
+/// Pick out relays whose status means they will not come back on their own
+/// (or are currently between retries) and should trigger a reconnect.
+pub fn dropped_relays(statuses: impl IntoIterator<Item = (String, RelayStatus)>) -> Vec<String> {
+    let mut dropped: Vec<String> = statuses
+        .into_iter()
+        .filter(|(_, status)| matches!(status, RelayStatus::Disconnected | RelayStatus::Terminated))
+        .map(|(url, _)| url)
+        .collect();
+    dropped.sort();
+    dropped
+}
+
+#[cfg(test)]
+impl NostrClient {
+    /// Block until the pool's relays are connected (or `timeout` passes).
+    pub async fn wait_for_connection(&self, timeout: Duration) {
+        self.client.wait_for_connection(timeout).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_relay::{self, MockRelay, RelayPolicy};
+
+    #[test]
+    fn parse_pubkey_rejects_event_references() {
+        let keys = Keys::generate();
+        let pk = keys.public_key();
+        assert_eq!(NostrClient::parse_pubkey(&pk.to_hex()).unwrap(), pk);
+        assert_eq!(
+            NostrClient::parse_pubkey(&pk.to_bech32().unwrap()).unwrap(),
+            pk
+        );
+        let nprofile = Nip19Profile::new(pk, []).to_bech32().unwrap();
+        assert_eq!(NostrClient::parse_pubkey(&nprofile).unwrap(), pk);
+
+        let event = EventBuilder::text_note("hi").sign_with_keys(&keys).unwrap();
+        let note = event.id.to_bech32().unwrap();
+        let err = NostrClient::parse_pubkey(&note).unwrap_err().to_string();
+        assert!(err.contains("event reference (note)"), "{err}");
+
+        let nevent = Nip19Event::new(event.id).to_bech32().unwrap();
+        let err = NostrClient::parse_pubkey(&nevent).unwrap_err().to_string();
+        assert!(err.contains("(nevent)"), "{err}");
+
+        let nsec = keys.secret_key().to_bech32().unwrap();
+        let err = NostrClient::parse_pubkey(&nsec).unwrap_err().to_string();
+        assert!(err.contains("private key") && !err.contains(&nsec), "{err}");
+    }
+
+    #[test]
+    fn dropped_relays_only_reports_disconnected_and_terminated() {
+        let statuses = vec![
+            ("wss://a.example".to_string(), RelayStatus::Connected),
+            ("wss://b.example".to_string(), RelayStatus::Disconnected),
+            ("wss://c.example".to_string(), RelayStatus::Connecting),
+            ("wss://d.example".to_string(), RelayStatus::Terminated),
+            ("wss://e.example".to_string(), RelayStatus::Pending),
+        ];
+        assert_eq!(
+            dropped_relays(statuses),
+            vec!["wss://b.example".to_string(), "wss://d.example".to_string()]
+        );
+    }
+
+    async fn cached_client(relay: String, cache: Arc<Cache>) -> NostrClient {
+        let client = NostrClient::new(
+            vec![relay],
+            Arc::new(RelayHealthTracker::new()),
+            PoolOptions::default(),
+        )
+        .await
+        .unwrap()
+        .with_cache(cache);
+        client
+            .client
+            .wait_for_connection(Duration::from_secs(5))
+            .await;
+        client
+    }
+
+    #[tokio::test]
+    async fn second_contact_list_fetch_hits_cache() {
+        let keys = Keys::generate();
+        let contact_list = EventBuilder::new(Kind::ContactList, "")
+            .tag(Tag::public_key(Keys::generate().public_key()))
+            .sign_with_keys(&keys)
+            .unwrap();
+        let relay = MockRelay::start(RelayPolicy::Events(vec![contact_list.clone()])).await;
+        let client = cached_client(relay.url.clone(), Arc::new(Cache::new_in_memory().await)).await;
+
+        let pubkey = keys.public_key();
+        let first = client.fetch_contact_list(&pubkey).await.unwrap().unwrap();
+        let second = client.fetch_contact_list(&pubkey).await.unwrap().unwrap();
+        assert_eq!(first.id, contact_list.id);
+        assert_eq!(second.id, contact_list.id);
+        assert_eq!(relay.reqs(), 1);
+
+        // Relay lists are cached separately
+        assert!(client.fetch_relay_list(&pubkey).await.unwrap().is_empty());
+        assert_eq!(relay.reqs(), 2);
+    }
+
+    #[tokio::test]
+    async fn events_by_id_fetch_only_cache_misses() {
+        let keys = Keys::generate();
+        let note = |text: &str| EventBuilder::text_note(text).sign_with_keys(&keys).unwrap();
+        let (cached, missing) = (note("cached"), note("missing"));
+        let relay = MockRelay::start(RelayPolicy::Events(vec![missing.clone()])).await;
+        let cache = Arc::new(Cache::new_in_memory().await);
+        cache
+            .set_events(std::slice::from_ref(&cached))
+            .await
+            .unwrap();
+        let client = cached_client(relay.url.clone(), Arc::clone(&cache)).await;
+
+        // Fully cached: no relay call
+        let events = client.fetch_events_by_ids(vec![cached.id]).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, cached.id);
+        assert_eq!(relay.reqs(), 0);
+
+        // A miss goes to the relays and is cached for next time
+        let mut events = client
+            .fetch_events_by_ids(vec![cached.id, missing.id])
+            .await
+            .unwrap();
+        events.sort_by_key(|e| e.content.clone());
+        let ids: Vec<EventId> = events.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![cached.id, missing.id]);
+        assert_eq!(relay.reqs(), 1);
+        assert_eq!(cache.get_events(&[missing.id]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn followers_page_past_relays_that_cap_limit() {
+        let target = Keys::generate().public_key();
+        let now = Timestamp::now().as_secs();
+        let lists: Vec<Event> = (0..5)
+            .map(|i| {
+                EventBuilder::new(Kind::ContactList, "")
+                    .tag(Tag::public_key(target))
+                    .custom_created_at(Timestamp::from(now - 60 * i))
+                    .sign_with_keys(&Keys::generate())
+                    .unwrap()
+            })
+            .collect();
+        // Two lists per REQ, far below the requested page size
+        let relay = MockRelay::start(RelayPolicy::Capped {
+            events: lists.clone(),
+            max: 2,
+        })
+        .await;
+        let client = cached_client(relay.url.clone(), Arc::new(Cache::new_in_memory().await)).await;
+
+        let followers = client.fetch_followers(&target, 10).await.unwrap();
+        let ids: Vec<EventId> = followers.iter().map(|e| e.id).collect();
+        let expected: Vec<EventId> = lists.iter().map(|e| e.id).collect();
+        assert_eq!(ids, expected);
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn deprioritized_relay_is_probed_and_recovers() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        // Refuses every subscription until `failing` is cleared
+        let failing = Arc::new(AtomicBool::new(true));
+        let reqs = Arc::new(AtomicUsize::new(0));
+        let (flag, counter) = (Arc::clone(&failing), Arc::clone(&reqs));
+        let flaky = mock_relay::serve(move || {
+            let (flag, counter) = (Arc::clone(&flag), Arc::clone(&counter));
+            move |msg: serde_json::Value| {
+                if msg[0] != "REQ" {
+                    return Vec::new();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                if flag.load(Ordering::SeqCst) {
+                    vec![serde_json::json!(["CLOSED", msg[1], "error: down"])]
+                } else {
+                    vec![serde_json::json!(["EOSE", msg[1]])]
+                }
+            }
+        })
+        .await;
+        let healthy = MockRelay::start(RelayPolicy::Events(Vec::new())).await;
+
+        let health =
+            Arc::new(RelayHealthTracker::new().with_probe_after(Duration::from_millis(300)));
+        let client = NostrClient::new(
+            vec![flaky.clone(), healthy.url],
+            Arc::clone(&health),
+            PoolOptions::default(),
+        )
+        .await
+        .unwrap();
+        client
+            .client
+            .wait_for_connection(Duration::from_secs(5))
+            .await;
+        let fetch = || client.fetch(Filter::new().kind(Kind::TextNote), Duration::from_secs(2));
+        let flaky_health = || async {
+            health
+                .snapshot()
+                .await
+                .into_iter()
+                .find(|(url, _)| url.trim_end_matches('/') == flaky)
+                .unwrap()
+                .1
+        };
+
+        // Enough failures to deprioritize it, after which fetches skip it
+        for _ in 0..5 {
+            fetch().await.unwrap();
+        }
+        assert!(flaky_health().await.is_deprioritized());
+        fetch().await.unwrap();
+        assert_eq!(reqs.load(Ordering::SeqCst), 5);
+
+        // After the cooldown one fetch probes it; the success puts it back in rotation
+        failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        fetch().await.unwrap();
+        assert_eq!(reqs.load(Ordering::SeqCst), 6);
+        assert!(!flaky_health().await.is_deprioritized());
+        fetch().await.unwrap();
+        assert_eq!(reqs.load(Ordering::SeqCst), 7);
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn search_events_returns_once_limit_is_reached() {
+        // Five notes and no EOSE: only an early return beats the fetch timeout
+        let keys = Keys::generate();
+        let notes = (0..5)
+            .map(|i| {
+                EventBuilder::text_note(format!("note {i}"))
+                    .sign_with_keys(&keys)
+                    .unwrap()
+            })
+            .collect();
+        let relay = MockRelay::start(RelayPolicy::EventsWithoutEose(notes)).await;
+        let client = NostrClient::new(
+            vec![relay.url],
+            Arc::new(RelayHealthTracker::new()),
+            PoolOptions::default(),
+        )
+        .await
+        .unwrap();
+        client
+            .client
+            .wait_for_connection(Duration::from_secs(5))
+            .await;
+
+        let start = Instant::now();
+        let results = client
+            .search_events(None, Some(vec![Kind::TextNote]), None, None, None, 3)
+            .await
+            .unwrap();
+        assert_eq!(results.events.len(), 3);
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "waited for timeout"
+        );
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn auth_required_relays_are_not_targeted() {
+        let relays = vec![
+            "ws://127.0.0.1:1".to_string(),
+            "ws://127.0.0.1:2".to_string(),
+        ];
+        let client = NostrClient::new(
+            relays.clone(),
+            Arc::new(RelayHealthTracker::new()),
+            PoolOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.target_relays().await, relays);
+
+        client
+            .set_auth_required(HashSet::from([relays[1].clone()]))
+            .await;
+        assert_eq!(client.target_relays().await, vec![relays[0].clone()]);
+        assert_eq!(client.skipped_relays().await, vec![relays[1].clone()]);
+        client.shutdown().await;
+    }
+
+    #[test]
+    fn dropped_relays_empty_when_all_connected() {
+        let statuses = vec![
+            ("wss://a.example".to_string(), RelayStatus::Connected),
+            ("wss://b.example".to_string(), RelayStatus::Connected),
+        ];
+        assert!(dropped_relays(statuses).is_empty());
+    }
+
+    #[tokio::test]
+    async fn max_connections_caps_the_pool() {
+        let relays = vec![
+            "ws://127.0.0.1:1".to_string(),
+            "ws://127.0.0.1:2".to_string(),
+            "ws://127.0.0.1:3".to_string(),
+        ];
+        let opts = PoolOptions {
+            max_connections: Some(2),
+            ..Default::default()
+        };
+        let client = NostrClient::new(relays.clone(), Arc::new(RelayHealthTracker::new()), opts)
+            .await
+            .unwrap();
+        assert_eq!(client.relay_urls(), &relays[..2]);
+        assert_eq!(client.client.relays().await.len(), 2);
+        client.shutdown().await;
     }
 }
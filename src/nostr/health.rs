@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+/// Number of most recent fetch outcomes used to compute a relay's failure rate.
+const RECENT_WINDOW: usize = 20;
+/// Minimum number of recent outcomes before a relay can be deprioritized.
+const MIN_SAMPLES: usize = 5;
+/// Relays whose recent failure rate exceeds this are skipped when fetching.
+const MAX_FAILURE_RATE: f64 = 0.5;
+/// Smoothing factor for the rolling (exponentially weighted) latency average.
+const LATENCY_ALPHA: f64 = 0.3;
+/// How long a deprioritized relay is skipped before one fetch probes it again.
+const PROBE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default)]
+pub struct RelayHealth {
+    pub successes: u64,
+    pub failures: u64,
+    pub avg_latency_ms: Option<f64>,
+    pub last_error: Option<String>,
+    recent: VecDeque<bool>,
+    /// While deprioritized, when the relay may be probed again
+    retry_at: Option<Instant>,
+}
+
+impl RelayHealth {
+    /// Failure rate over the most recent outcomes (0.0 when nothing recorded yet).
+    pub fn recent_failure_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        let failures = self.recent.iter().filter(|ok| !**ok).count();
+        failures as f64 / self.recent.len() as f64
+    }
+
+    /// Whether this relay should currently be skipped when building fetches. Once
+    /// its retry time passes it is let back in for a probe: a success keeps it in
+    /// rotation, another failure skips it for a further cooldown.
+    pub fn is_deprioritized(&self) -> bool {
+        self.retry_at.is_some_and(|t| Instant::now() < t)
+    }
+
+    fn failing(&self) -> bool {
+        self.recent.len() >= MIN_SAMPLES && self.recent_failure_rate() > MAX_FAILURE_RATE
+    }
+
+    fn push_outcome(&mut self, ok: bool) {
+        if self.recent.len() == RECENT_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ok);
+    }
+}
+
+/// Per-relay success/failure and latency tracking, shared across sessions.
+pub struct RelayHealthTracker {
+    relays: RwLock<HashMap<String, RelayHealth>>,
+    probe_after: Duration,
+}
+
+impl Default for RelayHealthTracker {
+    fn default() -> Self {
+        Self {
+            relays: RwLock::default(),
+            probe_after: PROBE_AFTER,
+        }
+    }
+}
+
+impl RelayHealthTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skip deprioritized relays for `probe_after` before probing them again.
+    pub fn with_probe_after(mut self, probe_after: Duration) -> Self {
+        self.probe_after = probe_after;
+        self
+    }
+
+    pub async fn record_success(&self, relay_url: &str, latency_ms: u64) {
+        let mut relays = self.relays.write().await;
+        let health = relays.entry(relay_url.to_string()).or_default();
+        health.successes += 1;
+        health.push_outcome(true);
+        health.retry_at = None;
+        health.avg_latency_ms = Some(match health.avg_latency_ms {
+            Some(avg) => avg + LATENCY_ALPHA * (latency_ms as f64 - avg),
+            None => latency_ms as f64,
+        });
+    }
+
+    pub async fn record_failure(&self, relay_url: &str, error: &str) {
+        let mut relays = self.relays.write().await;
+        let health = relays.entry(relay_url.to_string()).or_default();
+        health.failures += 1;
+        health.push_outcome(false);
+        health.last_error = Some(error.to_string());
+        if health.failing() {
+            health.retry_at = Some(Instant::now() + self.probe_after);
+        }
+    }
+
+    /// Filter `candidates` down to relays that are not deprioritized.
+    /// Falls back to the full list if every relay is unhealthy, so fetches never target nothing.
+    pub async fn healthy_relays(&self, candidates: &[String]) -> Vec<String> {
+        let relays = self.relays.read().await;
+        let healthy: Vec<String> = candidates
+            .iter()
+            .filter(|url| !relays.get(*url).is_some_and(|h| h.is_deprioritized()))
+            .cloned()
+            .collect();
+
+        if healthy.is_empty() {
+            candidates.to_vec()
+        } else {
+            healthy
+        }
+    }
+
+    /// Snapshot of all tracked relays, sorted by URL.
+    pub async fn snapshot(&self) -> Vec<(String, RelayHealth)> {
+        let relays = self.relays.read().await;
+        let mut out: Vec<(String, RelayHealth)> = relays
+            .iter()
+            .map(|(url, h)| (url.clone(), h.clone()))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failing_relay_is_deprioritized() {
+        let tracker = RelayHealthTracker::new();
+        let relays = vec![
+            "wss://good.example".to_string(),
+            "wss://bad.example".to_string(),
+        ];
+
+        for _ in 0..MIN_SAMPLES {
+            tracker.record_success("wss://good.example", 100).await;
+            tracker.record_failure("wss://bad.example", "timeout").await;
+        }
+
+        let healthy = tracker.healthy_relays(&relays).await;
+        assert_eq!(healthy, vec!["wss://good.example".to_string()]);
+
+        let snapshot = tracker.snapshot().await;
+        let (_, bad) = snapshot
+            .iter()
+            .find(|(url, _)| url == "wss://bad.example")
+            .unwrap();
+        assert!(bad.is_deprioritized());
+        assert_eq!(bad.failures, MIN_SAMPLES as u64);
+        assert_eq!(bad.last_error.as_deref(), Some("timeout"));
+    }
+
+    #[tokio::test]
+    async fn few_failures_do_not_deprioritize() {
+        let tracker = RelayHealthTracker::new();
+        let relays = vec!["wss://flaky.example".to_string()];
+
+        for _ in 0..MIN_SAMPLES - 1 {
+            tracker
+                .record_failure("wss://flaky.example", "timeout")
+                .await;
+        }
+
+        assert_eq!(tracker.healthy_relays(&relays).await, relays);
+    }
+
+    #[tokio::test]
+    async fn relay_recovers_after_successes() {
+        let tracker = RelayHealthTracker::new();
+        let relays = vec!["wss://a.example".to_string(), "wss://b.example".to_string()];
+
+        for _ in 0..MIN_SAMPLES {
+            tracker.record_failure("wss://a.example", "timeout").await;
+        }
+        assert_eq!(tracker.healthy_relays(&relays).await.len(), 1);
+
+        // Push enough successes to drop the recent failure rate under the threshold
+        for _ in 0..RECENT_WINDOW {
+            tracker.record_success("wss://a.example", 50).await;
+        }
+        assert_eq!(tracker.healthy_relays(&relays).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn all_unhealthy_falls_back_to_full_list() {
+        let tracker = RelayHealthTracker::new();
+        let relays = vec!["wss://a.example".to_string()];
+
+        for _ in 0..MIN_SAMPLES {
+            tracker.record_failure("wss://a.example", "timeout").await;
+        }
+
+        assert_eq!(tracker.healthy_relays(&relays).await, relays);
+    }
+
+    #[tokio::test]
+    async fn rolling_latency_average() {
+        let tracker = RelayHealthTracker::new();
+        tracker.record_success("wss://a.example", 100).await;
+        tracker.record_success("wss://a.example", 200).await;
+
+        let snapshot = tracker.snapshot().await;
+        // 100 + 0.3 * (200 - 100) = 130
+        assert_eq!(
+            snapshot[0].1.avg_latency_ms.map(|ms| ms.round()),
+            Some(130.0)
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod cache;
 pub mod client;
+pub mod health;
+pub mod nip05;
 pub mod search;
+pub mod spam;
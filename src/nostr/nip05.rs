@@ -0,0 +1,330 @@
+use std::time::Duration;
+
+/// Request timeout unless `network.nip05_timeout_seconds` says otherwise
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+
+/// Largest `nostr.json` body we will read. Big providers list thousands of
+/// names in one document, but anything past this is not worth buffering.
+pub const MAX_DOCUMENT_BYTES: usize = 256 * 1024;
+
+/// Fetches `.well-known/nostr.json` documents. Slow or flaky domains get one
+/// retry, and one redirect is followed (the HTTP client must not follow any
+/// itself, see `http_client_without_redirects`).
+pub struct Nip05Client {
+    http: reqwest::Client,
+    timeout: Duration,
+    scheme: &'static str,
+}
+
+/// Outcome of a single failed request.
+enum AttemptError {
+    /// Timeouts and 5xx responses
+    Retryable(String),
+    Fatal(String),
+}
+
+impl Nip05Client {
+    pub fn new(http: reqwest::Client, timeout: Duration) -> Self {
+        Self {
+            http,
+            timeout,
+            scheme: "https",
+        }
+    }
+
+    /// Fetch the document for a `user@domain` identifier.
+    /// Returns the name, domain, and parsed JSON body.
+    pub async fn fetch_document(
+        &self,
+        nip05: &str,
+    ) -> Result<(String, String, serde_json::Value), String> {
+        let parts: Vec<&str> = nip05.split('@').collect();
+        if parts.len() != 2 {
+            return Err("Invalid NIP-05 format, expected user@domain".into());
+        }
+        let (name, domain) = (parts[0], parts[1]);
+        let url = format!(
+            "{}://{domain}/.well-known/nostr.json?name={name}",
+            self.scheme
+        );
+
+        let json = match self.fetch_following_redirect(&url).await {
+            Err(AttemptError::Retryable(e)) => {
+                tracing::debug!("NIP-05 lookup for {domain} failed: {e}; retrying once");
+                self.fetch_following_redirect(&url).await
+            }
+            result => result,
+        }
+        .map_err(|(AttemptError::Retryable(e) | AttemptError::Fatal(e))| e)?;
+
+        Ok((name.to_string(), domain.to_string(), json))
+    }
+
+    async fn fetch_following_redirect(&self, url: &str) -> Result<serde_json::Value, AttemptError> {
+        let resp = self.get(url).await?;
+        if !resp.status().is_redirection() {
+            return Self::parse(resp).await;
+        }
+
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| AttemptError::Fatal("Redirect without a Location header".into()))?;
+        let target = resp
+            .url()
+            .join(location)
+            .map_err(|e| AttemptError::Fatal(format!("Invalid redirect target: {e}")))?;
+        if resp.url().scheme() == "https" && target.scheme() != "https" {
+            return Err(AttemptError::Fatal(format!(
+                "Refusing redirect from HTTPS to {target}"
+            )));
+        }
+
+        let resp = self.get(target.as_str()).await?;
+        if resp.status().is_redirection() {
+            return Err(AttemptError::Fatal(
+                "Too many redirects (only one is followed)".into(),
+            ));
+        }
+        Self::parse(resp).await
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response, AttemptError> {
+        self.http
+            .get(url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                let message = format!("HTTP request failed: {e}");
+                if e.is_timeout() {
+                    AttemptError::Retryable(message)
+                } else {
+                    AttemptError::Fatal(message)
+                }
+            })
+    }
+
+    async fn parse(resp: reqwest::Response) -> Result<serde_json::Value, AttemptError> {
+        let status = resp.status();
+        if status.is_server_error() {
+            return Err(AttemptError::Retryable(format!("HTTP error: {status}")));
+        }
+        if !status.is_success() {
+            return Err(AttemptError::Fatal(format!("HTTP error: {status}")));
+        }
+        check_content_type(&resp).map_err(AttemptError::Fatal)?;
+        let body = read_limited(resp, MAX_DOCUMENT_BYTES).await?;
+        serde_json::from_slice(&body)
+            .map_err(|e| AttemptError::Fatal(format!("JSON parse error: {e}")))
+    }
+}
+
+fn check_content_type(resp: &reqwest::Response) -> Result<(), String> {
+    let media_type = media_type(resp);
+    if is_json_media_type(&media_type) {
+        Ok(())
+    } else if media_type.is_empty() {
+        Err("NIP-05 response has no Content-Type, expected JSON".into())
+    } else {
+        Err(format!(
+            "NIP-05 response has Content-Type {media_type}, expected JSON"
+        ))
+    }
+}
+
+/// The response's media type, lowercased and without parameters such as
+/// charset; empty when there is no Content-Type header.
+pub fn media_type(resp: &reqwest::Response) -> String {
+    resp.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// `application/json` or any other `*json` type (`text/json`, `application/nostr+json`)
+pub fn is_json_media_type(media_type: &str) -> bool {
+    media_type.ends_with("json")
+}
+
+/// Read the body, stopping as soon as it grows past `limit` bytes so an
+/// oversized (or endless) response is never buffered in full.
+async fn read_limited(mut resp: reqwest::Response, limit: usize) -> Result<Vec<u8>, AttemptError> {
+    let too_large = || AttemptError::Fatal(format!("NIP-05 response exceeds {limit} bytes"));
+    if resp.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await.map_err(|e| {
+        let message = format!("Failed to read NIP-05 response: {e}");
+        if e.is_timeout() {
+            AttemptError::Retryable(message)
+        } else {
+            AttemptError::Fatal(message)
+        }
+    })? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+impl Nip05Client {
+    /// Talk plain HTTP so tests can point at a local mock domain.
+    pub fn with_plain_http(self) -> Self {
+        Self {
+            scheme: "http",
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Redirect};
+
+    use super::*;
+
+    fn client(timeout: Duration) -> Nip05Client {
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        Nip05Client::new(http, timeout).with_plain_http()
+    }
+
+    async fn serve(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr.to_string()
+    }
+
+    fn document() -> axum::Json<serde_json::Value> {
+        axum::Json(serde_json::json!({ "names": { "bob": "ab".repeat(32) } }))
+    }
+
+    #[tokio::test]
+    async fn follows_one_redirect() {
+        let app = axum::Router::new()
+            .route(
+                "/.well-known/nostr.json",
+                axum::routing::get(|| async { Redirect::permanent("/nostr/bob.json") }),
+            )
+            .route(
+                "/nostr/bob.json",
+                axum::routing::get(|| async { document() }),
+            );
+        let domain = serve(app).await;
+
+        let (name, _, json) = client(Duration::from_secs(5))
+            .fetch_document(&format!("bob@{domain}"))
+            .await
+            .unwrap();
+        assert_eq!(name, "bob");
+        assert_eq!(json["names"]["bob"], "ab".repeat(32));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_second_redirect() {
+        let app = axum::Router::new()
+            .route(
+                "/.well-known/nostr.json",
+                axum::routing::get(|| async { Redirect::temporary("/hop") }),
+            )
+            .route(
+                "/hop",
+                axum::routing::get(|| async { Redirect::temporary("/final") }),
+            );
+        let domain = serve(app).await;
+
+        let err = client(Duration::from_secs(5))
+            .fetch_document(&format!("bob@{domain}"))
+            .await
+            .unwrap_err();
+        assert!(err.contains("redirects"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn retries_once_on_server_error_and_timeout() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&calls);
+        let app = axum::Router::new().route(
+            "/.well-known/nostr.json",
+            axum::routing::get(move || {
+                let counter = Arc::clone(&counter);
+                async move {
+                    match counter.fetch_add(1, Ordering::SeqCst) {
+                        0 => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                        // Second round: time out once, then answer
+                        2 => {
+                            tokio::time::sleep(Duration::from_secs(2)).await;
+                            document().into_response()
+                        }
+                        _ => document().into_response(),
+                    }
+                }
+            }),
+        );
+        let domain = serve(app).await;
+        let client = client(Duration::from_millis(500));
+        let nip05 = format!("bob@{domain}");
+
+        client.fetch_document(&nip05).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        client.fetch_document(&nip05).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_and_non_json_responses() {
+        let app = axum::Router::new().route(
+            "/.well-known/nostr.json",
+            axum::routing::get(
+                |axum::extract::Query(q): axum::extract::Query<
+                    std::collections::HashMap<String, String>,
+                >| async move {
+                    match q.get("name").map(String::as_str) {
+                        Some("huge") => {
+                            let padding = "x".repeat(MAX_DOCUMENT_BYTES);
+                            axum::Json(serde_json::json!({ "names": {}, "padding": padding }))
+                                .into_response()
+                        }
+                        Some("html") => axum::response::Html("<html>{}</html>").into_response(),
+                        _ => document().into_response(),
+                    }
+                },
+            ),
+        );
+        let domain = serve(app).await;
+        let client = client(Duration::from_secs(5));
+
+        let err = client
+            .fetch_document(&format!("huge@{domain}"))
+            .await
+            .unwrap_err();
+        assert!(err.contains("exceeds"), "{err}");
+
+        let err = client
+            .fetch_document(&format!("html@{domain}"))
+            .await
+            .unwrap_err();
+        assert!(err.contains("text/html"), "{err}");
+
+        client
+            .fetch_document(&format!("bob@{domain}"))
+            .await
+            .unwrap();
+    }
+}
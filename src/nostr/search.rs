@@ -1,11 +1,27 @@
+use std::time::Duration;
+
+use nostr_sdk::Event;
 use serde::Deserialize;
 
-const PRIMAL_API_URL: &str = "https://cache1.primal.net/api";
+/// Delay before the first retry; doubles on each further attempt.
+const BASE_BACKOFF: Duration = Duration::from_millis(250);
 
 pub struct ProfileSearchClient {
     http: reqwest::Client,
+    api_url: String,
+    max_attempts: u32,
+    base_backoff: Duration,
+}
+
+/// Outcome of a single failed Primal request.
+enum AttemptError {
+    /// Timeouts, connection errors, and 5xx responses
+    Retryable(String),
+    /// 4xx responses and malformed bodies
+    Fatal(String),
 }
 
+#[derive(Debug)]
 pub struct ProfileSearchHit {
     pub pubkey: String,
     pub name: Option<String>,
@@ -25,6 +41,24 @@ struct PrimalEvent {
     content: Option<String>,
 }
 
+impl ProfileSearchHit {
+    /// Build a hit from a kind:0 metadata event (used by the relay search fallback).
+    pub fn from_metadata_event(event: &Event) -> Option<Self> {
+        let meta: ProfileMetadata = serde_json::from_str(&event.content).ok()?;
+        Some(Self {
+            pubkey: event.pubkey.to_hex(),
+            name: meta.name,
+            display_name: meta.display_name,
+            about: meta.about,
+            picture: meta.picture,
+            nip05: meta.nip05,
+            lud16: meta.lud16,
+            website: meta.website,
+            followers_count: None,
+        })
+    }
+}
+
 #[derive(Deserialize)]
 struct ProfileMetadata {
     name: Option<String>,
@@ -37,12 +71,22 @@ struct ProfileMetadata {
 }
 
 impl ProfileSearchClient {
-    pub fn new() -> Self {
+    /// `base_url` is the Primal cache API endpoint; `max_attempts` is the total number
+    /// of tries per search (at least 1).
+    pub fn new(base_url: &str, max_attempts: u32) -> Self {
         Self {
             http: reqwest::Client::new(),
+            api_url: base_url.to_string(),
+            max_attempts: max_attempts.max(1),
+            base_backoff: BASE_BACKOFF,
         }
     }
 
+    /// Use a shared HTTP client (e.g. one configured with a proxy).
+    pub fn with_http(self, http: reqwest::Client) -> Self {
+        Self { http, ..self }
+    }
+
     pub async fn search_profiles(
         &self,
         query: &str,
@@ -50,23 +94,22 @@ impl ProfileSearchClient {
     ) -> Result<Vec<ProfileSearchHit>, String> {
         let body = serde_json::json!(["user_search", {"query": query, "limit": limit}]);
 
-        let resp = self
-            .http
-            .post(PRIMAL_API_URL)
-            .json(&body)
-            .timeout(std::time::Duration::from_secs(15))
-            .send()
-            .await
-            .map_err(|e| format!("Primal API request failed: {e}"))?;
-
-        if !resp.status().is_success() {
-            return Err(format!("Primal API HTTP error: {}", resp.status()));
-        }
-
-        let events: Vec<PrimalEvent> = resp
-            .json()
-            .await
-            .map_err(|e| format!("Primal API JSON parse error: {e}"))?;
+        let mut attempt = 1;
+        let events = loop {
+            match self.request_once(&body).await {
+                Ok(events) => break events,
+                Err(AttemptError::Retryable(e)) if attempt < self.max_attempts => {
+                    let delay = backoff_with_jitter(self.base_backoff, attempt);
+                    tracing::debug!(
+                        "Primal search attempt {attempt}/{} failed: {e}; retrying in {delay:?}",
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(AttemptError::Retryable(e)) | Err(AttemptError::Fatal(e)) => return Err(e),
+            }
+        };
 
         // Collect kind:0 profiles
         let mut hits: Vec<ProfileSearchHit> = Vec::new();
@@ -121,4 +164,133 @@ impl ProfileSearchClient {
 
         Ok(hits)
     }
+
+    async fn request_once(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Vec<PrimalEvent>, AttemptError> {
+        let resp = self
+            .http
+            .post(&self.api_url)
+            .json(body)
+            .timeout(Duration::from_secs(15))
+            .send()
+            .await
+            .map_err(|e| AttemptError::Retryable(format!("Primal API request failed: {e}")))?;
+
+        let status = resp.status();
+        if status.is_server_error() {
+            return Err(AttemptError::Retryable(format!(
+                "Primal API HTTP error: {status}"
+            )));
+        }
+        if !status.is_success() {
+            return Err(AttemptError::Fatal(format!(
+                "Primal API HTTP error: {status}"
+            )));
+        }
+
+        resp.json()
+            .await
+            .map_err(|e| AttemptError::Fatal(format!("Primal API JSON parse error: {e}")))
+    }
+}
+
+/// Exponential backoff (`base * 2^(attempt-1)`) plus up to 50% jitter.
+fn backoff_with_jitter(base: Duration, attempt: u32) -> Duration {
+    let delay = base * 2u32.pow(attempt.saturating_sub(1).min(6));
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter_ms = (delay.as_millis() as u64 / 2).max(1);
+    delay + Duration::from_millis(nanos as u64 % jitter_ms)
+}
+
+#[cfg(test)]
+impl ProfileSearchClient {
+    /// Shorten retry delays so tests against a local mock run quickly.
+    fn with_backoff(self, base_backoff: Duration) -> Self {
+        Self {
+            base_backoff,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    /// Serve a Primal-like endpoint that returns `status` for the first `failures`
+    /// calls, then one profile. Returns the URL and a shared call counter.
+    async fn mock_primal(failures: u32, status: StatusCode) -> (String, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&calls);
+        let app = axum::Router::new().route(
+            "/api",
+            axum::routing::post(move || {
+                let counter = Arc::clone(&counter);
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                        return Err(status);
+                    }
+                    Ok(axum::Json(serde_json::json!([{
+                        "kind": 0,
+                        "pubkey": "abc",
+                        "content": "{\"name\":\"alice\"}"
+                    }])))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{addr}/api"), calls)
+    }
+
+    #[tokio::test]
+    async fn searches_configured_base_url() {
+        let (url, calls) = mock_primal(0, StatusCode::OK).await;
+        let client = ProfileSearchClient::new(&url, 1);
+
+        let hits = client.search_profiles("alice", 5).await.unwrap();
+        assert_eq!(hits[0].pubkey, "abc");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let (url, calls) = mock_primal(2, StatusCode::SERVICE_UNAVAILABLE).await;
+        let client = ProfileSearchClient::new(&url, 3).with_backoff(Duration::from_millis(5));
+
+        let hits = client.search_profiles("alice", 5).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name.as_deref(), Some("alice"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, calls) = mock_primal(5, StatusCode::BAD_GATEWAY).await;
+        let client = ProfileSearchClient::new(&url, 2).with_backoff(Duration::from_millis(5));
+
+        let err = client.search_profiles("alice", 5).await.unwrap_err();
+        assert!(err.contains("502"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, calls) = mock_primal(5, StatusCode::BAD_REQUEST).await;
+        let client = ProfileSearchClient::new(&url, 3).with_backoff(Duration::from_millis(5));
+
+        assert!(client.search_profiles("alice", 5).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,115 @@
+//! Heuristic bot/spam signal for profiles. Scores run from 0.0 (looks
+//! established) to 1.0 (looks like spam); each signal adds its weight.
+
+/// No NIP-05 identifier: cheap to skip for throwaway accounts
+pub const WEIGHT_NO_NIP05: f32 = 0.25;
+/// Known follower count of zero (unknown counts add nothing)
+pub const WEIGHT_NO_FOLLOWERS: f32 = 0.25;
+/// Scaled by the share of `about` words that are links
+pub const WEIGHT_LINK_DENSITY: f32 = 0.25;
+/// Name or display name matches a pattern from `SPAM_NAME_PATTERNS`
+pub const WEIGHT_SPAM_NAME: f32 = 0.25;
+
+/// Case-insensitive substrings common in spam account names
+pub const SPAM_NAME_PATTERNS: &[&str] = &[
+    "airdrop",
+    "giveaway",
+    "free btc",
+    "free bitcoin",
+    "crypto signals",
+    "investment",
+    "whatsapp",
+    "telegram",
+    "promo",
+    "100x",
+];
+
+/// The profile fields the heuristic looks at.
+pub struct ProfileSignals<'a> {
+    pub name: Option<&'a str>,
+    pub display_name: Option<&'a str>,
+    pub about: Option<&'a str>,
+    pub nip05: Option<&'a str>,
+    pub followers_count: Option<u64>,
+}
+
+/// Weighted spam score in `0.0..=1.0`, rounded to two decimals.
+pub fn profile_spam_score(profile: &ProfileSignals<'_>) -> f32 {
+    let mut score = 0.0;
+    if profile.nip05.is_none_or(|n| n.trim().is_empty()) {
+        score += WEIGHT_NO_NIP05;
+    }
+    if profile.followers_count == Some(0) {
+        score += WEIGHT_NO_FOLLOWERS;
+    }
+    score += WEIGHT_LINK_DENSITY * link_density(profile.about.unwrap_or_default());
+    if [profile.name, profile.display_name]
+        .into_iter()
+        .flatten()
+        .any(has_spam_pattern)
+    {
+        score += WEIGHT_SPAM_NAME;
+    }
+    (score.min(1.0) * 100.0).round() / 100.0
+}
+
+/// Fraction of whitespace-separated words that look like links.
+fn link_density(text: &str) -> f32 {
+    let (mut words, mut links) = (0usize, 0usize);
+    for word in text.split_whitespace() {
+        words += 1;
+        let word = word.to_lowercase();
+        if word.contains("://") || word.starts_with("www.") || word.starts_with("t.me/") {
+            links += 1;
+        }
+    }
+    if words == 0 {
+        0.0
+    } else {
+        links as f32 / words as f32
+    }
+}
+
+fn has_spam_pattern(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SPAM_NAME_PATTERNS.iter().any(|p| name.contains(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obvious_spam_scores_high() {
+        let score = profile_spam_score(&ProfileSignals {
+            name: Some("btc_airdrop_2024"),
+            display_name: Some("FREE BTC Giveaway"),
+            about: Some("claim now https://scam.example t.me/scam www.scam.example"),
+            nip05: None,
+            followers_count: Some(0),
+        });
+        assert!(score >= 0.9, "score {score}");
+    }
+
+    #[test]
+    fn established_profile_scores_low() {
+        let score = profile_spam_score(&ProfileSignals {
+            name: Some("jack"),
+            display_name: Some("jack"),
+            about: Some("no state is the best state. blog at https://example.com"),
+            nip05: Some("jack@example.com"),
+            followers_count: Some(120_000),
+        });
+        assert!(score < 0.1, "score {score}");
+
+        // An unknown follower count isn't treated as zero
+        let unknown = profile_spam_score(&ProfileSignals {
+            name: Some("alice"),
+            display_name: None,
+            about: None,
+            nip05: Some("alice@example.com"),
+            followers_count: None,
+        });
+        assert_eq!(unknown, 0.0);
+    }
+}
@@ -0,0 +1,102 @@
+//! BTC/USD rate used to show a fiat estimate next to sat prices.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+const SATS_PER_BTC: f64 = 100_000_000.0;
+
+/// Periodically refreshed BTC/USD rate. Estimates are omitted until the first
+/// successful fetch and once the last one is older than three refresh intervals.
+pub struct FiatRate {
+    url: String,
+    max_age: Duration,
+    usd_per_btc: RwLock<Option<(f64, Instant)>>,
+}
+
+impl FiatRate {
+    /// `url` must return a JSON object with a numeric `USD` field (the
+    /// mempool.space `/api/v1/prices` format). An empty URL disables estimates.
+    pub fn new(url: &str, refresh_secs: u64) -> Self {
+        Self {
+            url: url.trim().to_string(),
+            max_age: Duration::from_secs(refresh_secs.saturating_mul(3)),
+            usd_per_btc: RwLock::new(None),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.url.is_empty()
+    }
+
+    /// Fetch the current rate and cache it. On failure the previous rate is kept.
+    pub async fn refresh(&self, http: &reqwest::Client) -> anyhow::Result<f64> {
+        let body: serde_json::Value = http
+            .get(&self.url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let rate = body["USD"]
+            .as_f64()
+            .filter(|r| *r > 0.0)
+            .ok_or_else(|| anyhow::anyhow!("price feed response has no positive USD field"))?;
+        *self.usd_per_btc.write().await = Some((rate, Instant::now()));
+        Ok(rate)
+    }
+
+    /// USD value of `sats` rounded to cents, if a fresh rate is available.
+    pub async fn usd_for_sats(&self, sats: u64) -> Option<f64> {
+        let (rate, fetched) = (*self.usd_per_btc.read().await)?;
+        if fetched.elapsed() > self.max_age {
+            return None;
+        }
+        let usd = sats as f64 / SATS_PER_BTC * rate;
+        Some((usd * 100.0).round() / 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn mock_price_feed(body: serde_json::Value) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route(
+            "/api/v1/prices",
+            axum::routing::get(move || {
+                let body = body.clone();
+                async move { axum::Json(body) }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}/api/v1/prices")
+    }
+
+    #[tokio::test]
+    async fn converts_sats_at_fetched_rate() {
+        let url =
+            mock_price_feed(serde_json::json!({ "time": 1, "USD": 50_000, "EUR": 46_000 })).await;
+        let rate = FiatRate::new(&url, 600);
+        assert_eq!(rate.usd_for_sats(2000).await, None);
+
+        assert_eq!(
+            rate.refresh(&reqwest::Client::new()).await.unwrap(),
+            50_000.0
+        );
+        assert_eq!(rate.usd_for_sats(2000).await, Some(1.0));
+        assert_eq!(rate.usd_for_sats(60).await, Some(0.03));
+    }
+
+    #[tokio::test]
+    async fn malformed_feed_leaves_estimate_unavailable() {
+        let url = mock_price_feed(serde_json::json!({ "EUR": 46_000 })).await;
+        let rate = FiatRate::new(&url, 600);
+
+        assert!(rate.refresh(&reqwest::Client::new()).await.is_err());
+        assert_eq!(rate.usd_for_sats(2000).await, None);
+    }
+}
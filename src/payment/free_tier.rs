@@ -41,6 +41,11 @@ impl FreeTierLimiter {
             }
         }
     }
+
+    /// Clear a client's counter for today, returning how many calls it had used.
+    pub async fn reset(&self, client_id: &str) -> anyhow::Result<u32> {
+        self.cache.reset_rate(client_id, current_day()).await
+    }
 }
 
 fn current_day() -> u32 {
@@ -188,7 +188,7 @@ mod tests {
     fn test_verify_preimage() {
         use sha2::Digest;
         let preimage = [0x01_u8; 32];
-        let hash = Sha256::digest(&preimage);
+        let hash = Sha256::digest(preimage);
         let preimage_hex = hex::encode(preimage);
         let hash_hex = hex::encode(hash);
 
@@ -1,5 +1,7 @@
+pub mod fiat;
 pub mod free_tier;
 pub mod l402;
 pub mod nwc_gateway;
+pub mod webhook;
 #[allow(dead_code)]
 pub mod x402;
@@ -1,16 +1,20 @@
-use std::collections::HashMap;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use nostr_sdk::prelude::*;
 use nwc::NWC;
 
-struct PendingInvoice {
-    #[allow(dead_code)]
-    tool_name: String,
-    #[allow(dead_code)]
-    amount_sats: u64,
-    #[allow(dead_code)]
-    expires_at: i64,
+use crate::nostr::cache::Cache;
+
+/// Outcome of looking up a previously issued invoice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// Paid; carries the amount the invoice settled for
+    Settled { amount_sats: u64 },
+    /// Not paid yet, but still payable
+    Unpaid,
+    /// Expired without being paid; the client needs a fresh invoice
+    Expired,
 }
 
 #[allow(dead_code)]
@@ -21,25 +25,34 @@ pub struct InvoiceResponse {
     pub expires_at: Option<i64>,
 }
 
+/// Subset of the NWC `get_info` response useful to operators
+pub struct WalletInfo {
+    pub alias: Option<String>,
+    pub network: Option<String>,
+    /// NWC methods this connection may call, e.g. "make_invoice"
+    pub methods: Vec<String>,
+}
+
 pub struct NwcGateway {
     nwc: NWC,
-    pending_invoices: RwLock<HashMap<String, PendingInvoice>>,
+    /// Wallet pubkey from the connection URI; tags the invoices this connection issued
+    wallet: String,
+    /// Holds pending invoices, so they survive a restart
+    cache: Arc<Cache>,
 }
 
 impl NwcGateway {
-    pub fn new(nwc_url: &str) -> anyhow::Result<Self> {
+    pub fn new(nwc_url: &str, cache: Arc<Cache>) -> anyhow::Result<Self> {
         let uri: NostrWalletConnectURI =
             nwc_url
                 .parse()
                 .map_err(|e: nostr_sdk::prelude::nip47::Error| {
                     anyhow::anyhow!("Failed to parse NWC URI: {e}")
                 })?;
+        let wallet = uri.public_key.to_hex();
         let nwc = NWC::new(uri);
 
-        Ok(Self {
-            nwc,
-            pending_invoices: RwLock::new(HashMap::new()),
-        })
+        Ok(Self { nwc, wallet, cache })
     }
 
     pub async fn create_invoice(
@@ -68,17 +81,21 @@ impl NwcGateway {
 
         let expires_at = response.expires_at.map(|t| t.as_secs() as i64);
 
-        // Track pending invoice
+        // Track pending invoice, falling back to the expiry we asked for
+        let tracked_expiry =
+            expires_at.unwrap_or_else(|| chrono::Utc::now().timestamp() + expiry_secs as i64);
+        if let Err(e) = self
+            .cache
+            .record_pending_invoice(
+                &payment_hash,
+                &self.wallet,
+                tool_name,
+                amount_sats,
+                tracked_expiry,
+            )
+            .await
         {
-            let mut pending = self.pending_invoices.write().await;
-            pending.insert(
-                payment_hash.clone(),
-                PendingInvoice {
-                    tool_name: tool_name.to_string(),
-                    amount_sats,
-                    expires_at: expires_at.unwrap_or(0),
-                },
-            );
+            tracing::warn!("Failed to record pending invoice: {e}");
         }
 
         Ok(InvoiceResponse {
@@ -89,7 +106,11 @@ impl NwcGateway {
         })
     }
 
-    pub async fn verify_payment(&self, payment_hash: &str) -> anyhow::Result<bool> {
+    /// Look up an invoice and classify it. An unsettled invoice counts as expired
+    /// when either the expiry recorded at creation or the wallet's own
+    /// `expires_at`/state says so. Settled and expired invoices are dropped from
+    /// the pending set.
+    pub async fn verify_payment(&self, payment_hash: &str) -> anyhow::Result<PaymentStatus> {
         let request = LookupInvoiceRequest {
             payment_hash: Some(payment_hash.to_string()),
             invoice: None,
@@ -101,13 +122,264 @@ impl NwcGateway {
             .await
             .map_err(|e| anyhow::anyhow!("NWC lookup_invoice failed: {e}"))?;
 
-        let settled = response.settled_at.is_some();
+        if response.settled_at.is_some() {
+            self.forget(payment_hash).await;
+            return Ok(PaymentStatus::Settled {
+                amount_sats: response.amount / 1000,
+            });
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let tracked_expiry = match self.cache.get_pending_invoice(payment_hash).await {
+            Ok(pending) => pending.map(|(_, expires_at)| expires_at),
+            Err(e) => {
+                tracing::warn!("Pending invoice lookup failed: {e}");
+                None
+            }
+        };
+        let wallet_expiry = response.expires_at.map(|t| t.as_secs() as i64);
+        let expired = matches!(response.state, Some(TransactionState::Expired))
+            || tracked_expiry.or(wallet_expiry).is_some_and(|t| t <= now);
+
+        if expired {
+            self.forget(payment_hash).await;
+            return Ok(PaymentStatus::Expired);
+        }
+        Ok(PaymentStatus::Unpaid)
+    }
 
-        if settled {
-            let mut pending = self.pending_invoices.write().await;
-            pending.remove(payment_hash);
+    async fn forget(&self, payment_hash: &str) {
+        if let Err(e) = self.cache.delete_pending_invoice(payment_hash).await {
+            tracing::warn!("Failed to drop pending invoice: {e}");
         }
+    }
+
+    /// Whether this connection's wallet issued the still-pending invoice for `payment_hash`.
+    async fn issued(&self, payment_hash: &str) -> bool {
+        matches!(
+            self.cache.get_pending_invoice(payment_hash).await,
+            Ok(Some((wallet, _))) if wallet == self.wallet
+        )
+    }
+
+    /// Wallet balance in sats.
+    pub async fn get_balance(&self) -> anyhow::Result<u64> {
+        let msats = self
+            .nwc
+            .get_balance()
+            .await
+            .map_err(|e| anyhow::anyhow!("NWC get_balance failed: {e}"))?;
+        Ok(msats / 1000)
+    }
+
+    pub async fn get_info(&self) -> anyhow::Result<WalletInfo> {
+        let info = self
+            .nwc
+            .get_info()
+            .await
+            .map_err(|e| anyhow::anyhow!("NWC get_info failed: {e}"))?;
+        Ok(WalletInfo {
+            alias: info.alias,
+            network: info.network,
+            methods: info.methods.iter().map(|m| m.to_string()).collect(),
+        })
+    }
+}
+
+/// Ordered set of NWC connections. Calls go to healthy connections first (in
+/// configured order) and fail over to the next one on error.
+pub struct NwcPool {
+    gateways: Vec<NwcGateway>,
+    healthy: Vec<AtomicBool>,
+}
+
+impl NwcPool {
+    pub fn new(nwc_urls: &[String], cache: Arc<Cache>) -> anyhow::Result<Self> {
+        if nwc_urls.is_empty() {
+            anyhow::bail!("No NWC URIs configured");
+        }
+        let gateways = nwc_urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| {
+                NwcGateway::new(url, Arc::clone(&cache))
+                    .map_err(|e| anyhow::anyhow!("NWC connection #{}: {e}", i + 1))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let healthy = gateways.iter().map(|_| AtomicBool::new(true)).collect();
+        Ok(Self { gateways, healthy })
+    }
+
+    /// Health of each connection, in configured order.
+    pub fn connection_health(&self) -> Vec<bool> {
+        self.healthy
+            .iter()
+            .map(|h| h.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Connection indices to try: healthy ones first, each group in configured order.
+    fn attempt_order(&self) -> Vec<usize> {
+        let health = self.connection_health();
+        let (mut order, down): (Vec<usize>, Vec<usize>) =
+            (0..self.gateways.len()).partition(|&i| health[i]);
+        order.extend(down);
+        order
+    }
+
+    fn mark(&self, index: usize, healthy: bool) {
+        let was = self.healthy[index].swap(healthy, Ordering::Relaxed);
+        if was && !healthy {
+            tracing::warn!("NWC connection #{} marked unhealthy", index + 1);
+        } else if !was && healthy {
+            tracing::info!("NWC connection #{} recovered", index + 1);
+        }
+    }
+
+    /// Run `call` against each connection in attempt order until one succeeds,
+    /// updating health as it goes. Returns the last error if all fail.
+    async fn with_failover<'a, T, F, Fut>(&'a self, what: &str, call: F) -> anyhow::Result<T>
+    where
+        F: Fn(&'a NwcGateway) -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<T>>,
+    {
+        let mut last_err = None;
+        for i in self.attempt_order() {
+            match call(&self.gateways[i]).await {
+                Ok(value) => {
+                    self.mark(i, true);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    tracing::warn!("NWC connection #{} failed {what}: {e}", i + 1);
+                    self.mark(i, false);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("No NWC connections available")))
+    }
+
+    pub async fn create_invoice(
+        &self,
+        tool_name: &str,
+        amount_sats: u64,
+        description: &str,
+        expiry_secs: u64,
+    ) -> anyhow::Result<InvoiceResponse> {
+        self.with_failover("make_invoice", |gw| {
+            gw.create_invoice(tool_name, amount_sats, description, expiry_secs)
+        })
+        .await
+    }
+
+    /// Invoices can only be looked up on the wallet that issued them, so a hash
+    /// issued by a known connection is checked there alone. Otherwise (e.g. after
+    /// a restart) each connection is asked in turn; those misses say nothing about
+    /// a connection's health, so they don't change it.
+    pub async fn verify_payment(&self, payment_hash: &str) -> anyhow::Result<PaymentStatus> {
+        for i in 0..self.gateways.len() {
+            if self.gateways[i].issued(payment_hash).await {
+                let result = self.gateways[i].verify_payment(payment_hash).await;
+                self.mark(i, result.is_ok());
+                return result;
+            }
+        }
+
+        let mut last_err = None;
+        for i in self.attempt_order() {
+            match self.gateways[i].verify_payment(payment_hash).await {
+                Ok(status) => return Ok(status),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("No NWC connections available")))
+    }
+
+    /// Balance of the first connection that answers, in sats.
+    pub async fn get_balance(&self) -> anyhow::Result<u64> {
+        self.with_failover("get_balance", |gw| gw.get_balance())
+            .await
+    }
+
+    pub async fn get_info(&self) -> anyhow::Result<WalletInfo> {
+        self.with_failover("get_info", |gw| gw.get_info()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_relay::{mock_nwc, MockWallet};
+
+    #[tokio::test]
+    async fn balance_and_info_from_mock_wallet() {
+        let gateway = NwcGateway::new(
+            &mock_nwc(MockWallet {
+                balance_msats: 21_000_000,
+                ..Default::default()
+            })
+            .await
+            .0,
+            Arc::new(Cache::new_in_memory().await),
+        )
+        .unwrap();
+
+        assert_eq!(gateway.get_balance().await.unwrap(), 21_000);
+
+        let info = gateway.get_info().await.unwrap();
+        assert_eq!(info.alias.as_deref(), Some("mock-wallet"));
+        assert_eq!(info.network.as_deref(), Some("regtest"));
+        assert!(info.methods.contains(&"get_balance".to_string()));
+    }
+
+    #[tokio::test]
+    async fn expired_unsettled_invoice_is_reported_and_dropped() {
+        let cache = Arc::new(Cache::new_in_memory().await);
+        let uri = mock_nwc(MockWallet::default()).await.0;
+
+        let inv = NwcGateway::new(&uri, Arc::clone(&cache))
+            .unwrap()
+            .create_invoice("search_events", 10, "test", 600)
+            .await
+            .unwrap();
+
+        // A fresh gateway, as after a restart, still knows the invoice
+        let gateway = NwcGateway::new(&uri, Arc::clone(&cache)).unwrap();
+        assert!(gateway.issued(&inv.payment_hash).await);
+
+        // The lookup carries no expiry of its own; the tracked one decides
+        let status = gateway.verify_payment(&inv.payment_hash).await.unwrap();
+        assert_eq!(status, PaymentStatus::Expired);
+        assert!(cache
+            .get_pending_invoice(&inv.payment_hash)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn pool_fails_over_to_next_connection() {
+        let (down, _) = mock_nwc(MockWallet {
+            failing: true,
+            ..Default::default()
+        })
+        .await;
+        let (up, _) = mock_nwc(MockWallet::default()).await;
+        let pool = NwcPool::new(&[down, up], Arc::new(Cache::new_in_memory().await)).unwrap();
+
+        let inv = pool
+            .create_invoice("search_events", 10, "test", 600)
+            .await
+            .unwrap();
+        assert_eq!(pool.connection_health(), [false, true]);
+        assert!(pool.gateways[1].issued(&inv.payment_hash).await);
 
-        Ok(settled)
+        // Healthy connection is now tried first
+        assert_eq!(pool.attempt_order(), [1, 0]);
+        assert_eq!(
+            pool.verify_payment(&inv.payment_hash).await.unwrap(),
+            PaymentStatus::Expired
+        );
     }
 }
@@ -0,0 +1,112 @@
+//! Settlement callbacks for operators wiring payments into external billing.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const WEBHOOK_ATTEMPTS: u32 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementNotice {
+    pub payment_hash: String,
+    pub tool_name: String,
+    pub amount_sats: u64,
+    /// Unix seconds when the server first saw the invoice settled
+    pub settled_at: i64,
+}
+
+/// POST `notice` to `url` in the background, retrying once on failure.
+pub fn notify(
+    http: reqwest::Client,
+    url: String,
+    notice: SettlementNotice,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        for attempt in 1..=WEBHOOK_ATTEMPTS {
+            match deliver(&http, &url, &notice).await {
+                Ok(()) => return,
+                Err(e) => tracing::warn!(
+                    "Payment webhook for {} failed (attempt {attempt}/{WEBHOOK_ATTEMPTS}): {e}",
+                    notice.payment_hash
+                ),
+            }
+        }
+    })
+}
+
+async fn deliver(
+    http: &reqwest::Client,
+    url: &str,
+    notice: &SettlementNotice,
+) -> reqwest::Result<()> {
+    http.post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(notice)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn posts_notice_and_retries_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let received = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post({
+                let calls = Arc::clone(&calls);
+                let received = Arc::clone(&received);
+                move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                    received.lock().unwrap().push(body);
+                    // Fail the first delivery so the retry is exercised
+                    if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        StatusCode::OK
+                    }
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let notice = SettlementNotice {
+            payment_hash: "ab".repeat(32),
+            tool_name: "search_events".into(),
+            amount_sats: 10,
+            settled_at: 1_700_000_000,
+        };
+        notify(
+            reqwest::Client::new(),
+            format!("http://{addr}/hook"),
+            notice,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let received = received.lock().unwrap();
+        assert_eq!(
+            received[1],
+            serde_json::json!({
+                "payment_hash": "ab".repeat(32),
+                "tool_name": "search_events",
+                "amount_sats": 10,
+                "settled_at": 1_700_000_000,
+            })
+        );
+    }
+}
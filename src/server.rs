@@ -1,78 +1,247 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
+use anyhow::Context as _;
 use nostr_sdk::prelude::*;
 use rmcp::handler::server::router::tool::ToolRouter;
+use rmcp::handler::server::tool::ToolCallContext;
 use rmcp::handler::server::wrapper::Parameters;
-use rmcp::model::{ServerCapabilities, ServerInfo};
-use rmcp::{tool, tool_handler, tool_router, ServerHandler};
-
-use crate::config::Config;
+use rmcp::model::{
+    CallToolRequestParams, CallToolResult, InitializeRequestParams, InitializeResult,
+    ListToolsResult, Meta, PaginatedRequestParams, ProgressNotificationParam, ProgressToken,
+    ServerCapabilities, ServerInfo, Tool,
+};
+use rmcp::service::RequestContext;
+use rmcp::{tool, tool_router, ErrorData, Peer, RoleServer, ServerHandler};
+use sha2::{Digest, Sha256};
+
+use crate::call_log::{short_id, PhaseTimings, ToolCallLog};
+use crate::config::{CacheBackend, Config, FreeTierIdentity, NetworkConfig};
+use crate::ip_limit::IpRateLimiter;
+use crate::metrics::Metrics;
 use crate::nostr::cache::{Cache, CachedProfile, CachedRelayInfo};
-use crate::nostr::client::NostrClient;
-use crate::nostr::search::ProfileSearchClient;
+use crate::nostr::client::{NostrClient, PoolOptions, RelayResults};
+use crate::nostr::health::RelayHealthTracker;
+use crate::nostr::nip05::{self, Nip05Client};
+use crate::nostr::search::{ProfileSearchClient, ProfileSearchHit};
+use crate::nostr::spam::{profile_spam_score, ProfileSignals};
+use crate::payment::fiat::FiatRate;
 use crate::payment::free_tier::FreeTierLimiter;
-use crate::payment::nwc_gateway::NwcGateway;
+use crate::payment::nwc_gateway::{NwcPool, PaymentStatus};
+use crate::payment::webhook::{self, SettlementNotice};
+use crate::single_flight::SingleFlight;
+use crate::tools::admin::*;
 use crate::tools::free::*;
 use crate::tools::paid::*;
 
 pub struct NostrIntelServer {
     config: Arc<Config>,
     nostr_client: Arc<NostrClient>,
+    relay_health: Arc<RelayHealthTracker>,
     cache: Arc<Cache>,
     search_client: Arc<ProfileSearchClient>,
-    nwc_gateway: Option<Arc<NwcGateway>>,
+    nwc_gateway: Option<Arc<NwcPool>>,
     rate_limiter: Arc<FreeTierLimiter>,
+    metrics: Arc<Metrics>,
+    /// Shared HTTP client for NIP-11 and LNURL lookups
+    http: reqwest::Client,
+    nip05: Arc<Nip05Client>,
+    fiat: Arc<FiatRate>,
+    /// Per-IP HTTP request limiter (`server.ip_requests_per_minute`)
+    ip_limiter: Option<Arc<IpRateLimiter>>,
+    profile_flights: Arc<SingleFlight<Result<Option<Metadata>, String>>>,
+    relay_info_flights: Arc<SingleFlight<Result<CheckRelayResponse, String>>>,
     session_id: String,
+    /// Stable client ID derived from an `X-API-Key` header, set once at initialize (HTTP only)
+    api_key_session: OnceLock<String>,
+    /// Set at initialize when a configured `X-Admin-Key` was sent; unlocks operator tools
+    admin: OnceLock<()>,
     tool_router: ToolRouter<Self>,
 }
 
+/// Sent to clients at initialize unless `server.instructions` overrides it
+const DEFAULT_INSTRUCTIONS: &str =
+    "Nostr intelligence server. Provides tools to decode Nostr entities, \
+     resolve and verify NIP-05 identifiers, fetch profiles, search profiles by name, \
+     check relay status and relay health, and search events. Paid tools require Lightning \
+     payment after free tier (10 calls/day) is exhausted.";
+
 enum PaymentGateResult {
-    Proceed,
+    /// Run the tool. `free_tier_remaining` is set when the call was served from the free tier.
+    Proceed {
+        free_tier_remaining: Option<u32>,
+    },
     EarlyReturn(String),
 }
 
-#[tool_handler(router = self.tool_router)]
+/// Structured error returned when a tool call exceeds `server.request_timeout_seconds`
+#[derive(Debug, serde::Serialize)]
+struct ToolTimeout {
+    error: &'static str,
+    tool: String,
+    timeout_seconds: u64,
+}
+
+// Tool routing is spelled out rather than generated by `#[tool_handler]` so that
+// `call_tool` can bound each call with the request timeout.
 impl ServerHandler for NostrIntelServer {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let tool = request.name.to_string();
+        let call = ToolCallContext::new(self, request, context);
+        match self
+            .with_request_timeout(&tool, self.tool_router.call(call))
+            .await
+        {
+            Ok(result) => result,
+            Err(timeout) => Ok(CallToolResult::structured_error(
+                serde_json::to_value(timeout).unwrap_or_default(),
+            )),
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult {
+            tools: self.tool_router.list_all(),
+            meta: None,
+            next_cursor: None,
+        })
+    }
+
+    fn get_tool(&self, name: &str) -> Option<Tool> {
+        self.tool_router.get(name).cloned()
+    }
+
     fn get_info(&self) -> ServerInfo {
+        let instructions = self
+            .config
+            .server
+            .instructions
+            .as_deref()
+            .unwrap_or(DEFAULT_INSTRUCTIONS);
         ServerInfo {
-            instructions: Some(
-                "Nostr intelligence server. Provides tools to decode Nostr entities, \
-                 resolve NIP-05 identifiers, fetch profiles, search profiles by name, \
-                 check relay status, and search events. Paid tools require Lightning \
-                 payment after free tier (10 calls/day) is exhausted."
-                    .into(),
-            ),
+            instructions: Some(instructions.into()),
             capabilities: ServerCapabilities::builder().enable_tools().build(),
             ..Default::default()
         }
     }
+
+    async fn initialize(
+        &self,
+        request: InitializeRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, ErrorData> {
+        if context.peer.peer_info().is_none() {
+            context.peer.set_peer_info(request);
+        }
+
+        // Over HTTP, the raw request parts are injected into the context extensions
+        if let Some(parts) = context.extensions.get::<axum::http::request::Parts>() {
+            let api_key = parts.headers.get("x-api-key").and_then(|v| v.to_str().ok());
+            self.bind_api_key(api_key)
+                .map_err(|e| ErrorData::invalid_request(e, None))?;
+            let admin_key = parts
+                .headers
+                .get("x-admin-key")
+                .and_then(|v| v.to_str().ok());
+            self.bind_admin_key(admin_key)
+                .map_err(|e| ErrorData::invalid_request(e, None))?;
+        }
+
+        Ok(self.get_info())
+    }
 }
 
 #[tool_router(router = tool_router)]
 impl NostrIntelServer {
     pub async fn new(config: Config) -> anyhow::Result<Self> {
         let config = Arc::new(config);
+        let tool_router = Self::configured_tool_router(&config)?;
+
+        let cache = match config.cache.backend {
+            CacheBackend::File => {
+                Cache::new(
+                    &config.cache.database_path,
+                    config.cache.profile_ttl_seconds,
+                    config.cache.relay_info_ttl_seconds,
+                    std::time::Duration::from_millis(config.cache.busy_timeout_ms),
+                )
+                .await?
+            }
+            CacheBackend::Memory => {
+                tracing::info!("Using in-memory cache (contents are lost on restart)");
+                Cache::new_memory(
+                    config.cache.profile_ttl_seconds,
+                    config.cache.relay_info_ttl_seconds,
+                )
+                .await?
+            }
+        };
+        let cache = Arc::new(
+            cache
+                .with_profile_lru(config.cache.profile_lru_size)
+                .with_list_ttl(config.cache.list_ttl_seconds)
+                .with_event_ttl(config.cache.event_ttl_seconds),
+        );
 
-        let cache = Cache::new(
-            &config.cache.database_path,
-            config.cache.profile_ttl_seconds,
-            config.cache.relay_info_ttl_seconds,
+        let socks_proxy = config.network.socks_proxy_addr()?;
+        if let Some(proxy) = socks_proxy {
+            tracing::info!("Routing relay and HTTP traffic through SOCKS5 proxy {proxy}");
+        }
+        let http = http_client(&config.network)?;
+        let nip05 = Arc::new(Nip05Client::new(
+            http_client_without_redirects(&config.network)?,
+            std::time::Duration::from_secs(config.network.nip05_timeout_seconds),
+        ));
+
+        let relay_health = Arc::new(RelayHealthTracker::new().with_probe_after(
+            std::time::Duration::from_secs(config.relays.unhealthy_retry_seconds),
+        ));
+        let relays = config.relays.permitted_defaults();
+        if relays.len() < config.relays.default.len() {
+            tracing::info!(
+                "relays.allowlist keeps {} of {} default relays",
+                relays.len(),
+                config.relays.default.len()
+            );
+        }
+        let nostr_client = NostrClient::new(
+            relays,
+            Arc::clone(&relay_health),
+            PoolOptions {
+                max_connections: config.relays.max_concurrent_connections,
+                socks_proxy,
+            },
         )
-        .await?;
-        let cache = Arc::new(cache);
-
-        let nostr_client = NostrClient::new(config.relays.default.clone()).await?;
+        .await?
+        .with_cache(Arc::clone(&cache));
         let nostr_client = Arc::new(nostr_client);
 
-        let search_client = Arc::new(ProfileSearchClient::new());
+        let search_client = Arc::new(
+            ProfileSearchClient::new(
+                &config.search.primal_api_url,
+                config.search.primal_max_attempts,
+            )
+            .with_http(http.clone()),
+        );
 
         let rate_limiter = Arc::new(FreeTierLimiter::new(Arc::clone(&cache)));
 
-        let nwc_gateway = if !config.payment.nwc_url.is_empty() {
-            match NwcGateway::new(&config.payment.nwc_url) {
+        let nwc_gateway = if !config.payment.nwc_urls.is_empty() {
+            match NwcPool::new(&config.payment.nwc_urls, Arc::clone(&cache)) {
                 Ok(gw) => {
-                    tracing::info!("NWC gateway initialized");
+                    tracing::info!(
+                        "NWC gateway initialized ({} connection(s))",
+                        config.payment.nwc_urls.len()
+                    );
                     Some(Arc::new(gw))
                 }
                 Err(e) => {
@@ -85,18 +254,85 @@ impl NostrIntelServer {
             None
         };
 
+        let fiat = Arc::new(FiatRate::new(
+            &config.payment.price_api_url,
+            config.payment.price_refresh_seconds,
+        ));
+        let ip_limiter = (config.server.ip_requests_per_minute > 0).then(|| {
+            Arc::new(IpRateLimiter::new(
+                config.server.ip_requests_per_minute,
+                config.server.trust_x_forwarded_for,
+            ))
+        });
+
+        let session_id = session_identity(&config, "stdio");
         Ok(Self {
             config,
             nostr_client,
+            relay_health,
             cache,
             search_client,
             nwc_gateway,
             rate_limiter,
-            session_id: "stdio".into(),
-            tool_router: Self::tool_router(),
+            metrics: Arc::new(Metrics::new()),
+            http,
+            nip05,
+            fiat,
+            ip_limiter,
+            profile_flights: Arc::new(SingleFlight::new()),
+            relay_info_flights: Arc::new(SingleFlight::new()),
+            session_id,
+            api_key_session: OnceLock::new(),
+            admin: OnceLock::new(),
+            tool_router,
         })
     }
 
+    /// Run a tool call, giving up after `server.request_timeout_seconds`. Dropping
+    /// the call on timeout cancels its in-flight relay fetches.
+    async fn with_request_timeout<T>(
+        &self,
+        tool: &str,
+        call: impl std::future::Future<Output = T>,
+    ) -> Result<T, ToolTimeout> {
+        let timeout_seconds = self.config.server.request_timeout_seconds;
+        tokio::time::timeout(std::time::Duration::from_secs(timeout_seconds), call)
+            .await
+            .map_err(|_| {
+                tracing::warn!("{tool} timed out after {timeout_seconds}s");
+                ToolTimeout {
+                    error: "request_timeout",
+                    tool: tool.to_string(),
+                    timeout_seconds,
+                }
+            })
+    }
+
+    /// The tool router without `tools.disabled` and with any
+    /// `server.tool_descriptions` overrides applied.
+    fn configured_tool_router(config: &Config) -> anyhow::Result<ToolRouter<Self>> {
+        let mut router = Self::tool_router();
+        for name in &config.tools.disabled {
+            if !router.has_route(name) {
+                anyhow::bail!("tools.disabled: unknown tool '{name}'");
+            }
+            router.remove_route(name);
+        }
+        if !config.tools.disabled.is_empty() {
+            tracing::info!(
+                "Tools disabled by operator: {}",
+                config.tools.disabled.join(", ")
+            );
+        }
+        for (name, description) in &config.server.tool_descriptions {
+            let route = router.map.get_mut(name.as_str()).ok_or_else(|| {
+                anyhow::anyhow!("server.tool_descriptions: unknown tool '{name}'")
+            })?;
+            route.attr.description = Some(description.clone().into());
+        }
+        Ok(router)
+    }
+
     // ==================== Free tools ====================
 
     #[tool(
@@ -107,10 +343,61 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<DecodeNostrUriParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("decode_nostr_uri");
+        call.input(&format!("uri={}", short_id(params.uri.trim())));
         let response = decode_nostr_uri_inner(&params.uri)?;
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
+    #[tool(
+        name = "decode_nostr_uris",
+        description = "Decode up to 100 Nostr bech32 entities (npub, note, nprofile, nevent, naddr) in one call, e.g. a tag list; bad entries are reported in `errors` without failing the rest"
+    )]
+    async fn decode_nostr_uris(
+        &self,
+        Parameters(params): Parameters<DecodeNostrUrisParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("decode_nostr_uris");
+        call.input(&format!("uris={}", params.uris.len()));
+        if params.uris.is_empty() {
+            return Err("Provide at least one URI".into());
+        }
+        if params.uris.len() > MAX_BATCH_DECODE {
+            return Err(format!("At most {MAX_BATCH_DECODE} URIs per call"));
+        }
+
+        let mut response = DecodeNostrUrisResponse {
+            decoded: Vec::new(),
+            errors: Vec::new(),
+        };
+        for (index, uri) in params.uris.into_iter().enumerate() {
+            let index = index as u32;
+            match decode_nostr_uri_inner(&uri) {
+                Ok(entity) => response
+                    .decoded
+                    .push(DecodedNostrUri { index, uri, entity }),
+                Err(error) => response
+                    .errors
+                    .push(DecodeNostrUriError { index, uri, error }),
+            }
+        }
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    #[tool(
+        name = "build_share_link",
+        description = "Build a shareable NIP-19 nevent (and naddr for addressable events) from an event ID with optional author, kind, and relay hints, plus njump.me web links"
+    )]
+    async fn build_share_link(
+        &self,
+        Parameters(params): Parameters<BuildShareLinkParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("build_share_link");
+        call.input(&format!("event_id={}", short_id(params.event_id.trim())));
+        let response = build_share_link_inner(&params)?;
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
     #[tool(
         name = "resolve_nip05",
         description = "Resolve a NIP-05 identifier (user@domain.com) to a Nostr pubkey and relay list"
@@ -119,37 +406,14 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<ResolveNip05Params>,
     ) -> Result<String, String> {
+        let call = self.begin_call("resolve_nip05");
         let nip05 = params.nip05.trim();
+        call.input(&format!("nip05={nip05}"));
 
-        let parts: Vec<&str> = nip05.split('@').collect();
-        if parts.len() != 2 {
-            return Err("Invalid NIP-05 format, expected user@domain".into());
-        }
-        let (name, domain) = (parts[0], parts[1]);
-
-        let url = format!("https://{domain}/.well-known/nostr.json?name={name}");
-
-        let http = reqwest::Client::new();
-        let resp = http
-            .get(&url)
-            .timeout(std::time::Duration::from_secs(10))
-            .send()
-            .await
-            .map_err(|e| format!("HTTP request failed: {e}"))?;
-
-        if !resp.status().is_success() {
-            return Err(format!("HTTP error: {}", resp.status()));
-        }
-
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| format!("JSON parse error: {e}"))?;
+        let (name, domain, json) = self.nip05.fetch_document(nip05).await?;
 
-        let pubkey_hex = json["names"][name]
-            .as_str()
-            .ok_or_else(|| format!("NIP-05 name '{name}' not found at {domain}"))?
-            .to_string();
+        let pubkey_hex = nip05_names_entry(&json, &name)
+            .ok_or_else(|| format!("NIP-05 name '{name}' not found at {domain}"))?;
 
         let pubkey = PublicKey::from_hex(&pubkey_hex)
             .map_err(|e| format!("Invalid pubkey in response: {e}"))?;
@@ -169,6 +433,78 @@ impl NostrIntelServer {
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
+    #[tool(
+        name = "verify_nip05",
+        description = "Check whether a NIP-05 identifier (user@domain.com) actually belongs to a given pubkey (hex or npub)"
+    )]
+    async fn verify_nip05(
+        &self,
+        Parameters(params): Parameters<VerifyNip05Params>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("verify_nip05");
+        let nip05 = params.nip05.trim();
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        call.input(&format!(
+            "nip05={nip05} pubkey={}",
+            short_id(&pubkey.to_hex())
+        ));
+
+        let (name, _domain, json) = self.nip05.fetch_document(nip05).await?;
+        let response = verify_nip05_inner(&json, &name, nip05, &pubkey.to_hex());
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    #[tool(
+        name = "resolve_lightning_address",
+        description = "Resolve a lightning address (LUD-16, user@domain.com) to its LNURL-pay metadata: callback URL, min/max sendable, and whether it supports Nostr zaps"
+    )]
+    async fn resolve_lightning_address(
+        &self,
+        Parameters(params): Parameters<ResolveLightningAddressParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("resolve_lightning_address");
+        let address = params.address.trim();
+        call.input(&format!("address={address}"));
+
+        let url = lnurlp_url(address)?;
+        let response = fetch_lnurl_pay(&self.http, address, &url).await?;
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    #[tool(
+        name = "convert_key",
+        description = "Convert a pubkey between hex and npub. Accepts hex, npub, or NIP-05 (user@domain.com) and returns both forms plus the detected input format"
+    )]
+    async fn convert_key(
+        &self,
+        Parameters(params): Parameters<ConvertKeyParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("convert_key");
+        let input = params.pubkey.trim();
+        call.input(&format!("pubkey={}", short_id(input)));
+
+        let response = if input.contains('@') {
+            let nip05_params = ResolveNip05Params {
+                nip05: input.to_string(),
+            };
+            let result_json = self.resolve_nip05(Parameters(nip05_params)).await?;
+            let result: ResolveNip05Response =
+                serde_json::from_str(&result_json).map_err(|e| e.to_string())?;
+            ConvertKeyResponse {
+                hex: result.pubkey,
+                npub: result.pubkey_npub,
+                input_format: "nip05".into(),
+            }
+        } else {
+            convert_key_inner(input)?
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
     #[tool(
         name = "get_profile",
         description = "Fetch Nostr profile metadata (kind:0) for a given pubkey. Accepts hex, npub, NIP-05 identifier, or display name (fuzzy search via Primal)."
@@ -177,8 +513,41 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<GetProfileParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("get_profile");
         let input = params.pubkey.trim();
 
+        if params.cache_only.unwrap_or(false) {
+            let pubkey = NostrClient::parse_pubkey(input).map_err(|_| {
+                "cache_only requires a hex or npub pubkey (NIP-05 and name lookups go over the network)"
+                    .to_string()
+            })?;
+            let pubkey_hex = pubkey.to_hex();
+            call.input(&format!("pubkey={} cache_only", short_id(&pubkey_hex)));
+            let cached = self
+                .cache
+                .get_profile(&pubkey_hex)
+                .await
+                .map_err(|e| format!("Cache lookup failed: {e}"))?;
+            call.cache(cached.is_some());
+            let response = match cached {
+                Some(profile) => GetProfileResponse {
+                    in_cache: Some(true),
+                    ..profile_response(profile, None)
+                },
+                None => GetProfileResponse {
+                    in_cache: Some(false),
+                    ..profile_response(
+                        CachedProfile {
+                            pubkey: pubkey_hex,
+                            ..Default::default()
+                        },
+                        None,
+                    )
+                },
+            };
+            return serde_json::to_string_pretty(&response).map_err(|e| e.to_string());
+        }
+
         let (pubkey, matched_by) = if input.contains('@') {
             let nip05_params = ResolveNip05Params {
                 nip05: input.to_string(),
@@ -222,66 +591,64 @@ impl NostrIntelServer {
         };
 
         let pubkey_hex = pubkey.to_hex();
+        call.input(&format!("pubkey={}", short_id(&pubkey_hex)));
+
+        let (profile, cache_hit) = self.load_profile(&pubkey).await?;
+        call.cache(cache_hit);
+        if !cache_hit {
+            call.relays(self.nostr_client.active_relay_count().await);
+        }
+        let profile =
+            profile.ok_or_else(|| format!("Profile not found for pubkey: {pubkey_hex}"))?;
+
+        let response = profile_response(profile, matched_by);
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-        // Check cache
+    /// Cache-first kind:0 lookup. Returns the profile (if the pubkey has one) and
+    /// whether it was served from the cache; relay results are cached.
+    async fn load_profile(
+        &self,
+        pubkey: &PublicKey,
+    ) -> Result<(Option<CachedProfile>, bool), String> {
+        let pubkey_hex = pubkey.to_hex();
         if let Ok(Some(cached)) = self.cache.get_profile(&pubkey_hex).await {
             tracing::debug!("Cache hit for profile: {pubkey_hex}");
-            let response = GetProfileResponse {
-                pubkey: pubkey_hex,
-                name: cached.name,
-                display_name: cached.display_name,
-                about: cached.about,
-                picture: cached.picture,
-                banner: cached.banner,
-                nip05: cached.nip05,
-                lud16: cached.lud16,
-                website: cached.website,
-                matched_by,
-            };
-            return serde_json::to_string_pretty(&response).map_err(|e| e.to_string());
+            Metrics::inc(&self.metrics.cache_hits);
+            return Ok((Some(cached), true));
         }
 
-        // Fetch from relays
+        Metrics::inc(&self.metrics.cache_misses);
         tracing::debug!("Fetching profile from relays: {pubkey_hex}");
+        // Concurrent lookups of the same uncached profile share one relay fetch
         let metadata = self
-            .nostr_client
-            .get_metadata(&pubkey)
-            .await
-            .map_err(|e| format!("Failed to fetch metadata: {e}"))?;
-
-        match metadata {
-            Some(meta) => {
-                let cached = CachedProfile {
-                    pubkey: pubkey_hex.clone(),
-                    name: meta.name.clone(),
-                    display_name: meta.display_name.clone(),
-                    about: meta.about.clone(),
-                    picture: meta.picture.clone(),
-                    banner: meta.banner.clone(),
-                    nip05: meta.nip05.clone(),
-                    lud16: meta.lud16.clone(),
-                    website: meta.website.clone(),
-                };
-                if let Err(e) = self.cache.set_profile(&cached).await {
-                    tracing::warn!("Failed to cache profile: {e}");
-                }
+            .profile_flights
+            .run(&pubkey_hex, || async {
+                self.nostr_client
+                    .get_metadata(pubkey)
+                    .await
+                    .map_err(|e| format!("Failed to fetch metadata: {e}"))
+            })
+            .await?;
+        let Some(meta) = metadata else {
+            return Ok((None, false));
+        };
 
-                let response = GetProfileResponse {
-                    pubkey: pubkey_hex,
-                    name: meta.name,
-                    display_name: meta.display_name,
-                    about: meta.about,
-                    picture: meta.picture,
-                    banner: meta.banner,
-                    nip05: meta.nip05,
-                    lud16: meta.lud16,
-                    website: meta.website,
-                    matched_by,
-                };
-                serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
-            }
-            None => Err(format!("Profile not found for pubkey: {pubkey_hex}")),
+        let cached = CachedProfile {
+            pubkey: pubkey_hex,
+            name: meta.name,
+            display_name: meta.display_name,
+            about: meta.about,
+            picture: meta.picture,
+            banner: meta.banner,
+            nip05: meta.nip05,
+            lud16: meta.lud16,
+            website: meta.website,
+        };
+        if let Err(e) = self.cache.set_profile(&cached).await {
+            tracing::warn!("Failed to cache profile: {e}");
         }
+        Ok((Some(cached), false))
     }
 
     #[tool(
@@ -292,131 +659,121 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<CheckRelayParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("check_relay");
         let relay_url = params.relay_url.trim();
-
-        // Check cache
-        if let Ok(Some(cached)) = self.cache.get_relay_info(relay_url).await {
-            tracing::debug!("Cache hit for relay: {relay_url}");
-            let response = CheckRelayResponse {
-                online: cached.online,
-                latency_ms: cached.latency_ms.map(|ms| ms as u64),
-                name: cached.name,
-                description: cached.description,
-                supported_nips: Some(cached.supported_nips),
-                software: cached.software,
-                version: cached.version,
-            };
-            return serde_json::to_string_pretty(&response).map_err(|e| e.to_string());
+        call.input(&format!("relay_url={relay_url}"));
+        if !self.config.relays.check_policy(relay_url)? {
+            return Err(format!("Relay {relay_url} is not on the relay allowlist"));
         }
 
-        // Convert wss:// to https:// for NIP-11 fetch
-        let http_url = relay_url
-            .replace("wss://", "https://")
-            .replace("ws://", "http://");
-
-        let http = reqwest::Client::new();
-        let start = std::time::Instant::now();
+        let (response, cache_hit) = self.check_relay_inner(relay_url).await?;
+        call.cache(cache_hit);
 
-        let result = http
-            .get(&http_url)
-            .header("Accept", "application/nostr+json")
-            .timeout(std::time::Duration::from_secs(10))
-            .send()
-            .await;
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-        match result {
-            Ok(resp) if resp.status().is_success() => {
-                let latency_ms = start.elapsed().as_millis() as u64;
+    #[tool(
+        name = "check_relays",
+        description = "Check up to 20 Nostr relays concurrently: online status, latency, and NIP-11 info for each"
+    )]
+    async fn check_relays(
+        &self,
+        Parameters(params): Parameters<CheckRelaysParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("check_relays");
+        let mut relay_urls: Vec<String> = Vec::new();
+        let mut requested = 0;
+        for url in &params.relay_urls {
+            let url = url.trim().to_string();
+            if !url.is_empty() && !relay_urls.contains(&url) {
+                requested += 1;
+                if self.config.relays.check_policy(&url)? {
+                    relay_urls.push(url);
+                }
+            }
+        }
+        call.input(&format!("relays={requested}"));
+        if requested == 0 {
+            return Err("Provide at least one relay URL".into());
+        }
+        if relay_urls.is_empty() {
+            return Err("None of the relays are on the relay allowlist".into());
+        }
+        if relay_urls.len() > MAX_BATCH_RELAYS {
+            return Err(format!(
+                "Too many relays: {} (max {MAX_BATCH_RELAYS})",
+                relay_urls.len()
+            ));
+        }
+        call.relays(relay_urls.len());
 
-                let json: serde_json::Value = resp
-                    .json()
-                    .await
-                    .map_err(|e| format!("Failed to parse NIP-11: {e}"))?;
+        let results =
+            futures::future::join_all(relay_urls.iter().map(|url| self.check_relay_inner(url)))
+                .await;
 
-                let supported_nips = json["supported_nips"]
-                    .as_array()
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_u64().map(|n| n as u32))
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
+        let relays: Vec<CheckRelaysEntry> = relay_urls
+            .into_iter()
+            .zip(results)
+            .map(|(relay_url, result)| {
+                let status = result
+                    .map(|(status, _)| status)
+                    .unwrap_or_else(CheckRelayResponse::offline);
+                CheckRelaysEntry { relay_url, status }
+            })
+            .collect();
 
-                let name = json["name"].as_str().map(String::from);
-                let description = json["description"].as_str().map(String::from);
-                let software = json["software"].as_str().map(String::from);
-                let version = json["version"].as_str().map(String::from);
-
-                // Cache
-                let cached = CachedRelayInfo {
-                    relay_url: relay_url.to_string(),
-                    name: name.clone(),
-                    description: description.clone(),
-                    supported_nips: supported_nips.clone(),
-                    software: software.clone(),
-                    version: version.clone(),
-                    online: true,
-                    latency_ms: Some(latency_ms as i64),
-                };
-                if let Err(e) = self.cache.set_relay_info(&cached).await {
-                    tracing::warn!("Failed to cache relay info: {e}");
-                }
+        let online_count = relays.iter().filter(|r| r.status.online).count() as u32;
+        let response = CheckRelaysResponse {
+            count: relays.len() as u32,
+            online_count,
+            relays,
+        };
 
-                let response = CheckRelayResponse {
-                    online: true,
-                    latency_ms: Some(latency_ms),
-                    name,
-                    description,
-                    supported_nips: Some(supported_nips),
-                    software,
-                    version,
-                };
-                serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
-            }
-            Ok(resp) => {
-                let response = CheckRelayResponse {
-                    online: false,
-                    latency_ms: None,
-                    name: None,
-                    description: Some(format!("HTTP error: {}", resp.status())),
-                    supported_nips: None,
-                    software: None,
-                    version: None,
-                };
-                serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
-            }
-            Err(e) => {
-                let response = CheckRelayResponse {
-                    online: false,
-                    latency_ms: None,
-                    name: None,
-                    description: Some(format!("Connection failed: {e}")),
-                    supported_nips: None,
-                    software: None,
-                    version: None,
-                };
-                serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
-            }
-        }
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
     #[tool(
         name = "search_profiles",
-        description = "Search Nostr profiles by name or keyword using Primal's cache. Returns matching profiles with metadata and follower counts."
+        description = "Search Nostr profiles by name or keyword using Primal's cache, falling back to a NIP-50 relay search if Primal is unavailable. Returns matching profiles with metadata and follower counts."
     )]
     async fn search_profiles(
         &self,
         Parameters(params): Parameters<SearchProfilesParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("search_profiles");
         let query = params.query.trim();
+        call.input(&format!("query={query}"));
         if query.is_empty() {
             return Err("Search query cannot be empty".into());
         }
 
-        let limit = params.limit.unwrap_or(5).min(20);
+        let limit = self.config.limits.search_profiles.resolve(params.limit);
+        let mut timing = PhaseTimings::new(params.include_timing.unwrap_or(false));
 
-        let hits = self.search_client.search_profiles(query, limit).await?;
+        // Fall back to a NIP-50 relay search when Primal fails or finds nothing
+        let primal = timing
+            .time("primal", self.search_client.search_profiles(query, limit))
+            .await;
+        let relay = if primal.as_ref().map_or(true, |hits| hits.is_empty()) {
+            if let Err(e) = &primal {
+                tracing::warn!("Primal search failed, falling back to relay search: {e}");
+            }
+            call.relays(self.nostr_client.active_relay_count().await);
+            Some(
+                timing
+                    .time(
+                        "relay_fetch",
+                        self.nostr_client.search_metadata(query, limit),
+                    )
+                    .await
+                    .map_err(|e| format!("Relay search failed: {e}")),
+            )
+        } else {
+            None
+        };
+        let (hits, source) = combine_profile_search(primal, relay, limit as usize)?;
 
+        let score_spam = params.score_spam.unwrap_or(false);
         let mut profiles = Vec::new();
         for hit in &hits {
             let npub = match PublicKey::from_hex(&hit.pubkey) {
@@ -436,7 +793,7 @@ impl NostrIntelServer {
                 lud16: hit.lud16.clone(),
                 website: hit.website.clone(),
             };
-            if let Err(e) = self.cache.set_profile(&cached).await {
+            if let Err(e) = timing.time("cache", self.cache.set_profile(&cached)).await {
                 tracing::warn!("Failed to cache search result: {e}");
             }
 
@@ -451,6 +808,15 @@ impl NostrIntelServer {
                 lud16: hit.lud16.clone(),
                 website: hit.website.clone(),
                 followers_count: hit.followers_count,
+                spam_score: score_spam.then(|| {
+                    profile_spam_score(&ProfileSignals {
+                        name: hit.name.as_deref(),
+                        display_name: hit.display_name.as_deref(),
+                        about: hit.about.as_deref(),
+                        nip05: hit.nip05.as_deref(),
+                        followers_count: hit.followers_count,
+                    })
+                }),
             });
         }
 
@@ -459,107 +825,332 @@ impl NostrIntelServer {
             query: query.to_string(),
             profiles,
             count,
-            source: "primal_cache".to_string(),
+            source: source.to_string(),
+            timing_ms: timing.finish(),
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
-    // ==================== Paid tools ====================
-
     #[tool(
-        name = "search_events",
-        description = "Search Nostr events across multiple relays with filters. Costs 10-50 sats after free tier (10 calls/day)."
+        name = "relay_health",
+        description = "Report per-relay success/failure counts, rolling latency, and which relays are currently deprioritized due to repeated failures"
     )]
-    async fn search_events(
-        &self,
-        Parameters(params): Parameters<SearchEventsParams>,
-    ) -> Result<String, String> {
-        // Payment gate
-        let amount = self.calculate_price(&params);
-        match self
-            .payment_gate("search_events", amount, params.payment_hash.as_deref())
-            .await?
-        {
-            PaymentGateResult::EarlyReturn(json) => return Ok(json),
-            PaymentGateResult::Proceed => {}
-        }
+    async fn relay_health(&self) -> Result<String, String> {
+        let _call = self.begin_call("relay_health");
+        let relays: Vec<RelayHealthEntry> = self
+            .relay_health
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|(relay_url, h)| RelayHealthEntry {
+                relay_url,
+                successes: h.successes,
+                failures: h.failures,
+                recent_failure_rate: h.recent_failure_rate(),
+                avg_latency_ms: h.avg_latency_ms.map(|ms| ms.round() as u64),
+                last_error: h.last_error.clone(),
+                deprioritized: h.is_deprioritized(),
+            })
+            .collect();
 
-        // Execute search
-        let authors = if let Some(ref author_strs) = params.authors {
-            let mut pks = Vec::new();
-            for a in author_strs {
-                let pk = NostrClient::parse_pubkey(a)
-                    .map_err(|e| format!("Invalid author pubkey '{a}': {e}"))?;
-                pks.push(pk);
-            }
-            Some(pks)
-        } else {
-            None
+        let deprioritized_count = relays.iter().filter(|r| r.deprioritized).count() as u32;
+        let response = RelayHealthResponse {
+            relays,
+            deprioritized_count,
         };
 
-        let kinds = params
-            .kinds
-            .as_ref()
-            .map(|ks| ks.iter().map(|k| Kind::from(*k as u16)).collect());
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-        let since = params.since_hours.map(|hours| {
-            let secs_ago = hours * 3600;
-            let now = chrono::Utc::now().timestamp() as u64;
-            Timestamp::from(now.saturating_sub(secs_ago))
-        });
+    #[tool(
+        name = "get_server_info",
+        description = "List every tool with its price in sats, the free-tier policy, and which payment protocols (NWC, L402, x402) are enabled"
+    )]
+    async fn get_server_info(&self) -> Result<String, String> {
+        let _call = self.begin_call("get_server_info");
+        let pricing = &self.config.pricing;
 
-        let limit = params.limit;
+        let mut tools: Vec<ToolPricing> = self
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|tool| {
+                let name = tool.name.to_string();
+                let price_sats = pricing.price_for(&name, None, None);
+                let pricing_note = match name.as_str() {
+                    "search_events" => Some(format!(
+                        "{} sats up to 20 results, {} above 20, {} above 50",
+                        pricing.search_events_price(Some(20)),
+                        pricing.search_events_price(Some(21)),
+                        pricing.search_events_price(Some(51)),
+                    )),
+                    "get_follower_graph" => Some(format!(
+                        "{} sats at depth 2",
+                        pricing.follower_graph_price(2)
+                    )),
+                    _ => None,
+                };
+                ToolPricing {
+                    paid: price_sats.is_some(),
+                    name,
+                    price_sats,
+                    pricing_note,
+                }
+            })
+            .collect();
+        tools.sort_by(|a, b| a.paid.cmp(&b.paid).then_with(|| a.name.cmp(&b.name)));
+
+        let free_tier_calls_per_day = self.config.free_tier.calls_per_day;
+        let used = self.rate_limiter.get_current_count(self.client_id()).await;
+        let payment = &self.config.payment;
+
+        let response = GetServerInfoResponse {
+            name: self.config.server.name.clone(),
+            version: self.config.server.version.clone(),
+            tools,
+            free_tier_calls_per_day,
+            free_tier_remaining: free_tier_calls_per_day.saturating_sub(used),
+            payment_protocols: PaymentProtocols {
+                nwc: self.nwc_gateway.is_some(),
+                l402: payment.enable_l402 && !payment.l402_secret.is_empty(),
+                x402: payment.enable_x402,
+            },
+        };
 
-        let events = self
-            .nostr_client
-            .search_events(authors, kinds, params.search.clone(), since, limit)
-            .await
-            .map_err(|e| format!("Search failed: {e}"))?;
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-        let relays_queried: Vec<String> = self.config.relays.default.clone();
+    #[tool(
+        name = "get_price_quote",
+        description = "Quote the price in sats of a paid tool call without executing it, and report how many free-tier calls remain today"
+    )]
+    async fn get_price_quote(
+        &self,
+        Parameters(params): Parameters<GetPriceQuoteParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("get_price_quote");
+        let tool_name = params.tool_name.trim();
+        call.input(&format!(
+            "tool_name={tool_name} limit={:?} depth={:?}",
+            params.limit, params.depth
+        ));
+
+        // Quote on the same effective limit search_events will charge for
+        let limit = Some(self.config.limits.search_events.resolve(params.limit));
+        if !self.tool_router.has_route(tool_name) {
+            return Err(format!("Unknown paid tool: {tool_name}"));
+        }
+        let amount_sats = self
+            .config
+            .pricing
+            .price_for(tool_name, limit, params.depth)
+            .ok_or_else(|| format!("Unknown paid tool: {tool_name}"))?;
+
+        let limit = self.config.free_tier.calls_per_day;
+        let used = self.rate_limiter.get_current_count(self.client_id()).await;
+        let free_tier_remaining = limit.saturating_sub(used);
+
+        let response = GetPriceQuoteResponse {
+            tool_name: tool_name.to_string(),
+            amount_sats,
+            free_tier_remaining,
+            free_tier_limit: limit,
+            covered_by_free_tier: free_tier_remaining > 0,
+        };
 
-        let event_summaries: Vec<EventSummary> = events
-            .iter()
-            .map(|event| {
-                let content = if event.content.len() > 280 {
-                    format!("{}...", &event.content[..280])
-                } else {
-                    event.content.clone()
-                };
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-                let tags_summary = if event.tags.is_empty() {
-                    "none".to_string()
-                } else {
-                    let tag_kinds: Vec<String> = event
-                        .tags
-                        .iter()
-                        .take(5)
-                        .map(|t| t.kind().to_string())
-                        .collect();
-                    if event.tags.len() > 5 {
-                        format!("{} (+{} more)", tag_kinds.join(", "), event.tags.len() - 5)
-                    } else {
-                        tag_kinds.join(", ")
-                    }
-                };
+    // ==================== Paid tools ====================
 
-                EventSummary {
-                    id: event.id.to_hex(),
-                    pubkey: event.pubkey.to_hex(),
-                    kind: event.kind.as_u16() as u32,
-                    content,
-                    created_at: event.created_at.as_secs(),
-                    tags_summary,
-                }
-            })
-            .collect();
+    #[tool(
+        name = "search_events",
+        description = "Search Nostr events across multiple relays with filters. Costs 10-50 sats after free tier (10 calls/day)."
+    )]
+    async fn search_events(
+        &self,
+        Parameters(params): Parameters<SearchEventsParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("search_events");
+        call.input(&format!(
+            "authors={} kinds={:?} search={} since_hours={:?} limit={:?}",
+            params.authors.as_ref().map_or(0, |a| a.len()),
+            params.kinds,
+            params.search.is_some(),
+            params.since_hours,
+            params.limit
+        ));
+        let limit = self.config.limits.search_events.resolve(params.limit);
+
+        let after_event_id = match params.after_event_id.as_deref() {
+            Some(_) if params.after_timestamp.is_none() => {
+                return Err("after_event_id requires after_timestamp".into());
+            }
+            Some(id) => Some(
+                EventId::parse(id.trim())
+                    .map_err(|e| format!("Invalid after_event_id '{id}': {e}"))?,
+            ),
+            None => None,
+        };
+        let excluded_authors = parse_excluded_authors(params.exclude_authors.as_deref())?;
+        let sort = parse_event_sort(params.sort_by.as_deref())?;
+        if params.identifiers.is_some() && params.kinds.as_ref().is_none_or(|k| k.is_empty()) {
+            return Err("identifiers requires kinds (e.g. 30023 for long-form articles)".into());
+        }
+
+        // Payment gate (priced on the effective limit, after the configured cap)
+        let amount = self.config.pricing.search_events_price(Some(limit));
+        let free_tier_remaining = match self
+            .payment_gate("search_events", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute search
+        let authors = if let Some(ref author_strs) = params.authors {
+            let mut pks = Vec::new();
+            for a in author_strs {
+                let pk = NostrClient::parse_pubkey(a)
+                    .map_err(|e| format!("Invalid author pubkey '{a}': {e}"))?;
+                pks.push(pk);
+            }
+            Some(pks)
+        } else {
+            None
+        };
+
+        // An explicit list (even an empty one, meaning all kinds) overrides the default
+        let kinds: Vec<Kind> = match &params.kinds {
+            Some(ks) => ks.iter().map(|k| Kind::from(*k as u16)).collect(),
+            None => self
+                .config
+                .search
+                .default_kinds
+                .iter()
+                .copied()
+                .map(Kind::from)
+                .collect(),
+        };
+        let kinds = (!kinds.is_empty()).then_some(kinds);
+
+        let since = params.since_hours.map(|hours| {
+            let secs_ago = hours * 3600;
+            let now = chrono::Utc::now().timestamp() as u64;
+            now.saturating_sub(secs_ago)
+        });
+        // The cursor's second is included (other events may share it); the boundary
+        // event itself is dropped below
+        let since = since
+            .into_iter()
+            .chain(params.after_timestamp)
+            .max()
+            .map(Timestamp::from);
+
+        let mut timing = PhaseTimings::new(params.include_timing.unwrap_or(false));
+        call.relays(self.nostr_client.active_relay_count().await);
+        let RelayResults {
+            events,
+            mut seen_on,
+            relay_errors,
+        } = timing
+            .time(
+                "relay_fetch",
+                self.nostr_client.search_events(
+                    authors,
+                    kinds,
+                    params.search.clone(),
+                    since,
+                    params.identifiers.clone(),
+                    limit,
+                ),
+            )
+            .await
+            .map_err(|e| format!("Search failed: {e}"))?;
+        let events = dedupe_by_id(events);
+        let events = events_after_cursor(events, params.after_timestamp, after_event_id);
+        let (events, excluded) = without_authors(events, &excluded_authors);
+        let excluded_count = params.exclude_authors.is_some().then_some(excluded);
+        let exclude_replies = params.exclude_replies.unwrap_or(false);
+        let (events, too_short, empty_replies) =
+            without_low_content(events, params.min_content_length, exclude_replies);
+        let too_short_count = params.min_content_length.is_some().then_some(too_short);
+        let empty_replies_count = exclude_replies.then_some(empty_replies);
+
+        let (mut events, deleted_count) = if params.respect_deletions.unwrap_or(false) {
+            let ids: Vec<EventId> = events.iter().map(|e| e.id).collect();
+            let authors: Vec<PublicKey> = events
+                .iter()
+                .map(|e| e.pubkey)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            let deletions = timing
+                .time(
+                    "deletions",
+                    self.nostr_client.fetch_deletions(&ids, &authors),
+                )
+                .await
+                .map_err(|e| format!("Failed to fetch deletion requests: {e}"))?;
+            let deleted = deleted_event_ids(&events, &deletions);
+            let before = events.len();
+            let events: Vec<Event> = events
+                .into_iter()
+                .filter(|e| !deleted.contains(&e.id))
+                .collect();
+            let dropped = (before - events.len()) as u32;
+            (events, Some(dropped))
+        } else {
+            (events, None)
+        };
+        sort_events(&mut events, sort);
+        events.truncate(limit as usize);
+        let cursor = search_cursor(&events);
+
+        let relays_queried = self.nostr_client.target_relays().await;
+        let skipped_relays = self.nostr_client.skipped_relays().await;
+
+        let opts = SummaryOptions {
+            include_raw: params.include_raw.unwrap_or(false),
+            verify_signatures: params.verify_signatures.unwrap_or(false),
+            analyze_content: params.analyze_content.unwrap_or(false),
+            detect_language: params.detect_language.unwrap_or(false),
+            resolve_references: params.resolve_references.unwrap_or(false),
+        };
+        let mut event_summaries = timing
+            .time("enrich", async { summarize_events(&events, opts) })
+            .await;
+        if params.include_provenance.unwrap_or(false) {
+            for summary in &mut event_summaries {
+                let relays = EventId::from_hex(&summary.id)
+                    .ok()
+                    .and_then(|id| seen_on.remove(&id))
+                    .unwrap_or_default();
+                summary.seen_on = Some(relays.into_iter().collect());
+            }
+        }
 
         let count = event_summaries.len() as u32;
         let response = SearchEventsResponse {
             events: event_summaries,
             count,
+            cursor,
+            excluded_count,
+            deleted_count,
+            too_short_count,
+            empty_replies_count,
             relays_queried,
+            relay_errors: relay_errors
+                .into_iter()
+                .map(|(relay, error)| RelayFetchError { relay, error })
+                .collect(),
+            skipped_relays,
+            timing_ms: timing.finish(),
+            free_tier_remaining,
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
@@ -575,52 +1166,41 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<RelayDiscoveryParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("relay_discovery");
+        call.input(&format!("pubkey={}", short_id(params.pubkey.trim())));
         // Payment gate
         let amount = self.config.pricing.relay_discovery;
-        match self
+        let free_tier_remaining = match self
             .payment_gate("relay_discovery", amount, params.payment_hash.as_deref())
             .await?
         {
             PaymentGateResult::EarlyReturn(json) => return Ok(json),
-            PaymentGateResult::Proceed => {}
-        }
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
 
         // Execute
         let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
             .map_err(|e| format!("Invalid pubkey: {e}"))?;
 
+        call.relays(self.nostr_client.active_relay_count().await);
         let relay_events = self
             .nostr_client
             .fetch_relay_list(&pubkey)
             .await
             .map_err(|e| format!("Failed to fetch relay list: {e}"))?;
 
-        let mut write_relays = Vec::new();
-        let mut read_relays = Vec::new();
-
-        if let Some(event) = relay_events.first() {
-            for tag in event.tags.iter() {
-                let tag_vec: Vec<String> = tag.as_slice().iter().map(|s| s.to_string()).collect();
-                if tag_vec.first().map(|s| s.as_str()) == Some("r") {
-                    if let Some(url) = tag_vec.get(1) {
-                        match tag_vec.get(2).map(|s| s.as_str()) {
-                            Some("read") => read_relays.push(url.clone()),
-                            Some("write") => write_relays.push(url.clone()),
-                            _ => {
-                                // No marker means both read and write
-                                read_relays.push(url.clone());
-                                write_relays.push(url.clone());
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let (write_relays, read_relays) = relay_events
+            .first()
+            .map(parse_relay_list)
+            .unwrap_or_default();
 
-        // Build recommended relays from the union
-        let mut recommended: Vec<String> = write_relays.clone();
-        for r in &read_relays {
-            if !recommended.contains(r) {
+        // Build recommended relays from the union, leaving out relays the
+        // operator's allow/deny lists wouldn't let us use
+        let mut recommended: Vec<String> = Vec::new();
+        for r in write_relays.iter().chain(&read_relays) {
+            if !recommended.contains(r) && self.config.relays.check_policy(r) == Ok(true) {
                 recommended.push(r.clone());
             }
         }
@@ -633,6 +1213,7 @@ impl NostrIntelServer {
                 timestamp: e.created_at.as_secs(),
             }),
             recommended_relays: recommended,
+            free_tier_remaining,
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
@@ -648,37 +1229,92 @@ impl NostrIntelServer {
         &self,
         Parameters(params): Parameters<TrendingNotesParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("trending_notes");
+        call.input(&format!(
+            "timeframe={:?} limit={:?}",
+            params.timeframe, params.limit
+        ));
+        let excluded_authors = parse_excluded_authors(params.exclude_authors.as_deref())?;
+        let language = params.language.as_deref().map(parse_language).transpose()?;
+        let keywords = params.contains.as_deref().map(parse_keywords).transpose()?;
+        if params.max_per_author == Some(0) {
+            return Err("max_per_author must be at least 1".into());
+        }
+        if params.sample_size == Some(0) {
+            return Err("sample_size must be at least 1".into());
+        }
+        let timeframe_str = params.timeframe.as_deref().unwrap_or("24h");
+        let since_secs =
+            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
+
         // Payment gate
         let amount = self.config.pricing.trending_notes;
-        match self
+        let free_tier_remaining = match self
             .payment_gate("trending_notes", amount, params.payment_hash.as_deref())
             .await?
         {
             PaymentGateResult::EarlyReturn(json) => return Ok(json),
-            PaymentGateResult::Proceed => {}
-        }
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
 
         // Execute
-        let timeframe_str = params.timeframe.as_deref().unwrap_or("24h");
-        let since_secs =
-            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
         let now = chrono::Utc::now().timestamp() as u64;
         let since = Timestamp::from(now.saturating_sub(since_secs));
 
-        let limit = params.limit.unwrap_or(20).min(50) as usize;
+        let limit = self.config.limits.trending_notes.resolve(params.limit) as usize;
+        let sample_size = self
+            .config
+            .limits
+            .trending_sample(since_secs, params.sample_size);
 
         // Fetch recent notes
+        call.relays(self.nostr_client.active_relay_count().await);
         let notes = self
             .nostr_client
-            .fetch_recent_notes(since, 200)
+            .fetch_recent_notes(since, sample_size)
             .await
             .map_err(|e| format!("Failed to fetch notes: {e}"))?;
+        let sampled_count = notes.len() as u32;
+        let (mut notes, excluded) = without_authors(notes, &excluded_authors);
+        let excluded_count = params.exclude_authors.is_some().then_some(excluded);
+
+        let languages: HashMap<EventId, String> =
+            if params.detect_language.unwrap_or(false) || language.is_some() {
+                notes
+                    .iter()
+                    .filter_map(|n| Some((n.id, detect_language(&n.content)?)))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        if let Some(language) = language {
+            notes.retain(|n| languages.get(&n.id).map(String::as_str) == Some(language.code()));
+        }
+
+        // Filter before scoring so the ranking and limit apply to matches only
+        let (keyword_candidates, keyword_matches) = match &keywords {
+            Some(keywords) => {
+                let candidates = notes.len() as u32;
+                notes.retain(|n| contains_any_keyword(&n.content, keywords));
+                (Some(candidates), Some(notes.len() as u32))
+            }
+            None => (None, None),
+        };
 
         if notes.is_empty() {
             let response = TrendingNotesResponse {
                 notes: vec![],
                 timeframe: timeframe_str.to_string(),
                 count: 0,
+                sample_size: sample_size as u32,
+                sampled_count,
+                excluded_count,
+                keyword_candidates,
+                keyword_matches,
+                duplicates_collapsed: 0,
+                free_tier_remaining,
             };
             return serde_json::to_string_pretty(&response).map_err(|e| e.to_string());
         }
@@ -693,15 +1329,23 @@ impl NostrIntelServer {
         let reactions = reactions.map_err(|e| format!("Failed to fetch reactions: {e}"))?;
         let reposts = reposts.map_err(|e| format!("Failed to fetch reposts: {e}"))?;
 
-        // Count reactions per note
+        // Count reactions per note, split by content when a breakdown is wanted
+        let breakdown = params.reaction_breakdown.unwrap_or(false);
         let mut reaction_counts: std::collections::HashMap<String, u32> =
             std::collections::HashMap::new();
+        let mut reaction_tallies: HashMap<String, ReactionTally> = HashMap::new();
         for r in &reactions {
             for tag in r.tags.iter() {
                 let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
                 if tag_vec.first() == Some(&"e") {
                     if let Some(id) = tag_vec.get(1) {
                         *reaction_counts.entry(id.to_string()).or_default() += 1;
+                        if breakdown {
+                            reaction_tallies
+                                .entry(id.to_string())
+                                .or_default()
+                                .add(&r.content);
+                        }
                     }
                 }
             }
@@ -721,37 +1365,59 @@ impl NostrIntelServer {
             }
         }
 
-        // Score and sort notes
-        let mut scored_notes: Vec<(u64, &Event)> = notes
+        let engagement: Vec<NoteEngagement> = notes
             .iter()
             .map(|note| {
                 let id_hex = note.id.to_hex();
-                let r_count = reaction_counts.get(&id_hex).copied().unwrap_or(0);
-                let rp_count = repost_counts.get(&id_hex).copied().unwrap_or(0);
+                NoteEngagement {
+                    note,
+                    reactions: reaction_counts.get(&id_hex).copied().unwrap_or(0),
+                    reposts: repost_counts.get(&id_hex).copied().unwrap_or(0),
+                    tally: reaction_tallies.remove(&id_hex).unwrap_or_default(),
+                }
+            })
+            .collect();
+        let before_dedupe = engagement.len();
+        let engagement = if params.dedupe.unwrap_or(true) {
+            collapse_duplicate_content(engagement)
+        } else {
+            engagement
+        };
+        let duplicates_collapsed = (before_dedupe - engagement.len()) as u32;
+
+        // Score and sort notes
+        let mut scored_notes: Vec<(u64, NoteEngagement)> = engagement
+            .into_iter()
+            .map(|e| {
                 // Score: reactions * 1 + reposts * 3
-                let score = r_count as u64 + rp_count as u64 * 3;
-                (score, note)
+                let score = e.reactions as u64 + e.reposts as u64 * 3;
+                (score, e)
             })
             .collect();
 
-        scored_notes.sort_by(|a, b| b.0.cmp(&a.0));
+        scored_notes.sort_by_key(|b| std::cmp::Reverse(b.0));
+        if let Some(max) = params.max_per_author {
+            scored_notes = cap_per_author(scored_notes, max as usize, |(_, e)| e.note.pubkey);
+        }
         scored_notes.truncate(limit);
 
         let trending: Vec<TrendingNote> = scored_notes
             .into_iter()
-            .map(|(score, note)| {
-                let id_hex = note.id.to_hex();
-                let content_preview = truncate_content(&note.content, 280);
+            .map(|(score, e)| {
+                let note = e.note;
                 TrendingNote {
-                    id: id_hex.clone(),
+                    id: note.id.to_hex(),
                     author_pubkey: note.pubkey.to_hex(),
                     author_name: None,
-                    content_preview,
-                    reactions: reaction_counts.get(&id_hex).copied().unwrap_or(0),
-                    reposts: repost_counts.get(&id_hex).copied().unwrap_or(0),
+                    content_preview: truncate_content(&note.content, 280),
+                    reactions: e.reactions,
+                    reposts: e.reposts,
                     zap_total_sats: 0,
                     score,
                     created_at: note.created_at.as_secs(),
+                    detected_language: languages.get(&note.id).cloned(),
+                    reaction_breakdown: breakdown
+                        .then(|| e.tally.into_breakdown(MAX_BREAKDOWN_EMOJI)),
                 }
             })
             .collect();
@@ -761,6 +1427,13 @@ impl NostrIntelServer {
             notes: trending,
             timeframe: timeframe_str.to_string(),
             count,
+            sample_size: sample_size as u32,
+            sampled_count,
+            excluded_count,
+            keyword_candidates,
+            keyword_matches,
+            duplicates_collapsed,
+            free_tier_remaining,
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
@@ -775,18 +1448,50 @@ impl NostrIntelServer {
     async fn get_follower_graph(
         &self,
         Parameters(params): Parameters<GetFollowerGraphParams>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<String, String> {
+        self.follower_graph(params, ProgressReporter::new(&meta, peer))
+            .await
+    }
+
+    /// get_follower_graph, with depth-2 progress going to `progress`
+    async fn follower_graph(
+        &self,
+        params: GetFollowerGraphParams,
+        progress: ProgressReporter,
     ) -> Result<String, String> {
+        let call = self.begin_call("get_follower_graph");
         let depth = params.depth.unwrap_or(1).clamp(1, 2);
+        let follower_limit = params.follower_limit.unwrap_or(DEFAULT_FOLLOWER_PAGE) as usize;
+        let follower_offset = params.follower_offset.unwrap_or(0) as usize;
+        let include_self = params.include_self.unwrap_or(false);
+        call.input(&format!(
+            "pubkey={} depth={depth} limit={follower_limit} offset={follower_offset}",
+            short_id(params.pubkey.trim())
+        ));
+        if !(1..=MAX_FOLLOWER_PAGE).contains(&follower_limit) {
+            return Err(format!(
+                "follower_limit must be between 1 and {MAX_FOLLOWER_PAGE}"
+            ));
+        }
+        if follower_offset > MAX_FOLLOWER_OFFSET {
+            return Err(format!(
+                "follower_offset must be at most {MAX_FOLLOWER_OFFSET}"
+            ));
+        }
 
         // Payment gate
-        let amount = self.calculate_follower_graph_price(depth);
-        match self
+        let amount = self.config.pricing.follower_graph_price(depth);
+        let free_tier_remaining = match self
             .payment_gate("get_follower_graph", amount, params.payment_hash.as_deref())
             .await?
         {
             PaymentGateResult::EarlyReturn(json) => return Ok(json),
-            PaymentGateResult::Proceed => {}
-        }
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
 
         // Execute
         let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
@@ -794,6 +1499,7 @@ impl NostrIntelServer {
         let pubkey_hex = pubkey.to_hex();
 
         // Fetch the target's contact list (who they follow)
+        call.relays(self.nostr_client.active_relay_count().await);
         let contact_list = self
             .nostr_client
             .fetch_contact_list(&pubkey)
@@ -808,6 +1514,9 @@ impl NostrIntelServer {
                 let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
                 if tag_vec.first() == Some(&"p") {
                     if let Some(pk) = tag_vec.get(1) {
+                        if !include_self && *pk == pubkey_hex {
+                            continue;
+                        }
                         following_set.insert(pk.to_string());
                         following.push(PubkeySummary {
                             pubkey: pk.to_string(),
@@ -827,45 +1536,62 @@ impl NostrIntelServer {
 
         let following_count = following.len() as u32;
 
-        // Fetch followers: kind:3 events that have our target in their p tags
-        // This is expensive — we search for contact lists referencing this pubkey
-        let follower_filter = Filter::new()
-            .kind(Kind::ContactList)
-            .custom_tag(SingleLetterTag::lowercase(Alphabet::P), pubkey_hex.clone())
-            .limit(100);
-
-        let follower_events = self
+        // Fetch followers: kind:3 events that have our target in their p tags.
+        // One extra tells us whether another page exists, plus one more to
+        // make up for the target's own list when self-follows are dropped.
+        let mut follower_events = self
             .nostr_client
-            .client()
-            .fetch_events(follower_filter, std::time::Duration::from_secs(15))
+            .fetch_followers(
+                &pubkey,
+                follower_offset + follower_limit + 1 + usize::from(!include_self),
+            )
             .await
             .map_err(|e| format!("Failed to fetch followers: {e}"))?;
+        if !include_self {
+            follower_events.retain(|e| e.pubkey != pubkey);
+        }
+        let followers_total_estimate = follower_events.len() as u32;
+        let has_more = follower_events.len() > follower_offset + follower_limit;
 
-        let mut followers: Vec<PubkeySummary> = Vec::new();
-        let mut follower_set: std::collections::HashSet<String> = std::collections::HashSet::new();
+        // Compute mutual follows over every follower seen, not just this page
+        let mut mutual_follows: Vec<PubkeySummary> = Vec::new();
+        for event in &follower_events {
+            let pk_hex = event.pubkey.to_hex();
+            if !following_set.contains(&pk_hex) {
+                continue;
+            }
+            if let Some(f) = following.iter().find(|f| f.pubkey == pk_hex) {
+                mutual_follows.push(f.clone());
+            }
+        }
 
-        for event in follower_events.iter() {
+        let mut followers: Vec<PubkeySummary> = Vec::new();
+        for event in follower_events
+            .iter()
+            .skip(follower_offset)
+            .take(follower_limit)
+        {
             let pk_hex = event.pubkey.to_hex();
-            if follower_set.insert(pk_hex.clone()) {
-                let mut summary = PubkeySummary {
-                    pubkey: pk_hex.clone(),
-                    name: None,
-                };
-                if let Ok(Some(cached)) = self.cache.get_profile(&pk_hex).await {
-                    summary.name = cached.name.or(cached.display_name);
-                }
-                followers.push(summary);
+            let mut summary = PubkeySummary {
+                pubkey: pk_hex.clone(),
+                name: None,
+            };
+            if let Ok(Some(cached)) = self.cache.get_profile(&pk_hex).await {
+                summary.name = cached.name.or(cached.display_name);
             }
+            followers.push(summary);
         }
 
         let followers_count = followers.len() as u32;
 
-        // Compute mutual follows
-        let mutual_follows: Vec<PubkeySummary> = followers
-            .iter()
-            .filter(|f| following_set.contains(&f.pubkey))
-            .cloned()
-            .collect();
+        let second_degree = if depth == 2 {
+            Some(
+                self.expand_second_degree(&pubkey_hex, &following, &progress)
+                    .await,
+            )
+        } else {
+            None
+        };
 
         let response = GetFollowerGraphResponse {
             pubkey: pubkey_hex,
@@ -873,88 +1599,451 @@ impl NostrIntelServer {
             following,
             followers_count,
             followers_sample: followers,
+            followers_total_estimate,
+            has_more,
             mutual_follows,
+            second_degree,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
-    // ==================== zap_analytics ====================
+    /// Fetch the contact lists of the first `limits.follower_graph_fanout`
+    /// follows, a few at a time, and count who they follow. A list that fails
+    /// to load is skipped rather than failing the whole graph.
+    async fn expand_second_degree(
+        &self,
+        target_hex: &str,
+        following: &[PubkeySummary],
+        progress: &ProgressReporter,
+    ) -> SecondDegreeGraph {
+        // Unparseable or repeated entries must not use up fan-out slots
+        let mut distinct = HashSet::new();
+        let follows: Vec<PublicKey> = following
+            .iter()
+            .filter_map(|f| PublicKey::from_hex(&f.pubkey).ok())
+            .filter(|pk| distinct.insert(*pk))
+            .collect();
+        let fanout = self.config.limits.follower_graph_fanout as usize;
+        let total = follows.len().min(fanout) as u32;
+        let not_expanded = follows.len() as u32 - total;
+        let mut pending = follows.into_iter().take(fanout);
+
+        let followed: HashSet<&str> = following.iter().map(|f| f.pubkey.as_str()).collect();
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut processed = 0;
+        let mut tasks = tokio::task::JoinSet::new();
+        loop {
+            while tasks.len() < SECOND_DEGREE_CONCURRENCY {
+                let Some(pk) = pending.next() else { break };
+                let client = Arc::clone(&self.nostr_client);
+                tasks.spawn(async move { client.fetch_contact_list(&pk).await });
+            }
+            let Some(joined) = tasks.join_next().await else {
+                break;
+            };
+            processed += 1;
+            match joined {
+                Ok(Ok(Some(list))) => {
+                    for pk in contact_list_pubkeys(&list) {
+                        if pk != target_hex && !followed.contains(pk.as_str()) {
+                            *counts.entry(pk).or_default() += 1;
+                        }
+                    }
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => tracing::debug!("Second-degree contact list fetch failed: {e}"),
+                Err(e) => tracing::debug!("Second-degree contact list task failed: {e}"),
+            }
+            progress
+                .report(
+                    processed,
+                    total,
+                    format!("processed {processed}/{total} follows"),
+                )
+                .await;
+        }
+
+        let accounts_count = counts.len() as u32;
+        let mut ranked: Vec<(String, u32)> = counts.into_iter().collect();
+        ranked.sort_by(|(a_pk, a), (b_pk, b)| b.cmp(a).then_with(|| a_pk.cmp(b_pk)));
+        ranked.truncate(MAX_SECOND_DEGREE);
+        let keys: Vec<String> = ranked.iter().map(|(pk, _)| pk.clone()).collect();
+        let profiles = self
+            .cache
+            .get_profiles_batch(&keys)
+            .await
+            .unwrap_or_default();
+        let accounts = ranked
+            .into_iter()
+            .map(|(pubkey, followed_by)| SecondDegreeAccount {
+                name: profiles
+                    .get(&pubkey)
+                    .and_then(|p| p.name.clone().or(p.display_name.clone())),
+                pubkey,
+                followed_by,
+            })
+            .collect();
+
+        SecondDegreeGraph {
+            expanded_follows: total,
+            follows_not_expanded: not_expanded,
+            accounts_count,
+            accounts,
+        }
+    }
+
+    // ==================== common_follows ====================
 
     #[tool(
-        name = "zap_analytics",
-        description = "Analyze zap (Lightning tip) activity for a Nostr pubkey. Costs 50 sats after free tier."
+        name = "common_follows",
+        description = "Find accounts followed by all (or at least min_overlap) of 2-10 seed pubkeys, with per-account overlap counts. Costs 50 sats after free tier."
     )]
-    async fn zap_analytics(
+    async fn common_follows(
         &self,
-        Parameters(params): Parameters<ZapAnalyticsParams>,
+        Parameters(params): Parameters<CommonFollowsParams>,
     ) -> Result<String, String> {
+        let call = self.begin_call("common_follows");
+        call.input(&format!(
+            "seeds={} min_overlap={:?}",
+            params.pubkeys.len(),
+            params.min_overlap
+        ));
+
+        let mut seeds: Vec<PublicKey> = Vec::new();
+        for input in &params.pubkeys {
+            let pk = NostrClient::parse_pubkey(input.trim())
+                .map_err(|e| format!("Invalid pubkey '{input}': {e}"))?;
+            if !seeds.contains(&pk) {
+                seeds.push(pk);
+            }
+        }
+        if !(MIN_COMMON_FOLLOWS_SEEDS..=MAX_COMMON_FOLLOWS_SEEDS).contains(&seeds.len()) {
+            return Err(format!(
+                "Provide between {MIN_COMMON_FOLLOWS_SEEDS} and {MAX_COMMON_FOLLOWS_SEEDS} distinct pubkeys"
+            ));
+        }
+
         // Payment gate
-        let amount = self.config.pricing.zap_analytics;
-        match self
-            .payment_gate("zap_analytics", amount, params.payment_hash.as_deref())
+        let amount = self.config.pricing.common_follows;
+        let free_tier_remaining = match self
+            .payment_gate("common_follows", amount, params.payment_hash.as_deref())
             .await?
         {
             PaymentGateResult::EarlyReturn(json) => return Ok(json),
-            PaymentGateResult::Proceed => {}
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute: fetch every seed's contact list in parallel
+        call.relays(self.nostr_client.active_relay_count().await);
+        let mut tasks = tokio::task::JoinSet::new();
+        for (idx, pk) in seeds.iter().enumerate() {
+            let client = Arc::clone(&self.nostr_client);
+            let pk = *pk;
+            tasks.spawn(async move { (idx, client.fetch_contact_list(&pk).await) });
+        }
+        // The call is already paid for: a seed whose fetch fails is reported
+        // alongside seeds without a contact list rather than failing the call.
+        let mut contact_lists: Vec<Option<Event>> = vec![None; seeds.len()];
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((idx, Ok(list))) => contact_lists[idx] = list,
+                Ok((idx, Err(e))) => {
+                    tracing::warn!("Failed to fetch contact list for {}: {e}", seeds[idx]);
+                }
+                Err(e) => tracing::warn!("Contact list fetch task failed: {e}"),
+            }
         }
 
-        // Execute
-        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
-            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        let mut seeds_without_contact_list = Vec::new();
+        let mut follow_sets: Vec<Vec<String>> = Vec::new();
+        for (pk, list) in seeds.iter().zip(&contact_lists) {
+            match list {
+                Some(event) => follow_sets.push(contact_list_pubkeys(event)),
+                None => seeds_without_contact_list.push(pk.to_hex()),
+            }
+        }
 
-        let timeframe_str = params.timeframe.as_deref().unwrap_or("30d");
-        let since_secs =
-            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
-        let now = chrono::Utc::now().timestamp() as u64;
-        let since = Timestamp::from(now.saturating_sub(since_secs));
+        let min_overlap = params
+            .min_overlap
+            .unwrap_or(follow_sets.len() as u32)
+            .clamp(1, seeds.len() as u32);
+        // With fewer than two contact lists there is nothing to intersect
+        let overlaps = if follow_sets.len() < MIN_COMMON_FOLLOWS_SEEDS {
+            Vec::new()
+        } else {
+            count_common_follows(&follow_sets, min_overlap)
+        };
+        let common_count = overlaps.len() as u32;
 
-        let zap_receipts = self
-            .nostr_client
-            .fetch_zap_receipts(&pubkey, Some(since))
+        let shown: Vec<(String, u32)> = overlaps.into_iter().take(MAX_COMMON_FOLLOWS).collect();
+        let keys: Vec<String> = shown.iter().map(|(pk, _)| pk.clone()).collect();
+        let profiles = self
+            .cache
+            .get_profiles_batch(&keys)
             .await
-            .map_err(|e| format!("Failed to fetch zap receipts: {e}"))?;
+            .unwrap_or_default();
+        let common = shown
+            .into_iter()
+            .map(|(pubkey, overlap_count)| CommonFollow {
+                name: profiles
+                    .get(&pubkey)
+                    .and_then(|p| p.name.clone().or(p.display_name.clone())),
+                pubkey,
+                overlap_count,
+            })
+            .collect();
 
-        let mut total_sats: u64 = 0;
-        let mut zapper_totals: std::collections::HashMap<String, u64> =
-            std::collections::HashMap::new();
-        let mut note_totals: std::collections::HashMap<String, u64> =
-            std::collections::HashMap::new();
-        let mut daily_totals: std::collections::BTreeMap<String, (u32, u64)> =
-            std::collections::BTreeMap::new();
+        let response = CommonFollowsResponse {
+            seeds: seeds.iter().map(|pk| pk.to_hex()).collect(),
+            seeds_without_contact_list,
+            min_overlap,
+            common,
+            common_count,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
 
-        for event in &zap_receipts {
-            // Parse amount from the zap request description tag or bolt11
-            let amount_sats = extract_zap_amount(event);
-            total_sats += amount_sats;
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-            // Extract zapper pubkey from uppercase P tag (sender's pubkey in zap request)
-            // or from the embedded zap request in the description tag
-            let zapper_pk = extract_zapper_pubkey(event);
-            if let Some(ref pk) = zapper_pk {
-                *zapper_totals.entry(pk.clone()).or_default() += amount_sats;
-            }
+    // ==================== compare_accounts ====================
 
-            // Extract zapped note from e tag
-            for tag in event.tags.iter() {
-                let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
-                if tag_vec.first() == Some(&"e") {
-                    if let Some(note_id) = tag_vec.get(1) {
-                        *note_totals.entry(note_id.to_string()).or_default() += amount_sats;
-                    }
-                }
-            }
+    #[tool(
+        name = "compare_accounts",
+        description = "Compare two Nostr pubkeys' audiences: shared followers, shared follows, Jaccard overlap for each, and a sample of the shared accounts with names. Costs 50 sats after free tier."
+    )]
+    async fn compare_accounts(
+        &self,
+        Parameters(params): Parameters<CompareAccountsParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("compare_accounts");
+        call.input(&format!(
+            "pubkey_a={} pubkey_b={}",
+            short_id(params.pubkey_a.trim()),
+            short_id(params.pubkey_b.trim())
+        ));
+        let a = NostrClient::parse_pubkey(params.pubkey_a.trim())
+            .map_err(|e| format!("Invalid pubkey_a: {e}"))?;
+        let b = NostrClient::parse_pubkey(params.pubkey_b.trim())
+            .map_err(|e| format!("Invalid pubkey_b: {e}"))?;
+        if a == b {
+            return Err("pubkey_a and pubkey_b must be different accounts".into());
+        }
 
-            // Group by date
-            let date = chrono::DateTime::from_timestamp(event.created_at.as_secs() as i64, 0)
-                .map(|dt| dt.format("%Y-%m-%d").to_string())
-                .unwrap_or_else(|| "unknown".to_string());
+        // Payment gate
+        let amount = self.config.pricing.compare_accounts;
+        let free_tier_remaining = match self
+            .payment_gate("compare_accounts", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute: both accounts' contact lists and followers at once
+        call.relays(self.nostr_client.active_relay_count().await);
+        let (contacts_a, contacts_b, followers_a, followers_b) = tokio::join!(
+            self.nostr_client.fetch_contact_list(&a),
+            self.nostr_client.fetch_contact_list(&b),
+            self.nostr_client.fetch_followers(&a, MAX_COMPARE_FOLLOWERS),
+            self.nostr_client.fetch_followers(&b, MAX_COMPARE_FOLLOWERS),
+        );
+        let follows = |list: anyhow::Result<Option<Event>>| -> Result<HashSet<String>, String> {
+            let list = list.map_err(|e| format!("Failed to fetch contact list: {e}"))?;
+            Ok(list
+                .as_ref()
+                .map(contact_list_pubkeys)
+                .unwrap_or_default()
+                .into_iter()
+                .collect())
+        };
+        let followers = |events: anyhow::Result<Vec<Event>>| -> Result<HashSet<String>, String> {
+            let events = events.map_err(|e| format!("Failed to fetch followers: {e}"))?;
+            Ok(events
+                .iter()
+                .take(MAX_COMPARE_FOLLOWERS)
+                .map(|e| e.pubkey.to_hex())
+                .collect())
+        };
+        let (follows_a, follows_b) = (follows(contacts_a)?, follows(contacts_b)?);
+        let (followers_a, followers_b) = (followers(followers_a)?, followers(followers_b)?);
+        let followers_truncated = followers_a.len() >= MAX_COMPARE_FOLLOWERS
+            || followers_b.len() >= MAX_COMPARE_FOLLOWERS;
+
+        let (follower_overlap, shared_followers) = audience_overlap(&followers_a, &followers_b);
+        let (follow_overlap, shared_follows) = audience_overlap(&follows_a, &follows_b);
+
+        let shared_followers: Vec<String> = shared_followers
+            .into_iter()
+            .take(MAX_OVERLAP_SAMPLE)
+            .collect();
+        let shared_follows: Vec<String> = shared_follows
+            .into_iter()
+            .take(MAX_OVERLAP_SAMPLE)
+            .collect();
+        let lookup: Vec<String> = shared_followers
+            .iter()
+            .chain(&shared_follows)
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let names = self.resolve_names(&lookup).await;
+        let summarize = |pubkeys: Vec<String>| -> Vec<PubkeySummary> {
+            pubkeys
+                .into_iter()
+                .map(|pubkey| PubkeySummary {
+                    name: names.get(&pubkey).cloned(),
+                    pubkey,
+                })
+                .collect()
+        };
+
+        let response = CompareAccountsResponse {
+            pubkey_a: a.to_hex(),
+            pubkey_b: b.to_hex(),
+            followers: follower_overlap,
+            following: follow_overlap,
+            shared_followers_sample: summarize(shared_followers),
+            shared_follows_sample: summarize(shared_follows),
+            followers_truncated,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    // ==================== zap_analytics ====================
+
+    #[tool(
+        name = "zap_analytics",
+        description = "Analyze zap (Lightning tip) activity for a Nostr pubkey. Costs 50 sats after free tier."
+    )]
+    async fn zap_analytics(
+        &self,
+        Parameters(params): Parameters<ZapAnalyticsParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("zap_analytics");
+        call.input(&format!(
+            "pubkey={} timeframe={:?} min_sats={:?}",
+            short_id(params.pubkey.trim()),
+            params.timeframe,
+            params.min_sats
+        ));
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        let timeframe_str = params.timeframe.as_deref().unwrap_or("30d");
+        let since_secs =
+            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
+
+        // Payment gate
+        let amount = self.config.pricing.zap_analytics;
+        let free_tier_remaining = match self
+            .payment_gate("zap_analytics", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute
+        let now = chrono::Utc::now().timestamp() as u64;
+        let since = Timestamp::from(now.saturating_sub(since_secs));
+
+        call.relays(self.nostr_client.active_relay_count().await);
+        let zap_receipts = self
+            .nostr_client
+            .fetch_zap_receipts(&pubkey, Some(since))
+            .await
+            .map_err(|e| format!("Failed to fetch zap receipts: {e}"))?;
+
+        let mut total_sats: u64 = 0;
+        let mut zapper_totals: std::collections::HashMap<String, u64> =
+            std::collections::HashMap::new();
+        let mut note_totals: std::collections::HashMap<String, u64> =
+            std::collections::HashMap::new();
+        let mut daily_totals: std::collections::BTreeMap<String, (u32, u64)> =
+            std::collections::BTreeMap::new();
+
+        let verify = params.verify.unwrap_or(true);
+        let mut suspicious_zaps_count: u32 = 0;
+        let mut total_zaps_count: u32 = 0;
+        let mut top_zaps: Vec<TopZap> = Vec::new();
+        let mut anonymous_sats: u64 = 0;
+        let mut anonymous_zaps_count: u32 = 0;
+        let min_sats = params.min_sats.unwrap_or(0);
+        let mut below_min_sats_count: u32 = 0;
+
+        for event in &zap_receipts {
+            // Skip receipts whose embedded zap request doesn't check out (NIP-57)
+            if verify {
+                if let Err(reason) = verify_zap_receipt(event) {
+                    tracing::debug!("Suspicious zap receipt {}: {reason}", event.id);
+                    suspicious_zaps_count += 1;
+                    continue;
+                }
+            }
+
+            // Parse amount from the zap request description tag or bolt11
+            let amount_sats = extract_zap_amount(event);
+            if amount_sats < min_sats {
+                below_min_sats_count += 1;
+                continue;
+            }
+            total_zaps_count += 1;
+            total_sats += amount_sats;
+
+            // Extract zapper pubkey from uppercase P tag (sender's pubkey in zap request)
+            // or from the embedded zap request in the description tag. Anonymous zaps
+            // have no real sender and are bucketed separately.
+            let zapper_pk = extract_zapper_pubkey(event);
+            match zapper_pk {
+                Some(ref pk) => *zapper_totals.entry(pk.clone()).or_default() += amount_sats,
+                None => {
+                    anonymous_sats += amount_sats;
+                    anonymous_zaps_count += 1;
+                }
+            }
+
+            // Extract zapped note from e tag
+            let mut zapped_note = None;
+            for tag in event.tags.iter() {
+                let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
+                if tag_vec.first() == Some(&"e") {
+                    if let Some(note_id) = tag_vec.get(1) {
+                        *note_totals.entry(note_id.to_string()).or_default() += amount_sats;
+                        zapped_note.get_or_insert_with(|| note_id.to_string());
+                    }
+                }
+            }
+
+            top_zaps.push(TopZap {
+                zapper_pubkey: zapper_pk,
+                amount_sats,
+                comment: extract_zap_comment(event),
+                note_id: zapped_note,
+                timestamp: event.created_at.as_secs(),
+            });
+
+            // Group by date
+            let date = chrono::DateTime::from_timestamp(event.created_at.as_secs() as i64, 0)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "unknown".to_string());
             let entry = daily_totals.entry(date).or_insert((0, 0));
             entry.0 += 1;
             entry.1 += amount_sats;
         }
 
-        let total_zaps_count = zap_receipts.len() as u32;
         let avg_zap_sats = if total_zaps_count > 0 {
             total_sats / total_zaps_count as u64
         } else {
@@ -963,24 +2052,48 @@ impl NostrIntelServer {
 
         // Top zappers
         let mut zapper_vec: Vec<(String, u64)> = zapper_totals.into_iter().collect();
-        zapper_vec.sort_by(|a, b| b.1.cmp(&a.1));
-        let mut top_zappers: Vec<ZapperSummary> = Vec::new();
-        for (pk, sats) in zapper_vec.into_iter().take(10) {
-            let name = if let Ok(Some(cached)) = self.cache.get_profile(&pk).await {
-                cached.name.or(cached.display_name)
-            } else {
-                None
-            };
-            top_zappers.push(ZapperSummary {
+        zapper_vec.sort_by_key(|b| std::cmp::Reverse(b.1));
+        zapper_vec.truncate(10);
+        let zapper_keys: Vec<String> = zapper_vec.iter().map(|(pk, _)| pk.clone()).collect();
+        let names = self.resolve_names(&zapper_keys).await;
+
+        let reciprocity = if params.include_reciprocity.unwrap_or(false) {
+            let sent = self
+                .nostr_client
+                .fetch_zaps_sent(&pubkey, Some(since))
+                .await
+                .map_err(|e| format!("Failed to fetch sent zaps: {e}"))?;
+            let sent: Vec<Event> = sent
+                .into_iter()
+                .filter(|event| !verify || verify_zap_receipt(event).is_ok())
+                .filter(|event| extract_zap_amount(event) >= min_sats)
+                .collect();
+            let flows = zap_reciprocity(&pubkey.to_hex(), &zapper_vec, &sent);
+            Some(
+                flows
+                    .into_iter()
+                    .map(|mut flow| {
+                        flow.name = names.get(&flow.pubkey).cloned();
+                        flow
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        let top_zappers: Vec<ZapperSummary> = zapper_vec
+            .into_iter()
+            .map(|(pk, sats)| ZapperSummary {
+                name: names.get(&pk).cloned(),
                 pubkey: pk,
-                name,
                 total_sats: sats,
-            });
-        }
+            })
+            .collect();
 
         // Top zapped notes
         let mut note_vec: Vec<(String, u64)> = note_totals.into_iter().collect();
-        note_vec.sort_by(|a, b| b.1.cmp(&a.1));
+        note_vec.sort_by_key(|b| std::cmp::Reverse(b.1));
         let top_zapped_notes: Vec<ZappedNote> = note_vec
             .into_iter()
             .take(10)
@@ -991,6 +2104,10 @@ impl NostrIntelServer {
             })
             .collect();
 
+        // Largest individual zaps, newest first on ties
+        top_zaps.sort_by_key(|z| std::cmp::Reverse((z.amount_sats, z.timestamp)));
+        top_zaps.truncate(MAX_TOP_ZAPS);
+
         // Zaps over time
         let zaps_over_time: Vec<ZapPeriod> = daily_totals
             .into_iter()
@@ -1003,479 +2120,5773 @@ impl NostrIntelServer {
             avg_zap_sats,
             top_zappers,
             top_zapped_notes,
+            top_zaps,
+            anonymous_sats,
+            anonymous_zaps_count,
             zaps_over_time,
+            reciprocity,
+            suspicious_zaps_count: verify.then_some(suspicious_zaps_count),
+            below_min_sats_count: params.min_sats.map(|_| below_min_sats_count),
+            free_tier_remaining,
         };
 
         serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
-    // ==================== pricing helpers ====================
+    // ==================== note_zaps ====================
 
-    fn calculate_price(&self, params: &SearchEventsParams) -> u64 {
-        let mut price = self.config.pricing.search_events_base;
-        if let Some(limit) = params.limit {
-            if limit > 20 {
-                price += 15;
-            }
-            if limit > 50 {
-                price += 25;
+    #[tool(
+        name = "note_zaps",
+        description = "Who zapped a note: total sats, zap count, and zappers ranked by amount with their comments. Costs 20 sats after free tier."
+    )]
+    async fn note_zaps(
+        &self,
+        Parameters(params): Parameters<NoteZapsParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("note_zaps");
+        call.input(&format!("event_id={}", short_id(params.event_id.trim())));
+        let event_id = params.event_id.trim();
+        let event_id = EventId::parse(event_id.strip_prefix("nostr:").unwrap_or(event_id))
+            .map_err(|e| format!("Invalid event_id: {e}"))?;
+
+        // Payment gate
+        let amount = self.config.pricing.note_zaps;
+        let free_tier_remaining = match self
+            .payment_gate("note_zaps", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute
+        call.relays(self.nostr_client.active_relay_count().await);
+        let receipts = self
+            .nostr_client
+            .fetch_note_zap_receipts(&event_id)
+            .await
+            .map_err(|e| format!("Failed to fetch zap receipts: {e}"))?;
+
+        let verify = params.verify.unwrap_or(true);
+        let mut suspicious_zaps_count: u32 = 0;
+        let mut zaps: Vec<(u64, Event)> = Vec::new();
+        for event in dedupe_by_id(receipts) {
+            if verify {
+                if let Err(reason) = verify_zap_receipt(&event) {
+                    tracing::debug!("Suspicious zap receipt {}: {reason}", event.id);
+                    suspicious_zaps_count += 1;
+                    continue;
+                }
             }
+            zaps.push((extract_zap_amount(&event), event));
+        }
+        // Largest first, so each zapper's comments come out in that order
+        zaps.sort_by_key(|(sats, _)| std::cmp::Reverse(*sats));
+
+        let total_sats: u64 = zaps.iter().map(|(sats, _)| sats).sum();
+        let mut anonymous_sats: u64 = 0;
+        let mut anonymous_zaps_count: u32 = 0;
+        let mut by_zapper: HashMap<String, NoteZapper> = HashMap::new();
+        for (amount_sats, event) in &zaps {
+            let Some(pubkey) = extract_zapper_pubkey(event) else {
+                anonymous_sats += amount_sats;
+                anonymous_zaps_count += 1;
+                continue;
+            };
+            let zapper = by_zapper
+                .entry(pubkey.clone())
+                .or_insert_with(|| NoteZapper {
+                    pubkey,
+                    name: None,
+                    total_sats: 0,
+                    zap_count: 0,
+                    comments: Vec::new(),
+                });
+            zapper.total_sats += amount_sats;
+            zapper.zap_count += 1;
+            zapper.comments.extend(extract_zap_comment(event));
         }
-        price
+
+        let mut zappers: Vec<NoteZapper> = by_zapper.into_values().collect();
+        zappers.sort_by(|a, b| {
+            b.total_sats
+                .cmp(&a.total_sats)
+                .then_with(|| a.pubkey.cmp(&b.pubkey))
+        });
+        zappers.truncate(MAX_NOTE_ZAPPERS);
+        let keys: Vec<String> = zappers.iter().map(|z| z.pubkey.clone()).collect();
+        let names = self.resolve_names(&keys).await;
+        for zapper in &mut zappers {
+            zapper.name = names.get(&zapper.pubkey).cloned();
+        }
+
+        let response = NoteZapsResponse {
+            event_id: event_id.to_hex(),
+            total_sats,
+            zap_count: zaps.len() as u32,
+            zappers,
+            anonymous_sats,
+            anonymous_zaps_count,
+            suspicious_zaps_count: verify.then_some(suspicious_zaps_count),
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
-    fn calculate_follower_graph_price(&self, depth: u8) -> u64 {
-        if depth >= 2 {
-            self.config.pricing.get_follower_graph * 2
-        } else {
-            self.config.pricing.get_follower_graph
+    // ==================== Operator tools ====================
+
+    #[tool(
+        name = "wallet_status",
+        description = "Operator only (requires X-Admin-Key): report the NWC wallet's balance, node alias/network, and permitted NWC methods"
+    )]
+    async fn wallet_status(&self) -> Result<String, String> {
+        let _call = self.begin_call("wallet_status");
+        if self.admin.get().is_none() {
+            return Err("wallet_status requires a valid X-Admin-Key header".into());
         }
+        let gw = self
+            .nwc_gateway
+            .as_ref()
+            .ok_or("Payment system not configured")?;
+
+        let (balance, info) = tokio::join!(gw.get_balance(), gw.get_info());
+        let error = balance
+            .as_ref()
+            .err()
+            .or(info.as_ref().err())
+            .map(|e| e.to_string());
+        let info = info.ok();
+        let health = gw.connection_health();
+
+        let response = WalletStatusResponse {
+            connected: error.is_none(),
+            connections: health.len(),
+            healthy_connections: health.iter().filter(|&&h| h).count(),
+            balance_sats: balance.ok(),
+            alias: info.as_ref().and_then(|i| i.alias.clone()),
+            network: info.as_ref().and_then(|i| i.network.clone()),
+            methods: info.map(|i| i.methods).unwrap_or_default(),
+            error,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
 
-    /// Unified payment gate for all paid tools.
-    /// - With payment_hash: verify via NWC, return Proceed
-    /// - Under free tier: increment counter, return Proceed
-    /// - Over limit + NWC: create invoice, return EarlyReturn(PaymentRequiredResponse)
-    /// - Over limit + no NWC: return EarlyReturn(FreeTierExhaustedResponse) — Ok, not Err!
-    async fn payment_gate(
+    #[tool(
+        name = "purge_cache_entry",
+        description = "Operator only (requires X-Admin-Key): delete everything cached about a pubkey, e.g. for a data deletion request"
+    )]
+    async fn purge_cache_entry(
         &self,
-        tool_name: &str,
-        amount: u64,
-        payment_hash: Option<&str>,
-    ) -> Result<PaymentGateResult, String> {
-        if let Some(hash) = payment_hash {
-            let gw = self
-                .nwc_gateway
-                .as_ref()
-                .ok_or("Payment system not configured")?;
-            let paid = gw.verify_payment(hash).await.map_err(|e| e.to_string())?;
-            if !paid {
-                return Err("Payment not confirmed. Invoice may be unpaid or expired.".into());
-            }
-            return Ok(PaymentGateResult::Proceed);
+        Parameters(params): Parameters<PurgeCacheEntryParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("purge_cache_entry");
+        call.input(&format!("pubkey={}", short_id(params.pubkey.trim())));
+        if self.admin.get().is_none() {
+            return Err("purge_cache_entry requires a valid X-Admin-Key header".into());
         }
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?
+            .to_hex();
 
-        // No payment hash — check free tier
-        let under_limit = self
-            .rate_limiter
-            .check_and_increment(&self.session_id, self.config.free_tier.calls_per_day)
-            .await;
+        let counts = self
+            .cache
+            .purge_pubkey(&pubkey)
+            .await
+            .map_err(|e| format!("Failed to purge cache: {e}"))?;
+        tracing::info!(
+            "Purged {} cached row(s) for {}",
+            counts.total(),
+            short_id(&pubkey)
+        );
 
-        if under_limit {
-            return Ok(PaymentGateResult::Proceed);
-        }
+        let response = PurgeCacheEntryResponse {
+            pubkey,
+            profiles_removed: counts.profiles,
+            lists_removed: counts.replaceable_events,
+            events_removed: counts.events,
+            total_removed: counts.total(),
+        };
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
 
-        // Free tier exhausted
-        match &self.nwc_gateway {
-            Some(gw) => {
-                let description = format!("nostr-intel: {tool_name}");
-                let inv = gw
-                    .create_invoice(
-                        tool_name,
-                        amount,
-                        &description,
-                        self.config.payment.invoice_expiry_seconds,
-                    )
-                    .await
-                    .map_err(|e| e.to_string())?;
-                let resp = PaymentRequiredResponse {
-                    payment_required: true,
-                    tool_name: tool_name.into(),
-                    amount_sats: amount,
-                    invoice: inv.invoice,
-                    payment_hash: inv.payment_hash,
-                    message: format!(
-                        "Free tier exhausted. Payment required: {amount} sats. \
-                         Pay the invoice, then retry with the payment_hash parameter."
-                    ),
-                };
-                let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
-                Ok(PaymentGateResult::EarlyReturn(json))
-            }
-            None => {
-                let calls_used = self.rate_limiter.get_current_count(&self.session_id).await;
-                let resp = FreeTierExhaustedResponse {
-                    free_tier_exhausted: true,
-                    calls_used,
-                    calls_limit: self.config.free_tier.calls_per_day,
-                    message: format!(
-                        "Free tier exhausted ({calls_used}/{} calls used today). \
-                         Payment system is not currently available. \
-                         Free tier resets daily.",
-                        self.config.free_tier.calls_per_day
-                    ),
-                    payment_available: false,
-                };
-                let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
-                Ok(PaymentGateResult::EarlyReturn(json))
-            }
+    #[tool(
+        name = "reset_rate_limit",
+        description = "Operator only (requires X-Admin-Key): reset a client's free-tier counter for today, by client ID or API key"
+    )]
+    async fn reset_rate_limit(
+        &self,
+        Parameters(params): Parameters<ResetRateLimitParams>,
+    ) -> Result<String, String> {
+        let _call = self.begin_call("reset_rate_limit");
+        if self.admin.get().is_none() {
+            return Err("reset_rate_limit requires a valid X-Admin-Key header".into());
         }
+        let client_id = match (
+            params.client_id.as_deref().map(str::trim),
+            params.api_key.as_deref().map(str::trim),
+        ) {
+            (Some(id), None) if !id.is_empty() => id.to_string(),
+            (None, Some(key)) if !key.is_empty() => api_key_client_id(key),
+            _ => return Err("Provide exactly one of client_id or api_key".into()),
+        };
+
+        let previous_count = self
+            .rate_limiter
+            .reset(&client_id)
+            .await
+            .map_err(|e| format!("Failed to reset rate limit: {e}"))?;
+        tracing::info!("Reset free tier for {client_id} (had used {previous_count})");
+
+        let response = ResetRateLimitResponse {
+            client_id,
+            previous_count,
+        };
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
     }
-}
 
-// ==================== SharedState for HTTP transport ====================
+    // ==================== account_activity ====================
 
-/// Shared state that can be cloned across sessions (all fields are Arc-wrapped).
-pub struct SharedState {
-    pub config: Arc<Config>,
-    pub nostr_client: Arc<NostrClient>,
-    pub cache: Arc<Cache>,
-    pub search_client: Arc<ProfileSearchClient>,
-    pub nwc_gateway: Option<Arc<NwcGateway>>,
-    pub rate_limiter: Arc<FreeTierLimiter>,
-    pub session_counter: Arc<AtomicU64>,
-}
+    #[tool(
+        name = "account_activity",
+        description = "Profile a Nostr pubkey's posting activity: posts per day, average posts/day, most active hour (UTC), and longest gap between posts. Costs 30 sats after free tier."
+    )]
+    async fn account_activity(
+        &self,
+        Parameters(params): Parameters<AccountActivityParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("account_activity");
+        call.input(&format!(
+            "pubkey={} timeframe={:?}",
+            short_id(params.pubkey.trim()),
+            params.timeframe
+        ));
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        let timeframe_str = params.timeframe.as_deref().unwrap_or("30d");
+        let timeframe_secs =
+            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
 
-impl NostrIntelServer {
-    /// Extract the shared state from an existing server instance.
-    pub fn shared_state(&self) -> SharedState {
+        // Payment gate
+        let amount = self.config.pricing.account_activity;
+        let free_tier_remaining = match self
+            .payment_gate("account_activity", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute
+        let now = chrono::Utc::now().timestamp() as u64;
+        let since = Timestamp::from(now.saturating_sub(timeframe_secs));
+
+        let mut kinds = vec![Kind::TextNote];
+        if params.include_reactions.unwrap_or(false) {
+            kinds.extend([Kind::Repost, Kind::Reaction]);
+        }
+
+        call.relays(self.nostr_client.active_relay_count().await);
+        let events = self
+            .nostr_client
+            .fetch_recent_notes_by_authors(&[pubkey], kinds, since, MAX_ACTIVITY_EVENTS)
+            .await
+            .map_err(|e| format!("Failed to fetch events: {e}"))?;
+
+        let stats = posting_activity(&events, timeframe_secs);
+        let response = AccountActivityResponse {
+            pubkey: pubkey.to_hex(),
+            timeframe: timeframe_str.to_string(),
+            total_events: stats.total_events,
+            avg_posts_per_day: stats.avg_posts_per_day,
+            most_active_hour_utc: stats.most_active_hour_utc,
+            longest_gap: stats.longest_gap,
+            posts_per_day: stats.posts_per_day,
+            truncated: events.len() >= MAX_ACTIVITY_EVENTS,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    // ==================== get_dossier ====================
+
+    #[tool(
+        name = "get_dossier",
+        description = "One-call overview of a Nostr pubkey: profile, NIP-65 relays, following/follower counts, posting cadence, and zap totals (fetched concurrently). Costs 100 sats after free tier, less than the individual tools combined."
+    )]
+    async fn get_dossier(
+        &self,
+        Parameters(params): Parameters<GetDossierParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("get_dossier");
+        call.input(&format!(
+            "pubkey={} timeframe={:?}",
+            short_id(params.pubkey.trim()),
+            params.timeframe
+        ));
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        let timeframe_str = params.timeframe.as_deref().unwrap_or("30d");
+        let timeframe_secs =
+            parse_timeframe(timeframe_str).map_err(|e| format!("Invalid timeframe: {e}"))?;
+
+        // Payment gate
+        let amount = self.config.pricing.get_dossier;
+        let free_tier_remaining = match self
+            .payment_gate("get_dossier", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute: every section is fetched at once and fails independently
+        let now = chrono::Utc::now().timestamp() as u64;
+        let since = Timestamp::from(now.saturating_sub(timeframe_secs));
+        let follower_filter = Filter::new()
+            .kind(Kind::ContactList)
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::P), pubkey.to_hex())
+            .limit(100);
+        let authors = [pubkey];
+
+        call.relays(self.nostr_client.active_relay_count().await);
+        let (profile, relay_list, contact_list, follower_lists, notes, zap_receipts) = tokio::join!(
+            self.load_profile(&pubkey),
+            self.nostr_client.fetch_relay_list(&pubkey),
+            self.nostr_client.fetch_contact_list(&pubkey),
+            self.nostr_client
+                .fetch(follower_filter, std::time::Duration::from_secs(15)),
+            self.nostr_client.fetch_recent_notes_by_authors(
+                &authors,
+                vec![Kind::TextNote],
+                since,
+                MAX_ACTIVITY_EVENTS,
+            ),
+            self.nostr_client.fetch_zap_receipts(&pubkey, Some(since)),
+        );
+
+        let mut errors = Vec::new();
+        let mut section = |name: &str, err: String| errors.push(format!("{name}: {err}"));
+
+        let profile = match profile {
+            Ok((profile, _)) => profile.map(|p| profile_response(p, None)),
+            Err(e) => {
+                section("profile", e);
+                None
+            }
+        };
+
+        let relays = match relay_list {
+            Ok(events) => {
+                let (write_relays, read_relays) =
+                    events.first().map(parse_relay_list).unwrap_or_default();
+                Some(DossierRelays {
+                    write_relays,
+                    read_relays,
+                })
+            }
+            Err(e) => {
+                section("relays", e.to_string());
+                None
+            }
+        };
+
+        let social = match (contact_list, follower_lists) {
+            (Ok(contact_list), Ok(follower_lists)) => {
+                let followers: HashSet<PublicKey> =
+                    follower_lists.iter().map(|e| e.pubkey).collect();
+                Some(DossierSocial {
+                    following_count: contact_list.as_ref().map(following_count).unwrap_or(0),
+                    followers_count: followers.len() as u32,
+                })
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                section("social", e.to_string());
+                None
+            }
+        };
+
+        let activity = match notes {
+            Ok(notes) => {
+                let stats = posting_activity(&notes, timeframe_secs);
+                Some(DossierActivity {
+                    total_events: stats.total_events,
+                    avg_posts_per_day: stats.avg_posts_per_day,
+                    most_active_hour_utc: stats.most_active_hour_utc,
+                    longest_gap: stats.longest_gap,
+                    truncated: notes.len() >= MAX_ACTIVITY_EVENTS,
+                })
+            }
+            Err(e) => {
+                section("activity", e.to_string());
+                None
+            }
+        };
+
+        let zaps = match zap_receipts {
+            Ok(receipts) => Some(zap_totals(&receipts)),
+            Err(e) => {
+                section("zaps", e.to_string());
+                None
+            }
+        };
+
+        let response = GetDossierResponse {
+            pubkey: pubkey.to_hex(),
+            npub: pubkey.to_bech32().map_err(|e| e.to_string())?,
+            timeframe: timeframe_str.to_string(),
+            profile,
+            relays,
+            social,
+            activity,
+            zaps,
+            errors,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    // ==================== get_lists ====================
+
+    #[tool(
+        name = "get_lists",
+        description = "Fetch a Nostr pubkey's NIP-51 lists (mute list, pin list, follow sets, generic lists, bookmark sets) with each list's identifier, title, kind, and public members, member names resolved. Costs 20 sats after free tier."
+    )]
+    async fn get_lists(
+        &self,
+        Parameters(params): Parameters<GetListsParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("get_lists");
+        call.input(&format!(
+            "pubkey={} kinds={:?}",
+            short_id(params.pubkey.trim()),
+            params.kinds
+        ));
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+        let kinds: Vec<Kind> = match params.kinds {
+            Some(kinds) => {
+                if let Some(bad) = kinds.iter().find(|k| !LIST_KINDS.contains(k)) {
+                    return Err(format!(
+                        "Unsupported list kind {bad}; expected one of {LIST_KINDS:?}"
+                    ));
+                }
+                kinds.into_iter().map(Kind::from).collect()
+            }
+            None => LIST_KINDS.iter().copied().map(Kind::from).collect(),
+        };
+
+        // Payment gate
+        let amount = self.config.pricing.get_lists;
+        let free_tier_remaining = match self
+            .payment_gate("get_lists", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute
+        call.relays(self.nostr_client.active_relay_count().await);
+        let events = self
+            .nostr_client
+            .fetch_lists(&pubkey, kinds)
+            .await
+            .map_err(|e| format!("Failed to fetch lists: {e}"))?;
+
+        let mut lists: Vec<NostrList> = events.iter().map(nostr_list).collect();
+        let members: Vec<String> = lists
+            .iter()
+            .flat_map(|l| l.pubkeys.iter().map(|m| m.pubkey.clone()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let names = self.resolve_names(&members).await;
+        for member in lists.iter_mut().flat_map(|l| l.pubkeys.iter_mut()) {
+            member.name = names.get(&member.pubkey).cloned();
+        }
+
+        let response = GetListsResponse {
+            pubkey: pubkey.to_hex(),
+            lists,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    // ==================== get_bookmarks ====================
+
+    #[tool(
+        name = "get_bookmarks",
+        description = "Fetch a Nostr pubkey's NIP-51 bookmark list (kind:10003) and resolve the bookmarked notes into content previews with their authors. Costs 20 sats after free tier."
+    )]
+    async fn get_bookmarks(
+        &self,
+        Parameters(params): Parameters<GetBookmarksParams>,
+    ) -> Result<String, String> {
+        let call = self.begin_call("get_bookmarks");
+        call.input(&format!("pubkey={}", short_id(params.pubkey.trim())));
+        let pubkey = NostrClient::parse_pubkey(params.pubkey.trim())
+            .map_err(|e| format!("Invalid pubkey: {e}"))?;
+
+        // Payment gate
+        let amount = self.config.pricing.get_bookmarks;
+        let free_tier_remaining = match self
+            .payment_gate("get_bookmarks", amount, params.payment_hash.as_deref())
+            .await?
+        {
+            PaymentGateResult::EarlyReturn(json) => return Ok(json),
+            PaymentGateResult::Proceed {
+                free_tier_remaining,
+            } => free_tier_remaining,
+        };
+
+        // Execute
+        call.relays(self.nostr_client.active_relay_count().await);
+        let list = self
+            .nostr_client
+            .fetch_bookmark_list(&pubkey)
+            .await
+            .map_err(|e| format!("Failed to fetch bookmark list: {e}"))?;
+
+        let ids = list.as_ref().map(bookmarked_event_ids).unwrap_or_default();
+        let total_bookmarks = ids.len() as u32;
+        let ids: Vec<EventId> = ids.into_iter().take(MAX_BOOKMARKS).collect();
+        let mut notes: HashMap<EventId, Event> = self
+            .nostr_client
+            .fetch_events_by_ids(ids.clone())
+            .await
+            .map_err(|e| format!("Failed to fetch bookmarked notes: {e}"))?
+            .into_iter()
+            .map(|e| (e.id, e))
+            .collect();
+
+        let authors: Vec<String> = notes
+            .values()
+            .map(|e| e.pubkey.to_hex())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let names = self.resolve_names(&authors).await;
+
+        let mut bookmarks = Vec::new();
+        let mut missing_ids = Vec::new();
+        for id in ids {
+            let Some(note) = notes.remove(&id) else {
+                missing_ids.push(id.to_hex());
+                continue;
+            };
+            let author_pubkey = note.pubkey.to_hex();
+            bookmarks.push(BookmarkedNote {
+                id: id.to_hex(),
+                kind: note.kind.as_u16() as u32,
+                author_name: names.get(&author_pubkey).cloned(),
+                author_pubkey,
+                content_preview: truncate_content(&note.content, 280),
+                created_at: note.created_at.as_secs(),
+            });
+        }
+
+        let response = GetBookmarksResponse {
+            pubkey: pubkey.to_hex(),
+            updated_at: list.map(|l| l.created_at.as_secs()),
+            bookmarks,
+            missing_ids,
+            total_bookmarks,
+            skipped_relays: self.nostr_client.skipped_relays().await,
+            free_tier_remaining,
+        };
+
+        serde_json::to_string_pretty(&response).map_err(|e| e.to_string())
+    }
+
+    /// Re-check the pool relays' NIP-11 documents (cache-first) and stop querying
+    /// relays that require NIP-42 auth, which this server can't satisfy.
+    pub async fn refresh_relay_requirements(&self) {
+        let relays = self.nostr_client.relay_urls().to_vec();
+        let results =
+            futures::future::join_all(relays.iter().map(|url| self.check_relay_inner(url))).await;
+
+        let mut auth_required = HashSet::new();
+        for (url, result) in relays.into_iter().zip(results) {
+            match result {
+                Ok((info, _)) if info.requires_auth => {
+                    tracing::warn!("Relay {url} requires NIP-42 auth; skipping it for queries");
+                    auth_required.insert(url);
+                }
+                Ok((info, _)) if info.requires_payment => {
+                    tracing::warn!("Relay {url} requires payment; queries may return nothing");
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("NIP-11 check for {url} failed: {e}"),
+            }
+        }
+        self.nostr_client.set_auth_required(auth_required).await;
+    }
+
+    /// Check one relay, serving from cache when possible. Returns the status and
+    /// whether it was a cache hit.
+    async fn check_relay_inner(
+        &self,
+        relay_url: &str,
+    ) -> Result<(CheckRelayResponse, bool), String> {
+        if let Ok(Some(cached)) = self.cache.get_relay_info(relay_url).await {
+            tracing::debug!("Cache hit for relay: {relay_url}");
+            Metrics::inc(&self.metrics.cache_hits);
+            return Ok((check_relay_response(cached), true));
+        }
+
+        Metrics::inc(&self.metrics.cache_misses);
+
+        // Concurrent checks of the same relay share one NIP-11 fetch
+        let response = self
+            .relay_info_flights
+            .run(relay_url, || self.fetch_relay_info(relay_url))
+            .await?;
+        Ok((response, false))
+    }
+
+    /// Fetch a relay's NIP-11 document and cache it.
+    async fn fetch_relay_info(&self, relay_url: &str) -> Result<CheckRelayResponse, String> {
+        // Hidden services can't be reached over clearnet HTTP; only try through a proxy
+        let onion = is_onion_relay(relay_url);
+        if onion && self.config.network.socks_proxy.is_empty() {
+            return Ok(CheckRelayResponse {
+                requires_tor: true,
+                ..CheckRelayResponse::skipped(
+                    "Onion relay: requires Tor, so the NIP-11 check was skipped".into(),
+                )
+            });
+        }
+
+        // Convert wss:// to https:// for NIP-11 fetch
+        let http_url = relay_url
+            .replace("wss://", "https://")
+            .replace("ws://", "http://");
+
+        let start = std::time::Instant::now();
+
+        let result = self
+            .http
+            .get(&http_url)
+            .header("Accept", "application/nostr+json")
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if resp.status().is_success() => {
+                let latency_ms = start.elapsed().as_millis() as u64;
+
+                // Relays behind CDNs sometimes answer with an HTML page instead
+                let media_type = nip05::media_type(&resp);
+                if !nip05::is_json_media_type(&media_type) {
+                    let got = if media_type.is_empty() {
+                        "no Content-Type"
+                    } else {
+                        &media_type
+                    };
+                    return Err(format!("Relay did not return NIP-11 JSON (got {got})"));
+                }
+                let json: serde_json::Value = resp
+                    .json()
+                    .await
+                    .map_err(|e| format!("Failed to parse NIP-11: {e}"))?;
+
+                let cached = nip11_relay_info(relay_url, &json, latency_ms);
+                if let Err(e) = self.cache.set_relay_info(&cached).await {
+                    tracing::warn!("Failed to cache relay info: {e}");
+                }
+
+                Ok(CheckRelayResponse {
+                    requires_tor: onion,
+                    ..check_relay_response(cached)
+                })
+            }
+            Ok(resp) => Ok(CheckRelayResponse::offline(format!(
+                "HTTP error: {}",
+                resp.status()
+            ))),
+            Err(e) => Ok(CheckRelayResponse::offline(format!(
+                "Connection failed: {e}"
+            ))),
+        }
+    }
+
+    /// Identity used for free-tier accounting: the API-key-derived ID when one was
+    /// bound at initialize, otherwise the per-connection session ID.
+    fn client_id(&self) -> &str {
+        self.api_key_session.get().unwrap_or(&self.session_id)
+    }
+
+    /// Bind this session to a stable client ID derived from an API key.
+    /// With `server.api_keys` configured, only listed keys are accepted; otherwise any
+    /// non-empty key is used as-is. A missing key keeps the session ID, unless
+    /// `free_tier.identity = "api_key"` makes a key mandatory.
+    fn bind_api_key(&self, api_key: Option<&str>) -> Result<(), String> {
+        let Some(key) = api_key.map(str::trim).filter(|k| !k.is_empty()) else {
+            if self.config.free_tier.identity == FreeTierIdentity::ApiKey {
+                return Err("X-API-Key header required".into());
+            }
+            return Ok(());
+        };
+
+        let allowed = &self.config.server.api_keys;
+        if !allowed.is_empty() && !allowed.iter().any(|k| k == key) {
+            return Err("Unknown API key".into());
+        }
+
+        let _ = self.api_key_session.set(api_key_client_id(key));
+        Ok(())
+    }
+
+    /// Mark this session as an operator session if `admin_key` is configured. A key
+    /// that isn't configured is rejected rather than silently ignored.
+    fn bind_admin_key(&self, admin_key: Option<&str>) -> Result<(), String> {
+        let Some(key) = admin_key.map(str::trim).filter(|k| !k.is_empty()) else {
+            return Ok(());
+        };
+        if !self.config.server.admin_keys.iter().any(|k| k == key) {
+            return Err("Unknown admin key".into());
+        }
+        let _ = self.admin.set(());
+        Ok(())
+    }
+
+    /// Start logging/metrics for one tool invocation. Hold the returned guard for the
+    /// duration of the call; it emits the completion event when dropped.
+    fn begin_call(&self, tool: &'static str) -> ToolCallLog {
+        self.metrics.record_tool_call(tool);
+        ToolCallLog::new(tool, self.config.server.log_tool_calls)
+    }
+
+    /// Resolve display names for a set of hex pubkeys with one batched cache query, then
+    /// a single relay metadata fetch for whatever the cache didn't have. Relay hits are
+    /// written back to the cache. Pubkeys without a name are absent from the result.
+    async fn resolve_names(&self, pubkeys: &[String]) -> HashMap<String, String> {
+        let mut names: HashMap<String, String> = HashMap::new();
+
+        let cached = self
+            .cache
+            .get_profiles_batch(pubkeys)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Batch profile lookup failed: {e}");
+                HashMap::new()
+            });
+        for (pk, profile) in &cached {
+            if let Some(name) = profile.name.clone().or(profile.display_name.clone()) {
+                names.insert(pk.clone(), name);
+            }
+        }
+
+        let missing: Vec<PublicKey> = pubkeys
+            .iter()
+            .filter(|pk| !cached.contains_key(*pk))
+            .filter_map(|pk| PublicKey::from_hex(pk).ok())
+            .collect();
+        if missing.is_empty() {
+            return names;
+        }
+
+        let events = match self.nostr_client.fetch_metadata_batch(&missing).await {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::debug!("Metadata fetch for {} pubkeys failed: {e}", missing.len());
+                return names;
+            }
+        };
+
+        // Keep only the newest kind:0 per author
+        let mut latest: HashMap<PublicKey, &Event> = HashMap::new();
+        for event in &events {
+            let entry = latest.entry(event.pubkey).or_insert(event);
+            if event.created_at > entry.created_at {
+                *entry = event;
+            }
+        }
+
+        for (pubkey, event) in latest {
+            let Ok(meta) = Metadata::from_json(&event.content) else {
+                continue;
+            };
+            let pubkey_hex = pubkey.to_hex();
+            let cached = CachedProfile {
+                pubkey: pubkey_hex.clone(),
+                name: meta.name.clone(),
+                display_name: meta.display_name.clone(),
+                about: meta.about,
+                picture: meta.picture,
+                banner: meta.banner,
+                nip05: meta.nip05,
+                lud16: meta.lud16,
+                website: meta.website,
+            };
+            if let Err(e) = self.cache.set_profile(&cached).await {
+                tracing::warn!("Failed to cache profile: {e}");
+            }
+            if let Some(name) = meta.name.or(meta.display_name) {
+                names.insert(pubkey_hex, name);
+            }
+        }
+
+        names
+    }
+
+    // ==================== payment gate ====================
+
+    /// Free tier is exhausted but NWC couldn't produce an invoice: quote x402 when
+    /// it's enabled, otherwise explain the (likely temporary) outage.
+    async fn invoice_unavailable(
+        &self,
+        tool_name: &str,
+        amount: u64,
+    ) -> Result<PaymentGateResult, String> {
+        let payment = &self.config.payment;
+        if payment.enable_x402 {
+            let cents = amount.div_ceil(payment.x402_sats_per_cent).max(1);
+            let resp = X402PaymentRequiredResponse {
+                payment_required: true,
+                tool_name: tool_name.into(),
+                amount_sats: amount,
+                protocol: "x402".into(),
+                x402: crate::payment::x402::create_payment_details(cents, &payment.x402_address),
+                message: format!(
+                    "Free tier exhausted and Lightning invoicing is temporarily unavailable. \
+                     Payment required: {amount} sats, payable via x402 instead."
+                ),
+            };
+            let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
+            return Ok(PaymentGateResult::EarlyReturn(json));
+        }
+
+        let calls_used = self.rate_limiter.get_current_count(self.client_id()).await;
+        let resp = FreeTierExhaustedResponse {
+            free_tier_exhausted: true,
+            calls_used,
+            calls_limit: self.config.free_tier.calls_per_day,
+            message: format!(
+                "Free tier exhausted ({calls_used}/{} calls used today). \
+                 The Lightning wallet is temporarily unreachable, so no invoice could be \
+                 created. Try again shortly; the free tier also resets daily.",
+                self.config.free_tier.calls_per_day
+            ),
+            payment_available: false,
+        };
+        let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
+        Ok(PaymentGateResult::EarlyReturn(json))
+    }
+
+    /// Unified payment gate for all paid tools.
+    /// - With payment_hash already settled for this tool (within the reuse window): return Proceed
+    /// - With a new payment_hash: verify via NWC, check it covers the price, remember it, return Proceed
+    /// - Under free tier: increment counter, return Proceed with the remaining quota
+    /// - Over limit + NWC: create invoice, return EarlyReturn(PaymentRequiredResponse)
+    /// - Over limit + no NWC: return EarlyReturn(FreeTierExhaustedResponse) — Ok, not Err!
+    async fn payment_gate(
+        &self,
+        tool_name: &str,
+        amount: u64,
+        payment_hash: Option<&str>,
+    ) -> Result<PaymentGateResult, String> {
+        if let Some(hash) = payment_hash {
+            // Reuse a hash already settled for this tool within the reuse window
+            match self.cache.get_settled_payment(hash).await {
+                Ok(Some((paid_tool, settled_at))) => {
+                    if paid_tool != tool_name {
+                        return Err(format!(
+                            "Payment hash was already used for {paid_tool}, not {tool_name}"
+                        ));
+                    }
+                    let window = self.config.payment.payment_reuse_window_seconds as i64;
+                    if chrono::Utc::now().timestamp() - settled_at > window {
+                        return Err(
+                            "Payment hash reuse window has expired. Request a new invoice.".into(),
+                        );
+                    }
+                    Metrics::inc(&self.metrics.gate_paid);
+                    return Ok(PaymentGateResult::Proceed {
+                        free_tier_remaining: None,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Settled payment lookup failed: {e}"),
+            }
+
+            let gw = self
+                .nwc_gateway
+                .as_ref()
+                .ok_or("Payment system not configured")?;
+            // The webhook reports what was actually paid, not today's price
+            let amount_sats = match gw.verify_payment(hash).await.map_err(|e| e.to_string())? {
+                PaymentStatus::Settled { amount_sats } => amount_sats,
+                PaymentStatus::Expired => {
+                    return Err("Invoice expired without payment. Request a new one.".into());
+                }
+                PaymentStatus::Unpaid => {
+                    return Err("Payment not confirmed. Pay the invoice, then retry.".into());
+                }
+            };
+            // Checked before the hash is bound to this tool, so an invoice for a
+            // cheaper tool can't be spent here first
+            if amount_sats < amount {
+                return Err(format!(
+                    "Payment of {amount_sats} sats does not cover {tool_name} ({amount} sats)"
+                ));
+            }
+            let first_settlement = match self.cache.record_settled_payment(hash, tool_name).await {
+                Ok(recorded) => recorded,
+                Err(e) => {
+                    tracing::warn!("Failed to record settled payment: {e}");
+                    true
+                }
+            };
+            let webhook_url = &self.config.payment.payment_webhook_url;
+            if first_settlement && !webhook_url.is_empty() {
+                webhook::notify(
+                    self.http.clone(),
+                    webhook_url.clone(),
+                    SettlementNotice {
+                        payment_hash: hash.to_string(),
+                        tool_name: tool_name.to_string(),
+                        amount_sats,
+                        settled_at: chrono::Utc::now().timestamp(),
+                    },
+                );
+            }
+            Metrics::inc(&self.metrics.gate_paid);
+            return Ok(PaymentGateResult::Proceed {
+                free_tier_remaining: None,
+            });
+        }
+
+        // No payment hash — check free tier
+        let under_limit = self
+            .rate_limiter
+            .check_and_increment(self.client_id(), self.config.free_tier.calls_per_day)
+            .await;
+
+        if under_limit {
+            Metrics::inc(&self.metrics.gate_free);
+            let used = self.rate_limiter.get_current_count(self.client_id()).await;
+            return Ok(PaymentGateResult::Proceed {
+                free_tier_remaining: Some(self.config.free_tier.calls_per_day.saturating_sub(used)),
+            });
+        }
+
+        // Free tier exhausted
+        Metrics::inc(&self.metrics.gate_exhausted);
+        match &self.nwc_gateway {
+            Some(gw) => {
+                let description = format!("nostr-intel: {tool_name}");
+                let inv = match gw
+                    .create_invoice(
+                        tool_name,
+                        amount,
+                        &description,
+                        self.config.payment.invoice_expiry_seconds,
+                    )
+                    .await
+                {
+                    Ok(inv) => inv,
+                    Err(e) => {
+                        tracing::warn!("NWC invoice creation failed for {tool_name}: {e}");
+                        return self.invoice_unavailable(tool_name, amount).await;
+                    }
+                };
+                Metrics::inc(&self.metrics.invoices_created);
+                let resp = PaymentRequiredResponse {
+                    payment_required: true,
+                    tool_name: tool_name.into(),
+                    amount_sats: amount,
+                    amount_usd_estimate: self.fiat.usd_for_sats(amount).await,
+                    invoice: inv.invoice,
+                    payment_hash: inv.payment_hash,
+                    message: format!(
+                        "Free tier exhausted. Payment required: {amount} sats. \
+                         Pay the invoice, then retry with the payment_hash parameter."
+                    ),
+                };
+                let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
+                Ok(PaymentGateResult::EarlyReturn(json))
+            }
+            None => {
+                let calls_used = self.rate_limiter.get_current_count(self.client_id()).await;
+                let resp = FreeTierExhaustedResponse {
+                    free_tier_exhausted: true,
+                    calls_used,
+                    calls_limit: self.config.free_tier.calls_per_day,
+                    message: format!(
+                        "Free tier exhausted ({calls_used}/{} calls used today). \
+                         Payment system is not currently available. \
+                         Free tier resets daily.",
+                        self.config.free_tier.calls_per_day
+                    ),
+                    payment_available: false,
+                };
+                let json = serde_json::to_string_pretty(&resp).map_err(|e| e.to_string())?;
+                Ok(PaymentGateResult::EarlyReturn(json))
+            }
+        }
+    }
+}
+
+// ==================== SharedState for HTTP transport ====================
+
+/// Shared state that can be cloned across sessions (all fields are Arc-wrapped).
+pub struct SharedState {
+    pub config: Arc<Config>,
+    pub nostr_client: Arc<NostrClient>,
+    pub relay_health: Arc<RelayHealthTracker>,
+    pub cache: Arc<Cache>,
+    pub search_client: Arc<ProfileSearchClient>,
+    pub nwc_gateway: Option<Arc<NwcPool>>,
+    pub rate_limiter: Arc<FreeTierLimiter>,
+    pub metrics: Arc<Metrics>,
+    pub http: reqwest::Client,
+    pub nip05: Arc<Nip05Client>,
+    pub fiat: Arc<FiatRate>,
+    pub ip_limiter: Option<Arc<IpRateLimiter>>,
+    pub profile_flights: Arc<SingleFlight<Result<Option<Metadata>, String>>>,
+    pub relay_info_flights: Arc<SingleFlight<Result<CheckRelayResponse, String>>>,
+    pub session_counter: Arc<AtomicU64>,
+    /// Router with operator overrides applied, cloned into each session
+    pub tool_router: ToolRouter<NostrIntelServer>,
+}
+
+impl SharedState {
+    /// Probe pool relays' NIP-11 requirements (see `refresh_relay_requirements`).
+    pub async fn refresh_relay_requirements(&self) {
+        NostrIntelServer::from_shared(self)
+            .refresh_relay_requirements()
+            .await;
+    }
+
+    /// Flush the SQLite pool and close relay connections. Called on graceful shutdown.
+    pub async fn shutdown(&self) {
+        tracing::info!("Closing relay connections");
+        self.nostr_client.shutdown().await;
+        tracing::info!("Flushing SQLite cache");
+        self.cache.close().await;
+    }
+}
+
+impl NostrIntelServer {
+    /// Extract the shared state from an existing server instance.
+    pub fn shared_state(&self) -> SharedState {
         SharedState {
             config: Arc::clone(&self.config),
             nostr_client: Arc::clone(&self.nostr_client),
+            relay_health: Arc::clone(&self.relay_health),
             cache: Arc::clone(&self.cache),
             search_client: Arc::clone(&self.search_client),
             nwc_gateway: self.nwc_gateway.clone(),
             rate_limiter: Arc::clone(&self.rate_limiter),
+            metrics: Arc::clone(&self.metrics),
+            http: self.http.clone(),
+            nip05: Arc::clone(&self.nip05),
+            fiat: Arc::clone(&self.fiat),
+            ip_limiter: self.ip_limiter.clone(),
+            profile_flights: Arc::clone(&self.profile_flights),
+            relay_info_flights: Arc::clone(&self.relay_info_flights),
             session_counter: Arc::new(AtomicU64::new(0)),
+            tool_router: self.tool_router.clone(),
+        }
+    }
+
+    /// Create a new server instance from shared state (for per-session HTTP factory).
+    pub fn from_shared(state: &SharedState) -> Self {
+        let id = state.session_counter.fetch_add(1, Ordering::Relaxed);
+        Self {
+            config: Arc::clone(&state.config),
+            nostr_client: Arc::clone(&state.nostr_client),
+            relay_health: Arc::clone(&state.relay_health),
+            cache: Arc::clone(&state.cache),
+            search_client: Arc::clone(&state.search_client),
+            nwc_gateway: state.nwc_gateway.clone(),
+            rate_limiter: Arc::clone(&state.rate_limiter),
+            metrics: Arc::clone(&state.metrics),
+            http: state.http.clone(),
+            nip05: Arc::clone(&state.nip05),
+            fiat: Arc::clone(&state.fiat),
+            ip_limiter: state.ip_limiter.clone(),
+            profile_flights: Arc::clone(&state.profile_flights),
+            relay_info_flights: Arc::clone(&state.relay_info_flights),
+            session_id: session_identity(&state.config, &format!("http-{id}")),
+            api_key_session: OnceLock::new(),
+            admin: OnceLock::new(),
+            tool_router: state.tool_router.clone(),
+        }
+    }
+}
+
+/// Stable client ID for an API key. The key is hashed so raw keys never land
+/// in the rate-limit table or logs.
+fn api_key_client_id(key: &str) -> String {
+    let digest = hex::encode(Sha256::digest(key.as_bytes()));
+    format!("key-{}", &digest[..16])
+}
+
+/// Free-tier identity for a new session without an API key, per
+/// `free_tier.identity`. `connection_id` is used for the `session` and
+/// `api_key` modes.
+fn session_identity(config: &Config, connection_id: &str) -> String {
+    static PROCESS_ID: OnceLock<String> = OnceLock::new();
+    match config.free_tier.identity {
+        FreeTierIdentity::Process => PROCESS_ID
+            .get_or_init(|| {
+                let seed = format!(
+                    "{}-{}",
+                    std::process::id(),
+                    chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+                );
+                let digest = hex::encode(Sha256::digest(seed.as_bytes()));
+                format!("proc-{}", &digest[..16])
+            })
+            .clone(),
+        FreeTierIdentity::Env => {
+            let value = std::env::var(&config.free_tier.identity_env).unwrap_or_default();
+            format!("env-{}", value.trim())
+        }
+        FreeTierIdentity::Session | FreeTierIdentity::ApiKey => connection_id.into(),
+    }
+}
+
+#[cfg(test)]
+impl NostrIntelServer {
+    /// Server backed by an in-memory cache and an empty relay pool, for tests.
+    pub async fn new_for_test() -> Self {
+        let config = Arc::new(Config::test_default());
+        let search_client = Arc::new(ProfileSearchClient::new(&config.search.primal_api_url, 1));
+        let cache = Arc::new(Cache::new_in_memory().await);
+        let relay_health = Arc::new(RelayHealthTracker::new());
+        let nostr_client =
+            NostrClient::new(vec![], Arc::clone(&relay_health), PoolOptions::default())
+                .await
+                .expect("empty relay pool");
+
+        Self {
+            config,
+            nostr_client: Arc::new(nostr_client),
+            relay_health,
+            search_client,
+            nwc_gateway: None,
+            rate_limiter: Arc::new(FreeTierLimiter::new(Arc::clone(&cache))),
+            cache,
+            metrics: Arc::new(Metrics::new()),
+            http: reqwest::Client::new(),
+            nip05: Arc::new(Nip05Client::new(
+                reqwest::Client::new(),
+                std::time::Duration::from_secs(5),
+            )),
+            fiat: Arc::new(FiatRate::new("", 600)),
+            ip_limiter: None,
+            profile_flights: Arc::new(SingleFlight::new()),
+            relay_info_flights: Arc::new(SingleFlight::new()),
+            session_id: "test".into(),
+            api_key_session: OnceLock::new(),
+            admin: OnceLock::new(),
+            tool_router: Self::tool_router(),
+        }
+    }
+}
+
+// ==================== decode logic ====================
+
+fn decode_nostr_uri_inner(uri: &str) -> Result<DecodeNostrUriResponse, String> {
+    let uri = uri.trim();
+    let bech32 = uri.strip_prefix("nostr:").unwrap_or(uri);
+
+    let nip19 = Nip19::from_bech32(bech32).map_err(|e| format!("Invalid Nostr URI: {e}"))?;
+
+    match nip19 {
+        Nip19::Pubkey(pk) => Ok(DecodeNostrUriResponse {
+            entity_type: "pubkey".into(),
+            hex_id: pk.to_hex(),
+            relays: None,
+            author_hex: None,
+            kind: None,
+        }),
+        Nip19::EventId(id) => Ok(DecodeNostrUriResponse {
+            entity_type: "event_id".into(),
+            hex_id: id.to_hex(),
+            relays: None,
+            author_hex: None,
+            kind: None,
+        }),
+        Nip19::Profile(profile) => {
+            let relays: Vec<String> = profile.relays.into_iter().map(|r| r.to_string()).collect();
+            Ok(DecodeNostrUriResponse {
+                entity_type: "profile".into(),
+                hex_id: profile.public_key.to_hex(),
+                relays: if relays.is_empty() {
+                    None
+                } else {
+                    Some(relays)
+                },
+                author_hex: None,
+                kind: None,
+            })
+        }
+        Nip19::Event(event) => {
+            let relays: Vec<String> = event.relays.into_iter().map(|r| r.to_string()).collect();
+            Ok(DecodeNostrUriResponse {
+                entity_type: "event".into(),
+                hex_id: event.event_id.to_hex(),
+                relays: if relays.is_empty() {
+                    None
+                } else {
+                    Some(relays)
+                },
+                author_hex: event.author.map(|a| a.to_hex()),
+                kind: event.kind.map(|k| k.as_u16() as u32),
+            })
+        }
+        Nip19::Coordinate(coord) => {
+            let relays: Vec<String> = coord.relays.into_iter().map(|r| r.to_string()).collect();
+            Ok(DecodeNostrUriResponse {
+                entity_type: "coordinate".into(),
+                hex_id: coord.coordinate.identifier.clone(),
+                relays: if relays.is_empty() {
+                    None
+                } else {
+                    Some(relays)
+                },
+                author_hex: Some(coord.coordinate.public_key.to_hex()),
+                kind: Some(coord.coordinate.kind.as_u16() as u32),
+            })
+        }
+        _ => Err("Unsupported NIP-19 entity type".into()),
+    }
+}
+
+/// Convert a hex or npub pubkey into both forms.
+fn convert_key_inner(input: &str) -> Result<ConvertKeyResponse, String> {
+    let input = input.strip_prefix("nostr:").unwrap_or(input);
+    let pubkey = NostrClient::parse_pubkey(input).map_err(|e| e.to_string())?;
+    let input_format = if input.starts_with("npub1") {
+        "npub"
+    } else {
+        "hex"
+    };
+
+    Ok(ConvertKeyResponse {
+        hex: pubkey.to_hex(),
+        npub: pubkey.to_bech32().map_err(|e| e.to_string())?,
+        input_format: input_format.into(),
+    })
+}
+
+/// Encode an event pointer as `nevent` (and `naddr` when an identifier is
+/// given), validating every hint.
+fn build_share_link_inner(params: &BuildShareLinkParams) -> Result<BuildShareLinkResponse, String> {
+    let event_id = params.event_id.trim();
+    let event_id = EventId::parse(event_id.strip_prefix("nostr:").unwrap_or(event_id))
+        .map_err(|e| format!("Invalid event_id: {e}"))?;
+    let author = params
+        .author
+        .as_deref()
+        .map(|a| NostrClient::parse_pubkey(a.trim()).map_err(|e| format!("Invalid author: {e}")))
+        .transpose()?;
+    let kind = params
+        .kind
+        .map(|k| {
+            u16::try_from(k)
+                .map(Kind::from)
+                .map_err(|_| format!("Invalid kind {k}: must be at most 65535"))
+        })
+        .transpose()?;
+    let relays = params.relays.as_deref().unwrap_or_default();
+    if relays.len() > MAX_SHARE_RELAYS {
+        return Err(format!("At most {MAX_SHARE_RELAYS} relay hints"));
+    }
+    let relays = relays
+        .iter()
+        .map(|r| RelayUrl::parse(r.trim()).map_err(|e| format!("Invalid relay '{r}': {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut nevent = Nip19Event::new(event_id).relays(relays.clone());
+    if let Some(author) = author {
+        nevent = nevent.author(author);
+    }
+    if let Some(kind) = kind {
+        nevent = nevent.kind(kind);
+    }
+    let nevent = nevent.to_bech32().map_err(|e| e.to_string())?;
+
+    let naddr = match params.identifier.as_deref() {
+        Some(identifier) => {
+            let (Some(author), Some(kind)) = (author, kind) else {
+                return Err("identifier requires author and kind".into());
+            };
+            if !kind.is_addressable() {
+                return Err(format!(
+                    "identifier requires an addressable kind (30000-39999), got {kind}"
+                ));
+            }
+            let coordinate = Coordinate::new(kind, author).identifier(identifier);
+            Some(
+                Nip19Coordinate::new(coordinate, relays)
+                    .to_bech32()
+                    .map_err(|e| e.to_string())?,
+            )
+        }
+        None => None,
+    };
+
+    Ok(BuildShareLinkResponse {
+        njump_url: format!("{NJUMP_BASE_URL}{nevent}"),
+        nevent,
+        naddr_njump_url: naddr.as_ref().map(|n| format!("{NJUMP_BASE_URL}{n}")),
+        naddr,
+    })
+}
+
+/// Web gateway used for share links
+const NJUMP_BASE_URL: &str = "https://njump.me/";
+/// Relay hints per share link; more only make the link unwieldy
+const MAX_SHARE_RELAYS: usize = 5;
+
+/// Sends MCP progress notifications for long-running tools. Does nothing
+/// unless the client asked for progress with a `progressToken`.
+#[derive(Clone, Default)]
+struct ProgressReporter {
+    target: Option<(Peer<RoleServer>, ProgressToken)>,
+}
+
+impl ProgressReporter {
+    fn new(meta: &Meta, peer: Peer<RoleServer>) -> Self {
+        Self {
+            target: meta.get_progress_token().map(|token| (peer, token)),
+        }
+    }
+
+    async fn report(&self, progress: u32, total: u32, message: String) {
+        let Some((peer, token)) = &self.target else {
+            return;
+        };
+        let notification = ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress: progress.into(),
+            total: Some(total.into()),
+            message: Some(message),
+        };
+        if let Err(e) = peer.notify_progress(notification).await {
+            tracing::debug!("Progress notification failed: {e}");
+        }
+    }
+}
+
+// ==================== NIP-05 logic ====================
+
+/// Look up `name` in a NIP-05 document's `names` map.
+fn nip05_names_entry(json: &serde_json::Value, name: &str) -> Option<String> {
+    json["names"][name].as_str().map(String::from)
+}
+
+/// Compare the document's `names` entry against the expected hex pubkey.
+fn verify_nip05_inner(
+    json: &serde_json::Value,
+    name: &str,
+    nip05: &str,
+    pubkey_hex: &str,
+) -> VerifyNip05Response {
+    let resolved_pubkey = nip05_names_entry(json, name);
+    let verified = resolved_pubkey
+        .as_deref()
+        .is_some_and(|resolved| resolved.eq_ignore_ascii_case(pubkey_hex));
+    let reason = match &resolved_pubkey {
+        None => Some(format!(
+            "Name '{name}' is not listed in the NIP-05 document"
+        )),
+        Some(_) if !verified => Some("NIP-05 name maps to a different pubkey".into()),
+        Some(_) => None,
+    };
+
+    VerifyNip05Response {
+        nip05: nip05.to_string(),
+        pubkey: pubkey_hex.to_string(),
+        verified,
+        resolved_pubkey,
+        reason,
+    }
+}
+
+// ==================== NIP-11 logic ====================
+
+/// Extract the fields we keep from a relay's NIP-11 information document.
+fn nip11_relay_info(relay_url: &str, json: &serde_json::Value, latency_ms: u64) -> CachedRelayInfo {
+    let supported_nips = json["supported_nips"]
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_u64().map(|n| n as u32))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    // Store limitation/fees as JSON only when they're present objects
+    let object_json = |value: &serde_json::Value| value.is_object().then(|| value.to_string());
+
+    CachedRelayInfo {
+        relay_url: relay_url.to_string(),
+        name: json["name"].as_str().map(String::from),
+        description: json["description"].as_str().map(String::from),
+        supported_nips,
+        software: json["software"].as_str().map(String::from),
+        version: json["version"].as_str().map(String::from),
+        online: true,
+        latency_ms: Some(latency_ms as i64),
+        limitation: object_json(&json["limitation"]),
+        fees: object_json(&json["fees"]),
+        icon: json["icon"].as_str().map(String::from),
+    }
+}
+
+/// Relay-side NIPs clients commonly rely on, checked by check_relay:
+/// 1 basic protocol, 9 deletions, 11 info document, 40 expiration,
+/// 45 event counts, 50 search, 70 protected events
+const COMMON_RELAY_NIPS: [u32; 7] = [1, 9, 11, 40, 45, 50, 70];
+
+/// Common relay NIPs the relay doesn't advertise, in ascending order.
+fn missing_common_nips(supported: &[u32]) -> Vec<u32> {
+    COMMON_RELAY_NIPS
+        .into_iter()
+        .filter(|nip| !supported.contains(nip))
+        .collect()
+}
+
+/// Render a (fetched or cached) relay info record as a check_relay response.
+fn check_relay_response(info: CachedRelayInfo) -> CheckRelayResponse {
+    let limitation: Option<RelayLimitation> = info
+        .limitation
+        .and_then(|json| serde_json::from_str(&json).ok());
+    let requires_auth = limitation
+        .as_ref()
+        .and_then(|l| l.auth_required)
+        .unwrap_or(false);
+    let requires_payment = limitation
+        .as_ref()
+        .and_then(|l| l.payment_required)
+        .unwrap_or(false);
+
+    CheckRelayResponse {
+        online: info.online,
+        latency_ms: info.latency_ms.map(|ms| ms as u64),
+        name: info.name,
+        description: info.description,
+        icon: info.icon,
+        missing_common_nips: Some(missing_common_nips(&info.supported_nips)),
+        supported_nips: Some(info.supported_nips),
+        software: info.software,
+        version: info.version,
+        limitation,
+        fees: info.fees.and_then(|json| serde_json::from_str(&json).ok()),
+        requires_auth,
+        requires_payment,
+        requires_tor: false,
+        skipped_reason: None,
+    }
+}
+
+/// Shared outbound HTTP client identifying as `network.user_agent`, routed through
+/// `network.socks_proxy` when set.
+fn http_client(network: &NetworkConfig) -> anyhow::Result<reqwest::Client> {
+    http_client_builder(network)?
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+/// Like [`http_client`], but returning redirects to the caller, for
+/// [`Nip05Client`] which follows at most one itself.
+fn http_client_without_redirects(network: &NetworkConfig) -> anyhow::Result<reqwest::Client> {
+    http_client_builder(network)?
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+fn http_client_builder(network: &NetworkConfig) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder().user_agent(network.user_agent.trim());
+    let proxy = network.socks_proxy.trim();
+    if !proxy.is_empty() {
+        builder = builder.proxy(
+            reqwest::Proxy::all(proxy)
+                .with_context(|| format!("network.socks_proxy: unusable proxy '{proxy}'"))?,
+        );
+    }
+    Ok(builder)
+}
+
+/// Whether a relay URL points at a Tor hidden service.
+fn is_onion_relay(relay_url: &str) -> bool {
+    url::Url::parse(relay_url)
+        .ok()
+        .and_then(|url| url.host_str().map(|h| h.to_ascii_lowercase()))
+        .is_some_and(|host| host.ends_with(".onion"))
+}
+
+// ==================== LNURL logic ====================
+
+/// Build the LNURL-pay endpoint for a `user@domain` lightning address (LUD-16).
+fn lnurlp_url(address: &str) -> Result<String, String> {
+    let (user, domain) = address
+        .split_once('@')
+        .filter(|(u, d)| !u.is_empty() && !d.is_empty() && !d.contains('@'))
+        .ok_or("Invalid lightning address, expected user@domain")?;
+    Ok(format!(
+        "https://{domain}/.well-known/lnurlp/{}",
+        user.to_lowercase()
+    ))
+}
+
+/// Fetch and parse the LNURL-pay metadata document (LUD-06) at `url`.
+async fn fetch_lnurl_pay(
+    http: &reqwest::Client,
+    address: &str,
+    url: &str,
+) -> Result<ResolveLightningAddressResponse, String> {
+    let resp = http
+        .get(url)
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("HTTP request failed: {e}"))?;
+
+    if !resp.status().is_success() {
+        return Err(format!("HTTP error: {}", resp.status()));
+    }
+
+    let json: serde_json::Value = resp
+        .json()
+        .await
+        .map_err(|e| format!("JSON parse error: {e}"))?;
+
+    if json["status"].as_str() == Some("ERROR") {
+        let reason = json["reason"].as_str().unwrap_or("unknown error");
+        return Err(format!("LNURL error: {reason}"));
+    }
+    if json["tag"].as_str() != Some("payRequest") {
+        return Err("Not an LNURL-pay endpoint (missing payRequest tag)".into());
+    }
+
+    let callback = json["callback"]
+        .as_str()
+        .ok_or("LNURL response is missing a callback URL")?
+        .to_string();
+    let nostr_pubkey = json["nostrPubkey"].as_str().map(String::from);
+
+    Ok(ResolveLightningAddressResponse {
+        address: address.to_string(),
+        callback,
+        min_sendable_msats: json["minSendable"].as_u64(),
+        max_sendable_msats: json["maxSendable"].as_u64(),
+        allows_nostr: json["allowsNostr"].as_bool().unwrap_or(false),
+        supports_zaps: json["allowsNostr"].as_bool() == Some(true) && nostr_pubkey.is_some(),
+        nostr_pubkey,
+    })
+}
+
+// ==================== helper functions ====================
+
+/// Pick the profile search results to return: relay-fallback results (only fetched
+/// when Primal failed or found nothing) replace Primal's, deduplicated by pubkey.
+/// Returns the hits and the `source` label. Errors only when every attempted
+/// source failed.
+fn combine_profile_search(
+    primal: Result<Vec<ProfileSearchHit>, String>,
+    relay: Option<Result<Vec<Event>, String>>,
+    limit: usize,
+) -> Result<(Vec<ProfileSearchHit>, &'static str), String> {
+    let relay_hits = relay.map(|r| {
+        r.map(|events| {
+            let mut events = events;
+            // Newest metadata first, so dedup keeps the current profile
+            events.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+            events
+                .iter()
+                .filter_map(ProfileSearchHit::from_metadata_event)
+                .collect::<Vec<_>>()
+        })
+    });
+
+    let (mut hits, source) = match (primal, relay_hits) {
+        (Ok(primal), None) => (primal, "primal_cache"),
+        (Ok(primal), Some(Err(_))) => (primal, "primal_cache"),
+        (_, Some(Ok(relay))) => (relay, "relay_search"),
+        (Err(primal_err), Some(Err(relay_err))) => {
+            return Err(format!("{primal_err}; {relay_err}"))
+        }
+        (Err(primal_err), None) => return Err(primal_err),
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    hits.retain(|hit| seen.insert(hit.pubkey.clone()));
+    hits.truncate(limit);
+    Ok((hits, source))
+}
+
+/// Flatten an event for search_events output. With `include_raw`, the canonical
+/// event JSON (including signature and full tags) is attached as well.
+fn event_summary(event: &Event, include_raw: bool) -> EventSummary {
+    let content = truncate_content(&event.content, 280);
+
+    let tags_summary = if event.tags.is_empty() {
+        "none".to_string()
+    } else {
+        let tag_kinds: Vec<String> = event
+            .tags
+            .iter()
+            .take(5)
+            .map(|t| t.kind().to_string())
+            .collect();
+        if event.tags.len() > 5 {
+            format!("{} (+{} more)", tag_kinds.join(", "), event.tags.len() - 5)
+        } else {
+            tag_kinds.join(", ")
+        }
+    };
+
+    EventSummary {
+        id: event.id.to_hex(),
+        pubkey: event.pubkey.to_hex(),
+        kind: event.kind.as_u16() as u32,
+        content,
+        created_at: event.created_at.as_secs(),
+        tags_summary,
+        raw_event: include_raw.then(|| event.as_json()),
+        has_media: None,
+        url_count: None,
+        mention_count: None,
+        hashtag_count: None,
+        detected_language: None,
+        references: None,
+        verified: None,
+        seen_on: None,
+    }
+}
+
+/// Optional per-event work for `summarize_events`, all off by default.
+#[derive(Debug, Clone, Copy, Default)]
+struct SummaryOptions {
+    include_raw: bool,
+    verify_signatures: bool,
+    analyze_content: bool,
+    detect_language: bool,
+    resolve_references: bool,
+}
+
+/// Summarize events for output. With `verify_signatures`, each event's ID and
+/// signature are checked; failing events are logged and dropped.
+fn summarize_events(events: &[Event], opts: SummaryOptions) -> Vec<EventSummary> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let mut summary = event_summary(event, opts.include_raw);
+            if opts.verify_signatures {
+                if let Err(e) = event.verify() {
+                    tracing::warn!("Dropping event {} with invalid signature: {e}", event.id);
+                    return None;
+                }
+                summary.verified = Some(true);
+            }
+            if opts.analyze_content {
+                add_content_analysis(&mut summary, event);
+            }
+            if opts.detect_language {
+                summary.detected_language = detect_language(&event.content);
+            }
+            if opts.resolve_references {
+                summary.references = Some(nostr_references(&event.content));
+            }
+            Some(summary)
+        })
+        .collect()
+}
+
+const MEDIA_EXTENSIONS: &[&str] = &[
+    ".jpg", ".jpeg", ".png", ".gif", ".webp", ".avif", ".mp4", ".webm", ".mov", ".mp3", ".ogg",
+    ".wav",
+];
+
+/// Fill the media/link/mention/hashtag fields from the event's tags and content.
+fn add_content_analysis(summary: &mut EventSummary, event: &Event) {
+    let count_tags = |name: &str| {
+        event
+            .tags
+            .iter()
+            .filter(|t| t.as_slice().first().map(String::as_str) == Some(name))
+            .count() as u32
+    };
+
+    let urls = content_urls(&event.content);
+    let links_media = urls.iter().any(|url| {
+        let path = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(url)
+            .to_ascii_lowercase();
+        MEDIA_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+    });
+
+    summary.has_media = Some(count_tags("imeta") > 0 || links_media);
+    summary.url_count = Some(urls.len() as u32);
+    summary.mention_count = Some(count_tags("p"));
+    summary.hashtag_count = Some(count_tags("t"));
+}
+
+/// Detected language of a note as an ISO 639-3 code. URLs, `nostr:` references,
+/// and hashtags are ignored; returns `None` when nothing detectable is left.
+fn detect_language(content: &str) -> Option<String> {
+    let text: Vec<&str> = content
+        .split_whitespace()
+        .filter(|w| !w.contains("://") && !w.starts_with("nostr:") && !w.starts_with('#'))
+        .collect();
+    if text.is_empty() {
+        return None;
+    }
+    whatlang::detect(&text.join(" ")).map(|info| info.lang().code().to_string())
+}
+
+fn parse_language(code: &str) -> Result<whatlang::Lang, String> {
+    whatlang::Lang::from_code(code.trim().to_ascii_lowercase()).ok_or_else(|| {
+        format!("Unknown language '{code}' (use an ISO 639-3 code such as \"eng\" or \"jpn\")")
+    })
+}
+
+/// http(s) URLs in free text, split on whitespace with trailing punctuation trimmed.
+fn content_urls(content: &str) -> Vec<&str> {
+    content
+        .split_whitespace()
+        .filter_map(|word| {
+            let start = word.find("https://").or_else(|| word.find("http://"))?;
+            let url =
+                word[start..].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '"', '\'']);
+            let host = url.split_once("://").map_or("", |(_, rest)| rest);
+            (!host.is_empty()).then_some(url)
+        })
+        .collect()
+}
+
+/// Decode each distinct `nostr:` URI (NIP-21) in `content`, in order of first
+/// appearance. Undecodable or unsupported entities (e.g. nsec) are skipped.
+fn nostr_references(content: &str) -> Vec<DecodeNostrUriResponse> {
+    let mut seen = HashSet::new();
+    content
+        .match_indices("nostr:")
+        .filter_map(|(start, prefix)| {
+            let rest = &content[start + prefix.len()..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            decode_nostr_uri_inner(&rest[..end]).ok()
+        })
+        .filter(|r| {
+            seen.insert((
+                r.entity_type.clone(),
+                r.hex_id.clone(),
+                r.author_hex.clone(),
+                r.kind,
+            ))
+        })
+        .collect()
+}
+
+/// Drop repeat copies of the same event (relays overlap), keeping the first seen,
+/// so `count` reflects unique events whatever the SDK merged.
+fn dedupe_by_id(events: Vec<Event>) -> Vec<Event> {
+    let mut seen = HashSet::new();
+    events.into_iter().filter(|e| seen.insert(e.id)).collect()
+}
+
+/// Drop events older than the cursor timestamp and the boundary event itself,
+/// which a previous page already returned.
+fn events_after_cursor(
+    events: Vec<Event>,
+    after_timestamp: Option<u64>,
+    after_event_id: Option<EventId>,
+) -> Vec<Event> {
+    events
+        .into_iter()
+        .filter(|e| after_timestamp.is_none_or(|ts| e.created_at.as_secs() >= ts))
+        .filter(|e| after_event_id != Some(e.id))
+        .collect()
+}
+
+/// Parse an `exclude_authors` list (hex or npub) into a set.
+fn parse_excluded_authors(authors: Option<&[String]>) -> Result<HashSet<PublicKey>, String> {
+    authors
+        .unwrap_or_default()
+        .iter()
+        .map(|a| {
+            NostrClient::parse_pubkey(a)
+                .map_err(|e| format!("Invalid exclude_authors entry '{a}': {e}"))
+        })
+        .collect()
+}
+
+/// Drop events by excluded authors; relays can't negate an `authors` filter, so
+/// this happens after the fetch. Returns the kept events and how many were dropped.
+fn without_authors(events: Vec<Event>, excluded: &HashSet<PublicKey>) -> (Vec<Event>, u32) {
+    if excluded.is_empty() {
+        return (events, 0);
+    }
+    let before = events.len();
+    let kept: Vec<Event> = events
+        .into_iter()
+        .filter(|e| !excluded.contains(&e.pubkey))
+        .collect();
+    let dropped = (before - kept.len()) as u32;
+    (kept, dropped)
+}
+
+/// Drop events shorter than `min_length` characters (trimmed) and, with
+/// `exclude_replies`, replies whose text is empty once `nostr:` references are
+/// removed. Returns the kept events and the (too short, empty reply) counts.
+fn without_low_content(
+    events: Vec<Event>,
+    min_length: Option<u32>,
+    exclude_replies: bool,
+) -> (Vec<Event>, u32, u32) {
+    let (mut too_short, mut empty_replies) = (0, 0);
+    let kept = events
+        .into_iter()
+        .filter(|e| {
+            if exclude_replies && is_empty_reply(e) {
+                empty_replies += 1;
+                return false;
+            }
+            if min_length.is_some_and(|min| e.content.trim().chars().count() < min as usize) {
+                too_short += 1;
+                return false;
+            }
+            true
+        })
+        .collect();
+    (kept, too_short, empty_replies)
+}
+
+fn is_empty_reply(event: &Event) -> bool {
+    let is_reply = event
+        .tags
+        .iter()
+        .any(|tag| tag.as_slice().first().map(|s| s.as_str()) == Some("e"));
+    is_reply
+        && event
+            .content
+            .split_whitespace()
+            .all(|word| word.starts_with("nostr:"))
+}
+
+/// Normalize a `contains` keyword list to lowercase, rejecting blank entries.
+fn parse_keywords(keywords: &[String]) -> Result<Vec<String>, String> {
+    if keywords.is_empty() {
+        return Err("contains must list at least one keyword".into());
+    }
+    keywords
+        .iter()
+        .map(|k| match k.trim() {
+            "" => Err("contains entries must not be empty".to_string()),
+            k => Ok(k.to_lowercase()),
+        })
+        .collect()
+}
+
+/// Whether `content` contains any of the (already lowercased) keywords.
+fn contains_any_keyword(content: &str, keywords: &[String]) -> bool {
+    let content = content.to_lowercase();
+    keywords.iter().any(|k| content.contains(k.as_str()))
+}
+
+/// A trending candidate with its engagement counts.
+struct NoteEngagement<'a> {
+    note: &'a Event,
+    reactions: u32,
+    reposts: u32,
+    /// Reactions by content; empty unless a breakdown was requested
+    tally: ReactionTally,
+}
+
+/// Reaction counts per NIP-25 content: `+` (or empty) is a like, `-` a dislike,
+/// anything else an emoji or `:shortcode:` custom emoji.
+#[derive(Debug, Default)]
+struct ReactionTally {
+    likes: u32,
+    dislikes: u32,
+    emoji: HashMap<String, u32>,
+}
+
+impl ReactionTally {
+    fn add(&mut self, content: &str) {
+        match content.trim() {
+            "" | "+" => self.likes += 1,
+            "-" => self.dislikes += 1,
+            emoji => *self.emoji.entry(emoji.to_string()).or_default() += 1,
+        }
+    }
+
+    fn merge(&mut self, other: ReactionTally) {
+        self.likes += other.likes;
+        self.dislikes += other.dislikes;
+        for (emoji, count) in other.emoji {
+            *self.emoji.entry(emoji).or_default() += count;
+        }
+    }
+
+    /// Keep the `top` most used emoji (ties broken alphabetically).
+    fn into_breakdown(self, top: usize) -> ReactionBreakdown {
+        let mut emoji: Vec<(String, u32)> = self.emoji.into_iter().collect();
+        emoji.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        emoji.truncate(top);
+        ReactionBreakdown {
+            likes: self.likes,
+            dislikes: self.dislikes,
+            top_emoji: emoji.into_iter().collect(),
+        }
+    }
+}
+
+/// Collapse notes whose content is equal after whitespace normalization into
+/// the earliest one, summing engagement across the copies. Empty notes are kept.
+fn collapse_duplicate_content(notes: Vec<NoteEngagement<'_>>) -> Vec<NoteEngagement<'_>> {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut collapsed: Vec<NoteEngagement> = Vec::with_capacity(notes.len());
+    for entry in notes {
+        let key = entry
+            .note
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if key.is_empty() {
+            collapsed.push(entry);
+            continue;
+        }
+        match index.get(&key) {
+            Some(&i) => {
+                let kept = &mut collapsed[i];
+                kept.reactions += entry.reactions;
+                kept.reposts += entry.reposts;
+                kept.tally.merge(entry.tally);
+                if (entry.note.created_at, entry.note.id) < (kept.note.created_at, kept.note.id) {
+                    kept.note = entry.note;
+                }
+            }
+            None => {
+                index.insert(key, collapsed.len());
+                collapsed.push(entry);
+            }
+        }
+    }
+    collapsed
+}
+
+/// IDs among `events` that a kind:5 request from the same author deletes. A
+/// deletion signed by anyone else is ignored, as NIP-09 requires.
+fn deleted_event_ids(events: &[Event], deletions: &[Event]) -> HashSet<EventId> {
+    let authors: HashMap<EventId, PublicKey> = events.iter().map(|e| (e.id, e.pubkey)).collect();
+    deletions
+        .iter()
+        .filter(|d| d.kind == Kind::EventDeletion)
+        .flat_map(|d| d.tags.event_ids().map(move |id| (d.pubkey, *id)))
+        .filter(|(signer, id)| authors.get(id) == Some(signer))
+        .map(|(_, id)| id)
+        .collect()
+}
+
+/// Keep at most `max` items per author, preserving order; later items from
+/// other authors move up to fill the gaps.
+fn cap_per_author<T>(items: Vec<T>, max: usize, author: impl Fn(&T) -> PublicKey) -> Vec<T> {
+    let mut seen: HashMap<PublicKey, usize> = HashMap::new();
+    items
+        .into_iter()
+        .filter(|item| {
+            let count = seen.entry(author(item)).or_default();
+            *count += 1;
+            *count <= max
+        })
+        .collect()
+}
+
+/// Cursor for the next page: the newest event (ties broken by id).
+fn search_cursor(events: &[Event]) -> Option<SearchCursor> {
+    events
+        .iter()
+        .max_by_key(|e| (e.created_at, e.id))
+        .map(|e| SearchCursor {
+            timestamp: e.created_at.as_secs(),
+            event_id: e.id.to_hex(),
+        })
+}
+
+/// Result order for search_events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventSort {
+    CreatedAtDesc,
+    CreatedAtAsc,
+}
+
+fn parse_event_sort(sort_by: Option<&str>) -> Result<EventSort, String> {
+    match sort_by.map(|s| s.trim().to_lowercase()).as_deref() {
+        None | Some("created_at_desc") => Ok(EventSort::CreatedAtDesc),
+        Some("created_at_asc") => Ok(EventSort::CreatedAtAsc),
+        Some(other) => Err(format!(
+            "Unknown sort_by '{other}'. Use 'created_at_desc' or 'created_at_asc'"
+        )),
+    }
+}
+
+/// Sort by creation time, breaking ties on event ID so the order doesn't
+/// depend on which relay answered first
+fn sort_events(events: &mut [Event], sort: EventSort) {
+    events.sort_by(|a, b| {
+        let ord = a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id));
+        match sort {
+            EventSort::CreatedAtAsc => ord,
+            EventSort::CreatedAtDesc => ord.reverse(),
+        }
+    });
+}
+
+/// Maximum entities per decode_nostr_uris call
+const MAX_BATCH_DECODE: usize = 100;
+/// Maximum relays per check_relays call
+const MAX_BATCH_RELAYS: usize = 20;
+const MIN_COMMON_FOLLOWS_SEEDS: usize = 2;
+const MAX_COMMON_FOLLOWS_SEEDS: usize = 10;
+/// Events fetched per account_activity call
+const MAX_ACTIVITY_EVENTS: usize = 500;
+/// Followers returned by get_follower_graph unless `follower_limit` is set
+const DEFAULT_FOLLOWER_PAGE: u32 = 100;
+const MAX_FOLLOWER_PAGE: usize = 500;
+/// Deepest `follower_offset`; bounds the relay paging behind one call
+const MAX_FOLLOWER_OFFSET: usize = 1000;
+/// Contact lists fetched at once while expanding a depth-2 follower graph
+const SECOND_DEGREE_CONCURRENCY: usize = 8;
+/// Second-degree accounts returned by get_follower_graph
+const MAX_SECOND_DEGREE: usize = 100;
+/// NIP-51 kinds served by get_lists: mute list, pin list, follow sets,
+/// generic lists (deprecated) and bookmark sets
+const LIST_KINDS: [u16; 5] = [10000, 10001, 30000, 30001, 30003];
+/// Pubkeys and events returned per list by get_lists
+const MAX_LIST_MEMBERS: usize = 100;
+/// Bookmarked notes resolved per get_bookmarks call
+const MAX_BOOKMARKS: usize = 100;
+/// Largest individual zaps listed by zap_analytics
+const MAX_TOP_ZAPS: usize = 10;
+/// Zappers listed per note by note_zaps
+const MAX_NOTE_ZAPPERS: usize = 100;
+/// Custom emoji listed per note in a trending reaction breakdown
+const MAX_BREAKDOWN_EMOJI: usize = 5;
+/// Zap comments are cut to this many bytes
+const MAX_ZAP_COMMENT_LEN: usize = 200;
+
+struct PostingActivity {
+    total_events: u32,
+    avg_posts_per_day: f64,
+    most_active_hour_utc: Option<u8>,
+    longest_gap: Option<ActivityGap>,
+    posts_per_day: Vec<ActivityDay>,
+}
+
+/// Bucket events by UTC day and hour and find the longest gap between consecutive
+/// events. The average is spread over the whole timeframe, not just active days.
+fn posting_activity(events: &[Event], timeframe_secs: u64) -> PostingActivity {
+    let mut timestamps: Vec<u64> = events.iter().map(|e| e.created_at.as_secs()).collect();
+    timestamps.sort_unstable();
+
+    let mut daily: std::collections::BTreeMap<String, u32> = std::collections::BTreeMap::new();
+    let mut hourly = [0u32; 24];
+    for &ts in &timestamps {
+        let Some(dt) = chrono::DateTime::from_timestamp(ts as i64, 0) else {
+            continue;
+        };
+        *daily.entry(dt.format("%Y-%m-%d").to_string()).or_default() += 1;
+        hourly[chrono::Timelike::hour(&dt) as usize] += 1;
+    }
+
+    // Earliest hour wins ties
+    let most_active_hour_utc = (0..24u8)
+        .filter(|&h| hourly[h as usize] > 0)
+        .max_by_key(|&h| (hourly[h as usize], std::cmp::Reverse(h)));
+
+    let longest_gap = timestamps
+        .windows(2)
+        .max_by_key(|w| w[1] - w[0])
+        .filter(|w| w[1] > w[0])
+        .map(|w| ActivityGap {
+            from: w[0],
+            to: w[1],
+            hours: ((w[1] - w[0]) as f64 / 3600.0 * 10.0).round() / 10.0,
+        });
+
+    let days = (timeframe_secs as f64 / 86400.0).max(1.0 / 24.0);
+    let avg_posts_per_day = (timestamps.len() as f64 / days * 100.0).round() / 100.0;
+
+    PostingActivity {
+        total_events: timestamps.len() as u32,
+        avg_posts_per_day,
+        most_active_hour_utc,
+        longest_gap,
+        posts_per_day: daily
+            .into_iter()
+            .map(|(date, count)| ActivityDay { date, count })
+            .collect(),
+    }
+}
+
+/// Maximum accounts returned by common_follows
+const MAX_COMMON_FOLLOWS: usize = 100;
+/// Followers fetched per account by compare_accounts
+const MAX_COMPARE_FOLLOWERS: usize = 1000;
+/// Shared accounts listed per set by compare_accounts
+const MAX_OVERLAP_SAMPLE: usize = 20;
+
+/// Overlap metrics for two pubkey sets, plus the shared pubkeys sorted for
+/// stable output.
+fn audience_overlap(a: &HashSet<String>, b: &HashSet<String>) -> (AudienceOverlap, Vec<String>) {
+    let mut shared: Vec<String> = a.intersection(b).cloned().collect();
+    shared.sort();
+    let union = a.len() + b.len() - shared.len();
+    let jaccard = if union == 0 {
+        0.0
+    } else {
+        shared.len() as f64 / union as f64
+    };
+    let overlap = AudienceOverlap {
+        a_count: a.len() as u32,
+        b_count: b.len() as u32,
+        shared_count: shared.len() as u32,
+        jaccard,
+    };
+    (overlap, shared)
+}
+
+/// Followed pubkeys (`p` tags) from a kind:3 contact list, deduplicated in order.
+fn contact_list_pubkeys(event: &Event) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    event
+        .tags
+        .iter()
+        .filter_map(|tag| {
+            let slice = tag.as_slice();
+            (slice.first().map(|s| s.as_str()) == Some("p"))
+                .then(|| slice.get(1).cloned())
+                .flatten()
+        })
+        .filter(|pk| seen.insert(pk.clone()))
+        .collect()
+}
+
+/// Count how many follow sets contain each pubkey, keeping those at or above
+/// `min_overlap`. Sorted by overlap (desc), then pubkey for stable output.
+fn count_common_follows(follow_sets: &[Vec<String>], min_overlap: u32) -> Vec<(String, u32)> {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    for set in follow_sets {
+        for pk in set {
+            *counts.entry(pk.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut common: Vec<(String, u32)> = counts
+        .into_iter()
+        .filter(|(_, n)| *n >= min_overlap)
+        .map(|(pk, n)| (pk.to_string(), n))
+        .collect();
+    common.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    common
+}
+
+fn profile_response(profile: CachedProfile, matched_by: Option<String>) -> GetProfileResponse {
+    GetProfileResponse {
+        pubkey: profile.pubkey,
+        name: profile.name,
+        display_name: profile.display_name,
+        about: profile.about,
+        picture: profile.picture,
+        banner: profile.banner,
+        nip05: profile.nip05,
+        lud16: profile.lud16,
+        website: profile.website,
+        matched_by,
+        in_cache: None,
+    }
+}
+
+/// Split a NIP-65 relay list into (write, read) relays. Unmarked `r` tags count as both.
+fn parse_relay_list(event: &Event) -> (Vec<String>, Vec<String>) {
+    let mut write_relays = Vec::new();
+    let mut read_relays = Vec::new();
+    for tag in event.tags.iter() {
+        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
+        if tag_vec.first() != Some(&"r") {
+            continue;
+        }
+        let Some(url) = tag_vec.get(1).map(|u| u.to_string()) else {
+            continue;
+        };
+        match tag_vec.get(2) {
+            Some(&"read") => read_relays.push(url),
+            Some(&"write") => write_relays.push(url),
+            _ => {
+                read_relays.push(url.clone());
+                write_relays.push(url);
+            }
+        }
+    }
+    (write_relays, read_relays)
+}
+
+/// Count `p` tags in a contact list.
+fn following_count(contact_list: &Event) -> u32 {
+    contact_list
+        .tags
+        .iter()
+        .filter(|tag| tag.as_slice().first().map(|s| s.as_str()) == Some("p"))
+        .count() as u32
+}
+
+/// Totals over NIP-57-verified zap receipts; receipts that fail verification are
+/// only counted as suspicious.
+fn zap_totals(receipts: &[Event]) -> DossierZaps {
+    let mut totals = DossierZaps {
+        total_received_sats: 0,
+        total_zaps_count: 0,
+        avg_zap_sats: 0,
+        suspicious_zaps_count: 0,
+    };
+    for event in receipts {
+        if verify_zap_receipt(event).is_err() {
+            totals.suspicious_zaps_count += 1;
+            continue;
+        }
+        totals.total_zaps_count += 1;
+        totals.total_received_sats += extract_zap_amount(event);
+    }
+    if totals.total_zaps_count > 0 {
+        totals.avg_zap_sats = totals.total_received_sats / totals.total_zaps_count as u64;
+    }
+    totals
+}
+
+/// Parse timeframe strings like "30m", "24h", "7d", "2w", "3mo", "1y" into seconds.
+/// A bare `m` means minutes; months must be spelled `mo` (30 days each).
+fn parse_timeframe(tf: &str) -> Result<u64, String> {
+    const UNITS: [(&str, &str, u64); 6] = [
+        // "mo" must be tried before "m"
+        ("mo", "months", 30 * 86400),
+        ("m", "minutes", 60),
+        ("h", "hours", 3600),
+        ("d", "days", 86400),
+        ("w", "weeks", 7 * 86400),
+        ("y", "years", 365 * 86400),
+    ];
+
+    let tf = tf.trim().to_lowercase();
+    let Some((count, name, secs)) = UNITS
+        .iter()
+        .find_map(|&(suffix, name, secs)| Some((tf.strip_suffix(suffix)?, name, secs)))
+    else {
+        return Err(format!(
+            "Unknown timeframe format: {tf}. Use '30m', '24h', '7d', '2w', '3mo', '1y', etc."
+        ));
+    };
+    let n: u64 = count
+        .parse()
+        .map_err(|_| format!("Invalid {name}: {count}"))?;
+    if n == 0 {
+        return Err(format!("Timeframe must be greater than zero: {tf}"));
+    }
+    n.checked_mul(secs)
+        .ok_or_else(|| format!("Timeframe too large: {tf}"))
+}
+
+/// Public members of a NIP-51 list event, capped at `MAX_LIST_MEMBERS` each.
+/// Member names are left for the caller to resolve.
+fn nostr_list(event: &Event) -> NostrList {
+    let mut pubkeys = Vec::new();
+    let mut events = Vec::new();
+    let mut title = None;
+    let mut fallback_name = None;
+    for tag in event.tags.iter() {
+        match tag.as_slice() {
+            [name, value, ..] if name == "p" => {
+                if let Ok(pk) = PublicKey::from_hex(value) {
+                    pubkeys.push(pk.to_hex());
+                }
+            }
+            [name, value, ..] if name == "e" => {
+                if let Ok(id) = EventId::from_hex(value) {
+                    events.push(id.to_hex());
+                }
+            }
+            [name, value, ..] if name == "title" => title = Some(value.clone()),
+            [name, value, ..] if name == "name" => fallback_name = Some(value.clone()),
+            _ => {}
+        }
+    }
+    let member_count = (pubkeys.len() + events.len()) as u32;
+    pubkeys.truncate(MAX_LIST_MEMBERS);
+    events.truncate(MAX_LIST_MEMBERS);
+
+    NostrList {
+        kind: event.kind.as_u16() as u32,
+        identifier: event
+            .kind
+            .is_addressable()
+            .then(|| event.tags.identifier().unwrap_or_default().to_string()),
+        title: title.or(fallback_name),
+        updated_at: event.created_at.as_secs(),
+        pubkeys: pubkeys
+            .into_iter()
+            .map(|pubkey| ListMember { pubkey, name: None })
+            .collect(),
+        events,
+        member_count,
+        has_private_items: !event.content.trim().is_empty(),
+    }
+}
+
+/// Event IDs bookmarked in a kind:10003 list via `e` tags, most recently added
+/// first (NIP-51 appends new items). Other entries (`a`, `t`, `r`) are ignored.
+fn bookmarked_event_ids(list: &Event) -> Vec<EventId> {
+    let mut seen = HashSet::new();
+    list.tags
+        .iter()
+        .rev()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, id, ..] if name == "e" => EventId::from_hex(id).ok(),
+            _ => None,
+        })
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+/// Truncate content to at most `max_len` bytes (backing off to a char boundary),
+/// appending "..." if truncated
+fn truncate_content(content: &str, max_len: usize) -> String {
+    if content.len() > max_len {
+        let mut end = max_len;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &content[..end])
+    } else {
+        content.to_string()
+    }
+}
+
+/// Extract zap amount in sats from a kind:9735 zap receipt event.
+/// Tries the `bolt11` tag first, then the embedded zap request `description` tag.
+fn extract_zap_amount(event: &Event) -> u64 {
+    // Try bolt11 tag
+    for tag in event.tags.iter() {
+        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
+        if tag_vec.first() == Some(&"bolt11") {
+            if let Some(bolt11) = tag_vec.get(1) {
+                if let Some(amount) = parse_bolt11_amount(bolt11) {
+                    return amount;
+                }
+            }
+        }
+    }
+
+    // Try description tag (embedded zap request with amount)
+    for tag in event.tags.iter() {
+        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
+        if tag_vec.first() == Some(&"description") {
+            if let Some(desc) = tag_vec.get(1) {
+                if let Ok(zap_request) = serde_json::from_str::<serde_json::Value>(desc) {
+                    // Look for amount tag in the zap request
+                    if let Some(tags) = zap_request["tags"].as_array() {
+                        for t in tags {
+                            if let Some(arr) = t.as_array() {
+                                if arr.first().and_then(|v| v.as_str()) == Some("amount") {
+                                    if let Some(msats_str) = arr.get(1).and_then(|v| v.as_str()) {
+                                        if let Ok(msats) = msats_str.parse::<u64>() {
+                                            return msats / 1000;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    0
+}
+
+/// Parse amount from a bolt11 invoice string.
+/// Bolt11 amounts: number followed by multiplier (m=milli, u=micro, n=nano, p=pico)
+fn parse_bolt11_amount(bolt11: &str) -> Option<u64> {
+    let lower = bolt11.to_lowercase();
+    // Find "lnbc"/"lntb"/"lnbcrt" prefix and extract the amount portion
+    let after_prefix = if let Some(rest) = lower.strip_prefix("lnbcrt") {
+        rest
+    } else if let Some(rest) = lower.strip_prefix("lnbc") {
+        rest
+    } else {
+        lower.strip_prefix("lntb")?
+    };
+
+    // Amount is digits + optional multiplier before the '1' separator. The data part
+    // never contains '1', so the last one is the separator even if the amount has a 1.
+    let sep_pos = after_prefix.rfind('1')?;
+    let amount_str = &after_prefix[..sep_pos];
+
+    if amount_str.is_empty() {
+        return None; // No amount specified
+    }
+
+    // Check for multiplier suffix
+    if let Some(n) = amount_str.strip_suffix('m') {
+        let num: u64 = n.parse().ok()?;
+        Some(num * 100_000) // milli-BTC to sats
+    } else if let Some(n) = amount_str.strip_suffix('u') {
+        let num: u64 = n.parse().ok()?;
+        Some(num * 100) // micro-BTC to sats
+    } else if let Some(n) = amount_str.strip_suffix('n') {
+        let num: u64 = n.parse().ok()?;
+        Some(num / 10) // nano-BTC to sats (0.1 sat each)
+    } else if let Some(n) = amount_str.strip_suffix('p') {
+        let num: u64 = n.parse().ok()?;
+        Some(num / 100) // pico-BTC to sats (0.01 sat each)
+    } else {
+        let num: u64 = amount_str.parse().ok()?;
+        Some(num * 100_000_000) // plain BTC to sats
+    }
+}
+
+/// Bech32 alphabet used by bolt11 invoices.
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+/// bolt11 tagged-field type for the description hash (`h`).
+const BOLT11_TAG_DESCRIPTION_HASH: u8 = 23;
+/// bolt11 trailing signature length in 5-bit words.
+const BOLT11_SIGNATURE_WORDS: usize = 104;
+
+/// Extract the `h` (description hash) tagged field from a bolt11 invoice.
+/// Only walks the tagged fields; the invoice signature and checksum are not checked.
+fn bolt11_description_hash(bolt11: &str) -> Option<[u8; 32]> {
+    let lower = bolt11.to_lowercase();
+    let data = &lower[lower.rfind('1')? + 1..];
+    let words: Vec<u8> = data
+        .chars()
+        .map(|c| BECH32_CHARSET.find(c).map(|i| i as u8))
+        .collect::<Option<_>>()?;
+
+    // Layout: 7-word timestamp, tagged fields, 104-word signature, 6-word checksum
+    let end = words.len().checked_sub(BOLT11_SIGNATURE_WORDS + 6)?;
+    let mut pos = 7;
+    while pos + 3 <= end {
+        let tag = words[pos];
+        let len = ((words[pos + 1] as usize) << 5) | words[pos + 2] as usize;
+        let field = words.get(pos + 3..pos + 3 + len)?;
+        if tag == BOLT11_TAG_DESCRIPTION_HASH && len == 52 {
+            // Regroup 5-bit words into bytes, dropping the 4 padding bits
+            let mut out = [0u8; 32];
+            let (mut acc, mut bits, mut idx) = (0u32, 0u32, 0usize);
+            for w in field {
+                acc = (acc << 5) | *w as u32;
+                bits += 5;
+                if bits >= 8 && idx < 32 {
+                    bits -= 8;
+                    out[idx] = (acc >> bits) as u8;
+                    idx += 1;
+                }
+            }
+            return Some(out);
+        }
+        pos += 3 + len;
+    }
+
+    None
+}
+
+/// Verify a zap receipt per NIP-57: the embedded zap request must be a validly signed
+/// kind:9734 event, the bolt11 `description_hash` must equal SHA256 of the request JSON,
+/// and any `amount` tag on the request must match the invoice amount.
+fn verify_zap_receipt(event: &Event) -> Result<(), String> {
+    let tag_value = |name: &str| {
+        event.tags.iter().find_map(|tag| {
+            let slice = tag.as_slice();
+            (slice.first().map(|s| s.as_str()) == Some(name))
+                .then(|| slice.get(1).cloned())
+                .flatten()
+        })
+    };
+
+    let description = tag_value("description").ok_or("missing description tag")?;
+    let bolt11 = tag_value("bolt11").ok_or("missing bolt11 tag")?;
+
+    let zap_request =
+        Event::from_json(&description).map_err(|e| format!("invalid zap request: {e}"))?;
+    if zap_request.kind != Kind::ZapRequest {
+        return Err(format!("embedded event is kind {}", zap_request.kind));
+    }
+    zap_request
+        .verify()
+        .map_err(|e| format!("zap request signature invalid: {e}"))?;
+
+    let expected: [u8; 32] = Sha256::digest(description.as_bytes()).into();
+    match bolt11_description_hash(&bolt11) {
+        Some(hash) if hash == expected => {}
+        Some(_) => return Err("bolt11 description_hash does not match zap request".into()),
+        None => return Err("bolt11 has no description_hash".into()),
+    }
+
+    let requested_msats = zap_request.tags.iter().find_map(|tag| {
+        let slice = tag.as_slice();
+        (slice.first().map(|s| s.as_str()) == Some("amount"))
+            .then(|| slice.get(1).and_then(|v| v.parse::<u64>().ok()))
+            .flatten()
+    });
+    if let (Some(msats), Some(sats)) = (requested_msats, parse_bolt11_amount(&bolt11)) {
+        if msats / 1000 != sats {
+            return Err("zap request amount does not match invoice".into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Recipient of a zap receipt (lowercase `p` tag).
+fn extract_zap_recipient(event: &Event) -> Option<String> {
+    event.tags.iter().find_map(|tag| {
+        let slice = tag.as_slice();
+        (slice.first().map(|s| s.as_str()) == Some("p"))
+            .then(|| slice.get(1).cloned())
+            .flatten()
+    })
+}
+
+/// Net zap flow between `analyzed` and each of its top zappers, given the
+/// receipts for zaps `analyzed` sent. Names are left for the caller to fill.
+fn zap_reciprocity(
+    analyzed: &str,
+    top_zappers: &[(String, u64)],
+    sent_receipts: &[Event],
+) -> Vec<ZapReciprocity> {
+    let mut sent_to: HashMap<String, u64> = HashMap::new();
+    for event in sent_receipts {
+        // Relays match on the `P` tag; double-check against the embedded request
+        if extract_zapper_pubkey(event).as_deref() != Some(analyzed) {
+            continue;
+        }
+        if let Some(recipient) = extract_zap_recipient(event) {
+            *sent_to.entry(recipient).or_default() += extract_zap_amount(event);
+        }
+    }
+
+    top_zappers
+        .iter()
+        .map(|(pubkey, received_sats)| {
+            let sent_sats = sent_to.get(pubkey).copied().unwrap_or(0);
+            ZapReciprocity {
+                pubkey: pubkey.clone(),
+                name: None,
+                received_sats: *received_sats,
+                sent_sats,
+                net_sats: *received_sats as i64 - sent_sats as i64,
+            }
+        })
+        .collect()
+}
+
+/// Real sender of a zap, from the uppercase `P` tag or the embedded zap request.
+/// `None` for anonymous zaps, whose request is signed with a throwaway key.
+fn extract_zapper_pubkey(event: &Event) -> Option<String> {
+    let zap_request = embedded_zap_request(event);
+    if zap_request.as_ref().is_some_and(is_anonymous_zap_request) {
+        return None;
+    }
+
+    // Check for uppercase P tag (zapper's pubkey)
+    for tag in event.tags.iter() {
+        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
+        if tag_vec.first() == Some(&"P") {
+            return tag_vec.get(1).map(|s| s.to_string());
+        }
+    }
+
+    zap_request?["pubkey"].as_str().map(String::from)
+}
+
+/// The zap request (kind 9734) embedded in a receipt's `description` tag.
+fn embedded_zap_request(event: &Event) -> Option<serde_json::Value> {
+    let desc = event
+        .tags
+        .iter()
+        .find(|tag| tag.as_slice().first().map(String::as_str) == Some("description"))?
+        .as_slice()
+        .get(1)?;
+    serde_json::from_str(desc).ok()
+}
+
+/// NIP-57 anonymous (and private) zap requests carry an `anon` tag.
+fn is_anonymous_zap_request(zap_request: &serde_json::Value) -> bool {
+    zap_request["tags"].as_array().is_some_and(|tags| {
+        tags.iter()
+            .any(|tag| tag.get(0).and_then(|t| t.as_str()) == Some("anon"))
+    })
+}
+
+/// Comment left by the zapper: the content of the zap request embedded in the
+/// receipt's `description` tag. Empty comments are `None`.
+fn extract_zap_comment(event: &Event) -> Option<String> {
+    let zap_request = embedded_zap_request(event)?;
+    let comment = zap_request["content"].as_str()?.trim();
+    (!comment.is_empty()).then(|| truncate_content(comment, MAX_ZAP_COMMENT_LEN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_relay::{mock_nwc, mock_relay_with_events, MockRelay, MockWallet, RelayPolicy};
+
+    // A well-known hex pubkey for test vectors
+    const TEST_HEX: &str = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+
+    fn test_pubkey() -> PublicKey {
+        PublicKey::from_hex(TEST_HEX).unwrap()
+    }
+
+    fn test_event_id() -> EventId {
+        // Use the same hex as an event id for simplicity
+        EventId::from_hex(TEST_HEX).unwrap()
+    }
+
+    #[test]
+    fn decode_npub() {
+        let npub = test_pubkey().to_bech32().unwrap();
+        let resp = decode_nostr_uri_inner(&npub).unwrap();
+        assert_eq!(resp.entity_type, "pubkey");
+        assert_eq!(resp.hex_id, TEST_HEX);
+        assert!(resp.relays.is_none());
+        assert!(resp.author_hex.is_none());
+        assert!(resp.kind.is_none());
+    }
+
+    #[test]
+    fn decode_note() {
+        let note = test_event_id().to_bech32().unwrap();
+        let resp = decode_nostr_uri_inner(&note).unwrap();
+        assert_eq!(resp.entity_type, "event_id");
+        assert_eq!(resp.hex_id, TEST_HEX);
+    }
+
+    #[test]
+    fn decode_nprofile_with_relays() {
+        let relay = RelayUrl::parse("wss://relay.damus.io").unwrap();
+        let nprofile = Nip19Profile::new(test_pubkey(), [relay.clone()]);
+        let bech32 = nprofile.to_bech32().unwrap();
+
+        let resp = decode_nostr_uri_inner(&bech32).unwrap();
+        assert_eq!(resp.entity_type, "profile");
+        assert_eq!(resp.hex_id, TEST_HEX);
+        let relays = resp.relays.unwrap();
+        assert_eq!(relays.len(), 1);
+        assert_eq!(relays[0], "wss://relay.damus.io");
+    }
+
+    #[test]
+    fn decode_nprofile_no_relays() {
+        let nprofile = Nip19Profile::new(test_pubkey(), Vec::<RelayUrl>::new());
+        let bech32 = nprofile.to_bech32().unwrap();
+
+        let resp = decode_nostr_uri_inner(&bech32).unwrap();
+        assert_eq!(resp.entity_type, "profile");
+        assert!(resp.relays.is_none());
+    }
+
+    #[test]
+    fn decode_nevent_with_author_and_kind() {
+        let relay = RelayUrl::parse("wss://nos.lol").unwrap();
+        let nevent = Nip19Event::new(test_event_id())
+            .relays(vec![relay])
+            .author(test_pubkey())
+            .kind(Kind::TextNote);
+        let bech32 = nevent.to_bech32().unwrap();
+
+        let resp = decode_nostr_uri_inner(&bech32).unwrap();
+        assert_eq!(resp.entity_type, "event");
+        assert_eq!(resp.hex_id, TEST_HEX);
+        assert_eq!(resp.author_hex.as_deref(), Some(TEST_HEX));
+        assert_eq!(resp.kind, Some(1));
+        let relays = resp.relays.unwrap();
+        assert_eq!(relays[0], "wss://nos.lol");
+    }
+
+    #[test]
+    fn decode_naddr_coordinate() {
+        let coord = Coordinate::new(Kind::from(30023), test_pubkey()).identifier("my-article");
+        let relay = RelayUrl::parse("wss://relay.damus.io").unwrap();
+        let naddr = Nip19Coordinate::new(coord, [relay]);
+        let bech32 = naddr.to_bech32().unwrap();
+
+        let resp = decode_nostr_uri_inner(&bech32).unwrap();
+        assert_eq!(resp.entity_type, "coordinate");
+        assert_eq!(resp.hex_id, "my-article");
+        assert_eq!(resp.author_hex.as_deref(), Some(TEST_HEX));
+        assert_eq!(resp.kind, Some(30023));
+        assert!(resp.relays.is_some());
+    }
+
+    #[test]
+    fn decode_nostr_prefix_strip() {
+        let npub = test_pubkey().to_bech32().unwrap();
+        let with_prefix = format!("nostr:{npub}");
+
+        let resp = decode_nostr_uri_inner(&with_prefix).unwrap();
+        assert_eq!(resp.entity_type, "pubkey");
+        assert_eq!(resp.hex_id, TEST_HEX);
+    }
+
+    #[test]
+    fn decode_invalid_input() {
+        let result = decode_nostr_uri_inner("garbage");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Invalid Nostr URI"));
+    }
+
+    #[test]
+    fn share_link_round_trips_through_decode() {
+        let params = BuildShareLinkParams {
+            event_id: test_event_id().to_bech32().unwrap(),
+            author: Some(test_pubkey().to_bech32().unwrap()),
+            kind: Some(30023),
+            relays: Some(vec!["wss://nos.lol".into()]),
+            identifier: Some("my-article".into()),
+        };
+        let link = build_share_link_inner(&params).unwrap();
+        assert_eq!(link.njump_url, format!("https://njump.me/{}", link.nevent));
+
+        let decoded = decode_nostr_uri_inner(&link.nevent).unwrap();
+        assert_eq!(decoded.entity_type, "event");
+        assert_eq!(decoded.hex_id, TEST_HEX);
+        assert_eq!(decoded.author_hex.as_deref(), Some(TEST_HEX));
+        assert_eq!(decoded.kind, Some(30023));
+        assert_eq!(decoded.relays, Some(vec!["wss://nos.lol".to_string()]));
+
+        let naddr = decode_nostr_uri_inner(link.naddr.as_deref().unwrap()).unwrap();
+        assert_eq!(naddr.entity_type, "coordinate");
+        assert_eq!(naddr.hex_id, "my-article");
+        assert_eq!(naddr.kind, Some(30023));
+
+        // Bare event ID: no hints, no naddr
+        let bare = build_share_link_inner(&BuildShareLinkParams {
+            event_id: TEST_HEX.into(),
+            author: None,
+            kind: None,
+            relays: None,
+            identifier: None,
+        })
+        .unwrap();
+        let decoded = decode_nostr_uri_inner(&bare.nevent).unwrap();
+        assert!(decoded.author_hex.is_none() && decoded.relays.is_none());
+        assert!(bare.naddr.is_none());
+
+        let invalid = |params: BuildShareLinkParams| build_share_link_inner(&params).unwrap_err();
+        let base = || BuildShareLinkParams {
+            event_id: TEST_HEX.into(),
+            author: None,
+            kind: None,
+            relays: None,
+            identifier: None,
+        };
+        assert!(invalid(BuildShareLinkParams {
+            event_id: "nope".into(),
+            ..base()
+        })
+        .contains("event_id"));
+        assert!(invalid(BuildShareLinkParams {
+            relays: Some(vec!["https://not-a-relay".into()]),
+            ..base()
+        })
+        .contains("relay"));
+        assert!(invalid(BuildShareLinkParams {
+            kind: Some(70_000),
+            ..base()
+        })
+        .contains("kind"));
+        assert!(invalid(BuildShareLinkParams {
+            identifier: Some("slug".into()),
+            ..base()
+        })
+        .contains("requires author and kind"));
+    }
+
+    #[tokio::test]
+    async fn batch_decode_reports_bad_entries_separately() {
+        let server = NostrIntelServer::new_for_test().await;
+        let npub = test_pubkey().to_bech32().unwrap();
+        let nevent = Nip19Event::new(test_event_id())
+            .author(test_pubkey())
+            .kind(Kind::TextNote)
+            .to_bech32()
+            .unwrap();
+        let coord = Coordinate::new(Kind::from(30023), test_pubkey()).identifier("my-article");
+        let naddr = Nip19Coordinate::new(coord, Vec::<RelayUrl>::new())
+            .to_bech32()
+            .unwrap();
+
+        let params = DecodeNostrUrisParams {
+            uris: vec![format!("nostr:{npub}"), "garbage".into(), nevent, naddr],
+        };
+        let json = server.decode_nostr_uris(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let decoded = resp["decoded"].as_array().unwrap();
+        let types: Vec<(u64, &str)> = decoded
+            .iter()
+            .map(|d| {
+                (
+                    d["index"].as_u64().unwrap(),
+                    d["entity_type"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(types, vec![(0, "pubkey"), (2, "event"), (3, "coordinate")]);
+        assert_eq!(decoded[0]["hex_id"], TEST_HEX);
+        assert_eq!(decoded[2]["hex_id"], "my-article");
+
+        let errors = resp["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["index"], 1);
+        assert_eq!(errors[0]["uri"], "garbage");
+        assert!(errors[0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid Nostr URI"));
+
+        let params = DecodeNostrUrisParams { uris: vec![] };
+        assert!(server.decode_nostr_uris(Parameters(params)).await.is_err());
+    }
+
+    /// Writer that appends formatted tracing output to a shared buffer.
+    struct BufWriter(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for BufWriter {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_call_span_records_fields() {
+        let buf = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer_buf = Arc::clone(&buf);
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || BufWriter(Arc::clone(&writer_buf)))
+            .with_ansi(false)
+            .with_max_level(tracing::Level::DEBUG)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let server = NostrIntelServer::new_for_test().await;
+        let npub = test_pubkey().to_bech32().unwrap();
+        server
+            .decode_nostr_uri(Parameters(DecodeNostrUriParams { uri: npub.clone() }))
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|l| l.contains("tool call finished"))
+            .unwrap_or_else(|| panic!("no completion event in:\n{output}"));
+        assert!(line.contains("tool_call"));
+        assert!(line.contains("decode_nostr_uri"));
+        assert!(line.contains("input="));
+        assert!(line.contains("elapsed_ms="));
+        // Identifiers are truncated, never logged in full
+        assert!(!output.contains(&npub));
+    }
+
+    #[tokio::test]
+    async fn free_tier_remaining_decrements() {
+        let server = NostrIntelServer::new_for_test().await;
+        let limit = server.config.free_tier.calls_per_day;
+
+        for expected in (0..limit).rev() {
+            match server.payment_gate("relay_discovery", 20, None).await {
+                Ok(PaymentGateResult::Proceed {
+                    free_tier_remaining,
+                }) => assert_eq!(free_tier_remaining, Some(expected)),
+                _ => panic!("expected free-tier call to proceed"),
+            }
+        }
+
+        // Exhausted without NWC: early return, not a free call
+        assert!(matches!(
+            server.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::EarlyReturn(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_params_are_rejected_before_the_free_tier_is_charged() {
+        let server = NostrIntelServer::new_for_test().await;
+        let limit = server.config.free_tier.calls_per_day;
+
+        let activity = |pubkey: &str, timeframe: &str| AccountActivityParams {
+            pubkey: pubkey.into(),
+            timeframe: Some(timeframe.into()),
+            include_reactions: None,
+            payment_hash: None,
+        };
+        let err = server
+            .account_activity(Parameters(activity("not-a-key", "7d")))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Invalid pubkey"));
+        let err = server
+            .account_activity(Parameters(activity(TEST_HEX, "soon")))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Invalid timeframe"));
+
+        let params: TrendingNotesParams =
+            serde_json::from_value(serde_json::json!({ "timeframe": "soon" })).unwrap();
+        let err = server.trending_notes(Parameters(params)).await.unwrap_err();
+        assert!(err.starts_with("Invalid timeframe"));
+
+        let zaps = |pubkey: &str, timeframe: &str| -> ZapAnalyticsParams {
+            serde_json::from_value(serde_json::json!({ "pubkey": pubkey, "timeframe": timeframe }))
+                .unwrap()
+        };
+        let err = server
+            .zap_analytics(Parameters(zaps("not-a-key", "7d")))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Invalid pubkey"));
+        let err = server
+            .zap_analytics(Parameters(zaps(TEST_HEX, "soon")))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Invalid timeframe"));
+
+        match server.payment_gate("relay_discovery", 20, None).await {
+            Ok(PaymentGateResult::Proceed {
+                free_tier_remaining,
+            }) => assert_eq!(free_tier_remaining, Some(limit - 1)),
+            _ => panic!("expected free-tier call to proceed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn settled_payment_hash_reusable_for_same_tool_only() {
+        let server = NostrIntelServer::new_for_test().await;
+        server
+            .cache
+            .record_settled_payment("paidhash", "search_events")
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                server
+                    .payment_gate("search_events", 10, Some("paidhash"))
+                    .await,
+                Ok(PaymentGateResult::Proceed {
+                    free_tier_remaining: None
+                })
+            ));
+        }
+
+        let err = server
+            .payment_gate("zap_analytics", 50, Some("paidhash"))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("already used for search_events"));
+    }
+
+    #[tokio::test]
+    async fn settled_payment_hash_is_looked_up_with_the_wallet_once() {
+        let (uri, lookups) = mock_nwc(MockWallet {
+            settled: true,
+            ..Default::default()
+        })
+        .await;
+        let mut server = NostrIntelServer::new_for_test().await;
+        server.nwc_gateway = Some(Arc::new(
+            NwcPool::new(&[uri], Arc::clone(&server.cache)).unwrap(),
+        ));
+        let hash = "ab".repeat(32);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                server.payment_gate("search_events", 10, Some(&hash)).await,
+                Ok(PaymentGateResult::Proceed {
+                    free_tier_remaining: None
+                })
+            ));
+        }
+        // The repeat call is answered from settled_payments, not the wallet
+        assert_eq!(lookups.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn underpaid_hash_is_not_bound_to_a_pricier_tool() {
+        // The mock wallet settles every invoice for 10 sats
+        let (uri, _) = mock_nwc(MockWallet {
+            settled: true,
+            ..Default::default()
+        })
+        .await;
+        let mut server = NostrIntelServer::new_for_test().await;
+        server.nwc_gateway = Some(Arc::new(
+            NwcPool::new(&[uri], Arc::clone(&server.cache)).unwrap(),
+        ));
+        let hash = "ab".repeat(32);
+
+        let err = server
+            .payment_gate("zap_analytics", 50, Some(&hash))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("does not cover zap_analytics"));
+        assert!(server
+            .cache
+            .get_settled_payment(&hash)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Still spendable on the tool it does cover
+        assert!(matches!(
+            server.payment_gate("search_events", 10, Some(&hash)).await,
+            Ok(PaymentGateResult::Proceed { .. })
+        ));
+    }
+
+    #[test]
+    fn convert_key_from_hex() {
+        let resp = convert_key_inner(TEST_HEX).unwrap();
+        assert_eq!(resp.input_format, "hex");
+        assert_eq!(resp.hex, TEST_HEX);
+        assert_eq!(resp.npub, test_pubkey().to_bech32().unwrap());
+    }
+
+    #[test]
+    fn convert_key_from_npub() {
+        let npub = test_pubkey().to_bech32().unwrap();
+        let resp = convert_key_inner(&npub).unwrap();
+        assert_eq!(resp.input_format, "npub");
+        assert_eq!(resp.hex, TEST_HEX);
+
+        let resp = convert_key_inner(&format!("nostr:{npub}")).unwrap();
+        assert_eq!(resp.hex, TEST_HEX);
+    }
+
+    #[tokio::test]
+    async fn convert_key_invalid_inputs() {
+        assert!(convert_key_inner("not-a-key").is_err());
+        assert!(convert_key_inner(&TEST_HEX[..60]).is_err());
+
+        // NIP-05 input goes through resolve_nip05, which rejects malformed identifiers
+        let server = NostrIntelServer::new_for_test().await;
+        let params = ConvertKeyParams {
+            pubkey: "a@b@c".into(),
+        };
+        let err = server.convert_key(Parameters(params)).await.unwrap_err();
+        assert!(err.contains("Invalid NIP-05 format"));
+    }
+
+    fn nip05_doc(name: &str, pubkey: &str) -> serde_json::Value {
+        serde_json::json!({ "names": { name: pubkey } })
+    }
+
+    #[test]
+    fn verify_nip05_match() {
+        let doc = nip05_doc("bob", TEST_HEX);
+        let resp = verify_nip05_inner(&doc, "bob", "bob@example.com", TEST_HEX);
+        assert!(resp.verified);
+        assert_eq!(resp.resolved_pubkey.as_deref(), Some(TEST_HEX));
+        assert!(resp.reason.is_none());
+    }
+
+    #[test]
+    fn verify_nip05_mismatch() {
+        let other = "a".repeat(64);
+        let doc = nip05_doc("bob", &other);
+        let resp = verify_nip05_inner(&doc, "bob", "bob@example.com", TEST_HEX);
+        assert!(!resp.verified);
+        assert_eq!(resp.resolved_pubkey, Some(other));
+        assert!(resp.reason.is_some());
+    }
+
+    #[test]
+    fn verify_nip05_missing_name() {
+        let doc = nip05_doc("alice", TEST_HEX);
+        let resp = verify_nip05_inner(&doc, "bob", "bob@example.com", TEST_HEX);
+        assert!(!resp.verified);
+        assert!(resp.resolved_pubkey.is_none());
+        assert!(resp.reason.unwrap().contains("not listed"));
+    }
+
+    /// Build a bolt11-shaped string with an `h` tag. Signature and checksum are zeroed,
+    /// which is fine since `bolt11_description_hash` only walks the tagged fields.
+    fn fake_bolt11(hrp: &str, description_hash: &[u8; 32]) -> String {
+        let mut words = vec![0u8; 7];
+        words.extend([BOLT11_TAG_DESCRIPTION_HASH, 52 >> 5, 52 & 31]);
+        let (mut acc, mut bits) = (0u32, 0u32);
+        for byte in description_hash {
+            acc = (acc << 8) | *byte as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                words.push(((acc >> bits) & 31) as u8);
+            }
+        }
+        words.push(((acc << (5 - bits)) & 31) as u8);
+        words.extend(std::iter::repeat_n(0, BOLT11_SIGNATURE_WORDS + 6));
+
+        let charset = BECH32_CHARSET.as_bytes();
+        let data: String = words.iter().map(|w| charset[*w as usize] as char).collect();
+        format!("{hrp}1{data}")
+    }
+
+    fn zap_receipt(description: &str, bolt11: &str) -> Event {
+        EventBuilder::new(Kind::ZapReceipt, "")
+            .tags([
+                Tag::parse(["bolt11", bolt11]).unwrap(),
+                Tag::parse(["description", description]).unwrap(),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    fn zap_request_json() -> String {
+        EventBuilder::new(Kind::ZapRequest, "great post")
+            .tags([Tag::parse(["amount", "1000000000"]).unwrap()])
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+            .as_json()
+    }
+
+    #[test]
+    fn zap_reciprocity_nets_bidirectional_flows() {
+        let (me, alice, bob) = (
+            Keys::generate().public_key().to_hex(),
+            Keys::generate().public_key().to_hex(),
+            Keys::generate().public_key().to_hex(),
+        );
+        let sent = |from: &str, to: &str, bolt11: &str| {
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags([
+                    Tag::parse(["p", to]).unwrap(),
+                    Tag::parse(["P", from]).unwrap(),
+                    Tag::parse(["bolt11", bolt11]).unwrap(),
+                ])
+                .sign_with_keys(&Keys::generate())
+                .unwrap()
+        };
+        // 1000 + 2500 sats back to alice, nothing to bob; a stray receipt from
+        // someone else must not count
+        let receipts = vec![
+            sent(&me, &alice, "lnbc10u1pqqqqqq"),
+            sent(&me, &alice, "lnbc25u1pqqqqqq"),
+            sent(&bob, &alice, "lnbc10u1pqqqqqq"),
+        ];
+        let top = vec![(alice.clone(), 5000), (bob.clone(), 2000)];
+
+        let flows = zap_reciprocity(&me, &top, &receipts);
+        assert_eq!(flows[0].pubkey, alice);
+        assert_eq!((flows[0].received_sats, flows[0].sent_sats), (5000, 3500));
+        assert_eq!(flows[0].net_sats, 1500);
+        assert_eq!(flows[1].pubkey, bob);
+        assert_eq!((flows[1].sent_sats, flows[1].net_sats), (0, 2000));
+
+        let flows = zap_reciprocity(
+            &me,
+            &[(bob.clone(), 100)],
+            &[sent(&me, &bob, "lnbc10u1pqqqqqq")],
+        );
+        assert_eq!(flows[0].net_sats, -900);
+    }
+
+    #[test]
+    fn bolt11_amount_containing_one() {
+        assert_eq!(parse_bolt11_amount("lnbc10u1pqqqqqq"), Some(1000));
+        assert_eq!(parse_bolt11_amount("lnbc2500u1pqqqqqq"), Some(250_000));
+    }
+
+    #[test]
+    fn zap_comment_is_extracted_from_embedded_request() {
+        let receipt = zap_receipt(&zap_request_json(), "lnbc10u1pqqqqqq");
+        assert_eq!(extract_zap_comment(&receipt).as_deref(), Some("great post"));
+
+        // Long comments are cut on a char boundary; empty ones are dropped
+        let long = EventBuilder::new(Kind::ZapRequest, "⚡".repeat(100))
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+            .as_json();
+        let comment = extract_zap_comment(&zap_receipt(&long, "lnbc10u1pqqqqqq")).unwrap();
+        assert!(comment.ends_with("⚡..."));
+        assert!(comment.len() <= MAX_ZAP_COMMENT_LEN + 3);
+
+        let empty = EventBuilder::new(Kind::ZapRequest, "  ")
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+            .as_json();
+        assert_eq!(
+            extract_zap_comment(&zap_receipt(&empty, "lnbc10u1pqqqqqq")),
+            None
+        );
+    }
+
+    #[test]
+    fn anonymous_zaps_are_not_attributed() {
+        let sender = Keys::generate();
+        let request = |keys: &Keys, anon: bool| {
+            let mut tags = vec![Tag::parse(["amount", "1000000"]).unwrap()];
+            if anon {
+                tags.push(Tag::parse(["anon"]).unwrap());
+            }
+            EventBuilder::new(Kind::ZapRequest, "")
+                .tags(tags)
+                .sign_with_keys(keys)
+                .unwrap()
+                .as_json()
+        };
+        let named = zap_receipt(&request(&sender, false), "lnbc10u1pqqqqqq");
+        let anonymous = zap_receipt(&request(&Keys::generate(), true), "lnbc10u1pqqqqqq");
+        // A P tag on an anonymous receipt is the throwaway key, not the sender
+        let anonymous_with_p = EventBuilder::new(Kind::ZapReceipt, "")
+            .tags([
+                Tag::parse(["P", &Keys::generate().public_key().to_hex()]).unwrap(),
+                Tag::parse(["description", &request(&Keys::generate(), true)]).unwrap(),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        assert_eq!(
+            extract_zapper_pubkey(&named),
+            Some(sender.public_key().to_hex())
+        );
+        assert_eq!(extract_zapper_pubkey(&anonymous), None);
+        assert_eq!(extract_zapper_pubkey(&anonymous_with_p), None);
+    }
+
+    #[test]
+    fn valid_zap_receipt_verifies() {
+        let description = zap_request_json();
+        let hash: [u8; 32] = Sha256::digest(description.as_bytes()).into();
+        let bolt11 = fake_bolt11("lnbc10m", &hash);
+        assert_eq!(bolt11_description_hash(&bolt11), Some(hash));
+
+        let receipt = zap_receipt(&description, &bolt11);
+        assert_eq!(verify_zap_receipt(&receipt), Ok(()));
+        assert_eq!(extract_zap_amount(&receipt), 1_000_000);
+    }
+
+    #[test]
+    fn tampered_zap_receipt_is_rejected() {
+        let description = zap_request_json();
+        let hash: [u8; 32] = Sha256::digest(description.as_bytes()).into();
+        let bolt11 = fake_bolt11("lnbc10m", &hash);
+
+        // Edit the zap request content without re-signing it
+        let tampered = description.replace("great post", "evil post");
+        let receipt = zap_receipt(&tampered, &bolt11);
+        assert!(verify_zap_receipt(&receipt).is_err());
+
+        // Valid request, but the invoice commits to a different description
+        let receipt = zap_receipt(&description, &fake_bolt11("lnbc10m", &[0u8; 32]));
+        assert!(verify_zap_receipt(&receipt)
+            .unwrap_err()
+            .contains("description_hash"));
+
+        // Invoice amount differs from the requested amount
+        let receipt = zap_receipt(&description, &fake_bolt11("lnbc20m", &hash));
+        assert!(verify_zap_receipt(&receipt).unwrap_err().contains("amount"));
+    }
+
+    #[test]
+    fn lnurlp_url_from_address() {
+        assert_eq!(
+            lnurlp_url("Alice@getalby.com").unwrap(),
+            "https://getalby.com/.well-known/lnurlp/alice"
+        );
+        assert!(lnurlp_url("not-an-address").is_err());
+        assert!(lnurlp_url("@domain.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_lightning_address_against_mock_endpoint() {
+        let app = axum::Router::new().route(
+            "/.well-known/lnurlp/alice",
+            axum::routing::get(|| async {
+                axum::Json(serde_json::json!({
+                    "tag": "payRequest",
+                    "callback": "https://example.com/lnurlp/alice/callback",
+                    "minSendable": 1000,
+                    "maxSendable": 100000000,
+                    "metadata": "[[\"text/plain\",\"Sats for Alice\"]]",
+                    "allowsNostr": true,
+                    "nostrPubkey": TEST_HEX,
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("http://{addr}/.well-known/lnurlp/alice");
+        let resp = fetch_lnurl_pay(&reqwest::Client::new(), "alice@example.com", &url)
+            .await
+            .unwrap();
+        assert_eq!(resp.callback, "https://example.com/lnurlp/alice/callback");
+        assert_eq!(resp.min_sendable_msats, Some(1000));
+        assert_eq!(resp.max_sendable_msats, Some(100_000_000));
+        assert_eq!(resp.nostr_pubkey.as_deref(), Some(TEST_HEX));
+        assert!(resp.supports_zaps);
+
+        let missing = format!("http://{addr}/.well-known/lnurlp/bob");
+        assert!(
+            fetch_lnurl_pay(&reqwest::Client::new(), "bob@example.com", &missing)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn event_summary_raw_json_round_trips() {
+        let event = EventBuilder::text_note("hello nostr")
+            .tags([Tag::parse(["t", "nostr"]).unwrap()])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        assert!(event_summary(&event, false).raw_event.is_none());
+
+        let raw = event_summary(&event, true).raw_event.unwrap();
+        let parsed = Event::from_json(&raw).unwrap();
+        assert_eq!(parsed, event);
+        assert!(parsed.verify().is_ok());
+    }
+
+    #[test]
+    fn event_summary_truncates_multibyte_content_on_char_boundary() {
+        // Byte 280 falls inside a two-byte 'é'
+        let content = format!("a{}", "é".repeat(200));
+        let event = EventBuilder::text_note(&content)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        let summary = event_summary(&event, false);
+        assert_eq!(summary.content, format!("a{}...", "é".repeat(139)));
+    }
+
+    #[test]
+    fn content_analysis_counts_media_mentions_and_hashtags() {
+        let keys = Keys::generate();
+        let mentioned = Keys::generate().public_key().to_hex();
+
+        let image_note = EventBuilder::text_note(
+            "sunset https://nostr.build/i/abc.JPG?w=800 via (https://example.com/post).",
+        )
+        .sign_with_keys(&keys)
+        .unwrap();
+        let imeta_note = EventBuilder::text_note("look")
+            .tags([Tag::parse(["imeta", "url https://cdn.example/v", "m video/mp4"]).unwrap()])
+            .sign_with_keys(&keys)
+            .unwrap();
+        let social_note = EventBuilder::text_note("gm #nostr #bitcoin, hi friend")
+            .tags([
+                Tag::parse(["p", &mentioned]).unwrap(),
+                Tag::parse(["t", "nostr"]).unwrap(),
+                Tag::parse(["t", "bitcoin"]).unwrap(),
+            ])
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let opts = SummaryOptions {
+            analyze_content: true,
+            ..Default::default()
+        };
+        let summaries = summarize_events(&[image_note.clone(), imeta_note, social_note], opts);
+        assert_eq!(summaries[0].has_media, Some(true));
+        assert_eq!(summaries[0].url_count, Some(2));
+        assert_eq!(summaries[1].has_media, Some(true));
+        assert_eq!(summaries[1].url_count, Some(0));
+        assert_eq!(summaries[2].has_media, Some(false));
+        assert_eq!(summaries[2].mention_count, Some(1));
+        assert_eq!(summaries[2].hashtag_count, Some(2));
+
+        // Off by default: no derived fields
+        let plain = summarize_events(&[image_note], SummaryOptions::default());
+        assert!(plain[0].has_media.is_none() && plain[0].url_count.is_none());
+    }
+
+    #[test]
+    fn detects_english_and_japanese_notes() {
+        assert_eq!(
+            detect_language("Good morning everyone, the sun is shining and the coffee is hot")
+                .as_deref(),
+            Some("eng")
+        );
+        assert_eq!(
+            detect_language("おはようございます。今日はとても良い天気ですね #nostr").as_deref(),
+            Some("jpn")
+        );
+        assert_eq!(detect_language(""), None);
+        assert_eq!(detect_language("   https://example.com/a.png #gm"), None);
+
+        assert_eq!(parse_language(" JPN ").unwrap(), whatlang::Lang::Jpn);
+        assert!(parse_language("xx").unwrap_err().contains("ISO 639-3"));
+    }
+
+    #[test]
+    fn resolves_embedded_nostr_references() {
+        let author = Keys::generate().public_key();
+        let npub = author.to_bech32().unwrap();
+        let note = EventId::from_hex(TEST_HEX).unwrap().to_bech32().unwrap();
+        let event = EventBuilder::text_note(format!(
+            "gm nostr:{npub}, see nostr:{note}. Again: nostr:{npub} nostr:nsec1bogus"
+        ))
+        .sign_with_keys(&Keys::generate())
+        .unwrap();
+
+        let opts = SummaryOptions {
+            resolve_references: true,
+            ..Default::default()
+        };
+        let summary = &summarize_events(std::slice::from_ref(&event), opts)[0];
+        let refs = summary.references.as_ref().unwrap();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].entity_type, "pubkey");
+        assert_eq!(refs[0].hex_id, author.to_hex());
+        assert_eq!(refs[1].entity_type, "event_id");
+        assert_eq!(refs[1].hex_id, TEST_HEX);
+
+        let plain = summarize_events(&[event], SummaryOptions::default());
+        assert!(plain[0].references.is_none());
+    }
+
+    #[test]
+    fn invalid_signatures_are_dropped_when_verifying() {
+        let keys = Keys::generate();
+        let valid = EventBuilder::text_note("valid")
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        // Swap in another event's signature; parsing doesn't verify, so this loads fine
+        let other = EventBuilder::text_note("other")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let mut json: serde_json::Value = serde_json::from_str(&valid.as_json()).unwrap();
+        json["content"] = "forged".into();
+        json["id"] = other.id.to_hex().into();
+        json["sig"] = other.sig.to_string().into();
+        let forged = Event::from_json(json.to_string()).unwrap();
+
+        let events = vec![valid.clone(), forged];
+        let unchecked = summarize_events(&events, SummaryOptions::default());
+        assert_eq!(unchecked.len(), 2);
+        assert!(unchecked.iter().all(|s| s.verified.is_none()));
+
+        let checked = summarize_events(
+            &events,
+            SummaryOptions {
+                verify_signatures: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(checked.len(), 1);
+        assert_eq!(checked[0].id, valid.id.to_hex());
+        assert_eq!(checked[0].verified, Some(true));
+    }
+
+    fn metadata_event(keys: &Keys, name: &str) -> Event {
+        EventBuilder::new(Kind::Metadata, format!(r#"{{"name":"{name}"}}"#))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn profile_search_falls_back_to_relays_when_primal_fails() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let relay_events = vec![
+            metadata_event(&alice, "alice"),
+            metadata_event(&bob, "bob"),
+            // Same author seen on a second relay
+            metadata_event(&alice, "alice"),
+        ];
+
+        let (hits, source) = combine_profile_search(
+            Err("Primal API HTTP error: 503".into()),
+            Some(Ok(relay_events)),
+            10,
+        )
+        .unwrap();
+        assert_eq!(source, "relay_search");
+        assert_eq!(hits.len(), 2);
+        assert!(
+            hits.iter()
+                .any(|h| h.pubkey == alice.public_key().to_hex()
+                    && h.name.as_deref() == Some("alice"))
+        );
+
+        let err = combine_profile_search(
+            Err("primal down".into()),
+            Some(Err("relays down".into())),
+            10,
+        )
+        .unwrap_err();
+        assert!(err.contains("primal down") && err.contains("relays down"));
+    }
+
+    #[test]
+    fn parse_timeframe_units() {
+        assert_eq!(parse_timeframe("30m"), Ok(30 * 60));
+        assert_eq!(parse_timeframe("24h"), Ok(86400));
+        assert_eq!(parse_timeframe("2w"), Ok(14 * 86400));
+        assert_eq!(parse_timeframe("3mo"), Ok(90 * 86400));
+        assert_eq!(parse_timeframe(" 1Y "), Ok(365 * 86400));
+    }
+
+    #[test]
+    fn parse_timeframe_rejects_invalid() {
+        for tf in [
+            "0d",
+            "-5h",
+            "7",
+            "3months",
+            "m",
+            "1.5d",
+            "99999999999999999y",
+        ] {
+            assert!(parse_timeframe(tf).is_err(), "{tf} should be rejected");
+        }
+        assert!(parse_timeframe("0m")
+            .unwrap_err()
+            .contains("greater than zero"));
+    }
+
+    fn contact_list(follows: &[&str]) -> Event {
+        contact_list_by(&Keys::generate(), follows)
+    }
+
+    fn contact_list_by(keys: &Keys, follows: &[&str]) -> Event {
+        EventBuilder::new(Kind::ContactList, "")
+            .tags(follows.iter().map(|pk| Tag::parse(["p", pk]).unwrap()))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn posting_activity_buckets_days_hours_and_gaps() {
+        let keys = Keys::generate();
+        // 2024-01-01 00:00:00 UTC
+        let day0 = 1_704_067_200;
+        let note = |ts: u64| {
+            EventBuilder::text_note("gm")
+                .custom_created_at(Timestamp::from(ts))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let events = vec![
+            note(day0 + 9 * 3600),
+            note(day0 + 9 * 3600 + 60),
+            note(day0 + 20 * 3600),
+            // Nothing on Jan 2; Jan 3 09:30 and Jan 4 09:00
+            note(day0 + 2 * 86400 + 9 * 3600 + 1800),
+            note(day0 + 3 * 86400 + 9 * 3600),
+        ];
+
+        let stats = posting_activity(&events, 10 * 86400);
+        assert_eq!(stats.total_events, 5);
+        assert_eq!(stats.avg_posts_per_day, 0.5);
+        assert_eq!(stats.most_active_hour_utc, Some(9));
+
+        let days: Vec<(&str, u32)> = stats
+            .posts_per_day
+            .iter()
+            .map(|d| (d.date.as_str(), d.count))
+            .collect();
+        assert_eq!(
+            days,
+            vec![("2024-01-01", 3), ("2024-01-03", 1), ("2024-01-04", 1)]
+        );
+
+        let gap = stats.longest_gap.unwrap();
+        assert_eq!(gap.from, day0 + 20 * 3600);
+        assert_eq!(gap.to, day0 + 2 * 86400 + 9 * 3600 + 1800);
+        assert_eq!(gap.hours, 37.5);
+
+        let empty = posting_activity(&[], 86400);
+        assert_eq!(empty.total_events, 0);
+        assert!(empty.most_active_hour_utc.is_none() && empty.longest_gap.is_none());
+    }
+
+    #[test]
+    fn common_follows_intersection() {
+        let lists = [
+            contact_list(&["a", "b", "c", "c"]),
+            contact_list(&["b", "c", "d"]),
+            contact_list(&["c", "d", "e"]),
+        ];
+        let sets: Vec<Vec<String>> = lists.iter().map(contact_list_pubkeys).collect();
+        assert_eq!(sets[0], vec!["a", "b", "c"]);
+
+        // Strict intersection: followed by all three
+        assert_eq!(count_common_follows(&sets, 3), vec![("c".to_string(), 3)]);
+
+        // Looser threshold, most-shared first
+        assert_eq!(
+            count_common_follows(&sets, 2),
+            vec![
+                ("c".to_string(), 3),
+                ("b".to_string(), 2),
+                ("d".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn common_follows_with_empty_contact_list() {
+        let sets = vec![vec!["a".to_string()], Vec::new()];
+        assert!(count_common_follows(&sets, 2).is_empty());
+        assert!(count_common_follows(&[], 1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn common_follows_rejects_bad_seed_count() {
+        let server = NostrIntelServer::new_for_test().await;
+        let params = CommonFollowsParams {
+            pubkeys: vec![TEST_HEX.to_string(), TEST_HEX.to_string()],
+            min_overlap: None,
+            payment_hash: None,
+        };
+        let err = server.common_follows(Parameters(params)).await.unwrap_err();
+        assert!(err.contains("between 2 and 10"));
+    }
+
+    #[tokio::test]
+    async fn common_follows_needs_two_contact_lists() {
+        let with_list = Keys::generate();
+        let without_list = Keys::generate().public_key();
+        let followed = Keys::generate().public_key();
+        let list = EventBuilder::new(Kind::ContactList, "")
+            .tags([Tag::public_key(followed)])
+            .sign_with_keys(&with_list)
+            .unwrap();
+        let relay = mock_relay_with_events(vec![list]).await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let params = CommonFollowsParams {
+            pubkeys: vec![with_list.public_key().to_hex(), without_list.to_hex()],
+            min_overlap: None,
+            payment_hash: None,
+        };
+        let json = server.common_follows(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        // A single loaded contact list must not be reported as "common"
+        assert_eq!(resp["common_count"], 0);
+        assert_eq!(
+            resp["seeds_without_contact_list"],
+            serde_json::json!([without_list.to_hex()])
+        );
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn invoice_failure_without_x402_reports_outage() {
+        let server = NostrIntelServer::new_for_test().await;
+        let PaymentGateResult::EarlyReturn(json) = server
+            .invoice_unavailable("trending_notes", 20)
+            .await
+            .unwrap()
+        else {
+            panic!("expected an early return");
+        };
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["free_tier_exhausted"], true);
+        assert_eq!(resp["payment_available"], false);
+        assert!(resp["message"]
+            .as_str()
+            .unwrap()
+            .contains("temporarily unreachable"));
+    }
+
+    #[tokio::test]
+    async fn invoice_failure_falls_back_to_x402() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.payment.enable_x402 = true;
+        config.payment.x402_address = "0xfeed".into();
+        config.payment.x402_sats_per_cent = 15;
+        server.config = Arc::new(config);
+
+        let PaymentGateResult::EarlyReturn(json) = server
+            .invoice_unavailable("trending_notes", 20)
+            .await
+            .unwrap()
+        else {
+            panic!("expected an early return");
+        };
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["payment_required"], true);
+        assert_eq!(resp["protocol"], "x402");
+        assert_eq!(resp["x402"]["payment_address"], "0xfeed");
+        // 20 sats at 15 sats/cent rounds up to 2 cents
+        assert_eq!(resp["x402"]["amount_usdc"], "0.02");
+    }
+
+    #[tokio::test]
+    async fn wallet_status_requires_admin_key() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.server.admin_keys = vec!["op-secret".into()];
+        server.config = Arc::new(config);
+
+        let err = server.wallet_status().await.unwrap_err();
+        assert!(err.contains("X-Admin-Key"));
+
+        assert!(server.bind_admin_key(Some("guess")).is_err());
+        server.bind_admin_key(Some("op-secret")).unwrap();
+        // Past the gate; the test server has no wallet configured
+        let err = server.wallet_status().await.unwrap_err();
+        assert!(err.contains("not configured"));
+    }
+
+    #[tokio::test]
+    async fn configured_instructions_and_descriptions_are_served() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        assert_eq!(
+            server.get_info().instructions.as_deref(),
+            Some(DEFAULT_INSTRUCTIONS)
+        );
+
+        let mut config = (*server.config).clone();
+        config.server.instructions = Some("Servidor de inteligencia Nostr.".into());
+        config
+            .server
+            .tool_descriptions
+            .insert("get_profile".into(), "Obtener un perfil".into());
+        server.tool_router = NostrIntelServer::configured_tool_router(&config).unwrap();
+        server.config = Arc::new(config.clone());
+
+        assert_eq!(
+            server.get_info().instructions.as_deref(),
+            Some("Servidor de inteligencia Nostr.")
+        );
+        let tool = server.tool_router.get("get_profile").unwrap();
+        assert_eq!(tool.description.as_deref(), Some("Obtener un perfil"));
+        // HTTP sessions get the same router
+        let session = NostrIntelServer::from_shared(&server.shared_state());
+        let tool = session.tool_router.get("get_profile").unwrap();
+        assert_eq!(tool.description.as_deref(), Some("Obtener un perfil"));
+        let untouched = server.tool_router.get("decode_nostr_uri").unwrap();
+        assert!(untouched
+            .description
+            .as_deref()
+            .unwrap()
+            .starts_with("Decode"));
+
+        config
+            .server
+            .tool_descriptions
+            .insert("no_such_tool".into(), "x".into());
+        let err = NostrIntelServer::configured_tool_router(&config)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no_such_tool"));
+    }
+
+    #[tokio::test]
+    async fn disabled_tools_are_not_registered() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.tools.disabled = vec!["get_follower_graph".into()];
+        server.tool_router = NostrIntelServer::configured_tool_router(&config).unwrap();
+        server.config = Arc::new(config.clone());
+
+        assert!(!server.tool_router.has_route("get_follower_graph"));
+        assert!(server.tool_router.has_route("trending_notes"));
+        // HTTP sessions get the same router
+        let session = NostrIntelServer::from_shared(&server.shared_state());
+        assert!(!session.tool_router.has_route("get_follower_graph"));
+
+        let json = server.get_server_info().await.unwrap();
+        let info: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let names: Vec<&str> = info["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert!(!names.contains(&"get_follower_graph"));
+        assert!(names.contains(&"trending_notes"));
+
+        let quote = |tool_name: &str| GetPriceQuoteParams {
+            tool_name: tool_name.into(),
+            limit: None,
+            depth: None,
+        };
+        assert!(server
+            .get_price_quote(Parameters(quote("get_follower_graph")))
+            .await
+            .is_err());
+        assert!(server
+            .get_price_quote(Parameters(quote("trending_notes")))
+            .await
+            .is_ok());
+
+        config.tools.disabled = vec!["no_such_tool".into()];
+        let err = NostrIntelServer::configured_tool_router(&config)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("tools.disabled"));
+    }
+
+    #[tokio::test]
+    async fn purge_cache_entry_requires_admin_and_removes_profile() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.server.admin_keys = vec!["op-secret".into()];
+        server.config = Arc::new(config);
+
+        let keys = Keys::generate();
+        let hex = keys.public_key().to_hex();
+        server
+            .cache
+            .set_profile(&CachedProfile {
+                pubkey: hex.clone(),
+                name: Some("alice".into()),
+                display_name: None,
+                about: None,
+                picture: None,
+                banner: None,
+                nip05: None,
+                lud16: None,
+                website: None,
+            })
+            .await
+            .unwrap();
+        let params = || PurgeCacheEntryParams {
+            pubkey: keys.public_key().to_bech32().unwrap(),
+        };
+
+        let err = server
+            .purge_cache_entry(Parameters(params()))
+            .await
+            .unwrap_err();
+        assert!(err.contains("X-Admin-Key"));
+        assert!(server.cache.get_profile(&hex).await.unwrap().is_some());
+
+        server.bind_admin_key(Some("op-secret")).unwrap();
+        let json = server
+            .purge_cache_entry(Parameters(params()))
+            .await
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["pubkey"], hex);
+        assert_eq!(resp["profiles_removed"], 1);
+        assert_eq!(resp["total_removed"], 1);
+        assert!(server.cache.get_profile(&hex).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reset_rate_limit_restores_exhausted_quota() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.server.admin_keys = vec!["op-secret".into()];
+        server.config = Arc::new(config);
+        let limit = server.config.free_tier.calls_per_day;
+
+        let shared = server.shared_state();
+        let client = NostrIntelServer::from_shared(&shared);
+        client.bind_api_key(Some("agent-key")).unwrap();
+        for _ in 0..limit {
+            client
+                .payment_gate("relay_discovery", 20, None)
+                .await
+                .unwrap();
         }
+        assert!(matches!(
+            client.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::EarlyReturn(_))
+        ));
+
+        let params = || ResetRateLimitParams {
+            client_id: None,
+            api_key: Some("agent-key".into()),
+        };
+        let err = server
+            .reset_rate_limit(Parameters(params()))
+            .await
+            .unwrap_err();
+        assert!(err.contains("X-Admin-Key"));
+
+        server.bind_admin_key(Some("op-secret")).unwrap();
+        let json = server.reset_rate_limit(Parameters(params())).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["client_id"], client.client_id());
+        assert_eq!(resp["previous_count"], limit);
+
+        assert!(matches!(
+            client.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::Proceed { .. })
+        ));
     }
 
-    /// Create a new server instance from shared state (for per-session HTTP factory).
-    pub fn from_shared(state: &SharedState) -> Self {
-        let id = state.session_counter.fetch_add(1, Ordering::Relaxed);
-        Self {
-            config: Arc::clone(&state.config),
-            nostr_client: Arc::clone(&state.nostr_client),
-            cache: Arc::clone(&state.cache),
-            search_client: Arc::clone(&state.search_client),
-            nwc_gateway: state.nwc_gateway.clone(),
-            rate_limiter: Arc::clone(&state.rate_limiter),
-            session_id: format!("http-{id}"),
-            tool_router: Self::tool_router(),
+    #[tokio::test]
+    async fn note_id_in_pubkey_param_is_rejected() {
+        let server = NostrIntelServer::new_for_test().await;
+        let note = EventId::from_hex(TEST_HEX).unwrap().to_bech32().unwrap();
+        let params = ZapAnalyticsParams {
+            pubkey: note,
+            timeframe: None,
+            verify: None,
+            include_reciprocity: None,
+            min_sats: None,
+            payment_hash: None,
+        };
+        let err = server.zap_analytics(Parameters(params)).await.unwrap_err();
+        assert!(err.contains("not a pubkey"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn check_relays_mixes_reachable_and_unreachable() {
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(|| async {
+                axum::Json(serde_json::json!({
+                    "name": "mock relay",
+                    "supported_nips": [1, 11],
+                    "software": "mock",
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        // Grab a free port and close it so nothing is listening there
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+
+        let reachable = format!("ws://{addr}");
+        let unreachable = format!("ws://{closed_addr}");
+        let server = NostrIntelServer::new_for_test().await;
+        let params = CheckRelaysParams {
+            relay_urls: vec![reachable.clone(), unreachable.clone(), reachable.clone()],
+        };
+        let json = server.check_relays(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(resp["count"], 2);
+        assert_eq!(resp["online_count"], 1);
+        assert_eq!(resp["relays"][0]["relay_url"], reachable.as_str());
+        assert_eq!(resp["relays"][0]["online"], true);
+        assert_eq!(resp["relays"][0]["name"], "mock relay");
+        assert_eq!(resp["relays"][1]["relay_url"], unreachable.as_str());
+        assert_eq!(resp["relays"][1]["online"], false);
+
+        // Second check of the reachable relay is served from cache
+        let (cached, hit) = server.check_relay_inner(&reachable).await.unwrap();
+        assert!(hit && cached.online);
+    }
+
+    #[tokio::test]
+    async fn check_relays_enforces_allow_and_deny_lists() {
+        // Nothing listens on these; offline results are fine, we only check selection
+        let (allowed, other, denied) = ("ws://127.0.0.1:1", "ws://127.0.0.1:2", "ws://127.0.0.1:3");
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.relays.allowlist = vec![allowed.into()];
+        config.relays.denylist = vec![denied.into()];
+        server.config = Arc::new(config);
+        let check = |urls: &[&str]| CheckRelaysParams {
+            relay_urls: urls.iter().map(|u| u.to_string()).collect(),
+        };
+
+        // Relays off the allowlist are dropped silently
+        let json = server
+            .check_relays(Parameters(check(&[allowed, other])))
+            .await
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["count"], 1);
+        assert_eq!(resp["relays"][0]["relay_url"], allowed);
+        let err = server
+            .check_relays(Parameters(check(&[other])))
+            .await
+            .unwrap_err();
+        assert!(err.contains("allowlist"), "{err}");
+
+        // Denied relays are rejected outright, even alongside allowed ones
+        let err = server
+            .check_relays(Parameters(check(&[allowed, denied])))
+            .await
+            .unwrap_err();
+        assert!(err.contains("denied"), "{err}");
+        let err = server
+            .check_relay(Parameters(CheckRelayParams {
+                relay_url: denied.into(),
+            }))
+            .await
+            .unwrap_err();
+        assert!(err.contains("denied"), "{err}");
+    }
+
+    #[test]
+    fn check_relay_lists_missing_common_nips() {
+        let doc = serde_json::json!({ "supported_nips": [1, 2, 9, 11, 42, 45, 50] });
+        let resp = check_relay_response(nip11_relay_info("wss://subset.example", &doc, 5));
+        assert_eq!(resp.missing_common_nips, Some(vec![40, 70]));
+
+        let all: Vec<u32> = COMMON_RELAY_NIPS.to_vec();
+        let doc = serde_json::json!({ "supported_nips": all });
+        let resp = check_relay_response(nip11_relay_info("wss://full.example", &doc, 5));
+        assert_eq!(resp.missing_common_nips, Some(vec![]));
+
+        // Without a NIP list every common NIP counts as missing
+        let resp = check_relay_response(nip11_relay_info(
+            "wss://silent.example",
+            &serde_json::json!({}),
+            5,
+        ));
+        assert_eq!(resp.missing_common_nips, Some(COMMON_RELAY_NIPS.to_vec()));
+        assert!(CheckRelayResponse::offline("down".into())
+            .missing_common_nips
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn nip11_limitation_and_fees_survive_cache() {
+        let doc = serde_json::json!({
+            "name": "paid relay",
+            "icon": "https://paid.example/icon.png",
+            "supported_nips": [1, 11, 42],
+            "limitation": {
+                "max_message_length": 16384,
+                "max_subscriptions": 20,
+                "auth_required": true,
+                "payment_required": true
+            },
+            "fees": {
+                "admission": [{ "amount": 21000, "unit": "msats" }]
+            }
+        });
+
+        let info = nip11_relay_info("wss://paid.example", &doc, 42);
+        let server = NostrIntelServer::new_for_test().await;
+        server.cache.set_relay_info(&info).await.unwrap();
+
+        let (resp, hit) = server
+            .check_relay_inner("wss://paid.example")
+            .await
+            .unwrap();
+        assert!(hit);
+        let limitation = resp.limitation.unwrap();
+        assert_eq!(limitation.max_message_length, Some(16384));
+        assert_eq!(limitation.max_subscriptions, Some(20));
+        assert_eq!(limitation.auth_required, Some(true));
+        assert_eq!(limitation.payment_required, Some(true));
+        assert_eq!(resp.fees.unwrap()["admission"][0]["amount"], 21000);
+        assert_eq!(resp.icon.as_deref(), Some("https://paid.example/icon.png"));
+
+        // Relays without these sections leave the fields unset
+        let bare = nip11_relay_info("wss://bare.example", &serde_json::json!({}), 1);
+        let resp = check_relay_response(bare);
+        assert!(resp.limitation.is_none() && resp.fees.is_none() && resp.icon.is_none());
+    }
+
+    #[test]
+    fn cursor_pages_do_not_repeat_boundary_event() {
+        let keys = Keys::generate();
+        let note = |text: &str, ts: u64| {
+            EventBuilder::text_note(text)
+                .custom_created_at(Timestamp::from(ts))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let (a, b) = (note("a", 100), note("b", 200));
+
+        let page1 = events_after_cursor(vec![a.clone(), b.clone()], None, None);
+        let cursor = search_cursor(&page1).unwrap();
+        assert_eq!(cursor.timestamp, 200);
+        assert_eq!(cursor.event_id, b.id.to_hex());
+
+        // A relay honouring `since = 200` returns the boundary event again,
+        // plus a same-second note and a newer one
+        let (c, d) = (note("c", 200), note("d", 300));
+        let boundary = EventId::parse(&cursor.event_id).unwrap();
+        let page2 = events_after_cursor(
+            vec![b.clone(), c.clone(), d.clone(), a.clone()],
+            Some(cursor.timestamp),
+            Some(boundary),
+        );
+
+        let ids: Vec<EventId> = page2.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![c.id, d.id]);
+        assert!(page1.iter().all(|e| !ids.contains(&e.id)));
+        assert_eq!(search_cursor(&page2).unwrap().timestamp, 300);
+    }
+
+    #[test]
+    fn duplicate_events_are_summarized_once() {
+        let keys = Keys::generate();
+        let note = |text: &str| EventBuilder::text_note(text).sign_with_keys(&keys).unwrap();
+        let (a, b) = (note("a"), note("b"));
+        // The same events as returned by three overlapping relays
+        let events = vec![a.clone(), b.clone(), a.clone(), a.clone(), b.clone()];
+
+        let events = dedupe_by_id(events);
+        let ids: Vec<EventId> = events.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![a.id, b.id]);
+        assert_eq!(
+            summarize_events(&events, SummaryOptions::default()).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn short_notes_and_empty_replies_are_dropped() {
+        let keys = Keys::generate();
+        let parent = EventBuilder::text_note("parent post")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let note = |text: &str, reply: bool| {
+            let mut builder = EventBuilder::text_note(text);
+            if reply {
+                builder = builder.tag(Tag::event(parent.id));
+            }
+            builder.sign_with_keys(&keys).unwrap()
+        };
+        let long = note("a proper note with some words", false);
+        let short = note("gm", false);
+        let empty_reply = note("  ", true);
+        let mention_reply = note(&format!("nostr:{}", parent.id.to_bech32().unwrap()), true);
+        let real_reply = note("agreed, nice write-up", true);
+        let events = vec![
+            long.clone(),
+            short.clone(),
+            empty_reply.clone(),
+            mention_reply.clone(),
+            real_reply.clone(),
+        ];
+
+        let (kept, too_short, empty) = without_low_content(events.clone(), Some(5), true);
+        let ids: Vec<EventId> = kept.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![long.id, real_reply.id]);
+        assert_eq!((too_short, empty), (1, 2));
+
+        // Replies only: short top-level notes stay
+        let (kept, too_short, empty) = without_low_content(events.clone(), None, true);
+        assert_eq!(kept.len(), 3);
+        assert_eq!((too_short, empty), (0, 2));
+
+        // No flags: nothing is dropped
+        let (kept, _, _) = without_low_content(events, None, false);
+        assert_eq!(kept.len(), 5);
+    }
+
+    #[test]
+    fn excluded_authors_events_are_dropped() {
+        let (muted, kept) = (Keys::generate(), Keys::generate());
+        let note = |keys: &Keys| EventBuilder::text_note("gm").sign_with_keys(keys).unwrap();
+        let events = vec![note(&muted), note(&kept), note(&muted)];
+
+        let list = vec![muted.public_key().to_bech32().unwrap()];
+        let excluded = parse_excluded_authors(Some(&list)).unwrap();
+        let (events, dropped) = without_authors(events, &excluded);
+
+        assert_eq!(dropped, 2);
+        assert_eq!(events.len(), 1);
+        assert!(events.iter().all(|e| e.pubkey == kept.public_key()));
+
+        let err = parse_excluded_authors(Some(&["nope".to_string()])).unwrap_err();
+        assert!(err.contains("exclude_authors"));
+    }
+
+    #[test]
+    fn keyword_filter_is_case_insensitive_any_match() {
+        let keywords = parse_keywords(&["Bitcoin".to_string(), " LIGHTNING ".to_string()]).unwrap();
+        assert_eq!(keywords, vec!["bitcoin", "lightning"]);
+
+        assert!(contains_any_keyword("Stacking BITCOIN today", &keywords));
+        assert!(contains_any_keyword("lightning channels opened", &keywords));
+        assert!(!contains_any_keyword("gm nostr", &keywords));
+        assert!(!contains_any_keyword("", &keywords));
+
+        assert!(parse_keywords(&[]).is_err());
+        assert!(parse_keywords(&["  ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn duplicate_content_collapses_to_earliest_with_summed_engagement() {
+        let keys = Keys::generate();
+        let note = |content: &str, at: u64| {
+            EventBuilder::text_note(content)
+                .custom_created_at(Timestamp::from(at))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let (a, b, c) = (
+            note("buy the  dip", 300),
+            note("buy the dip", 100),
+            note(" buy the\ndip ", 200),
+        );
+        let other = note("gm", 150);
+        let entry = |note, reactions, reposts| NoteEngagement {
+            note,
+            reactions,
+            reposts,
+            tally: ReactionTally::default(),
+        };
+
+        let collapsed = collapse_duplicate_content(vec![
+            entry(&a, 1, 0),
+            entry(&other, 4, 1),
+            entry(&b, 2, 1),
+            entry(&c, 3, 2),
+        ]);
+
+        assert_eq!(collapsed.len(), 2);
+        let dup = &collapsed[0];
+        assert_eq!(dup.note.id, b.id);
+        assert_eq!((dup.reactions, dup.reposts), (6, 3));
+        assert_eq!(collapsed[1].note.id, other.id);
+        assert_eq!((collapsed[1].reactions, collapsed[1].reposts), (4, 1));
+    }
+
+    #[test]
+    fn reaction_breakdown_splits_likes_dislikes_and_emoji() {
+        let mut tally = ReactionTally::default();
+        for content in [
+            "+", "", "+", "-", "🤙", "🤙", ":shaka:", "🔥", "🤙", ":shaka:",
+        ] {
+            tally.add(content);
         }
+        let mut duplicate = ReactionTally::default();
+        duplicate.add("-");
+        duplicate.add("🔥");
+        tally.merge(duplicate);
+
+        let breakdown = tally.into_breakdown(2);
+        assert_eq!((breakdown.likes, breakdown.dislikes), (3, 2));
+        // 🔥 ties :shaka: at 2 and loses alphabetically
+        assert_eq!(
+            breakdown.top_emoji,
+            std::collections::BTreeMap::from([("🤙".to_string(), 3), (":shaka:".to_string(), 2)])
+        );
     }
-}
 
-// ==================== decode logic ====================
+    #[test]
+    fn author_cap_backfills_with_other_authors() {
+        let (prolific, a, b) = (Keys::generate(), Keys::generate(), Keys::generate());
+        // Scored and sorted: the prolific author holds the top three spots
+        let ranked = vec![
+            (90, prolific.public_key()),
+            (80, prolific.public_key()),
+            (70, prolific.public_key()),
+            (50, a.public_key()),
+            (40, b.public_key()),
+        ];
+
+        let capped = cap_per_author(ranked.clone(), 1, |(_, pk)| *pk);
+        let scores: Vec<u64> = capped.iter().map(|(s, _)| *s).collect();
+        assert_eq!(scores, vec![90, 50, 40]);
+
+        let capped = cap_per_author(ranked, 2, |(_, pk)| *pk);
+        assert_eq!(capped.len(), 4);
+    }
 
-fn decode_nostr_uri_inner(uri: &str) -> Result<DecodeNostrUriResponse, String> {
-    let uri = uri.trim();
-    let bech32 = uri.strip_prefix("nostr:").unwrap_or(uri);
+    #[tokio::test]
+    async fn server_info_prices_match_config() {
+        let server = NostrIntelServer::new_for_test().await;
+        let json = server.get_server_info().await.unwrap();
+        let info: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let pricing = &server.config.pricing;
+        let tools = info["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), server.tool_router.list_all().len());
+        for tool in tools {
+            let name = tool["name"].as_str().unwrap();
+            let expected = pricing.price_for(name, None, None);
+            assert_eq!(tool["price_sats"].as_u64(), expected, "{name}");
+            assert_eq!(tool["paid"].as_bool(), Some(expected.is_some()), "{name}");
+        }
+        let zap = tools.iter().find(|t| t["name"] == "zap_analytics").unwrap();
+        assert_eq!(zap["price_sats"].as_u64(), Some(pricing.zap_analytics));
+        assert!(tools
+            .iter()
+            .any(|t| t["name"] == "get_server_info" && t["paid"] == false));
 
-    let nip19 = Nip19::from_bech32(bech32).map_err(|e| format!("Invalid Nostr URI: {e}"))?;
+        assert_eq!(
+            info["free_tier_calls_per_day"].as_u64(),
+            Some(server.config.free_tier.calls_per_day as u64)
+        );
+        assert_eq!(info["payment_protocols"]["nwc"], false);
+        assert_eq!(info["payment_protocols"]["l402"], false);
+    }
 
-    match nip19 {
-        Nip19::Pubkey(pk) => Ok(DecodeNostrUriResponse {
-            entity_type: "pubkey".into(),
-            hex_id: pk.to_hex(),
-            relays: None,
-            author_hex: None,
-            kind: None,
-        }),
-        Nip19::EventId(id) => Ok(DecodeNostrUriResponse {
-            entity_type: "event_id".into(),
-            hex_id: id.to_hex(),
-            relays: None,
-            author_hex: None,
-            kind: None,
-        }),
-        Nip19::Profile(profile) => {
-            let relays: Vec<String> = profile.relays.into_iter().map(|r| r.to_string()).collect();
-            Ok(DecodeNostrUriResponse {
-                entity_type: "profile".into(),
-                hex_id: profile.public_key.to_hex(),
-                relays: if relays.is_empty() {
-                    None
-                } else {
-                    Some(relays)
-                },
-                author_hex: None,
-                kind: None,
-            })
+    #[tokio::test]
+    async fn auth_required_default_relay_is_excluded() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let open = "ws://127.0.0.1:1".to_string();
+        let gated = "ws://127.0.0.1:2".to_string();
+        server.nostr_client = Arc::new(
+            NostrClient::new(
+                vec![open.clone(), gated.clone()],
+                Arc::clone(&server.relay_health),
+                PoolOptions::default(),
+            )
+            .await
+            .unwrap(),
+        );
+
+        // Seed the relay-info cache so no NIP-11 request goes out
+        let nip11 = |auth: bool| serde_json::json!({ "limitation": { "auth_required": auth } });
+        for (url, auth) in [(&open, false), (&gated, true)] {
+            let info = nip11_relay_info(url, &nip11(auth), 10);
+            server.cache.set_relay_info(&info).await.unwrap();
         }
-        Nip19::Event(event) => {
-            let relays: Vec<String> = event.relays.into_iter().map(|r| r.to_string()).collect();
-            Ok(DecodeNostrUriResponse {
-                entity_type: "event".into(),
-                hex_id: event.event_id.to_hex(),
-                relays: if relays.is_empty() {
-                    None
-                } else {
-                    Some(relays)
-                },
-                author_hex: event.author.map(|a| a.to_hex()),
-                kind: event.kind.map(|k| k.as_u16() as u32),
+
+        server.refresh_relay_requirements().await;
+        assert_eq!(server.nostr_client.target_relays().await, vec![open]);
+        assert_eq!(
+            server.nostr_client.skipped_relays().await,
+            vec![gated.clone()]
+        );
+
+        let (resp, _) = server.check_relay_inner(&gated).await.unwrap();
+        assert!(resp.requires_auth && !resp.requires_payment);
+        server.nostr_client.shutdown().await;
+    }
+
+    /// Test server whose relay pool is the given relay, already connected.
+    async fn test_server_with_relay(relay: String) -> NostrIntelServer {
+        test_server_with_relays(vec![relay]).await
+    }
+
+    async fn test_server_with_relays(relays: Vec<String>) -> NostrIntelServer {
+        let mut server = NostrIntelServer::new_for_test().await;
+        server.nostr_client = Arc::new(
+            NostrClient::new(
+                relays,
+                Arc::clone(&server.relay_health),
+                PoolOptions::default(),
+            )
+            .await
+            .unwrap(),
+        );
+        server
+            .nostr_client
+            .wait_for_connection(std::time::Duration::from_secs(5))
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn slow_tool_call_trips_request_timeout() {
+        // A relay that accepts subscriptions but never answers them
+        let relay = MockRelay::start(RelayPolicy::Silent).await;
+        let mut server = test_server_with_relay(relay.url).await;
+        let mut config = (*server.config).clone();
+        config.server.request_timeout_seconds = 1;
+        server.config = Arc::new(config);
+
+        let params: TrendingNotesParams = serde_json::from_value(serde_json::json!({})).unwrap();
+        let started = std::time::Instant::now();
+        let timeout = server
+            .with_request_timeout("trending_notes", server.trending_notes(Parameters(params)))
+            .await
+            .unwrap_err();
+
+        // trending_notes waits up to 15s on relays; the request timeout cuts it short
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        let json = serde_json::to_value(&timeout).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": "request_timeout",
+                "tool": "trending_notes",
+                "timeout_seconds": 1,
             })
+        );
+    }
+
+    #[tokio::test]
+    async fn zap_analytics_buckets_anonymous_zaps() {
+        let recipient = Keys::generate().public_key();
+        let sender = Keys::generate();
+        let receipt = |request_keys: &Keys, anon: bool, bolt11: &str| {
+            let mut tags = vec![];
+            if anon {
+                tags.push(Tag::parse(["anon"]).unwrap());
+            }
+            let request = EventBuilder::new(Kind::ZapRequest, "")
+                .tags(tags)
+                .sign_with_keys(request_keys)
+                .unwrap()
+                .as_json();
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags([
+                    Tag::public_key(recipient),
+                    Tag::parse(["bolt11", bolt11]).unwrap(),
+                    Tag::parse(["description", &request]).unwrap(),
+                ])
+                .sign_with_keys(&Keys::generate())
+                .unwrap()
+        };
+        let relay = mock_relay_with_events(vec![
+            receipt(&sender, false, "lnbc10u1pqqqqqq"),
+            receipt(&Keys::generate(), true, "lnbc20u1pqqqqqq"),
+            receipt(&Keys::generate(), true, "lnbc5u1pqqqqqq"),
+        ])
+        .await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let params = ZapAnalyticsParams {
+            pubkey: recipient.to_hex(),
+            timeframe: None,
+            verify: Some(false),
+            include_reciprocity: None,
+            min_sats: None,
+            payment_hash: None,
+        };
+        let json = server.zap_analytics(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["total_received_sats"], 3500);
+        assert_eq!(resp["anonymous_sats"], 2500);
+        assert_eq!(resp["anonymous_zaps_count"], 2);
+        let zappers = resp["top_zappers"].as_array().unwrap();
+        assert_eq!(zappers.len(), 1);
+        assert_eq!(zappers[0]["pubkey"], sender.public_key().to_hex());
+        assert_eq!(zappers[0]["total_sats"], 1000);
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn zap_analytics_drops_zaps_below_min_sats() {
+        let recipient = Keys::generate().public_key();
+        let small = Keys::generate();
+        let large = Keys::generate();
+        let receipt = |sender: &Keys, bolt11: &str| {
+            let request = EventBuilder::new(Kind::ZapRequest, "")
+                .sign_with_keys(sender)
+                .unwrap()
+                .as_json();
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags([
+                    Tag::public_key(recipient),
+                    Tag::parse(["bolt11", bolt11]).unwrap(),
+                    Tag::parse(["description", &request]).unwrap(),
+                ])
+                .sign_with_keys(&Keys::generate())
+                .unwrap()
+        };
+        // 21 sats (dust), 100 sats (exactly the threshold) and 1000 sats
+        let relay = mock_relay_with_events(vec![
+            receipt(&small, "lnbc210n1pqqqqqq"),
+            receipt(&large, "lnbc1u1pqqqqqq"),
+            receipt(&large, "lnbc10u1pqqqqqq"),
+        ])
+        .await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let analytics = |min_sats: Option<u64>| {
+            let server = &server;
+            let params = ZapAnalyticsParams {
+                pubkey: recipient.to_hex(),
+                timeframe: None,
+                verify: Some(false),
+                include_reciprocity: None,
+                min_sats,
+                payment_hash: None,
+            };
+            async move {
+                let json = server.zap_analytics(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            }
+        };
+
+        let all = analytics(None).await;
+        assert_eq!(all["total_received_sats"], 1121);
+        assert_eq!(all["total_zaps_count"], 3);
+        assert!(all.get("below_min_sats_count").is_none());
+
+        let resp = analytics(Some(100)).await;
+        assert_eq!(resp["total_received_sats"], 1100);
+        assert_eq!(resp["total_zaps_count"], 2);
+        assert_eq!(resp["avg_zap_sats"], 550);
+        assert_eq!(resp["below_min_sats_count"], 1);
+        let zappers = resp["top_zappers"].as_array().unwrap();
+        assert_eq!(zappers.len(), 1);
+        assert_eq!(zappers[0]["pubkey"], large.public_key().to_hex());
+        assert_eq!(resp["top_zaps"].as_array().unwrap().len(), 2);
+        let counted: u64 = resp["zaps_over_time"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["count"].as_u64().unwrap())
+            .sum();
+        assert_eq!(counted, 2);
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn note_zaps_ranks_zappers_of_one_note() {
+        let note = test_event_id();
+        let other_note = EventId::from_hex(&"cd".repeat(32)).unwrap();
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let receipt = |sender: &Keys, zapped: EventId, comment: &str, bolt11: &str| {
+            let request = EventBuilder::new(Kind::ZapRequest, comment)
+                .sign_with_keys(sender)
+                .unwrap()
+                .as_json();
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags([
+                    Tag::event(zapped),
+                    Tag::parse(["bolt11", bolt11]).unwrap(),
+                    Tag::parse(["description", &request]).unwrap(),
+                ])
+                .sign_with_keys(&Keys::generate())
+                .unwrap()
+        };
+        let relay = mock_relay_with_events(vec![
+            receipt(&alice, note, "great post", "lnbc1u1pqqqqqq"),
+            receipt(&alice, note, "", "lnbc2u1pqqqqqq"),
+            receipt(&bob, note, "nice", "lnbc5u1pqqqqqq"),
+            // A different note's zap is not counted
+            receipt(&bob, other_note, "elsewhere", "lnbc10u1pqqqqqq"),
+        ])
+        .await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let params = NoteZapsParams {
+            event_id: note.to_bech32().unwrap(),
+            verify: Some(false),
+            payment_hash: None,
+        };
+        let json = server.note_zaps(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["event_id"], TEST_HEX);
+        assert_eq!(resp["total_sats"], 800);
+        assert_eq!(resp["zap_count"], 3);
+        let zappers = resp["zappers"].as_array().unwrap();
+        assert_eq!(zappers.len(), 2);
+        assert_eq!(zappers[0]["pubkey"], bob.public_key().to_hex());
+        assert_eq!(zappers[0]["total_sats"], 500);
+        assert_eq!(zappers[0]["comments"], serde_json::json!(["nice"]));
+        assert_eq!(zappers[1]["pubkey"], alice.public_key().to_hex());
+        assert_eq!(zappers[1]["total_sats"], 300);
+        assert_eq!(zappers[1]["zap_count"], 2);
+        assert_eq!(zappers[1]["comments"], serde_json::json!(["great post"]));
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn self_follow_is_excluded_unless_requested() {
+        let target = Keys::generate();
+        let friend = Keys::generate();
+        let target_hex = target.public_key().to_hex();
+        let friend_hex = friend.public_key().to_hex();
+        // EventBuilder drops self p-tags unless told otherwise
+        let self_following = EventBuilder::new(Kind::ContactList, "")
+            .tags([
+                Tag::public_key(target.public_key()),
+                Tag::public_key(friend.public_key()),
+            ])
+            .allow_self_tagging()
+            .sign_with_keys(&target)
+            .unwrap();
+        let events = vec![self_following, contact_list_by(&friend, &[&target_hex])];
+        let relay = mock_relay_with_events(events).await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let graph = |include_self: Option<bool>| {
+            let server = &server;
+            let params: GetFollowerGraphParams = serde_json::from_value(serde_json::json!({
+                "pubkey": target_hex,
+                "include_self": include_self,
+            }))
+            .unwrap();
+            async move {
+                let json = server
+                    .follower_graph(params, ProgressReporter::default())
+                    .await
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            }
+        };
+        let pubkeys = |list: &serde_json::Value| -> Vec<String> {
+            let mut pks: Vec<String> = list
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|s| s["pubkey"].as_str().unwrap().to_string())
+                .collect();
+            pks.sort();
+            pks
+        };
+
+        let resp = graph(None).await;
+        assert_eq!(resp["following_count"], 1);
+        assert_eq!(pubkeys(&resp["following"]), vec![friend_hex.clone()]);
+        assert_eq!(pubkeys(&resp["followers_sample"]), vec![friend_hex.clone()]);
+        assert_eq!(pubkeys(&resp["mutual_follows"]), vec![friend_hex.clone()]);
+
+        let resp = graph(Some(true)).await;
+        assert_eq!(resp["following_count"], 2);
+        let mut both = vec![friend_hex.clone(), target_hex.clone()];
+        both.sort();
+        assert_eq!(pubkeys(&resp["followers_sample"]), both);
+        assert_eq!(pubkeys(&resp["mutual_follows"]), both);
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn depth_two_expands_at_most_fanout_follows() {
+        let target = Keys::generate();
+        let follows: Vec<Keys> = (0..5).map(|_| Keys::generate()).collect();
+        let hex = |keys: &Keys| keys.public_key().to_hex();
+        let others: Vec<String> = (0..5)
+            .map(|_| Keys::generate().public_key().to_hex())
+            .collect();
+        let shared = Keys::generate().public_key().to_hex();
+
+        let follow_hexes: Vec<String> = follows.iter().map(hex).collect();
+        // A junk entry and a differently-cased duplicate ahead of the real follows
+        let duplicate = follow_hexes[0].to_uppercase();
+        let mut follow_refs: Vec<&str> = vec!["not-a-pubkey", &follow_hexes[0], &duplicate];
+        follow_refs.extend(follow_hexes[1..].iter().map(String::as_str));
+        let mut events = vec![contact_list_by(&target, &follow_refs)];
+        for (i, keys) in follows.iter().enumerate() {
+            // The target and accounts it already follows are not second-degree
+            let target_hex = hex(&target);
+            events.push(contact_list_by(
+                keys,
+                &[&others[i], &shared, &target_hex, &follow_hexes[(i + 1) % 5]],
+            ));
         }
-        Nip19::Coordinate(coord) => {
-            let relays: Vec<String> = coord.relays.into_iter().map(|r| r.to_string()).collect();
-            Ok(DecodeNostrUriResponse {
-                entity_type: "coordinate".into(),
-                hex_id: coord.coordinate.identifier.clone(),
-                relays: if relays.is_empty() {
-                    None
-                } else {
-                    Some(relays)
-                },
-                author_hex: Some(coord.coordinate.public_key.to_hex()),
-                kind: Some(coord.coordinate.kind.as_u16() as u32),
+        let relay = mock_relay_with_events(events).await;
+
+        let mut server = test_server_with_relay(relay).await;
+        let mut config = (*server.config).clone();
+        config.limits.follower_graph_fanout = 3;
+        server.config = Arc::new(config);
+
+        let params: GetFollowerGraphParams = serde_json::from_value(serde_json::json!({
+            "pubkey": hex(&target),
+            "depth": 2,
+        }))
+        .unwrap();
+        let json = server
+            .follower_graph(params, ProgressReporter::default())
+            .await
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let graph = &resp["second_degree"];
+        assert_eq!(graph["expanded_follows"], 3);
+        assert_eq!(graph["follows_not_expanded"], 2);
+        assert_eq!(graph["accounts_count"], 4);
+        assert_eq!(graph["accounts"][0]["pubkey"], shared.as_str());
+        assert_eq!(graph["accounts"][0]["followed_by"], 3);
+        let found: HashSet<&str> = graph["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["pubkey"].as_str().unwrap())
+            .collect();
+        // Only the first three follows were expanded
+        assert!(others[..3].iter().all(|pk| found.contains(pk.as_str())));
+        assert!(others[3..].iter().all(|pk| !found.contains(pk.as_str())));
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn follower_offset_skips_the_first_page() {
+        let target = Keys::generate();
+        let followers: Vec<Keys> = (0..5).map(|_| Keys::generate()).collect();
+        // Follower i published its contact list i minutes ago, so newest-first
+        // order matches `followers`
+        let now = Timestamp::now().as_secs();
+        let mut events: Vec<Event> = followers
+            .iter()
+            .enumerate()
+            .map(|(i, keys)| {
+                EventBuilder::new(Kind::ContactList, "")
+                    .tag(Tag::public_key(target.public_key()))
+                    .custom_created_at(Timestamp::from(now - 60 * i as u64))
+                    .sign_with_keys(keys)
+                    .unwrap()
             })
+            .collect();
+        events.push(
+            EventBuilder::new(Kind::ContactList, "")
+                .tag(Tag::public_key(followers[4].public_key()))
+                .sign_with_keys(&target)
+                .unwrap(),
+        );
+        let relay = mock_relay_with_events(events).await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let page = |offset: u32| {
+            let server = &server;
+            let params: GetFollowerGraphParams = serde_json::from_value(serde_json::json!({
+                "pubkey": target.public_key().to_hex(),
+                "follower_limit": 2,
+                "follower_offset": offset,
+            }))
+            .unwrap();
+            async move {
+                let json = server
+                    .follower_graph(params, ProgressReporter::default())
+                    .await
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            }
+        };
+        let sample = |resp: &serde_json::Value| -> Vec<String> {
+            resp["followers_sample"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|f| f["pubkey"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let hex = |i: usize| followers[i].public_key().to_hex();
+
+        let first = page(0).await;
+        assert_eq!(sample(&first), vec![hex(0), hex(1)]);
+        assert_eq!(first["has_more"], true);
+
+        let second = page(2).await;
+        assert_eq!(sample(&second), vec![hex(2), hex(3)]);
+        assert_eq!(second["has_more"], true);
+        assert_eq!(second["followers_total_estimate"], 5);
+        // Mutual follows cover every follower seen, not just this page
+        assert_eq!(second["mutual_follows"][0]["pubkey"], hex(4));
+
+        let last = page(4).await;
+        assert_eq!(sample(&last), vec![hex(4)]);
+        assert_eq!(last["has_more"], false);
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn search_events_defaults_to_configured_kinds() {
+        let keys = Keys::generate();
+        let note = EventBuilder::text_note("a note")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let reaction = EventBuilder::new(Kind::Reaction, "+")
+            .tag(Tag::event(note.id))
+            .sign_with_keys(&keys)
+            .unwrap();
+        let relay = mock_relay_with_events(vec![note.clone(), reaction.clone()]).await;
+
+        let mut server = test_server_with_relay(relay).await;
+
+        async fn kinds_found(server: &NostrIntelServer, params: serde_json::Value) -> Vec<u64> {
+            let params: SearchEventsParams = serde_json::from_value(params).unwrap();
+            let json = server.search_events(Parameters(params)).await.unwrap();
+            let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let mut kinds: Vec<u64> = resp["events"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e["kind"].as_u64().unwrap())
+                .collect();
+            kinds.sort();
+            kinds
         }
-        _ => Err("Unsupported NIP-19 entity type".into()),
+
+        // No kinds: text notes only
+        assert_eq!(kinds_found(&server, serde_json::json!({})).await, vec![1]);
+        // An explicit list overrides the default; [] means every kind
+        assert_eq!(
+            kinds_found(&server, serde_json::json!({ "kinds": [] })).await,
+            vec![1, 7]
+        );
+
+        let mut config = (*server.config).clone();
+        config.search.default_kinds = vec![7];
+        server.config = Arc::new(config);
+        assert_eq!(kinds_found(&server, serde_json::json!({})).await, vec![7]);
+        server.nostr_client.shutdown().await;
     }
-}
 
-// ==================== helper functions ====================
+    #[tokio::test]
+    async fn search_events_filters_by_d_identifier() {
+        let keys = Keys::generate();
+        let article = |slug: &str| {
+            EventBuilder::new(Kind::LongFormTextNote, format!("article {slug}"))
+                .tag(Tag::identifier(slug))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let wanted = article("nostr-intro");
+        let relay = mock_relay_with_events(vec![wanted.clone(), article("other-post")]).await;
 
-/// Parse timeframe strings like "1h", "24h", "7d", "30d", "90d", "1y" into seconds
-fn parse_timeframe(tf: &str) -> Result<u64, String> {
-    let tf = tf.trim().to_lowercase();
-    if let Some(hours) = tf.strip_suffix('h') {
-        let h: u64 = hours
-            .parse()
-            .map_err(|_| format!("Invalid hours: {hours}"))?;
-        Ok(h * 3600)
-    } else if let Some(days) = tf.strip_suffix('d') {
-        let d: u64 = days.parse().map_err(|_| format!("Invalid days: {days}"))?;
-        Ok(d * 86400)
-    } else if let Some(years) = tf.strip_suffix('y') {
-        let y: u64 = years
-            .parse()
-            .map_err(|_| format!("Invalid years: {years}"))?;
-        Ok(y * 365 * 86400)
-    } else {
-        Err(format!(
-            "Unknown timeframe format: {tf}. Use '1h', '24h', '7d', '30d', etc."
-        ))
+        let server = test_server_with_relay(relay).await;
+
+        let params = |kinds: Option<Vec<u32>>| -> SearchEventsParams {
+            serde_json::from_value(serde_json::json!({
+                "kinds": kinds,
+                "identifiers": ["nostr-intro"],
+            }))
+            .unwrap()
+        };
+
+        let json = server
+            .search_events(Parameters(params(Some(vec![30023]))))
+            .await
+            .unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["count"], 1);
+        assert_eq!(resp["events"][0]["id"], wanted.id.to_hex());
+
+        let err = server
+            .search_events(Parameters(params(None)))
+            .await
+            .unwrap_err();
+        assert!(err.contains("requires kinds"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn search_events_reports_which_relays_returned_each_event() {
+        let keys = Keys::generate();
+        let everywhere = EventBuilder::text_note("on both relays")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let only_first = EventBuilder::text_note("on the first relay")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let first = mock_relay_with_events(vec![everywhere.clone(), only_first.clone()]).await;
+        let second = mock_relay_with_events(vec![everywhere.clone()]).await;
+
+        let server = test_server_with_relays(vec![first.clone(), second.clone()]).await;
+
+        let search = |include_provenance: Option<bool>| {
+            let params: SearchEventsParams = serde_json::from_value(serde_json::json!({
+                "kinds": [1],
+                "include_provenance": include_provenance,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let json = server.search_events(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            }
+        };
+        let seen_on = |resp: &serde_json::Value, id: EventId| -> Vec<String> {
+            let event = resp["events"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|e| e["id"] == id.to_hex())
+                .unwrap();
+            let mut relays: Vec<String> = event["seen_on"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r.as_str().unwrap().to_string())
+                .collect();
+            relays.sort();
+            relays
+        };
+
+        let resp = search(Some(true)).await;
+        assert_eq!(resp["count"], 2);
+        let mut both = vec![first.clone(), second.clone()];
+        both.sort();
+        assert_eq!(seen_on(&resp, everywhere.id), both);
+        assert_eq!(seen_on(&resp, only_first.id), vec![first.clone()]);
+
+        let resp = search(None).await;
+        assert!(resp["events"][0].get("seen_on").is_none());
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn search_events_reports_failing_relays_alongside_results() {
+        // A relay that refuses every subscription
+        let failing = MockRelay::start(RelayPolicy::Closed("error: overloaded".into()))
+            .await
+            .url;
+        let note = EventBuilder::text_note("still here")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let healthy = mock_relay_with_events(vec![note.clone()]).await;
+
+        let server = test_server_with_relays(vec![healthy, failing.clone()]).await;
+
+        let params: SearchEventsParams =
+            serde_json::from_value(serde_json::json!({ "kinds": [1] })).unwrap();
+        let json = server.search_events(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp["count"], 1);
+        assert_eq!(resp["events"][0]["id"], note.id.to_hex());
+        let errors = resp["relay_errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["relay"], failing.as_str());
+        assert!(errors[0]["error"].as_str().unwrap().contains("overloaded"));
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn search_events_sorts_by_created_at() {
+        let keys = Keys::generate();
+        let now = Timestamp::now().as_secs();
+        let offsets = [300, 10, 7200, 60];
+        let events: Vec<Event> = offsets
+            .iter()
+            .map(|ago| {
+                EventBuilder::text_note(format!("{ago}s ago"))
+                    .custom_created_at(Timestamp::from(now - ago))
+                    .sign_with_keys(&keys)
+                    .unwrap()
+            })
+            .collect();
+
+        let server = test_server_with_relay(mock_relay_with_events(events).await).await;
+
+        let contents = |sort_by: Option<&str>| {
+            let params: SearchEventsParams = serde_json::from_value(serde_json::json!({
+                "kinds": [1],
+                "sort_by": sort_by,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let json = server.search_events(Parameters(params)).await?;
+                let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+                Ok::<_, String>(
+                    resp["events"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|e| e["content"].as_str().unwrap().to_string())
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+
+        let newest_first = ["10s ago", "60s ago", "300s ago", "7200s ago"];
+        assert_eq!(contents(None).await.unwrap(), newest_first);
+        assert_eq!(
+            contents(Some("created_at_desc")).await.unwrap(),
+            newest_first
+        );
+        let mut oldest_first = newest_first;
+        oldest_first.reverse();
+        assert_eq!(
+            contents(Some("created_at_asc")).await.unwrap(),
+            oldest_first
+        );
+
+        let err = contents(Some("score")).await.unwrap_err();
+        assert!(err.contains("Unknown sort_by"), "{err}");
+        server.nostr_client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn search_events_reports_phase_timing_on_request() {
+        let note = EventBuilder::text_note("timed")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let server = test_server_with_relay(mock_relay_with_events(vec![note]).await).await;
+
+        let search = |extra: serde_json::Value| {
+            let mut params = serde_json::json!({ "kinds": [1] });
+            params
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            let params: SearchEventsParams = serde_json::from_value(params).unwrap();
+            let server = &server;
+            async move {
+                let json = server.search_events(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            }
+        };
+
+        let resp = search(serde_json::json!({})).await;
+        assert!(resp.get("timing_ms").is_none());
+
+        let resp = search(serde_json::json!({
+            "include_timing": true,
+            "respect_deletions": true,
+        }))
+        .await;
+        let phases: Vec<&str> = resp["timing_ms"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(phases, vec!["deletions", "enrich", "relay_fetch"]);
+        assert!(resp["timing_ms"]["relay_fetch"].is_u64());
+        server.nostr_client.shutdown().await;
     }
-}
 
-/// Truncate content to a max length, appending "..." if truncated
-fn truncate_content(content: &str, max_len: usize) -> String {
-    if content.len() > max_len {
-        format!("{}...", &content[..max_len])
-    } else {
-        content.to_string()
+    #[tokio::test]
+    async fn search_events_drops_author_deleted_notes() {
+        let (author, stranger) = (Keys::generate(), Keys::generate());
+        let note = |text: &str| {
+            EventBuilder::text_note(text)
+                .sign_with_keys(&author)
+                .unwrap()
+        };
+        let (deleted, kept, spoofed) = (note("oops"), note("gm"), note("still here"));
+        let delete = |keys: &Keys, target: &Event| {
+            EventBuilder::delete(EventDeletionRequest::new().id(target.id))
+                .sign_with_keys(keys)
+                .unwrap()
+        };
+        let relay = mock_relay_with_events(vec![
+            deleted.clone(),
+            kept.clone(),
+            spoofed.clone(),
+            delete(&author, &deleted),
+            // Only the author can delete their note
+            delete(&stranger, &spoofed),
+        ])
+        .await;
+
+        let server = test_server_with_relay(relay).await;
+
+        let params: SearchEventsParams = serde_json::from_value(serde_json::json!({
+            "kinds": [1],
+            "respect_deletions": true,
+        }))
+        .unwrap();
+        let json = server.search_events(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(resp["deleted_count"], 1);
+        let ids: HashSet<&str> = resp["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            HashSet::from([kept.id.to_hex().as_str(), spoofed.id.to_hex().as_str()])
+        );
     }
-}
 
-/// Extract zap amount in sats from a kind:9735 zap receipt event.
-/// Tries the `bolt11` tag first, then the embedded zap request `description` tag.
-fn extract_zap_amount(event: &Event) -> u64 {
-    // Try bolt11 tag
-    for tag in event.tags.iter() {
-        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
-        if tag_vec.first() == Some(&"bolt11") {
-            if let Some(bolt11) = tag_vec.get(1) {
-                if let Some(amount) = parse_bolt11_amount(bolt11) {
-                    return amount;
-                }
-            }
+    #[tokio::test]
+    async fn dossier_populates_every_section() {
+        let target = Keys::generate();
+        let target_hex = target.public_key().to_hex();
+        let signed = |builder: EventBuilder, keys: &Keys| builder.sign_with_keys(keys).unwrap();
+
+        let zap_request = zap_request_json();
+        let bolt11 = fake_bolt11("lnbc10m", &Sha256::digest(zap_request.as_bytes()).into());
+        let mut events = vec![
+            signed(
+                EventBuilder::metadata(&Metadata::new().name("target")),
+                &target,
+            ),
+            signed(
+                EventBuilder::new(Kind::RelayList, "").tags([
+                    Tag::parse(["r", "wss://write.example", "write"]).unwrap(),
+                    Tag::parse(["r", "wss://both.example"]).unwrap(),
+                ]),
+                &target,
+            ),
+            contact_list_by(&target, &[&"a".repeat(64), &"b".repeat(64)]),
+            signed(
+                EventBuilder::new(Kind::ZapReceipt, "").tags([
+                    Tag::parse(["p", &target_hex]).unwrap(),
+                    Tag::parse(["bolt11", &bolt11]).unwrap(),
+                    Tag::parse(["description", &zap_request]).unwrap(),
+                ]),
+                &Keys::generate(),
+            ),
+        ];
+        for _ in 0..2 {
+            events.push(contact_list_by(&Keys::generate(), &[&target_hex]));
         }
+        for i in 0..3 {
+            events.push(signed(
+                EventBuilder::text_note(format!("note {i}")),
+                &target,
+            ));
+        }
+
+        let server = test_server_with_relay(mock_relay_with_events(events).await).await;
+
+        let params = GetDossierParams {
+            pubkey: target.public_key().to_bech32().unwrap(),
+            timeframe: None,
+            payment_hash: None,
+        };
+        let json = server.get_dossier(Parameters(params)).await.unwrap();
+        let dossier: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(dossier["pubkey"], target_hex);
+        assert!(dossier.get("errors").is_none(), "{dossier}");
+        assert_eq!(dossier["profile"]["name"], "target");
+        assert_eq!(
+            dossier["relays"]["write_relays"],
+            serde_json::json!(["wss://write.example", "wss://both.example"])
+        );
+        assert_eq!(
+            dossier["relays"]["read_relays"],
+            serde_json::json!(["wss://both.example"])
+        );
+        assert_eq!(dossier["social"]["following_count"], 2);
+        assert_eq!(dossier["social"]["followers_count"], 2);
+        assert_eq!(dossier["activity"]["total_events"], 3);
+        assert_eq!(dossier["zaps"]["total_zaps_count"], 1);
+        assert_eq!(dossier["zaps"]["total_received_sats"], 1_000_000);
+        server.nostr_client.shutdown().await;
     }
 
-    // Try description tag (embedded zap request with amount)
-    for tag in event.tags.iter() {
-        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
-        if tag_vec.first() == Some(&"description") {
-            if let Some(desc) = tag_vec.get(1) {
-                if let Ok(zap_request) = serde_json::from_str::<serde_json::Value>(desc) {
-                    // Look for amount tag in the zap request
-                    if let Some(tags) = zap_request["tags"].as_array() {
-                        for t in tags {
-                            if let Some(arr) = t.as_array() {
-                                if arr.first().and_then(|v| v.as_str()) == Some("amount") {
-                                    if let Some(msats_str) = arr.get(1).and_then(|v| v.as_str()) {
-                                        if let Ok(msats) = msats_str.parse::<u64>() {
-                                            return msats / 1000;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    #[tokio::test]
+    async fn compare_accounts_counts_shared_followers_and_follows() {
+        let (a, b) = (Keys::generate(), Keys::generate());
+        let others: Vec<Keys> = (0..9).map(|_| Keys::generate()).collect();
+        let hex = |i: usize| others[i].public_key().to_hex();
+        let (a_hex, b_hex) = (a.public_key().to_hex(), b.public_key().to_hex());
+
+        // a follows 0,1,2 and b follows 1,2,3: 2 shared of 4
+        let mut events = vec![
+            contact_list_by(&a, &[&hex(0), &hex(1), &hex(2)]),
+            contact_list_by(&b, &[&hex(1), &hex(2), &hex(3)]),
+        ];
+        // 4 follows a, 5 and 6 follow both, 7 and 8 follow b: 2 shared of 5
+        events.push(contact_list_by(&others[4], &[&a_hex]));
+        for i in [5, 6] {
+            events.push(contact_list_by(&others[i], &[&a_hex, &b_hex]));
+        }
+        for i in [7, 8] {
+            events.push(contact_list_by(&others[i], &[&b_hex]));
         }
-    }
 
-    0
-}
+        let server = test_server_with_relay(mock_relay_with_events(events).await).await;
 
-/// Parse amount from a bolt11 invoice string.
-/// Bolt11 amounts: number followed by multiplier (m=milli, u=micro, n=nano, p=pico)
-fn parse_bolt11_amount(bolt11: &str) -> Option<u64> {
-    let lower = bolt11.to_lowercase();
-    // Find "lnbc"/"lntb"/"lnbcrt" prefix and extract the amount portion
-    let after_prefix = if let Some(rest) = lower.strip_prefix("lnbcrt") {
-        rest
-    } else if let Some(rest) = lower.strip_prefix("lnbc") {
-        rest
-    } else if let Some(rest) = lower.strip_prefix("lntb") {
-        rest
-    } else {
-        return None;
-    };
+        let params = CompareAccountsParams {
+            pubkey_a: a.public_key().to_bech32().unwrap(),
+            pubkey_b: b_hex.clone(),
+            payment_hash: None,
+        };
+        let json = server.compare_accounts(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
 
-    // Amount is digits + optional multiplier before the first '1' separator
-    let sep_pos = after_prefix.find('1')?;
-    let amount_str = &after_prefix[..sep_pos];
+        assert_eq!(
+            resp["following"],
+            serde_json::json!({ "a_count": 3, "b_count": 3, "shared_count": 2, "jaccard": 0.5 })
+        );
+        assert_eq!(
+            resp["followers"],
+            serde_json::json!({ "a_count": 3, "b_count": 4, "shared_count": 2, "jaccard": 0.4 })
+        );
+        let sample = |key: &str| -> HashSet<String> {
+            resp[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p["pubkey"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            sample("shared_follows_sample"),
+            HashSet::from([hex(1), hex(2)])
+        );
+        assert_eq!(
+            sample("shared_followers_sample"),
+            HashSet::from([hex(5), hex(6)])
+        );
+        assert_eq!(resp["followers_truncated"], false);
 
-    if amount_str.is_empty() {
-        return None; // No amount specified
+        let params = CompareAccountsParams {
+            pubkey_a: a_hex.clone(),
+            pubkey_b: a_hex,
+            payment_hash: None,
+        };
+        assert!(server.compare_accounts(Parameters(params)).await.is_err());
+        server.nostr_client.shutdown().await;
     }
 
-    // Check for multiplier suffix
-    if let Some(n) = amount_str.strip_suffix('m') {
-        let num: u64 = n.parse().ok()?;
-        Some(num * 100_000) // milli-BTC to sats
-    } else if let Some(n) = amount_str.strip_suffix('u') {
-        let num: u64 = n.parse().ok()?;
-        Some(num * 100) // micro-BTC to sats
-    } else if let Some(n) = amount_str.strip_suffix('n') {
-        let num: u64 = n.parse().ok()?;
-        Some(num / 10) // nano-BTC to sats (0.1 sat each)
-    } else if let Some(n) = amount_str.strip_suffix('p') {
-        let num: u64 = n.parse().ok()?;
-        Some(num / 100) // pico-BTC to sats (0.01 sat each)
-    } else {
-        let num: u64 = amount_str.parse().ok()?;
-        Some(num * 100_000_000) // plain BTC to sats
-    }
-}
+    #[tokio::test]
+    async fn get_lists_returns_follow_set_members_with_names() {
+        let owner = Keys::generate();
+        let members: Vec<Keys> = (0..2).map(|_| Keys::generate()).collect();
+        let follow_set = |title: &str, at: u64| {
+            EventBuilder::new(Kind::FollowSet, "")
+                .tags([
+                    Tag::identifier("devs"),
+                    Tag::parse(["title", title]).unwrap(),
+                    Tag::public_key(members[0].public_key()),
+                    Tag::public_key(members[1].public_key()),
+                ])
+                .custom_created_at(Timestamp::from(at))
+                .sign_with_keys(&owner)
+                .unwrap()
+        };
+        let now = Timestamp::now().as_secs();
+        let events = vec![
+            follow_set("Old title", now - 3600),
+            follow_set("Nostr devs", now),
+            EventBuilder::new(Kind::MuteList, "encrypted-private-items")
+                .sign_with_keys(&owner)
+                .unwrap(),
+        ];
+
+        let server = test_server_with_relay(mock_relay_with_events(events).await).await;
+        server
+            .cache
+            .set_profile(&CachedProfile {
+                pubkey: members[0].public_key().to_hex(),
+                name: Some("alice".into()),
+                display_name: None,
+                about: None,
+                picture: None,
+                banner: None,
+                nip05: None,
+                lud16: None,
+                website: None,
+            })
+            .await
+            .unwrap();
 
-/// Extract the zapper's pubkey from a zap receipt event.
-/// Looks for uppercase 'P' tag or parses from embedded zap request description.
-fn extract_zapper_pubkey(event: &Event) -> Option<String> {
-    // Check for uppercase P tag (zapper's pubkey)
-    for tag in event.tags.iter() {
-        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
-        if tag_vec.first() == Some(&"P") {
-            return tag_vec.get(1).map(|s| s.to_string());
-        }
+        let params = GetListsParams {
+            pubkey: owner.public_key().to_hex(),
+            kinds: Some(vec![30000]),
+            payment_hash: None,
+        };
+        let json = server.get_lists(Parameters(params)).await.unwrap();
+        let resp: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        // Only the newest version of the set, and no mute list
+        let lists = resp["lists"].as_array().unwrap();
+        assert_eq!(lists.len(), 1, "{resp}");
+        let list = &lists[0];
+        assert_eq!(list["kind"], 30000);
+        assert_eq!(list["identifier"], "devs");
+        assert_eq!(list["title"], "Nostr devs");
+        assert_eq!(list["member_count"], 2);
+        assert_eq!(list["has_private_items"], false);
+        assert_eq!(
+            list["pubkeys"][0]["pubkey"],
+            members[0].public_key().to_hex()
+        );
+        assert_eq!(list["pubkeys"][0]["name"], "alice");
+        assert_eq!(
+            list["pubkeys"][1]["pubkey"],
+            members[1].public_key().to_hex()
+        );
+
+        let params = GetListsParams {
+            pubkey: owner.public_key().to_hex(),
+            kinds: Some(vec![1]),
+            payment_hash: None,
+        };
+        let err = server.get_lists(Parameters(params)).await.unwrap_err();
+        assert!(err.contains("Unsupported list kind 1"), "{err}");
+        server.nostr_client.shutdown().await;
     }
 
-    // Try description tag (embedded zap request)
-    for tag in event.tags.iter() {
-        let tag_vec: Vec<&str> = tag.as_slice().iter().map(|s| s.as_str()).collect();
-        if tag_vec.first() == Some(&"description") {
-            if let Some(desc) = tag_vec.get(1) {
-                if let Ok(zap_request) = serde_json::from_str::<serde_json::Value>(desc) {
-                    return zap_request["pubkey"].as_str().map(String::from);
-                }
+    #[tokio::test]
+    async fn get_bookmarks_resolves_bookmarked_notes() {
+        let owner = Keys::generate();
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let note =
+            |text: &str, keys: &Keys| EventBuilder::text_note(text).sign_with_keys(keys).unwrap();
+        let first = note("first bookmark", &alice);
+        let second = note("second bookmark", &bob);
+        let gone = EventId::all_zeros();
+        let list = EventBuilder::new(Kind::Bookmarks, "")
+            .tags([
+                Tag::event(first.id),
+                Tag::hashtag("nostr"),
+                Tag::event(gone),
+                Tag::event(second.id),
+            ])
+            .sign_with_keys(&owner)
+            .unwrap();
+
+        let server = test_server_with_relay(
+            mock_relay_with_events(vec![list, first.clone(), second.clone()]).await,
+        )
+        .await;
+
+        let bookmarks = |pubkey: &Keys| {
+            let params = GetBookmarksParams {
+                pubkey: pubkey.public_key().to_bech32().unwrap(),
+                payment_hash: None,
+            };
+            let server = &server;
+            async move {
+                let json = server.get_bookmarks(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
             }
-        }
+        };
+
+        let resp = bookmarks(&owner).await;
+        assert_eq!(resp["total_bookmarks"], 3);
+        let notes = resp["bookmarks"].as_array().unwrap();
+        assert_eq!(notes.len(), 2);
+        // Most recently bookmarked first
+        assert_eq!(notes[0]["id"], second.id.to_hex());
+        assert_eq!(notes[0]["author_pubkey"], bob.public_key().to_hex());
+        assert_eq!(notes[0]["content_preview"], "second bookmark");
+        assert_eq!(notes[1]["id"], first.id.to_hex());
+        assert_eq!(notes[1]["author_pubkey"], alice.public_key().to_hex());
+        assert_eq!(resp["missing_ids"], serde_json::json!([gone.to_hex()]));
+
+        // No list at all is an empty result, not an error
+        let resp = bookmarks(&alice).await;
+        assert_eq!(resp["total_bookmarks"], 0);
+        assert_eq!(resp["bookmarks"], serde_json::json!([]));
+        assert!(resp.get("updated_at").is_none());
+        server.nostr_client.shutdown().await;
     }
 
-    None
-}
+    #[test]
+    fn http_client_uses_configured_socks_proxy() {
+        let network = NetworkConfig {
+            socks_proxy: "socks5h://127.0.0.1:9050".into(),
+            ..Default::default()
+        };
+        let client = http_client(&network).unwrap();
+        let debug = format!("{client:?}");
+        assert!(debug.contains("proxies"), "{debug}");
+        assert!(debug.contains("socks5h://127.0.0.1:9050"), "{debug}");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn check_relay_sends_user_agent_and_rejects_html() {
+        use axum::http::HeaderMap;
+
+        // Echo the User-Agent as the relay name; the "cdn" host serves a challenge page
+        let app = axum::Router::new()
+            .route(
+                "/",
+                axum::routing::get(|headers: HeaderMap| async move {
+                    let agent = headers["user-agent"].to_str().unwrap().to_string();
+                    (
+                        [("content-type", "application/nostr+json")],
+                        serde_json::json!({ "name": agent }).to_string(),
+                    )
+                }),
+            )
+            .route(
+                "/cdn",
+                axum::routing::get(|| async {
+                    axum::response::Html("<html>Checking your browser...</html>")
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut server = NostrIntelServer::new_for_test().await;
+        let network = NetworkConfig {
+            user_agent: "Mozilla/5.0 (nostr-intel test)".into(),
+            ..Default::default()
+        };
+        server.http = http_client(&network).unwrap();
 
-    // A well-known hex pubkey for test vectors
-    const TEST_HEX: &str = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+        let (resp, _) = server
+            .check_relay_inner(&format!("ws://{addr}"))
+            .await
+            .unwrap();
+        assert_eq!(resp.name.as_deref(), Some("Mozilla/5.0 (nostr-intel test)"));
 
-    fn test_pubkey() -> PublicKey {
-        PublicKey::from_hex(TEST_HEX).unwrap()
+        let err = server
+            .check_relay_inner(&format!("ws://{addr}/cdn"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Relay did not return NIP-11 JSON (got text/html)");
     }
 
-    fn test_event_id() -> EventId {
-        // Use the same hex as an event id for simplicity
-        EventId::from_hex(TEST_HEX).unwrap()
+    #[tokio::test]
+    async fn onion_relay_is_flagged_without_fetching() {
+        let server = NostrIntelServer::new_for_test().await;
+        let onion = "ws://2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+
+        let (resp, cache_hit) = server.check_relay_inner(onion).await.unwrap();
+        assert!(!cache_hit);
+        assert!(resp.requires_tor && !resp.online);
+        assert!(resp.description.is_none());
+        assert!(resp.skipped_reason.unwrap().contains("requires Tor"));
+
+        assert!(is_onion_relay("wss://ABC.ONION/"));
+        assert!(!is_onion_relay("wss://onion.example.com"));
     }
 
-    #[test]
-    fn decode_npub() {
-        let npub = test_pubkey().to_bech32().unwrap();
-        let resp = decode_nostr_uri_inner(&npub).unwrap();
-        assert_eq!(resp.entity_type, "pubkey");
-        assert_eq!(resp.hex_id, TEST_HEX);
-        assert!(resp.relays.is_none());
-        assert!(resp.author_hex.is_none());
-        assert!(resp.kind.is_none());
+    #[tokio::test]
+    async fn check_relays_caps_batch_size() {
+        let server = NostrIntelServer::new_for_test().await;
+        let params = CheckRelaysParams {
+            relay_urls: (0..=MAX_BATCH_RELAYS)
+                .map(|i| format!("wss://relay{i}.example"))
+                .collect(),
+        };
+        let err = server.check_relays(Parameters(params)).await.unwrap_err();
+        assert!(err.contains("Too many relays"));
     }
 
-    #[test]
-    fn decode_note() {
-        let note = test_event_id().to_bech32().unwrap();
-        let resp = decode_nostr_uri_inner(&note).unwrap();
-        assert_eq!(resp.entity_type, "event_id");
-        assert_eq!(resp.hex_id, TEST_HEX);
+    #[tokio::test]
+    async fn cache_only_profile_lookup_never_fetches() {
+        let keys = Keys::generate();
+        let metadata = EventBuilder::metadata(&Metadata::new().name("alice"))
+            .sign_with_keys(&keys)
+            .unwrap();
+        let server = test_server_with_relay(mock_relay_with_events(vec![metadata]).await).await;
+
+        let lookup = |pubkey: String, cache_only: bool| {
+            let server = &server;
+            async move {
+                let params = GetProfileParams {
+                    pubkey,
+                    cache_only: Some(cache_only),
+                };
+                let json = server.get_profile(Parameters(params)).await?;
+                Ok::<_, String>(serde_json::from_str::<serde_json::Value>(&json).unwrap())
+            }
+        };
+        let npub = keys.public_key().to_bech32().unwrap();
+
+        // The relay has the profile, but a cache-only miss doesn't ask it
+        let miss = lookup(npub.clone(), true).await.unwrap();
+        assert_eq!(miss["in_cache"], false);
+        assert_eq!(miss["pubkey"], keys.public_key().to_hex());
+        assert!(miss.get("name").is_none());
+        assert!(server.relay_health.snapshot().await.is_empty());
+
+        // A normal lookup fetches and caches it; cache-only then hits
+        let fetched = lookup(npub.clone(), false).await.unwrap();
+        assert_eq!(fetched["name"], "alice");
+        assert!(fetched.get("in_cache").is_none());
+        let hit = lookup(npub, true).await.unwrap();
+        assert_eq!(hit["in_cache"], true);
+        assert_eq!(hit["name"], "alice");
+
+        let err = lookup("alice@example.com".into(), true).await.unwrap_err();
+        assert!(err.contains("cache_only"), "{err}");
+        server.nostr_client.shutdown().await;
     }
 
-    #[test]
-    fn decode_nprofile_with_relays() {
-        let relay = RelayUrl::parse("wss://relay.damus.io").unwrap();
-        let nprofile = Nip19Profile::new(test_pubkey(), [relay.clone()]);
-        let bech32 = nprofile.to_bech32().unwrap();
+    #[tokio::test]
+    async fn resolve_names_uses_cached_profiles() {
+        let keys: Vec<Keys> = (0..10).map(|_| Keys::generate()).collect();
+        let zappers: Vec<String> = keys.iter().map(|k| k.public_key().to_hex()).collect();
+        // Only the relay knows the ninth zapper's name
+        let metadata = EventBuilder::metadata(&Metadata::new().name("relay-only"))
+            .sign_with_keys(&keys[8])
+            .unwrap();
+        let relay = MockRelay::start(RelayPolicy::Events(vec![metadata])).await;
+        let server = test_server_with_relay(relay.url.clone()).await;
+        // Cache names for all but the last two zappers
+        for (i, pk) in zappers.iter().take(8).enumerate() {
+            server
+                .cache
+                .set_profile(&CachedProfile {
+                    pubkey: pk.clone(),
+                    name: (i % 2 == 0).then(|| format!("zapper{i}")),
+                    display_name: Some(format!("Zapper {i}")),
+                    about: None,
+                    picture: None,
+                    banner: None,
+                    nip05: None,
+                    lud16: None,
+                    website: None,
+                })
+                .await
+                .unwrap();
+        }
 
-        let resp = decode_nostr_uri_inner(&bech32).unwrap();
-        assert_eq!(resp.entity_type, "profile");
-        assert_eq!(resp.hex_id, TEST_HEX);
-        let relays = resp.relays.unwrap();
-        assert_eq!(relays.len(), 1);
-        assert_eq!(relays[0], "wss://relay.damus.io");
+        let names = server.resolve_names(&zappers).await;
+        assert_eq!(names.len(), 9);
+        assert_eq!(names[&zappers[0]], "zapper0");
+        // Falls back to display_name when name is unset
+        assert_eq!(names[&zappers[1]], "Zapper 1");
+        // Uncached zappers go to a single relay fetch
+        assert_eq!(names[&zappers[8]], "relay-only");
+        assert!(!names.contains_key(&zappers[9]));
+        assert_eq!(relay.reqs(), 1);
+        let health = server.relay_health.snapshot().await;
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].1.successes, 1);
+        assert_eq!(health[0].1.failures, 0);
+        server.nostr_client.shutdown().await;
     }
 
-    #[test]
-    fn decode_nprofile_no_relays() {
-        let nprofile = Nip19Profile::new(test_pubkey(), Vec::<RelayUrl>::new());
-        let bech32 = nprofile.to_bech32().unwrap();
+    #[tokio::test]
+    async fn same_api_key_shares_quota_across_sessions() {
+        let server = NostrIntelServer::new_for_test().await;
+        let shared = server.shared_state();
+        let limit = server.config.free_tier.calls_per_day;
+
+        let first = NostrIntelServer::from_shared(&shared);
+        let second = NostrIntelServer::from_shared(&shared);
+        let other = NostrIntelServer::from_shared(&shared);
+        first.bind_api_key(Some("agent-key")).unwrap();
+        second.bind_api_key(Some("agent-key")).unwrap();
+        assert_eq!(first.client_id(), second.client_id());
+        assert_ne!(first.client_id(), other.client_id());
+
+        // Use up the quota on the first session...
+        for _ in 0..limit {
+            first
+                .payment_gate("relay_discovery", 20, None)
+                .await
+                .unwrap();
+        }
 
-        let resp = decode_nostr_uri_inner(&bech32).unwrap();
-        assert_eq!(resp.entity_type, "profile");
-        assert!(resp.relays.is_none());
+        // ...and the second session with the same key sees it exhausted
+        assert!(matches!(
+            second.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::EarlyReturn(_))
+        ));
+        // A session without the key still has its own quota
+        assert!(matches!(
+            other.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::Proceed { .. })
+        ));
     }
 
-    #[test]
-    fn decode_nevent_with_author_and_kind() {
-        let relay = RelayUrl::parse("wss://nos.lol").unwrap();
-        let nevent = Nip19Event::new(test_event_id())
-            .relays(vec![relay])
-            .author(test_pubkey())
-            .kind(Kind::TextNote);
-        let bech32 = nevent.to_bech32().unwrap();
+    #[tokio::test]
+    async fn api_key_allow_list_rejects_unknown_keys() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.server.api_keys = vec!["known".into()];
+        server.config = Arc::new(config);
 
-        let resp = decode_nostr_uri_inner(&bech32).unwrap();
-        assert_eq!(resp.entity_type, "event");
-        assert_eq!(resp.hex_id, TEST_HEX);
-        assert_eq!(resp.author_hex.as_deref(), Some(TEST_HEX));
-        assert_eq!(resp.kind, Some(1));
-        let relays = resp.relays.unwrap();
-        assert_eq!(relays[0], "wss://nos.lol");
+        assert!(server.bind_api_key(Some("unknown")).is_err());
+        server.bind_api_key(Some("known")).unwrap();
+        assert!(server.client_id().starts_with("key-"));
     }
 
-    #[test]
-    fn decode_naddr_coordinate() {
-        let coord = Coordinate::new(Kind::from(30023), test_pubkey()).identifier("my-article");
-        let relay = RelayUrl::parse("wss://relay.damus.io").unwrap();
-        let naddr = Nip19Coordinate::new(coord, [relay]);
-        let bech32 = naddr.to_bech32().unwrap();
+    #[tokio::test]
+    async fn api_key_header_sets_quota_identity_over_http() {
+        use rmcp::transport::streamable_http_server::{
+            session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
+        };
 
-        let resp = decode_nostr_uri_inner(&bech32).unwrap();
-        assert_eq!(resp.entity_type, "coordinate");
-        assert_eq!(resp.hex_id, "my-article");
-        assert_eq!(resp.author_hex.as_deref(), Some(TEST_HEX));
-        assert_eq!(resp.kind, Some(30023));
-        assert!(resp.relays.is_some());
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.server.api_keys = vec!["agent-key".into()];
+        server.config = Arc::new(config);
+        let limit = server.config.free_tier.calls_per_day;
+        let shared = Arc::new(server.shared_state());
+
+        let factory_state = Arc::clone(&shared);
+        let mcp = StreamableHttpService::new(
+            move || Ok(NostrIntelServer::from_shared(&factory_state)),
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig {
+                sse_keep_alive: None,
+                ..Default::default()
+            },
+        );
+        let app = axum::Router::new().nest_service("/mcp", mcp);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let post = |session: Option<&str>, api_key: Option<&str>, body: serde_json::Value| {
+            let mut req = http
+                .post(&url)
+                .header("Accept", "application/json, text/event-stream")
+                .json(&body);
+            if let Some(session) = session {
+                req = req.header("mcp-session-id", session);
+            }
+            if let Some(key) = api_key {
+                req = req.header("x-api-key", key);
+            }
+            req.send()
+        };
+        // Initialize a session, returning its ID or the rejection body
+        let connect = |api_key: Option<&'static str>| async move {
+            let init = serde_json::json!({
+                "jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {
+                    "protocolVersion": "2025-06-18",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "1.0" },
+                },
+            });
+            let resp = post(None, api_key, init).await.unwrap();
+            let session = resp
+                .headers()
+                .get("mcp-session-id")
+                .map(|v| v.to_str().unwrap().to_string());
+            let body = resp.text().await.unwrap();
+            if body.contains("Unknown API key") {
+                return Err(body);
+            }
+            let session = session.unwrap();
+            let initialized =
+                serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+            post(Some(&session), None, initialized).await.unwrap();
+            Ok(session)
+        };
+        let remaining = |session: String| async move {
+            let call = serde_json::json!({
+                "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                "params": { "name": "get_price_quote", "arguments": { "tool_name": "search_events" } },
+            });
+            let body = post(Some(&session), None, call)
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            let data = body
+                .lines()
+                .filter_map(|l| l.strip_prefix("data:"))
+                .find(|d| d.contains("\"result\""))
+                .unwrap();
+            let msg: serde_json::Value = serde_json::from_str(data.trim()).unwrap();
+            let text = msg["result"]["content"][0]["text"].as_str().unwrap();
+            serde_json::from_str::<serde_json::Value>(text).unwrap()["free_tier_remaining"].clone()
+        };
+
+        // Spend one free call under the key outside HTTP
+        let direct = NostrIntelServer::from_shared(&shared);
+        direct.bind_api_key(Some("agent-key")).unwrap();
+        direct
+            .payment_gate("relay_discovery", 20, None)
+            .await
+            .unwrap();
+
+        let keyed = connect(Some("agent-key")).await.unwrap();
+        assert_eq!(remaining(keyed).await, limit - 1);
+        let anonymous = connect(None).await.unwrap();
+        assert_eq!(remaining(anonymous).await, limit);
+        assert!(connect(Some("unknown")).await.is_err());
     }
 
-    #[test]
-    fn decode_nostr_prefix_strip() {
-        let npub = test_pubkey().to_bech32().unwrap();
-        let with_prefix = format!("nostr:{npub}");
+    #[tokio::test]
+    async fn configured_identities_get_independent_quotas() {
+        std::env::set_var("NOSTR_INTEL_TEST_IDENTITY_A", "tenant-a");
+        std::env::set_var("NOSTR_INTEL_TEST_IDENTITY_B", "tenant-b");
+        let shared_for = |var: &str| {
+            let var = var.to_string();
+            async move {
+                let mut server = NostrIntelServer::new_for_test().await;
+                let mut config = (*server.config).clone();
+                config.free_tier.identity = FreeTierIdentity::Env;
+                config.free_tier.identity_env = var;
+                server.config = Arc::new(config);
+                server.shared_state()
+            }
+        };
+        let shared_a = shared_for("NOSTR_INTEL_TEST_IDENTITY_A").await;
+        let shared_b = shared_for("NOSTR_INTEL_TEST_IDENTITY_B").await;
+        let limit = shared_a.config.free_tier.calls_per_day;
+
+        // Both tenants share one cache-backed limiter, as separate processes would
+        let first_a = NostrIntelServer::from_shared(&shared_a);
+        let second_a = NostrIntelServer::from_shared(&shared_a);
+        let mut tenant_b = NostrIntelServer::from_shared(&shared_b);
+        tenant_b.rate_limiter = Arc::clone(&first_a.rate_limiter);
+        assert_eq!(first_a.client_id(), "env-tenant-a");
+        assert_eq!(first_a.client_id(), second_a.client_id());
+        assert_eq!(tenant_b.client_id(), "env-tenant-b");
+
+        for _ in 0..limit {
+            first_a
+                .payment_gate("relay_discovery", 20, None)
+                .await
+                .unwrap();
+        }
+        // Every session of tenant A sees the quota exhausted...
+        assert!(matches!(
+            second_a.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::EarlyReturn(_))
+        ));
+        // ...while tenant B still has its own
+        assert!(matches!(
+            tenant_b.payment_gate("relay_discovery", 20, None).await,
+            Ok(PaymentGateResult::Proceed { .. })
+        ));
+    }
 
-        let resp = decode_nostr_uri_inner(&with_prefix).unwrap();
-        assert_eq!(resp.entity_type, "pubkey");
-        assert_eq!(resp.hex_id, TEST_HEX);
+    #[tokio::test]
+    async fn process_identity_is_shared_and_api_key_identity_requires_a_key() {
+        let mut server = NostrIntelServer::new_for_test().await;
+        let mut config = (*server.config).clone();
+        config.free_tier.identity = FreeTierIdentity::Process;
+        server.config = Arc::new(config.clone());
+        let shared = server.shared_state();
+        let first = NostrIntelServer::from_shared(&shared);
+        let second = NostrIntelServer::from_shared(&shared);
+        assert!(first.client_id().starts_with("proc-"));
+        assert_eq!(first.client_id(), second.client_id());
+
+        config.free_tier.identity = FreeTierIdentity::ApiKey;
+        server.config = Arc::new(config);
+        let shared = server.shared_state();
+        let session = NostrIntelServer::from_shared(&shared);
+        assert!(session.bind_api_key(None).is_err());
+        session.bind_api_key(Some("agent-key")).unwrap();
+        assert!(session.client_id().starts_with("key-"));
     }
 
-    #[test]
-    fn decode_invalid_input() {
-        let result = decode_nostr_uri_inner("garbage");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid Nostr URI"));
+    #[tokio::test]
+    async fn metrics_endpoint_counts_tool_calls() {
+        let server = NostrIntelServer::new_for_test().await;
+        let shared = Arc::new(server.shared_state());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::metrics::router(shared);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("http://{addr}/metrics");
+        let line = "nostr_intel_tool_calls_total{tool=\"decode_nostr_uri\"} 1";
+
+        let before = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(!before.contains(line));
+
+        let npub = test_pubkey().to_bech32().unwrap();
+        server
+            .decode_nostr_uri(Parameters(DecodeNostrUriParams { uri: npub }))
+            .await
+            .unwrap();
+
+        let after = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(after.contains(line), "metrics output:\n{after}");
     }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+/// Collapses concurrent identical fetches into one.
+///
+/// The first caller for a key runs the fetch; callers arriving while it is in flight
+/// wait for and clone its result. Once it completes the key is forgotten, so later
+/// calls go through the cache (or fetch again) as usual.
+pub struct SingleFlight<T> {
+    inflight: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn run<F, Fut>(&self, key: &str, fetch: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let cell = {
+            let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(inflight.entry(key.to_string()).or_default())
+        };
+
+        let value = cell.get_or_init(fetch).await.clone();
+
+        // Drop the entry unless a newer flight has already replaced it
+        let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+        if inflight.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+            inflight.remove(key);
+        }
+
+        value
+    }
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_calls_share_one_fetch() {
+        let flights = Arc::new(SingleFlight::<String>::new());
+        let fetches = Arc::new(AtomicU32::new(0));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..8 {
+            let flights = Arc::clone(&flights);
+            let fetches = Arc::clone(&fetches);
+            tasks.spawn(async move {
+                flights
+                    .run("pubkey", || async {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        "profile".to_string()
+                    })
+                    .await
+            });
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            assert_eq!(result.unwrap(), "profile");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn completed_flight_is_forgotten() {
+        let flights = SingleFlight::<u32>::new();
+        let fetches = AtomicU32::new(0);
+
+        for _ in 0..2 {
+            flights
+                .run("relay", || async { fetches.fetch_add(1, Ordering::SeqCst) })
+                .await;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
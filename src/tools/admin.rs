@@ -0,0 +1,66 @@
+use rmcp::schemars::{self, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+// ==================== wallet_status ====================
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WalletStatusResponse {
+    /// Whether the NWC wallet answered
+    pub connected: bool,
+    /// Configured NWC connections (failover order)
+    pub connections: usize,
+    /// Connections whose last call succeeded
+    pub healthy_connections: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_sats: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// NWC methods the connection allows (invoicing needs make_invoice and lookup_invoice)
+    pub methods: Vec<String>,
+    /// First error from the wallet, when it didn't answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// ==================== purge_cache_entry ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PurgeCacheEntryParams {
+    /// Pubkey whose cached data should be deleted (hex or npub)
+    pub pubkey: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PurgeCacheEntryResponse {
+    /// Hex pubkey that was purged
+    pub pubkey: String,
+    /// Cached profile rows removed
+    pub profiles_removed: u64,
+    /// Cached contact and relay lists removed
+    pub lists_removed: u64,
+    /// Cached events authored by the pubkey removed
+    pub events_removed: u64,
+    /// Rows removed across all tables
+    pub total_removed: u64,
+}
+
+// ==================== reset_rate_limit ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResetRateLimitParams {
+    /// Free-tier client ID, e.g. "env-tenant-a" or "key-<16 hex>" for an API key
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Raw X-API-Key whose client ID should be reset, instead of `client_id`
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ResetRateLimitResponse {
+    pub client_id: String,
+    /// Free-tier calls used today before the reset
+    pub previous_count: u32,
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use rmcp::schemars::{self, JsonSchema};
 use serde::{Deserialize, Serialize};
 
@@ -26,6 +28,70 @@ pub struct DecodeNostrUriResponse {
     pub kind: Option<u32>,
 }
 
+// ==================== decode_nostr_uris ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DecodeNostrUrisParams {
+    /// Bech32 entities to decode (npub, note, nprofile, nevent, naddr; max 100),
+    /// with or without the `nostr:` prefix
+    pub uris: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DecodeNostrUrisResponse {
+    /// Successfully decoded entries, in request order
+    pub decoded: Vec<DecodedNostrUri>,
+    /// Entries that failed to decode, in request order
+    pub errors: Vec<DecodeNostrUriError>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DecodedNostrUri {
+    /// Position in the request's `uris`
+    pub index: u32,
+    pub uri: String,
+    #[serde(flatten)]
+    pub entity: DecodeNostrUriResponse,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DecodeNostrUriError {
+    /// Position in the request's `uris`
+    pub index: u32,
+    pub uri: String,
+    pub error: String,
+}
+
+// ==================== build_share_link ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BuildShareLinkParams {
+    /// Event ID (hex or note1...)
+    pub event_id: String,
+    /// Author public key (hex or npub), embedded as a hint
+    pub author: Option<String>,
+    /// Event kind, embedded as a hint
+    pub kind: Option<u32>,
+    /// Relays the event can be found on (ws:// or wss://, max 5)
+    pub relays: Option<Vec<String>>,
+    /// `d` tag of a parameterized replaceable event; also builds an `naddr`
+    /// (requires `author` and `kind`)
+    pub identifier: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BuildShareLinkResponse {
+    /// NIP-19 `nevent` with the given hints
+    pub nevent: String,
+    /// Web link via njump.me
+    pub njump_url: String,
+    /// NIP-19 `naddr` pointing at the latest version (only with `identifier`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub naddr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub naddr_njump_url: Option<String>,
+}
+
 // ==================== resolve_nip05 ====================
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -45,12 +111,86 @@ pub struct ResolveNip05Response {
     pub relays: Option<Vec<String>>,
 }
 
+// ==================== verify_nip05 ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct VerifyNip05Params {
+    /// NIP-05 identifier, e.g. "jack@cash.app"
+    pub nip05: String,
+    /// Public key the identifier should belong to (hex or npub)
+    pub pubkey: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct VerifyNip05Response {
+    pub nip05: String,
+    /// Hex-encoded public key that was checked
+    pub pubkey: String,
+    /// True only when the domain's `names` entry matches `pubkey`
+    pub verified: bool,
+    /// Hex pubkey the domain lists for this name, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_pubkey: Option<String>,
+    /// Why verification failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// ==================== resolve_lightning_address ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ResolveLightningAddressParams {
+    /// Lightning address (LUD-16), e.g. "alice@getalby.com"
+    pub address: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ResolveLightningAddressResponse {
+    pub address: String,
+    /// LNURL-pay callback URL used to request invoices
+    pub callback: String,
+    /// Minimum payable amount in millisats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_sendable_msats: Option<u64>,
+    /// Maximum payable amount in millisats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_sendable_msats: Option<u64>,
+    /// Whether the endpoint accepts NIP-57 zap requests
+    pub allows_nostr: bool,
+    /// Pubkey that signs zap receipts (NIP-57 `nostrPubkey`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nostr_pubkey: Option<String>,
+    /// True when the address can receive Nostr zaps (allowsNostr + nostrPubkey)
+    pub supports_zaps: bool,
+}
+
+// ==================== convert_key ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ConvertKeyParams {
+    /// Public key as hex, npub, or NIP-05 identifier (user@domain)
+    pub pubkey: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConvertKeyResponse {
+    /// Hex-encoded public key
+    pub hex: String,
+    /// Bech32-encoded public key (npub)
+    pub npub: String,
+    /// Detected input format: "hex", "npub", or "nip05"
+    pub input_format: String,
+}
+
 // ==================== get_profile ====================
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetProfileParams {
     /// Public key in hex, npub (bech32), NIP-05 (user@domain), or display name (fuzzy search via Primal)
     pub pubkey: String,
+    /// Only answer from the local cache, never contacting relays, Primal or NIP-05
+    /// domains; requires a hex or npub pubkey (default: false)
+    pub cache_only: Option<bool>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -76,6 +216,10 @@ pub struct GetProfileResponse {
     /// How the profile was matched (e.g. "name_search" for fuzzy name lookup)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub matched_by: Option<String>,
+    /// Whether the profile was in the cache (only set with `cache_only`; `false`
+    /// means nothing is known locally and no other field is set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_cache: Option<bool>,
 }
 
 // ==================== check_relay ====================
@@ -86,7 +230,7 @@ pub struct CheckRelayParams {
     pub relay_url: String,
 }
 
-#[derive(Debug, Serialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct CheckRelayResponse {
     /// Whether the relay is online and responding
     pub online: bool,
@@ -99,15 +243,119 @@ pub struct CheckRelayResponse {
     /// Relay description from NIP-11 info document
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Relay icon URL from NIP-11 info document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
     /// List of supported NIP numbers
     #[serde(skip_serializing_if = "Option::is_none")]
     pub supported_nips: Option<Vec<u32>>,
+    /// Commonly relied-on relay NIPs missing from `supported_nips` (e.g. 50 means
+    /// no full-text search, 40 no expiring events)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_common_nips: Option<Vec<u32>>,
     /// Relay software name
     #[serde(skip_serializing_if = "Option::is_none")]
     pub software: Option<String>,
     /// Relay software version
     #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
+    /// NIP-11 `limitation` object (message/subscription limits, auth/payment requirements)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limitation: Option<RelayLimitation>,
+    /// NIP-11 `fees` object (admission, subscription, publication), as published
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees: Option<serde_json::Value>,
+    /// Relay requires NIP-42 auth; this server's anonymous queries will get nothing
+    pub requires_auth: bool,
+    /// Relay requires payment before use
+    pub requires_payment: bool,
+    /// Relay is a Tor hidden service (.onion); its NIP-11 document isn't fetched over clearnet
+    pub requires_tor: bool,
+    /// Why the NIP-11 check was not attempted (e.g. an onion relay without a Tor proxy)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped_reason: Option<String>,
+}
+
+impl CheckRelayResponse {
+    /// Response for a relay that couldn't be reached, with the reason in `description`.
+    pub fn offline(reason: String) -> Self {
+        Self {
+            description: Some(reason),
+            ..Self::unchecked()
+        }
+    }
+
+    /// Response for a relay that wasn't contacted, with the reason in `skipped_reason`.
+    pub fn skipped(reason: String) -> Self {
+        Self {
+            skipped_reason: Some(reason),
+            ..Self::unchecked()
+        }
+    }
+
+    fn unchecked() -> Self {
+        Self {
+            online: false,
+            latency_ms: None,
+            name: None,
+            description: None,
+            icon: None,
+            supported_nips: None,
+            missing_common_nips: None,
+            software: None,
+            version: None,
+            limitation: None,
+            fees: None,
+            requires_auth: false,
+            requires_payment: false,
+            requires_tor: false,
+            skipped_reason: None,
+        }
+    }
+}
+
+/// Subset of the NIP-11 `limitation` object
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RelayLimitation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_message_length: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_subscriptions: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_event_tags: Option<u64>,
+    /// NIP-42 authentication required before any request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_required: Option<bool>,
+    /// Payment required before use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_required: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restricted_writes: Option<bool>,
+}
+
+// ==================== check_relays ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CheckRelaysParams {
+    /// Relay WebSocket URLs to check (max 20)
+    pub relay_urls: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CheckRelaysResponse {
+    /// Per-relay results, in request order (duplicates removed)
+    pub relays: Vec<CheckRelaysEntry>,
+    pub count: u32,
+    pub online_count: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CheckRelaysEntry {
+    pub relay_url: String,
+    #[serde(flatten)]
+    pub status: CheckRelayResponse,
 }
 
 // ==================== search_profiles ====================
@@ -118,6 +366,10 @@ pub struct SearchProfilesParams {
     pub query: String,
     /// Maximum number of profiles to return (default: 5, max: 20)
     pub limit: Option<u32>,
+    /// Add a heuristic 0-1 `spam_score` to each result (default: false)
+    pub score_spam: Option<bool>,
+    /// Add `timing_ms`, milliseconds spent per phase, for debugging slow calls (default: false)
+    pub include_timing: Option<bool>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -128,8 +380,11 @@ pub struct SearchProfilesResponse {
     pub profiles: Vec<ProfileSearchResult>,
     /// Number of results returned
     pub count: u32,
-    /// Data source used for the search
+    /// Data source used: "primal_cache" or "relay_search"
     pub source: String,
+    /// Milliseconds per phase: `primal`, `relay_fetch`, `cache` (only with `include_timing`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing_ms: Option<BTreeMap<String, u64>>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -154,4 +409,97 @@ pub struct ProfileSearchResult {
     pub website: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub followers_count: Option<u64>,
+    /// Heuristic bot/spam likelihood, 0 (established) to 1 (spam); only with `score_spam`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spam_score: Option<f32>,
+}
+
+// ==================== relay_health ====================
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RelayHealthResponse {
+    /// Per-relay health stats for relays queried since startup
+    pub relays: Vec<RelayHealthEntry>,
+    /// Number of relays currently skipped due to a high recent failure rate
+    pub deprioritized_count: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RelayHealthEntry {
+    /// Relay WebSocket URL
+    pub relay_url: String,
+    /// Total successful fetches
+    pub successes: u64,
+    /// Total failed or timed-out fetches
+    pub failures: u64,
+    /// Failure rate over the most recent fetches (0.0 - 1.0)
+    pub recent_failure_rate: f64,
+    /// Rolling average latency in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_latency_ms: Option<u64>,
+    /// Most recent error message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Whether the relay is currently skipped when fetching
+    pub deprioritized: bool,
+}
+
+// ==================== get_server_info ====================
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetServerInfoResponse {
+    pub name: String,
+    pub version: String,
+    /// Every tool this server exposes, with its price
+    pub tools: Vec<ToolPricing>,
+    /// Paid-tool calls per client per day served without payment
+    pub free_tier_calls_per_day: u32,
+    /// Free-tier calls left today for this client
+    pub free_tier_remaining: u32,
+    pub payment_protocols: PaymentProtocols,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ToolPricing {
+    pub name: String,
+    pub paid: bool,
+    /// Base price in sats once the free tier is exhausted (paid tools only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_sats: Option<u64>,
+    /// How the price varies with parameters; use get_price_quote for an exact figure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing_note: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PaymentProtocols {
+    /// Invoices via Nostr Wallet Connect (pass `payment_hash` on retry)
+    pub nwc: bool,
+    /// L402 challenge endpoint (HTTP transport only)
+    pub l402: bool,
+    pub x402: bool,
+}
+
+// ==================== get_price_quote ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetPriceQuoteParams {
+    /// Paid tool to quote, e.g. "search_events" or "get_follower_graph"
+    pub tool_name: String,
+    /// Result limit (affects search_events price)
+    pub limit: Option<u32>,
+    /// Graph depth (affects get_follower_graph price)
+    pub depth: Option<u8>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetPriceQuoteResponse {
+    pub tool_name: String,
+    /// Price in sats once the free tier is exhausted
+    pub amount_sats: u64,
+    /// Free-tier calls left today for this client
+    pub free_tier_remaining: u32,
+    pub free_tier_limit: u32,
+    /// Whether the next call would be served free
+    pub covered_by_free_tier: bool,
 }
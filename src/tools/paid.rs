@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use rmcp::schemars::{self, JsonSchema};
 use serde::{Deserialize, Serialize};
 
@@ -7,14 +9,49 @@ use serde::{Deserialize, Serialize};
 pub struct SearchEventsParams {
     /// Filter by author public keys (hex or npub)
     pub authors: Option<Vec<String>>,
-    /// Filter by event kinds (e.g., 1 for text notes)
+    /// Filter by event kinds (e.g., 1 for text notes). Omitted = the server's
+    /// default kinds (text notes unless configured otherwise); [] = all kinds
     pub kinds: Option<Vec<u32>>,
     /// Full-text search (NIP-50)
     pub search: Option<String>,
+    /// `d` tag values of parameterized replaceable events (e.g. article slugs
+    /// with kind 30023); requires `kinds`
+    pub identifiers: Option<Vec<String>>,
     /// Only events from the last N hours
     pub since_hours: Option<u64>,
     /// Maximum number of events to return (default: 20, max: 100)
     pub limit: Option<u32>,
+    /// Include each event's full signed JSON as `raw_event` (default: false)
+    pub include_raw: Option<bool>,
+    /// Verify each event's signature and drop events that fail (default: false)
+    pub verify_signatures: Option<bool>,
+    /// Only events at or after this unix timestamp (pass `cursor.timestamp` from a
+    /// previous response to poll for newer events)
+    pub after_timestamp: Option<u64>,
+    /// Boundary event to leave out of the results (pass `cursor.event_id`; requires
+    /// `after_timestamp`)
+    pub after_event_id: Option<String>,
+    /// Drop events by these authors (hex or npub), e.g. a mute list
+    pub exclude_authors: Option<Vec<String>>,
+    /// Add media/link/mention/hashtag counts to each event (default: false)
+    pub analyze_content: Option<bool>,
+    /// Add each event's detected language as an ISO 639-3 code (default: false)
+    pub detect_language: Option<bool>,
+    /// Decode `nostr:` URIs embedded in each event's content (default: false)
+    pub resolve_references: Option<bool>,
+    /// Drop events their author deleted with a NIP-09 (kind:5) request (default: false)
+    pub respect_deletions: Option<bool>,
+    /// Result order: "created_at_desc" (newest first, default) or "created_at_asc"
+    pub sort_by: Option<String>,
+    /// Drop events whose trimmed content is shorter than this many characters
+    pub min_content_length: Option<u32>,
+    /// Drop replies (events with an `e` tag) that carry no text beyond
+    /// `nostr:` references (default: false)
+    pub exclude_replies: Option<bool>,
+    /// Add `timing_ms`, milliseconds spent per phase, for debugging slow calls (default: false)
+    pub include_timing: Option<bool>,
+    /// Add `seen_on`, the relays each event was received from (default: false)
+    pub include_provenance: Option<bool>,
     /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
     pub payment_hash: Option<String>,
 }
@@ -23,7 +60,40 @@ pub struct SearchEventsParams {
 pub struct SearchEventsResponse {
     pub events: Vec<EventSummary>,
     pub count: u32,
+    /// Newest event returned; pass it back as `after_timestamp`/`after_event_id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<SearchCursor>,
+    /// Events dropped by `exclude_authors` (only set when it was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excluded_count: Option<u32>,
+    /// Events dropped as deleted by their author (only set with `respect_deletions`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_count: Option<u32>,
+    /// Events dropped as shorter than `min_content_length` (only set when it was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub too_short_count: Option<u32>,
+    /// Textless replies dropped (only set with `exclude_replies`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_replies_count: Option<u32>,
+    /// Milliseconds per phase: `relay_fetch`, `deletions`, `enrich` (only with `include_timing`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing_ms: Option<BTreeMap<String, u64>>,
     pub relays_queried: Vec<String>,
+    /// Relays that failed or timed out; results may be incomplete when set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub relay_errors: Vec<RelayFetchError>,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RelayFetchError {
+    pub relay: String,
+    pub error: String,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -34,6 +104,40 @@ pub struct EventSummary {
     pub content: String,
     pub created_at: u64,
     pub tags_summary: String,
+    /// Canonical event JSON with signature and full tags (only with `include_raw`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_event: Option<String>,
+    /// Signature check result (only with `verify_signatures`; failing events are dropped)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    /// Has an `imeta` tag or links an image/video/audio file (only with `analyze_content`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_media: Option<bool>,
+    /// http(s) URLs in the content (only with `analyze_content`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_count: Option<u32>,
+    /// `p` tags (only with `analyze_content`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mention_count: Option<u32>,
+    /// `t` tags (only with `analyze_content`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hashtag_count: Option<u32>,
+    /// ISO 639-3 code, e.g. "eng" (only with `detect_language`; unset for empty content)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// Distinct `nostr:` references in the content, decoded (only with `resolve_references`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub references: Option<Vec<crate::tools::free::DecodeNostrUriResponse>>,
+    /// Relays that returned this event before the search stopped listening
+    /// (only with `include_provenance`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seen_on: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct SearchCursor {
+    pub timestamp: u64,
+    pub event_id: String,
 }
 
 // ==================== relay_discovery ====================
@@ -52,6 +156,9 @@ pub struct RelayDiscoveryResponse {
     pub read_relays: Vec<String>,
     pub last_event_seen: Option<LastEventSeen>,
     pub recommended_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -64,10 +171,29 @@ pub struct LastEventSeen {
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct TrendingNotesParams {
-    /// Timeframe: "1h", "24h", "7d" (default "24h")
+    /// Timeframe: "30m", "1h", "24h", "7d", "2w" (default "24h"); "m" is minutes, "mo" months
     pub timeframe: Option<String>,
     /// Maximum number of trending notes to return (default: 20, max: 50)
     pub limit: Option<u32>,
+    /// Drop notes by these authors (hex or npub), e.g. a mute list
+    pub exclude_authors: Option<Vec<String>>,
+    /// Add each note's detected language as an ISO 639-3 code (default: false)
+    pub detect_language: Option<bool>,
+    /// Keep only notes detected as this language (ISO 639-3, e.g. "eng", "jpn")
+    pub language: Option<String>,
+    /// Keep only notes whose content contains any of these keywords (case-insensitive)
+    pub contains: Option<Vec<String>>,
+    /// Collapse notes with identical (whitespace-normalized) content into the
+    /// earliest copy, summing their engagement (default: true)
+    pub dedupe: Option<bool>,
+    /// Keep at most this many notes per author, backfilling with other authors'
+    /// next-best notes (default: unlimited)
+    pub max_per_author: Option<u32>,
+    /// Split each note's reactions into likes, dislikes and top emoji (default: false)
+    pub reaction_breakdown: Option<bool>,
+    /// Recent notes to fetch and score (default: scales with the timeframe;
+    /// capped by the server)
+    pub sample_size: Option<usize>,
     /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
     pub payment_hash: Option<String>,
 }
@@ -77,6 +203,24 @@ pub struct TrendingNotesResponse {
     pub notes: Vec<TrendingNote>,
     pub timeframe: String,
     pub count: u32,
+    /// Recent notes requested from relays
+    pub sample_size: u32,
+    /// Notes the relays actually returned, before any filtering
+    pub sampled_count: u32,
+    /// Notes dropped by `exclude_authors` (only set when it was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excluded_count: Option<u32>,
+    /// Candidate notes before the `contains` filter (only set when it was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword_candidates: Option<u32>,
+    /// Candidate notes matching `contains`; ranking only considers these
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword_matches: Option<u32>,
+    /// Notes merged into an earlier copy of the same content
+    pub duplicates_collapsed: u32,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -90,6 +234,22 @@ pub struct TrendingNote {
     pub zap_total_sats: u64,
     pub score: u64,
     pub created_at: u64,
+    /// ISO 639-3 code (only with `detect_language` or `language`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// Reactions by content (only with `reaction_breakdown`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reaction_breakdown: Option<ReactionBreakdown>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReactionBreakdown {
+    /// `+` or empty reactions
+    pub likes: u32,
+    /// `-` reactions
+    pub dislikes: u32,
+    /// Most used emoji and `:shortcode:` custom emoji reactions, with counts
+    pub top_emoji: BTreeMap<String, u32>,
 }
 
 // ==================== get_follower_graph ====================
@@ -100,6 +260,12 @@ pub struct GetFollowerGraphParams {
     pub pubkey: String,
     /// Graph depth: 1 (default) or 2 (more expensive)
     pub depth: Option<u8>,
+    /// Followers to return, newest contact list first (default: 100, max: 500)
+    pub follower_limit: Option<u32>,
+    /// Followers to skip, for paging through large follower sets (default: 0, max: 1000)
+    pub follower_offset: Option<u32>,
+    /// Keep the target's own pubkey when its contact list follows itself (default: false)
+    pub include_self: Option<bool>,
     /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
     pub payment_hash: Option<String>,
 }
@@ -109,9 +275,24 @@ pub struct GetFollowerGraphResponse {
     pub pubkey: String,
     pub following_count: u32,
     pub following: Vec<PubkeySummary>,
+    /// Followers in `followers_sample`
     pub followers_count: u32,
     pub followers_sample: Vec<PubkeySummary>,
+    /// Distinct followers seen while fetching this page (a lower bound)
+    pub followers_total_estimate: u32,
+    /// More followers exist past this page; request again with a larger `follower_offset`
+    pub has_more: bool,
+    /// Followed accounts among the followers seen, not just this page
     pub mutual_follows: Vec<PubkeySummary>,
+    /// Depth 2 only: who the target's follows follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub second_degree: Option<SecondDegreeGraph>,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
@@ -120,14 +301,338 @@ pub struct PubkeySummary {
     pub name: Option<String>,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SecondDegreeGraph {
+    /// Followed accounts whose contact lists were fetched
+    pub expanded_follows: u32,
+    /// Followed accounts skipped because of the server's fan-out cap
+    pub follows_not_expanded: u32,
+    /// Distinct accounts found that the target doesn't already follow
+    pub accounts_count: u32,
+    /// Most-followed first
+    pub accounts: Vec<SecondDegreeAccount>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SecondDegreeAccount {
+    pub pubkey: String,
+    pub name: Option<String>,
+    /// Expanded follows that follow this account
+    pub followed_by: u32,
+}
+
+// ==================== common_follows ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CommonFollowsParams {
+    /// 2-10 seed public keys (hex or npub)
+    pub pubkeys: Vec<String>,
+    /// Minimum number of seeds that must follow an account (default: all seeds with a contact list)
+    pub min_overlap: Option<u32>,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CommonFollowsResponse {
+    /// Seed pubkeys (hex) that were compared
+    pub seeds: Vec<String>,
+    /// Seeds with no contact list on the queried relays
+    pub seeds_without_contact_list: Vec<String>,
+    /// Overlap threshold that was applied
+    pub min_overlap: u32,
+    /// Accounts followed by at least `min_overlap` seeds, most-shared first (max 100)
+    pub common: Vec<CommonFollow>,
+    /// Total accounts meeting the threshold before truncation
+    pub common_count: u32,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CommonFollow {
+    pub pubkey: String,
+    pub name: Option<String>,
+    /// Number of seeds following this account
+    pub overlap_count: u32,
+}
+
+// ==================== compare_accounts ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CompareAccountsParams {
+    /// First public key (hex or npub)
+    pub pubkey_a: String,
+    /// Second public key (hex or npub)
+    pub pubkey_b: String,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CompareAccountsResponse {
+    pub pubkey_a: String,
+    pub pubkey_b: String,
+    /// Overlap between the two accounts' followers
+    pub followers: AudienceOverlap,
+    /// Overlap between the accounts each one follows
+    pub following: AudienceOverlap,
+    /// Followers of both accounts, with names where cached or fetchable
+    pub shared_followers_sample: Vec<PubkeySummary>,
+    /// Accounts both of them follow
+    pub shared_follows_sample: Vec<PubkeySummary>,
+    /// Follower sets hit the fetch cap, so follower overlap is over a sample
+    pub followers_truncated: bool,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, JsonSchema)]
+pub struct AudienceOverlap {
+    pub a_count: u32,
+    pub b_count: u32,
+    pub shared_count: u32,
+    /// shared / union, 0.0-1.0 (0 when both sets are empty)
+    pub jaccard: f64,
+}
+
+// ==================== account_activity ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AccountActivityParams {
+    /// Public key (hex or npub) to profile
+    pub pubkey: String,
+    /// Timeframe: "7d", "2w", "30d" (default), "3mo", "1y"; "m" is minutes, "mo" months
+    pub timeframe: Option<String>,
+    /// Also count reposts (kind:6) and reactions (kind:7) (default: false)
+    pub include_reactions: Option<bool>,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AccountActivityResponse {
+    pub pubkey: String,
+    pub timeframe: String,
+    /// Events counted (kind:1, plus kind:6/7 with `include_reactions`)
+    pub total_events: u32,
+    pub avg_posts_per_day: f64,
+    /// Hour of day (0-23, UTC) with the most events
+    pub most_active_hour_utc: Option<u8>,
+    /// Longest stretch between two consecutive events
+    pub longest_gap: Option<ActivityGap>,
+    /// Days with at least one event, oldest first
+    pub posts_per_day: Vec<ActivityDay>,
+    /// Relays hit the fetch cap, so older activity in the timeframe may be missing
+    pub truncated: bool,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ActivityDay {
+    pub date: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ActivityGap {
+    pub from: u64,
+    pub to: u64,
+    pub hours: f64,
+}
+
+// ==================== get_dossier ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetDossierParams {
+    /// Public key (hex or npub) to profile
+    pub pubkey: String,
+    /// Timeframe for posting cadence and zap totals: "7d", "30d" (default), "3mo"
+    pub timeframe: Option<String>,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetDossierResponse {
+    pub pubkey: String,
+    pub npub: String,
+    pub timeframe: String,
+    /// Kind:0 metadata; absent when the pubkey has none or the fetch failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<crate::tools::free::GetProfileResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relays: Option<DossierRelays>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub social: Option<DossierSocial>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<DossierActivity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zaps: Option<DossierZaps>,
+    /// Sections that could not be fetched, with the reason
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Pool relays not queried because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+/// NIP-65 relay list (unmarked relays appear in both lists)
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DossierRelays {
+    pub write_relays: Vec<String>,
+    pub read_relays: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DossierSocial {
+    pub following_count: u32,
+    /// Distinct followers among up to 100 sampled contact lists
+    pub followers_count: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DossierActivity {
+    /// Kind:1 notes in the timeframe
+    pub total_events: u32,
+    pub avg_posts_per_day: f64,
+    /// Hour of day (0-23, UTC) with the most notes
+    pub most_active_hour_utc: Option<u8>,
+    pub longest_gap: Option<ActivityGap>,
+    /// Relays hit the fetch cap, so older activity in the timeframe may be missing
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DossierZaps {
+    pub total_received_sats: u64,
+    pub total_zaps_count: u32,
+    pub avg_zap_sats: u64,
+    /// Receipts that failed NIP-57 verification and were excluded
+    pub suspicious_zaps_count: u32,
+}
+
+// ==================== get_bookmarks ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetBookmarksParams {
+    /// Public key (hex or npub) whose bookmarks to fetch
+    pub pubkey: String,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetBookmarksResponse {
+    pub pubkey: String,
+    /// When the bookmark list was last published; absent when the pubkey has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    /// Bookmarked notes, most recently bookmarked first
+    pub bookmarks: Vec<BookmarkedNote>,
+    /// Bookmarked event IDs that no relay returned
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_ids: Vec<String>,
+    /// Bookmarked event IDs in the list, including any past the resolve cap
+    pub total_bookmarks: u32,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BookmarkedNote {
+    pub id: String,
+    pub kind: u32,
+    pub author_pubkey: String,
+    pub author_name: Option<String>,
+    pub content_preview: String,
+    pub created_at: u64,
+}
+
+// ==================== get_lists ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetListsParams {
+    /// Public key (hex or npub) whose lists to fetch
+    pub pubkey: String,
+    /// List kinds to include (default: all of 10000 mute list, 10001 pin list,
+    /// 30000 follow sets, 30001 generic lists, 30003 bookmark sets)
+    pub kinds: Option<Vec<u16>>,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GetListsResponse {
+    pub pubkey: String,
+    /// Newest version of each list, most recently updated first
+    pub lists: Vec<NostrList>,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NostrList {
+    pub kind: u32,
+    /// `d` tag of a parameterized list (set kinds); absent for kinds 10000/10001
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// `title` tag, falling back to the deprecated `name` tag
+    pub title: Option<String>,
+    pub updated_at: u64,
+    /// Public `p` members (capped, in list order)
+    pub pubkeys: Vec<ListMember>,
+    /// Public `e` members as hex event IDs (capped, in list order)
+    pub events: Vec<String>,
+    /// Public `p` and `e` members before capping
+    pub member_count: u32,
+    /// The list also carries encrypted private items, which are not decoded
+    pub has_private_items: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListMember {
+    pub pubkey: String,
+    pub name: Option<String>,
+}
+
 // ==================== zap_analytics ====================
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ZapAnalyticsParams {
     /// Public key (hex or npub) to analyze zaps for
     pub pubkey: String,
-    /// Timeframe: "7d", "30d" (default), "90d", "1y"
+    /// Timeframe: "7d", "2w", "30d" (default), "3mo", "1y"; "m" is minutes, "mo" months
     pub timeframe: Option<String>,
+    /// Verify each zap receipt's embedded zap request (NIP-57) and exclude failures (default: true)
+    pub verify: Option<bool>,
+    /// Also fetch zaps sent by the pubkey and report net flow with each top zapper
+    /// (default: false; doubles the relay queries)
+    pub include_reciprocity: Option<bool>,
+    /// Ignore zaps below this many sats in every total, ranking and time series
+    /// (default: none)
+    pub min_sats: Option<u64>,
     /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
     pub payment_hash: Option<String>,
 }
@@ -139,7 +644,24 @@ pub struct ZapAnalyticsResponse {
     pub avg_zap_sats: u64,
     pub top_zappers: Vec<ZapperSummary>,
     pub top_zapped_notes: Vec<ZappedNote>,
+    /// Largest individual zaps, with the zapper's comment
+    pub top_zaps: Vec<TopZap>,
+    /// Sats from anonymous zaps (NIP-57 `anon` tag), not attributed to any zapper
+    pub anonymous_sats: u64,
+    pub anonymous_zaps_count: u32,
     pub zaps_over_time: Vec<ZapPeriod>,
+    /// Zaps exchanged with each top zapper (only with `include_reciprocity`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reciprocity: Option<Vec<ZapReciprocity>>,
+    /// Receipts excluded from totals because verification failed (only set when verifying)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspicious_zaps_count: Option<u32>,
+    /// Receipts excluded for being below `min_sats` (only set with `min_sats`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub below_min_sats_count: Option<u32>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -149,6 +671,18 @@ pub struct ZapperSummary {
     pub total_sats: u64,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ZapReciprocity {
+    pub pubkey: String,
+    pub name: Option<String>,
+    /// Sats this counterparty zapped the analyzed pubkey
+    pub received_sats: u64,
+    /// Sats the analyzed pubkey zapped back
+    pub sent_sats: u64,
+    /// received_sats - sent_sats
+    pub net_sats: i64,
+}
+
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct ZappedNote {
     pub note_id: String,
@@ -156,6 +690,19 @@ pub struct ZappedNote {
     pub total_sats: u64,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TopZap {
+    /// Sender, or `None` for anonymous zaps
+    pub zapper_pubkey: Option<String>,
+    pub amount_sats: u64,
+    /// Comment from the embedded zap request (truncated)
+    pub comment: Option<String>,
+    /// Zapped note, when the zap targeted one
+    pub note_id: Option<String>,
+    /// Receipt creation time (unix seconds)
+    pub timestamp: u64,
+}
+
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct ZapPeriod {
     pub date: String,
@@ -163,6 +710,50 @@ pub struct ZapPeriod {
     pub sats: u64,
 }
 
+// ==================== note_zaps ====================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NoteZapsParams {
+    /// Event ID of the zapped note (hex, note1... or nevent1...)
+    pub event_id: String,
+    /// Verify each zap receipt's embedded zap request (NIP-57) and exclude failures (default: true)
+    pub verify: Option<bool>,
+    /// Payment hash from a paid Lightning invoice (required after free tier exhausted)
+    pub payment_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NoteZapsResponse {
+    pub event_id: String,
+    pub total_sats: u64,
+    pub zap_count: u32,
+    /// Zappers by total sats, largest first
+    pub zappers: Vec<NoteZapper>,
+    /// Sats from anonymous zaps (NIP-57 `anon` tag), not attributed to any zapper
+    pub anonymous_sats: u64,
+    pub anonymous_zaps_count: u32,
+    /// Receipts excluded because verification failed (only set when verifying)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspicious_zaps_count: Option<u32>,
+    /// Pool relays skipped because they require NIP-42 auth
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_relays: Vec<String>,
+    /// Free-tier calls left today (only set when this call was served free)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_tier_remaining: Option<u32>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NoteZapper {
+    pub pubkey: String,
+    pub name: Option<String>,
+    pub total_sats: u64,
+    pub zap_count: u32,
+    /// Non-empty zap comments, largest zap first (truncated)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+}
+
 // ==================== payment required ====================
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -170,11 +761,27 @@ pub struct PaymentRequiredResponse {
     pub payment_required: bool,
     pub tool_name: String,
     pub amount_sats: u64,
+    /// Approximate USD value at the last fetched BTC price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_usd_estimate: Option<f64>,
     pub invoice: String,
     pub payment_hash: String,
     pub message: String,
 }
 
+// ==================== x402 fallback ====================
+
+#[derive(Debug, Serialize)]
+pub struct X402PaymentRequiredResponse {
+    pub payment_required: bool,
+    pub tool_name: String,
+    pub amount_sats: u64,
+    /// Always "x402"
+    pub protocol: String,
+    pub x402: crate::payment::x402::X402PaymentDetails,
+    pub message: String,
+}
+
 // ==================== free tier exhausted (no NWC) ====================
 
 #[derive(Debug, Serialize, JsonSchema)]